@@ -13,6 +13,8 @@
 //! transport-agnostic.
 //!
 //! You can use any bidirectional stream that implements `AsyncRead` and `AsyncWrite`.
+//! With the `websocket` feature, `WebSocketTransport` adapts a WebSocket to these
+//! traits.
 //!
 //! ## Core Components
 //!
@@ -47,41 +49,109 @@
 //!   to [`ClientSideConnection::new`], which returns a connection providing [`Agent`]
 //!   methods for managing sessions and sending prompts.
 //!
+//! Both sides report failures with [`Error`], whose [`ErrorCode`] is sent to the
+//! other side as the JSON-RPC error code.
+//!
+//! ## Debugging
+//!
+//! Connections log every message they send and receive through the `log` crate at
+//! the `trace` level. With the `tracing` feature, they also emit a `tracing` span
+//! named `acp.request` for each request sent or handled. The span records the method,
+//! request ID, and session ID. Events inside it report when the response was
+//! received and how long the request took.
+//!
 //! For the complete protocol specification and documentation, visit:
 //! [https://agentclientprotocol.com](https://agentclientprotocol.com)
 
 mod agent;
+mod cancellation;
 mod client;
 mod content;
 mod error;
 mod ext;
+mod history;
+#[cfg(feature = "image")]
+mod image;
+mod message_accumulator;
+mod method;
+mod permission_policy;
 mod plan;
+mod prompt_queue;
+mod request_span;
+mod resource_uri;
+mod retry;
+mod ring_buffer;
 mod rpc;
 #[cfg(test)]
 mod rpc_tests;
+#[cfg(feature = "schema-validation")]
+mod schema_validation;
+#[cfg(test)]
+mod serde_tests;
+mod session_registry;
+mod session_store;
 mod stream_broadcast;
+mod stream_replay;
 mod tool_call;
 mod version;
+#[cfg(feature = "websocket")]
+mod websocket;
 
 pub use agent::*;
+pub use cancellation::*;
 pub use client::*;
 pub use content::*;
 pub use error::*;
 pub use ext::*;
+pub use history::*;
+pub use message_accumulator::*;
+pub use method::*;
+pub use permission_policy::*;
 pub use plan::*;
+pub use prompt_queue::*;
+pub use resource_uri::*;
+pub use retry::*;
+pub use ring_buffer::*;
+pub use rpc::{ConnectionStats, Framing, OversizedMessagePolicy, PeerUnreachable};
+#[cfg(feature = "schema-validation")]
+pub use schema_validation::*;
 pub use serde_json::value::RawValue;
+pub use session_registry::*;
+pub use session_store::*;
 pub use stream_broadcast::{
-    StreamMessage, StreamMessageContent, StreamMessageDirection, StreamReceiver,
+    StreamMessage, StreamMessageContent, StreamMessageDirection, StreamMessageInfo,
+    StreamMessageKind, StreamReceiver, StreamRecvError, StreamValue,
 };
+pub use stream_replay::*;
 pub use tool_call::*;
 pub use version::*;
+#[cfg(feature = "websocket")]
+pub use websocket::*;
 
 use anyhow::Result;
-use futures::{AsyncRead, AsyncWrite, Future, future::LocalBoxFuture};
+#[cfg(feature = "send")]
+use futures::future::BoxFuture;
+use futures::{
+    AsyncRead, AsyncWrite, Future, Stream,
+    channel::oneshot,
+    future::{self, Either, LocalBoxFuture},
+};
+use parking_lot::Mutex;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fmt, sync::Arc};
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
+#[cfg(feature = "send")]
+use crate::rpc::SendMessageHandler;
 use crate::rpc::{MessageHandler, RpcConnection, Side};
 
 /// A unique identifier for a conversation session between a client and agent.
@@ -109,6 +179,49 @@ impl fmt::Display for SessionId {
     }
 }
 
+/// An identifier chosen by the client to correlate progress updates with the
+/// request that caused them.
+///
+/// See protocol docs: [Progress](https://agentclientprotocol.com/protocol/session-setup#progress)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct ProgressToken(pub Arc<str>);
+
+impl fmt::Display for ProgressToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An identifier chosen by the client for a prompt turn, which the agent echoes
+/// on the session updates it sends during that turn.
+///
+/// See protocol docs: [Turn IDs](https://agentclientprotocol.com/protocol/prompt-turn#turn-ids)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct TurnId(pub Arc<str>);
+
+impl fmt::Display for TurnId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Checks that `path` is absolute, as the protocol requires for working
+/// directories and file system paths.
+///
+/// Fails with [`Error::invalid_params`], with the offending path in its data.
+pub fn require_absolute(path: &Path) -> Result<(), Error> {
+    if path.is_absolute() {
+        Ok(())
+    } else {
+        Err(Error::invalid_params().with_data(serde_json::json!({
+            "path": path.display().to_string(),
+            "reason": "path must be absolute",
+        })))
+    }
+}
+
 // Client to Agent
 
 /// A client-side connection to an agent.
@@ -121,6 +234,8 @@ impl fmt::Display for SessionId {
 /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
 pub struct ClientSideConnection {
     conn: RpcConnection<ClientSide, AgentSide>,
+    agent_capabilities: Arc<Mutex<Option<AgentCapabilities>>>,
+    require_absolute_paths: Arc<AtomicBool>,
 }
 
 impl ClientSideConnection {
@@ -149,8 +264,26 @@ impl ClientSideConnection {
         incoming_bytes: impl Unpin + AsyncRead,
         spawn: impl Fn(LocalBoxFuture<'static, ()>) + 'static,
     ) -> (Self, impl Future<Output = Result<()>>) {
-        let (conn, io_task) = RpcConnection::new(client, outgoing_bytes, incoming_bytes, spawn);
-        (Self { conn }, io_task)
+        let require_absolute_paths = Arc::default();
+        let client = RequireAbsolutePaths {
+            handler: client,
+            enabled: Arc::clone(&require_absolute_paths),
+        };
+        let (conn, io_task) = RpcConnection::new(
+            client,
+            outgoing_bytes,
+            incoming_bytes,
+            Arc::default(),
+            spawn,
+        );
+        (
+            Self {
+                conn,
+                agent_capabilities: Arc::default(),
+                require_absolute_paths,
+            },
+            io_task,
+        )
     }
 
     /// Subscribe to receive stream updates from the agent.
@@ -164,104 +297,471 @@ impl ClientSideConnection {
     pub fn subscribe(&self) -> StreamReceiver {
         self.conn.subscribe()
     }
-}
 
-#[async_trait::async_trait(?Send)]
-impl Agent for ClientSideConnection {
-    async fn initialize(&self, args: InitializeRequest) -> Result<InitializeResponse, Error> {
-        self.conn
-            .request(
-                INITIALIZE_METHOD_NAME,
-                Some(ClientRequest::InitializeRequest(args)),
-            )
-            .await
+    /// Like [`Self::subscribe`], but only receives the messages `filter` accepts.
+    ///
+    /// The filter is called before the message is serialized, so observers
+    /// that only care about a few methods don't pay for serializing the rest.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use agent_client_protocol::ClientSideConnection;
+    /// # fn example(conn: &ClientSideConnection) {
+    /// let updates = conn.subscribe_filtered(|message| message.method == Some("session/update"));
+    /// # }
+    /// ```
+    pub fn subscribe_filtered(
+        &self,
+        filter: impl Fn(&StreamMessageInfo<'_>) -> bool + Send + Sync + 'static,
+    ) -> StreamReceiver {
+        self.conn.subscribe_filtered(filter)
     }
 
-    async fn authenticate(&self, args: AuthenticateRequest) -> Result<AuthenticateResponse, Error> {
-        self.conn
-            .request::<Option<_>>(
-                AUTHENTICATE_METHOD_NAME,
-                Some(ClientRequest::AuthenticateRequest(args)),
-            )
-            .await
-            .map(Option::unwrap_or_default)
+    /// Counts of the requests, responses, and notifications exchanged with the
+    /// agent so far, for observability.
+    ///
+    /// Messages the connection handles itself, such as keepalive pings, aren't
+    /// counted.
+    #[must_use]
+    pub fn stats(&self) -> ConnectionStats {
+        self.conn.stats()
     }
 
-    async fn new_session(&self, args: NewSessionRequest) -> Result<NewSessionResponse, Error> {
-        self.conn
-            .request(
-                SESSION_NEW_METHOD_NAME,
-                Some(ClientRequest::NewSessionRequest(args)),
-            )
-            .await
+    /// The capabilities the agent advertised in its `initialize` response, if
+    /// initialization has completed.
+    ///
+    /// Once they are known, prompts with content the agent doesn't support are
+    /// rejected before being sent.
+    #[must_use]
+    pub fn agent_capabilities(&self) -> Option<AgentCapabilities> {
+        self.agent_capabilities.lock().clone()
     }
 
-    async fn load_session(&self, args: LoadSessionRequest) -> Result<LoadSessionResponse, Error> {
-        self.conn
-            .request::<Option<_>>(
-                SESSION_LOAD_METHOD_NAME,
-                Some(ClientRequest::LoadSessionRequest(args)),
-            )
-            .await
-            .map(Option::unwrap_or_default)
+    fn check_prompt_capabilities(&self, prompt: &[ContentBlock]) -> Result<(), Error> {
+        let agent_capabilities = self.agent_capabilities.lock();
+        let Some(capabilities) = agent_capabilities.as_ref().map(|c| &c.prompt_capabilities) else {
+            return Ok(());
+        };
+        for block in prompt {
+            let unsupported = match block {
+                ContentBlock::Image(_) if !capabilities.image => "image",
+                ContentBlock::Audio(_) if !capabilities.audio => "audio",
+                ContentBlock::Resource(_) if !capabilities.embedded_context => "embedded context",
+                _ => continue,
+            };
+            return Err(Error::invalid_params().with_data(format!(
+                "agent does not support {unsupported} content in prompts"
+            )));
+        }
+        Ok(())
     }
 
-    async fn set_session_mode(
-        &self,
-        args: SetSessionModeRequest,
-    ) -> Result<SetSessionModeResponse, Error> {
+    /// Fails requests to the agent with [`Error::request_timeout`] if no response
+    /// arrives within `timeout`.
+    ///
+    /// The `timer` function must return a future that resolves after the given
+    /// duration (e.g., `tokio::time::sleep`), which keeps this crate independent
+    /// of any particular async runtime.
+    #[must_use]
+    pub fn with_request_timeout<F>(
+        mut self,
+        timeout: Duration,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
         self.conn
-            .request(
-                SESSION_SET_MODE_METHOD_NAME,
-                Some(ClientRequest::SetSessionModeRequest(args)),
-            )
-            .await
+            .set_request_timeout(timeout, Arc::new(move |duration| Box::pin(timer(duration))));
+        self
     }
 
-    async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error> {
+    /// Sends a `$/ping` request to the agent every `interval` to detect a dead peer.
+    ///
+    /// The I/O future fails with [`PeerUnreachable`] once `max_missed` consecutive
+    /// pings went unanswered. Any response counts as an answer, so peers that don't
+    /// implement `$/ping` are still considered alive. Pings from the agent are always
+    /// answered by the connection itself and never reach the handler.
+    ///
+    /// Must be called before the I/O future is first polled. The `timer` function
+    /// works as in [`Self::with_request_timeout`].
+    #[must_use]
+    pub fn with_keepalive<F>(
+        self,
+        interval: Duration,
+        max_missed: u32,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.conn.set_keepalive(
+            interval,
+            max_missed,
+            Arc::new(move |duration| Box::pin(timer(duration))),
+        );
+        self
+    }
+
+    /// Limits how many requests from the agent are handled at the same time.
+    ///
+    /// Once `max_concurrent` requests are in flight, further requests wait in a queue
+    /// of up to `max_queued` entries and are answered with [`Error::too_many_requests`]
+    /// (`-32003`) when that queue is full. Notifications are always handled
+    /// immediately.
+    ///
+    /// By default, the number of concurrent requests is unbounded.
+    #[must_use]
+    pub fn with_max_concurrent_requests(self, max_concurrent: usize, max_queued: usize) -> Self {
         self.conn
-            .request(
-                SESSION_PROMPT_METHOD_NAME,
-                Some(ClientRequest::PromptRequest(args)),
-            )
-            .await
+            .set_max_concurrent_requests(max_concurrent, max_queued);
+        self
     }
 
-    async fn cancel(&self, args: CancelNotification) -> Result<(), Error> {
-        self.conn.notify(
-            SESSION_CANCEL_METHOD_NAME,
-            Some(ClientNotification::CancelNotification(args)),
+    /// Sets how messages are delimited on the byte streams.
+    ///
+    /// Defaults to [`Framing::NewlineDelimited`], which is what the protocol's stdio
+    /// transport uses. [`Framing::ContentLength`] uses LSP-style headers instead,
+    /// for transports where both sides agree on it.
+    ///
+    /// Must be called before the I/O future is first polled.
+    #[must_use]
+    pub fn with_framing(self, framing: Framing) -> Self {
+        self.conn.set_framing(framing);
+        self
+    }
+
+    /// Limits the size of incoming messages to `max_bytes`, so that a misbehaving
+    /// agent can't make the connection buffer an unbounded amount of data.
+    ///
+    /// Larger messages are discarded as they are read, and answered with an
+    /// [`Error::message_too_large`] error. Depending on `policy`, the connection
+    /// then either continues with the next message or closes, in which case the
+    /// I/O future fails with that error. With [`Framing::ContentLength`], the limit
+    /// also applies to the decompressed size of compressed messages.
+    ///
+    /// By default, messages can be of any size.
+    ///
+    /// Must be called before the I/O future is first polled.
+    #[must_use]
+    pub fn with_max_message_size(self, max_bytes: usize, policy: OversizedMessagePolicy) -> Self {
+        self.conn.set_max_message_size(max_bytes, policy);
+        self
+    }
+
+    /// Rejects requests from the agent whose file system paths are relative.
+    ///
+    /// The protocol requires these paths to be absolute. When `required` is set,
+    /// requests with a relative path fail with the error from [`require_absolute`]
+    /// before reaching the client. By default, they are passed on as is.
+    #[must_use]
+    pub fn with_absolute_paths_required(self, required: bool) -> Self {
+        self.require_absolute_paths
+            .store(required, Ordering::Relaxed);
+        self
+    }
+
+    /// Shuts down the connection.
+    ///
+    /// Closes the outgoing message stream, which causes the IO task to resolve with
+    /// `Ok(())`, and fails any requests to the agent still awaiting a response.
+    /// Subscribed [`StreamReceiver`]s observe the end of the stream once the IO task
+    /// finishes.
+    ///
+    /// Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        self.conn.shutdown();
+    }
+}
+
+/// Implements [`Agent`], or [`SendAgent`] with the `send` feature, for a connection
+/// by forwarding every call to the agent.
+macro_rules! impl_agent_for_connection {
+    (#[$async_trait:meta] $trait:ident for $connection:ty) => {
+        #[$async_trait]
+        impl $trait for $connection {
+            async fn initialize(
+                &self,
+                args: InitializeRequest,
+            ) -> Result<InitializeResponse, Error> {
+                let compression = args.client_capabilities.compression.clone();
+                let response: InitializeResponse = self
+                    .conn
+                    .request(
+                        INITIALIZE_METHOD_NAME,
+                        Some(ClientRequest::InitializeRequest(args)),
+                    )
+                    .await?;
+                *self.agent_capabilities.lock() = Some(response.agent_capabilities.clone());
+                self.conn.set_content_encoding(ContentEncoding::negotiate(
+                    &compression,
+                    &response.agent_capabilities.compression,
+                ));
+                Ok(response)
+            }
+
+            async fn authenticate(
+                &self,
+                args: AuthenticateRequest,
+            ) -> Result<AuthenticateResponse, Error> {
+                self.conn
+                    .request::<Option<_>>(
+                        AUTHENTICATE_METHOD_NAME,
+                        Some(ClientRequest::AuthenticateRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn new_session(
+                &self,
+                args: NewSessionRequest,
+            ) -> Result<NewSessionResponse, Error> {
+                self.conn
+                    .request(
+                        SESSION_NEW_METHOD_NAME,
+                        Some(ClientRequest::NewSessionRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn load_session(
+                &self,
+                args: LoadSessionRequest,
+            ) -> Result<LoadSessionResponse, Error> {
+                self.conn
+                    .request::<Option<_>>(
+                        SESSION_LOAD_METHOD_NAME,
+                        Some(ClientRequest::LoadSessionRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn set_session_mode(
+                &self,
+                args: SetSessionModeRequest,
+            ) -> Result<SetSessionModeResponse, Error> {
+                self.conn
+                    .request(
+                        SESSION_SET_MODE_METHOD_NAME,
+                        Some(ClientRequest::SetSessionModeRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn list_commands(
+                &self,
+                args: ListCommandsRequest,
+            ) -> Result<ListCommandsResponse, Error> {
+                self.conn
+                    .request(
+                        SESSION_LIST_COMMANDS_METHOD_NAME,
+                        Some(ClientRequest::ListCommandsRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn invoke_command(
+                &self,
+                args: InvokeCommandRequest,
+            ) -> Result<PromptResponse, Error> {
+                self.conn
+                    .request(
+                        SESSION_INVOKE_COMMAND_METHOD_NAME,
+                        Some(ClientRequest::InvokeCommandRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error> {
+                self.check_prompt_capabilities(&args.prompt)?;
+                self.conn
+                    .request(
+                        SESSION_PROMPT_METHOD_NAME,
+                        Some(ClientRequest::PromptRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn cancel(&self, args: CancelNotification) -> Result<(), Error> {
+                self.conn.notify(
+                    SESSION_CANCEL_METHOD_NAME,
+                    Some(ClientNotification::CancelNotification(args)),
+                )
+            }
+
+            async fn cancel_tool_call(
+                &self,
+                args: CancelToolCallNotification,
+            ) -> Result<(), Error> {
+                self.conn.notify(
+                    SESSION_CANCEL_TOOL_CALL_METHOD_NAME,
+                    Some(ClientNotification::CancelToolCallNotification(args)),
+                )
+            }
+
+            async fn terminal_exited(&self, args: TerminalExitedNotification) -> Result<(), Error> {
+                self.conn.notify(
+                    TERMINAL_EXITED_NOTIFICATION,
+                    Some(ClientNotification::TerminalExitedNotification(args)),
+                )
+            }
+
+            async fn set_session_model(
+                &self,
+                args: SetSessionModelRequest,
+            ) -> Result<SetSessionModelResponse, Error> {
+                self.conn
+                    .request(
+                        SESSION_SET_MODEL_METHOD_NAME,
+                        Some(ClientRequest::SetSessionModelRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
+                self.conn
+                    .request(
+                        format!("_{}", args.method),
+                        Some(ClientRequest::ExtMethodRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn ext_notification(&self, args: ExtNotification) -> Result<(), Error> {
+                self.conn.notify(
+                    format!("_{}", args.method),
+                    Some(ClientNotification::ExtNotification(args)),
+                )
+            }
+        }
+    };
+}
+
+impl_agent_for_connection!(#[async_trait::async_trait(?Send)] Agent for ClientSideConnection);
+#[cfg(feature = "send")]
+impl_agent_for_connection!(#[async_trait::async_trait] SendAgent for SendClientSideConnection);
+
+/// A [`ClientSideConnection`] that can be driven by a multi-threaded runtime.
+///
+/// The wire format and method routing are identical to [`ClientSideConnection`], which this
+/// type dereferences to. The difference is that incoming requests are handled by a
+/// [`SendClient`], handler futures are spawned as [`Send`] futures, and the returned
+/// I/O future is [`Send`], so everything can be spawned with e.g. `tokio::spawn`.
+/// Requests to the agent go through [`SendAgent`], whose futures are [`Send`] as well.
+///
+/// Only available with the `send` feature.
+#[cfg(feature = "send")]
+pub struct SendClientSideConnection(ClientSideConnection);
+
+#[cfg(feature = "send")]
+impl SendClientSideConnection {
+    /// Creates a new client-side connection whose tasks can run on any thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A handler that implements the [`SendClient`] trait to process incoming agent requests
+    /// * `outgoing_bytes` - The stream for sending data to the agent
+    /// * `incoming_bytes` - The stream for receiving data from the agent
+    /// * `spawn` - A function to spawn [`Send`] async tasks (e.g., `tokio::spawn`)
+    ///
+    /// # Returns
+    ///
+    /// Returns a tuple containing:
+    /// - The connection instance for making requests to the agent
+    /// - An I/O future that must be spawned to handle the underlying communication
+    pub fn new(
+        client: impl SendClient + 'static,
+        outgoing_bytes: impl Unpin + AsyncWrite + Send,
+        incoming_bytes: impl Unpin + AsyncRead + Send,
+        spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) -> (Self, impl Future<Output = Result<()>> + Send) {
+        let require_absolute_paths = Arc::default();
+        let client = RequireAbsolutePaths {
+            handler: SendClientHandler(client),
+            enabled: Arc::clone(&require_absolute_paths),
+        };
+        let (conn, io_task) = RpcConnection::new_send(
+            client,
+            outgoing_bytes,
+            incoming_bytes,
+            Arc::default(),
+            spawn,
+        );
+        (
+            Self(ClientSideConnection {
+                conn,
+                agent_capabilities: Arc::default(),
+                require_absolute_paths,
+            }),
+            io_task,
         )
     }
 
-    #[cfg(feature = "unstable")]
-    async fn set_session_model(
-        &self,
-        args: SetSessionModelRequest,
-    ) -> Result<SetSessionModelResponse, Error> {
-        self.conn
-            .request(
-                SESSION_SET_MODEL_METHOD_NAME,
-                Some(ClientRequest::SetSessionModelRequest(args)),
-            )
-            .await
+    /// See [`ClientSideConnection::with_request_timeout`].
+    #[must_use]
+    pub fn with_request_timeout<F>(
+        self,
+        timeout: Duration,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self(self.0.with_request_timeout(timeout, timer))
     }
 
-    async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
-        self.conn
-            .request(
-                format!("_{}", args.method),
-                Some(ClientRequest::ExtMethodRequest(args)),
-            )
-            .await
+    /// See [`ClientSideConnection::with_keepalive`].
+    #[must_use]
+    pub fn with_keepalive<F>(
+        self,
+        interval: Duration,
+        max_missed: u32,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self(self.0.with_keepalive(interval, max_missed, timer))
     }
 
-    async fn ext_notification(&self, args: ExtNotification) -> Result<(), Error> {
-        self.conn.notify(
-            format!("_{}", args.method),
-            Some(ClientNotification::ExtNotification(args)),
+    /// See [`ClientSideConnection::with_max_concurrent_requests`].
+    #[must_use]
+    pub fn with_max_concurrent_requests(self, max_concurrent: usize, max_queued: usize) -> Self {
+        Self(
+            self.0
+                .with_max_concurrent_requests(max_concurrent, max_queued),
         )
     }
+
+    /// See [`ClientSideConnection::with_framing`].
+    #[must_use]
+    pub fn with_framing(self, framing: Framing) -> Self {
+        Self(self.0.with_framing(framing))
+    }
+
+    /// See [`ClientSideConnection::with_max_message_size`].
+    #[must_use]
+    pub fn with_max_message_size(self, max_bytes: usize, policy: OversizedMessagePolicy) -> Self {
+        Self(self.0.with_max_message_size(max_bytes, policy))
+    }
+
+    /// See [`ClientSideConnection::with_absolute_paths_required`].
+    #[must_use]
+    pub fn with_absolute_paths_required(self, required: bool) -> Self {
+        Self(self.0.with_absolute_paths_required(required))
+    }
+}
+
+#[cfg(feature = "send")]
+impl std::ops::Deref for SendClientSideConnection {
+    type Target = ClientSideConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
 }
 
 /// Marker type representing the client side of an ACP connection.
@@ -306,11 +806,41 @@ impl Side for ClientSide {
             TERMINAL_WAIT_FOR_EXIT_METHOD_NAME => serde_json::from_str(params.get())
                 .map(AgentRequest::WaitForTerminalExitRequest)
                 .map_err(Into::into),
+            FS_LIST_DIRECTORY_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::ListDirectoryRequest)
+                .map_err(Into::into),
+            FS_READ_TEXT_FILE_CHUNK_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::ReadTextFileChunkRequest)
+                .map_err(Into::into),
+            FS_DELETE_FILE_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::DeleteFileRequest)
+                .map_err(Into::into),
+            FS_RENAME_FILE_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::RenameFileRequest)
+                .map_err(Into::into),
+            FS_STAT_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::StatRequest)
+                .map_err(Into::into),
+            FS_SEARCH_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::SearchFilesRequest)
+                .map_err(Into::into),
+            FS_GREP_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::GrepRequest)
+                .map_err(Into::into),
+            SESSION_REQUEST_USER_INPUT_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::RequestUserInputRequest)
+                .map_err(Into::into),
+            TERMINAL_INPUT_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::WriteTerminalInputRequest)
+                .map_err(Into::into),
+            TERMINAL_RESIZE_METHOD_NAME => serde_json::from_str(params.get())
+                .map(AgentRequest::ResizeTerminalRequest)
+                .map_err(Into::into),
             _ => {
                 if let Some(custom_method) = method.strip_prefix('_') {
                     Ok(AgentRequest::ExtMethodRequest(ExtRequest {
                         method: custom_method.into(),
-                        params: RawValue::from_string(params.get().to_string())?.into(),
+                        params: params.to_owned().into(),
                     }))
                 } else {
                     Err(Error::method_not_found())
@@ -333,7 +863,7 @@ impl Side for ClientSide {
                 if let Some(custom_method) = method.strip_prefix('_') {
                     Ok(AgentNotification::ExtNotification(ExtNotification {
                         method: custom_method.into(),
-                        params: RawValue::from_string(params.get().to_string())?.into(),
+                        params: params.to_owned().into(),
                     }))
                 } else {
                     Err(Error::method_not_found())
@@ -343,61 +873,144 @@ impl Side for ClientSide {
     }
 }
 
-impl<T: Client> MessageHandler<ClientSide> for T {
-    async fn handle_request(&self, request: AgentRequest) -> Result<ClientResponse, Error> {
-        match request {
-            AgentRequest::RequestPermissionRequest(args) => {
-                let response = self.request_permission(args).await?;
-                Ok(ClientResponse::RequestPermissionResponse(response))
-            }
-            AgentRequest::WriteTextFileRequest(args) => {
-                let response = self.write_text_file(args).await?;
-                Ok(ClientResponse::WriteTextFileResponse(response))
-            }
-            AgentRequest::ReadTextFileRequest(args) => {
-                let response = self.read_text_file(args).await?;
-                Ok(ClientResponse::ReadTextFileResponse(response))
-            }
-            AgentRequest::CreateTerminalRequest(args) => {
-                let response = self.create_terminal(args).await?;
-                Ok(ClientResponse::CreateTerminalResponse(response))
-            }
-            AgentRequest::TerminalOutputRequest(args) => {
-                let response = self.terminal_output(args).await?;
-                Ok(ClientResponse::TerminalOutputResponse(response))
-            }
-            AgentRequest::ReleaseTerminalRequest(args) => {
-                let response = self.release_terminal(args).await?;
-                Ok(ClientResponse::ReleaseTerminalResponse(response))
-            }
-            AgentRequest::WaitForTerminalExitRequest(args) => {
-                let response = self.wait_for_terminal_exit(args).await?;
-                Ok(ClientResponse::WaitForTerminalExitResponse(response))
-            }
-            AgentRequest::KillTerminalCommandRequest(args) => {
-                let response = self.kill_terminal_command(args).await?;
-                Ok(ClientResponse::KillTerminalResponse(response))
-            }
-            AgentRequest::ExtMethodRequest(args) => {
-                let response = self.ext_method(args).await?;
-                Ok(ClientResponse::ExtMethodResponse(response))
-            }
-        }
+/// Routes incoming requests to a [`SendClient`].
+#[cfg(feature = "send")]
+struct SendClientHandler<T>(T);
+
+#[cfg(feature = "send")]
+impl<T> std::ops::Deref for SendClientHandler<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(feature = "send")]
+impl<T: SendClient> SendMessageHandler<ClientSide> for RequireAbsolutePaths<SendClientHandler<T>> {
+    fn handle_request(
+        &self,
+        request: AgentRequest,
+    ) -> impl Future<Output = Result<ClientResponse, Error>> + Send {
+        MessageHandler::handle_request(self, request)
     }
 
-    async fn handle_notification(&self, notification: AgentNotification) -> Result<(), Error> {
-        match notification {
-            AgentNotification::SessionNotification(args) => {
-                self.session_notification(args).await?;
+    fn handle_notification(
+        &self,
+        notification: AgentNotification,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        MessageHandler::handle_notification(self, notification)
+    }
+}
+
+/// Implements [`MessageHandler`] for a [`Client`], or a [`SendClient`] with the `send`
+/// feature, by routing every request and notification to its method.
+macro_rules! impl_client_message_handler {
+    (impl<$t:ident: $bound:ident> for $handler:ty) => {
+        impl<$t: $bound> MessageHandler<ClientSide> for $handler {
+            async fn handle_request(&self, request: AgentRequest) -> Result<ClientResponse, Error> {
+                match request {
+                    AgentRequest::RequestPermissionRequest(args) => {
+                        let response = self.request_permission(args).await?;
+                        Ok(ClientResponse::RequestPermissionResponse(response))
+                    }
+                    AgentRequest::WriteTextFileRequest(args) => {
+                        let response = self.write_text_file(args).await?;
+                        Ok(ClientResponse::WriteTextFileResponse(response))
+                    }
+                    AgentRequest::ReadTextFileRequest(args) => {
+                        let response = self.read_text_file(args).await?;
+                        Ok(ClientResponse::ReadTextFileResponse(response))
+                    }
+                    AgentRequest::CreateTerminalRequest(args) => {
+                        let response = self.create_terminal(args).await?;
+                        Ok(ClientResponse::CreateTerminalResponse(response))
+                    }
+                    AgentRequest::TerminalOutputRequest(args) => {
+                        let response = self.terminal_output(args).await?;
+                        Ok(ClientResponse::TerminalOutputResponse(response))
+                    }
+                    AgentRequest::ReleaseTerminalRequest(args) => {
+                        let response = self.release_terminal(args).await?;
+                        Ok(ClientResponse::ReleaseTerminalResponse(response))
+                    }
+                    AgentRequest::WaitForTerminalExitRequest(args) => {
+                        let response = self.wait_for_terminal_exit(args).await?;
+                        Ok(ClientResponse::WaitForTerminalExitResponse(response))
+                    }
+                    AgentRequest::KillTerminalCommandRequest(args) => {
+                        let response = self.kill_terminal_command(args).await?;
+                        Ok(ClientResponse::KillTerminalResponse(response))
+                    }
+                    AgentRequest::ListDirectoryRequest(args) => {
+                        let response = self.list_directory(args).await?;
+                        Ok(ClientResponse::ListDirectoryResponse(response))
+                    }
+                    AgentRequest::ReadTextFileChunkRequest(args) => {
+                        let response = self.read_text_file_chunk(args).await?;
+                        Ok(ClientResponse::ReadTextFileChunkResponse(response))
+                    }
+                    AgentRequest::DeleteFileRequest(args) => {
+                        let response = self.delete_file(args).await?;
+                        Ok(ClientResponse::DeleteFileResponse(response))
+                    }
+                    AgentRequest::RenameFileRequest(args) => {
+                        let response = self.rename_file(args).await?;
+                        Ok(ClientResponse::RenameFileResponse(response))
+                    }
+                    AgentRequest::StatRequest(args) => {
+                        let response = self.stat(args).await?;
+                        Ok(ClientResponse::StatResponse(response))
+                    }
+                    AgentRequest::SearchFilesRequest(args) => {
+                        let response = self.search_files(args).await?;
+                        Ok(ClientResponse::SearchFilesResponse(response))
+                    }
+                    AgentRequest::GrepRequest(args) => {
+                        let response = self.grep(args).await?;
+                        Ok(ClientResponse::GrepResponse(response))
+                    }
+                    AgentRequest::RequestUserInputRequest(args) => {
+                        let response = self.request_user_input(args).await?;
+                        Ok(ClientResponse::RequestUserInputResponse(response))
+                    }
+                    AgentRequest::WriteTerminalInputRequest(args) => {
+                        let response = self.write_terminal_input(args).await?;
+                        Ok(ClientResponse::WriteTerminalInputResponse(response))
+                    }
+                    AgentRequest::ResizeTerminalRequest(args) => {
+                        let response = self.resize_terminal(args).await?;
+                        Ok(ClientResponse::ResizeTerminalResponse(response))
+                    }
+                    AgentRequest::ExtMethodRequest(args) => {
+                        let response = self.ext_method(args).await?;
+                        Ok(ClientResponse::ExtMethodResponse(response))
+                    }
+                }
             }
-            AgentNotification::ExtNotification(args) => {
-                self.ext_notification(args).await?;
+
+            async fn handle_notification(
+                &self,
+                notification: AgentNotification,
+            ) -> Result<(), Error> {
+                match notification {
+                    AgentNotification::SessionNotification(args) => {
+                        self.session_notification(args).await?;
+                    }
+                    AgentNotification::ExtNotification(args) => {
+                        self.ext_notification(args).await?;
+                    }
+                }
+                Ok(())
             }
         }
-        Ok(())
-    }
+    };
 }
 
+impl_client_message_handler!(impl<T: Client> for T);
+#[cfg(feature = "send")]
+impl_client_message_handler!(impl<T: SendClient> for SendClientHandler<T>);
+
 // Agent to Client
 
 /// An agent-side connection to a client.
@@ -407,9 +1020,20 @@ impl<T: Client> MessageHandler<ClientSide> for T {
 /// to provide methods for requesting permissions, accessing the file system,
 /// and sending session updates.
 ///
+/// When the client sends `session/cancel`, permission requests still pending for
+/// that session resolve right away with [`RequestPermissionOutcome::Cancelled`],
+/// without waiting for the client to answer them, and the session's
+/// [`CancellationToken`] from [`Self::cancellation_token`] is tripped before the
+/// agent's `cancel` handler runs.
+///
 /// See protocol docs: [Agent](https://agentclientprotocol.com/protocol/overview#agent)
 pub struct AgentSideConnection {
     conn: RpcConnection<AgentSide, ClientSide>,
+    client_capabilities: Arc<Mutex<Option<ClientCapabilities>>>,
+    require_absolute_paths: Arc<AtomicBool>,
+    read_coalescer: ReadCoalescer,
+    pending_permissions: Arc<PendingPermissions>,
+    cancellations: Arc<Mutex<CancellationRegistry>>,
 }
 
 impl AgentSideConnection {
@@ -438,8 +1062,41 @@ impl AgentSideConnection {
         incoming_bytes: impl Unpin + AsyncRead,
         spawn: impl Fn(LocalBoxFuture<'static, ()>) + 'static,
     ) -> (Self, impl Future<Output = Result<()>>) {
-        let (conn, io_task) = RpcConnection::new(agent, outgoing_bytes, incoming_bytes, spawn);
-        (Self { conn }, io_task)
+        let client_capabilities = Arc::default();
+        let content_encoding = Arc::default();
+        let require_absolute_paths = Arc::default();
+        let pending_permissions = Arc::default();
+        let cancellations = Arc::default();
+        let agent = CancelSession {
+            handler: RequireAbsolutePaths {
+                handler: RecordClientCapabilities {
+                    agent,
+                    capabilities: Arc::clone(&client_capabilities),
+                    content_encoding: Arc::clone(&content_encoding),
+                },
+                enabled: Arc::clone(&require_absolute_paths),
+            },
+            pending: Arc::clone(&pending_permissions),
+            cancellations: Arc::clone(&cancellations),
+        };
+        let (conn, io_task) = RpcConnection::new(
+            agent,
+            outgoing_bytes,
+            incoming_bytes,
+            content_encoding,
+            spawn,
+        );
+        (
+            Self {
+                conn,
+                client_capabilities,
+                require_absolute_paths,
+                read_coalescer: ReadCoalescer::default(),
+                pending_permissions,
+                cancellations,
+            },
+            io_task,
+        )
     }
 
     /// Subscribe to receive stream updates from the client.
@@ -453,131 +1110,690 @@ impl AgentSideConnection {
     pub fn subscribe(&self) -> StreamReceiver {
         self.conn.subscribe()
     }
-}
 
-#[async_trait::async_trait(?Send)]
-impl Client for AgentSideConnection {
-    async fn request_permission(
+    /// Like [`Self::subscribe`], but only receives the messages `filter` accepts.
+    ///
+    /// The filter is called before the message is serialized, so observers
+    /// that only care about a few methods don't pay for serializing the rest.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use agent_client_protocol::AgentSideConnection;
+    /// # fn example(conn: &AgentSideConnection) {
+    /// let cancellations =
+    ///     conn.subscribe_filtered(|message| message.method == Some("session/cancel"));
+    /// # }
+    /// ```
+    pub fn subscribe_filtered(
         &self,
-        args: RequestPermissionRequest,
-    ) -> Result<RequestPermissionResponse, Error> {
-        self.conn
-            .request(
-                SESSION_REQUEST_PERMISSION_METHOD_NAME,
-                Some(AgentRequest::RequestPermissionRequest(args)),
-            )
-            .await
+        filter: impl Fn(&StreamMessageInfo<'_>) -> bool + Send + Sync + 'static,
+    ) -> StreamReceiver {
+        self.conn.subscribe_filtered(filter)
     }
 
-    async fn write_text_file(
-        &self,
-        args: WriteTextFileRequest,
-    ) -> Result<WriteTextFileResponse, Error> {
-        self.conn
-            .request::<Option<_>>(
-                FS_WRITE_TEXT_FILE_METHOD_NAME,
-                Some(AgentRequest::WriteTextFileRequest(args)),
-            )
-            .await
-            .map(Option::unwrap_or_default)
+    /// Counts of the requests, responses, and notifications exchanged with the
+    /// client so far, for observability.
+    ///
+    /// Messages the connection handles itself, such as keepalive pings, aren't
+    /// counted.
+    #[must_use]
+    pub fn stats(&self) -> ConnectionStats {
+        self.conn.stats()
     }
 
-    async fn read_text_file(
-        &self,
-        args: ReadTextFileRequest,
-    ) -> Result<ReadTextFileResponse, Error> {
-        self.conn
-            .request(
-                FS_READ_TEXT_FILE_METHOD_NAME,
-                Some(AgentRequest::ReadTextFileRequest(args)),
-            )
-            .await
+    /// The capabilities the client advertised in its `initialize` request, if the
+    /// agent has responded to it.
+    ///
+    /// Once they are known, requests for methods the client doesn't support fail
+    /// before being sent.
+    #[must_use]
+    pub fn client_capabilities(&self) -> Option<ClientCapabilities> {
+        self.client_capabilities.lock().clone()
     }
 
-    async fn create_terminal(
-        &self,
-        args: CreateTerminalRequest,
-    ) -> Result<CreateTerminalResponse, Error> {
-        self.conn
-            .request(
-                TERMINAL_CREATE_METHOD_NAME,
-                Some(AgentRequest::CreateTerminalRequest(args)),
-            )
-            .await
+    /// Registers a prompt turn for the given session and returns its token, which
+    /// is tripped when the client sends `session/cancel` for the session.
+    ///
+    /// The agent's `prompt` handler should hold the token for as long as the turn
+    /// runs, and wait on it alongside the turn, e.g. with
+    /// [`CancellationToken::run_until_cancelled`]. Dropping the token unregisters
+    /// the turn. See [`CancellationRegistry::register`].
+    pub fn cancellation_token(&self, session_id: SessionId) -> CancellationToken {
+        self.cancellations.lock().register(session_id)
     }
 
-    async fn terminal_output(
+    fn check_client_capability(
         &self,
-        args: TerminalOutputRequest,
-    ) -> Result<TerminalOutputResponse, Error> {
-        self.conn
-            .request(
-                TERMINAL_OUTPUT_METHOD_NAME,
-                Some(AgentRequest::TerminalOutputRequest(args)),
-            )
-            .await
+        method: &str,
+        supported: impl FnOnce(&ClientCapabilities) -> bool,
+    ) -> Result<(), Error> {
+        match &*self.client_capabilities.lock() {
+            Some(capabilities) if !supported(capabilities) => {
+                Err(Error::invalid_request().with_data(format!("client does not support {method}")))
+            }
+            _ => Ok(()),
+        }
     }
 
-    async fn release_terminal(
+    /// Reads a text file from the client chunk by chunk, starting at `request.offset`.
+    ///
+    /// Sends one `fs/read_text_file_chunk` request at a time and yields the chunks in
+    /// file order, so at most one chunk is held in memory. The stream ends after the
+    /// chunk marked `eof`, or after the first error.
+    ///
+    /// Only available if the client advertises the `fs.readTextFileChunk` capability.
+    pub fn read_text_file_stream(
         &self,
-        args: ReleaseTerminalRequest,
-    ) -> Result<ReleaseTerminalResponse, Error> {
-        self.conn
-            .request::<Option<_>>(
-                TERMINAL_RELEASE_METHOD_NAME,
-                Some(AgentRequest::ReleaseTerminalRequest(args)),
-            )
-            .await
-            .map(Option::unwrap_or_default)
+        request: ReadTextFileChunkRequest,
+    ) -> impl Stream<Item = Result<ReadTextFileChunkResponse, Error>> + '_ {
+        futures::stream::try_unfold(Some(request), move |request| async move {
+            let Some(request) = request else {
+                return Ok(None);
+            };
+            let chunk = self.read_text_file_chunk(request.clone()).await?;
+            if chunk.eof {
+                return Ok(Some((chunk, None)));
+            }
+            if chunk.content.is_empty() {
+                return Err(Error::internal_error()
+                    .with_data("client returned an empty chunk before the end of the file"));
+            }
+            let next = ReadTextFileChunkRequest {
+                offset: request.offset + chunk.content.len() as u64,
+                ..request
+            };
+            Ok(Some((chunk, Some(next))))
+        })
     }
 
-    async fn wait_for_terminal_exit(
-        &self,
-        args: WaitForTerminalExitRequest,
-    ) -> Result<WaitForTerminalExitResponse, Error> {
+    /// Fails requests to the client with [`Error::request_timeout`] if no response
+    /// arrives within `timeout`.
+    ///
+    /// The `timer` function must return a future that resolves after the given
+    /// duration (e.g., `tokio::time::sleep`), which keeps this crate independent
+    /// of any particular async runtime.
+    #[must_use]
+    pub fn with_request_timeout<F>(
+        mut self,
+        timeout: Duration,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
         self.conn
-            .request(
-                TERMINAL_WAIT_FOR_EXIT_METHOD_NAME,
-                Some(AgentRequest::WaitForTerminalExitRequest(args)),
-            )
-            .await
+            .set_request_timeout(timeout, Arc::new(move |duration| Box::pin(timer(duration))));
+        self
     }
 
-    async fn kill_terminal_command(
-        &self,
-        args: KillTerminalCommandRequest,
-    ) -> Result<KillTerminalCommandResponse, Error> {
+    /// Sends a `$/ping` request to the client every `interval` to detect a dead peer.
+    ///
+    /// The I/O future fails with [`PeerUnreachable`] once `max_missed` consecutive
+    /// pings went unanswered. Any response counts as an answer, so peers that don't
+    /// implement `$/ping` are still considered alive. Pings from the client are always
+    /// answered by the connection itself and never reach the handler.
+    ///
+    /// Must be called before the I/O future is first polled. The `timer` function
+    /// works as in [`Self::with_request_timeout`].
+    #[must_use]
+    pub fn with_keepalive<F>(
+        self,
+        interval: Duration,
+        max_missed: u32,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.conn.set_keepalive(
+            interval,
+            max_missed,
+            Arc::new(move |duration| Box::pin(timer(duration))),
+        );
+        self
+    }
+
+    /// Limits how many requests from the client are handled at the same time.
+    ///
+    /// Once `max_concurrent` requests are in flight, further requests wait in a queue
+    /// of up to `max_queued` entries and are answered with [`Error::too_many_requests`]
+    /// (`-32003`) when that queue is full. Notifications are always handled
+    /// immediately.
+    ///
+    /// By default, the number of concurrent requests is unbounded.
+    #[must_use]
+    pub fn with_max_concurrent_requests(self, max_concurrent: usize, max_queued: usize) -> Self {
         self.conn
-            .request::<Option<_>>(
-                TERMINAL_KILL_METHOD_NAME,
-                Some(AgentRequest::KillTerminalCommandRequest(args)),
-            )
-            .await
-            .map(Option::unwrap_or_default)
-    }
-
-    async fn session_notification(&self, args: SessionNotification) -> Result<(), Error> {
-        self.conn.notify(
-            SESSION_UPDATE_NOTIFICATION,
-            Some(AgentNotification::SessionNotification(args)),
+            .set_max_concurrent_requests(max_concurrent, max_queued);
+        self
+    }
+
+    /// Sets how messages are delimited on the byte streams.
+    ///
+    /// See [`ClientSideConnection::with_framing`].
+    #[must_use]
+    pub fn with_framing(self, framing: Framing) -> Self {
+        self.conn.set_framing(framing);
+        self
+    }
+
+    /// Limits the size of incoming messages to `max_bytes`.
+    ///
+    /// See [`ClientSideConnection::with_max_message_size`].
+    #[must_use]
+    pub fn with_max_message_size(self, max_bytes: usize, policy: OversizedMessagePolicy) -> Self {
+        self.conn.set_max_message_size(max_bytes, policy);
+        self
+    }
+
+    /// Rejects requests from the client whose working directory is relative.
+    ///
+    /// The protocol requires the `cwd` of `session/new` and `session/load` to be
+    /// absolute. When `required` is set, requests with a relative one fail with the
+    /// error from [`require_absolute`] before reaching the agent. By default, they
+    /// are passed on as is.
+    #[must_use]
+    pub fn with_absolute_paths_required(self, required: bool) -> Self {
+        self.require_absolute_paths
+            .store(required, Ordering::Relaxed);
+        self
+    }
+
+    /// Trips the tokens of `registry` on `session/cancel`, in place of the
+    /// connection's own registry.
+    ///
+    /// This lets an agent created before the connection register its prompt turns
+    /// with a registry it already holds, rather than through
+    /// [`Self::cancellation_token`].
+    #[must_use]
+    pub fn with_cancellation_registry(self, registry: CancellationRegistry) -> Self {
+        *self.cancellations.lock() = registry;
+        self
+    }
+
+    /// Sends identical `fs/read_text_file` requests that are in flight at the same
+    /// time to the client only once.
+    ///
+    /// When `enabled` is set, a read for the same session, path and range as one
+    /// still awaiting its response waits for that response instead of sending its
+    /// own request, which saves the client from reading the same file repeatedly
+    /// when many tool calls run concurrently. Reads that carry `_meta`,
+    /// and all other requests, are always sent. By default, every read is sent.
+    #[must_use]
+    pub fn with_read_coalescing(self, enabled: bool) -> Self {
+        self.read_coalescer
+            .enabled
+            .store(enabled, Ordering::Relaxed);
+        self
+    }
+
+    /// Shuts down the connection.
+    ///
+    /// Closes the outgoing message stream, which causes the IO task to resolve with
+    /// `Ok(())`, and fails any requests to the client still awaiting a response.
+    /// Subscribed [`StreamReceiver`]s observe the end of the stream once the IO task
+    /// finishes.
+    ///
+    /// Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        self.conn.shutdown();
+    }
+}
+
+/// Implements [`Client`], or [`SendClient`] with the `send` feature, for a connection
+/// by forwarding every call to the client.
+macro_rules! impl_client_for_connection {
+    (#[$async_trait:meta] $trait:ident for $connection:ty) => {
+        #[$async_trait]
+        impl $trait for $connection {
+            async fn request_permission(
+                &self,
+                args: RequestPermissionRequest,
+            ) -> Result<RequestPermissionResponse, Error> {
+                let session_id = args.session_id.clone();
+                let response = self.conn.request(
+                    SESSION_REQUEST_PERMISSION_METHOD_NAME,
+                    Some(AgentRequest::RequestPermissionRequest(args)),
+                );
+                self.pending_permissions.wait(session_id, response).await
+            }
+
+            async fn request_user_input(
+                &self,
+                args: RequestUserInputRequest,
+            ) -> Result<RequestUserInputResponse, Error> {
+                self.check_client_capability(
+                    SESSION_REQUEST_USER_INPUT_METHOD_NAME,
+                    |capabilities| capabilities.request_user_input,
+                )?;
+                self.conn
+                    .request(
+                        SESSION_REQUEST_USER_INPUT_METHOD_NAME,
+                        Some(AgentRequest::RequestUserInputRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn write_text_file(
+                &self,
+                args: WriteTextFileRequest,
+            ) -> Result<WriteTextFileResponse, Error> {
+                self.check_client_capability(FS_WRITE_TEXT_FILE_METHOD_NAME, |capabilities| {
+                    capabilities.fs.write_text_file
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        FS_WRITE_TEXT_FILE_METHOD_NAME,
+                        Some(AgentRequest::WriteTextFileRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn read_text_file(
+                &self,
+                args: ReadTextFileRequest,
+            ) -> Result<ReadTextFileResponse, Error> {
+                self.check_client_capability(FS_READ_TEXT_FILE_METHOD_NAME, |capabilities| {
+                    capabilities.fs.read_text_file
+                })?;
+                args.validate()?;
+                if args.is_byte_range() {
+                    self.check_client_capability("byte range reads", |capabilities| {
+                        capabilities.fs.read_text_file_byte_range
+                    })?;
+                }
+                self.read_coalescer
+                    .read(args, |args| {
+                        self.conn.request(
+                            FS_READ_TEXT_FILE_METHOD_NAME,
+                            Some(AgentRequest::ReadTextFileRequest(args)),
+                        )
+                    })
+                    .await
+            }
+
+            async fn read_text_file_chunk(
+                &self,
+                args: ReadTextFileChunkRequest,
+            ) -> Result<ReadTextFileChunkResponse, Error> {
+                self.check_client_capability(
+                    FS_READ_TEXT_FILE_CHUNK_METHOD_NAME,
+                    |capabilities| capabilities.fs.read_text_file_chunk,
+                )?;
+                self.conn
+                    .request(
+                        FS_READ_TEXT_FILE_CHUNK_METHOD_NAME,
+                        Some(AgentRequest::ReadTextFileChunkRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn list_directory(
+                &self,
+                args: ListDirectoryRequest,
+            ) -> Result<ListDirectoryResponse, Error> {
+                self.check_client_capability(FS_LIST_DIRECTORY_METHOD_NAME, |capabilities| {
+                    capabilities.fs.list_directory
+                })?;
+                self.conn
+                    .request(
+                        FS_LIST_DIRECTORY_METHOD_NAME,
+                        Some(AgentRequest::ListDirectoryRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn delete_file(
+                &self,
+                args: DeleteFileRequest,
+            ) -> Result<DeleteFileResponse, Error> {
+                self.check_client_capability(FS_DELETE_FILE_METHOD_NAME, |capabilities| {
+                    capabilities.fs.delete_file
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        FS_DELETE_FILE_METHOD_NAME,
+                        Some(AgentRequest::DeleteFileRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn rename_file(
+                &self,
+                args: RenameFileRequest,
+            ) -> Result<RenameFileResponse, Error> {
+                self.check_client_capability(FS_RENAME_FILE_METHOD_NAME, |capabilities| {
+                    capabilities.fs.rename_file
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        FS_RENAME_FILE_METHOD_NAME,
+                        Some(AgentRequest::RenameFileRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn stat(&self, args: StatRequest) -> Result<StatResponse, Error> {
+                self.check_client_capability(FS_STAT_METHOD_NAME, |capabilities| {
+                    capabilities.fs.stat
+                })?;
+                self.conn
+                    .request(FS_STAT_METHOD_NAME, Some(AgentRequest::StatRequest(args)))
+                    .await
+            }
+
+            async fn search_files(
+                &self,
+                args: SearchFilesRequest,
+            ) -> Result<SearchFilesResponse, Error> {
+                self.check_client_capability(FS_SEARCH_METHOD_NAME, |capabilities| {
+                    capabilities.fs.search
+                })?;
+                self.conn
+                    .request(
+                        FS_SEARCH_METHOD_NAME,
+                        Some(AgentRequest::SearchFilesRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn grep(&self, args: GrepRequest) -> Result<GrepResponse, Error> {
+                self.check_client_capability(FS_GREP_METHOD_NAME, |capabilities| {
+                    capabilities.fs.grep
+                })?;
+                self.conn
+                    .request(FS_GREP_METHOD_NAME, Some(AgentRequest::GrepRequest(args)))
+                    .await
+            }
+
+            async fn create_terminal(
+                &self,
+                args: CreateTerminalRequest,
+            ) -> Result<CreateTerminalResponse, Error> {
+                self.check_client_capability(TERMINAL_CREATE_METHOD_NAME, |capabilities| {
+                    capabilities.terminal
+                })?;
+                self.conn
+                    .request(
+                        TERMINAL_CREATE_METHOD_NAME,
+                        Some(AgentRequest::CreateTerminalRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn terminal_output(
+                &self,
+                args: TerminalOutputRequest,
+            ) -> Result<TerminalOutputResponse, Error> {
+                self.check_client_capability(TERMINAL_OUTPUT_METHOD_NAME, |capabilities| {
+                    capabilities.terminal
+                })?;
+                self.conn
+                    .request(
+                        TERMINAL_OUTPUT_METHOD_NAME,
+                        Some(AgentRequest::TerminalOutputRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn release_terminal(
+                &self,
+                args: ReleaseTerminalRequest,
+            ) -> Result<ReleaseTerminalResponse, Error> {
+                self.check_client_capability(TERMINAL_RELEASE_METHOD_NAME, |capabilities| {
+                    capabilities.terminal
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        TERMINAL_RELEASE_METHOD_NAME,
+                        Some(AgentRequest::ReleaseTerminalRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn wait_for_terminal_exit(
+                &self,
+                args: WaitForTerminalExitRequest,
+            ) -> Result<WaitForTerminalExitResponse, Error> {
+                self.check_client_capability(TERMINAL_WAIT_FOR_EXIT_METHOD_NAME, |capabilities| {
+                    capabilities.terminal
+                })?;
+                self.conn
+                    .request(
+                        TERMINAL_WAIT_FOR_EXIT_METHOD_NAME,
+                        Some(AgentRequest::WaitForTerminalExitRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn kill_terminal_command(
+                &self,
+                args: KillTerminalCommandRequest,
+            ) -> Result<KillTerminalCommandResponse, Error> {
+                self.check_client_capability(TERMINAL_KILL_METHOD_NAME, |capabilities| {
+                    capabilities.terminal
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        TERMINAL_KILL_METHOD_NAME,
+                        Some(AgentRequest::KillTerminalCommandRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn write_terminal_input(
+                &self,
+                args: WriteTerminalInputRequest,
+            ) -> Result<WriteTerminalInputResponse, Error> {
+                self.check_client_capability(TERMINAL_INPUT_METHOD_NAME, |capabilities| {
+                    capabilities.terminal_input
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        TERMINAL_INPUT_METHOD_NAME,
+                        Some(AgentRequest::WriteTerminalInputRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn resize_terminal(
+                &self,
+                args: ResizeTerminalRequest,
+            ) -> Result<ResizeTerminalResponse, Error> {
+                self.check_client_capability(TERMINAL_RESIZE_METHOD_NAME, |capabilities| {
+                    capabilities.terminal_resize
+                })?;
+                self.conn
+                    .request::<Option<_>>(
+                        TERMINAL_RESIZE_METHOD_NAME,
+                        Some(AgentRequest::ResizeTerminalRequest(args)),
+                    )
+                    .await
+                    .map(Option::unwrap_or_default)
+            }
+
+            async fn session_notification(&self, args: SessionNotification) -> Result<(), Error> {
+                self.conn.notify(
+                    SESSION_UPDATE_NOTIFICATION,
+                    Some(AgentNotification::SessionNotification(args)),
+                )
+            }
+
+            async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
+                self.conn
+                    .request(
+                        format!("_{}", args.method),
+                        Some(AgentRequest::ExtMethodRequest(args)),
+                    )
+                    .await
+            }
+
+            async fn ext_notification(&self, args: ExtNotification) -> Result<(), Error> {
+                self.conn.notify(
+                    format!("_{}", args.method),
+                    Some(AgentNotification::ExtNotification(args)),
+                )
+            }
+        }
+    };
+}
+
+impl_client_for_connection!(#[async_trait::async_trait(?Send)] Client for AgentSideConnection);
+#[cfg(feature = "send")]
+impl_client_for_connection!(#[async_trait::async_trait] SendClient for SendAgentSideConnection);
+
+/// A [`AgentSideConnection`] that can be driven by a multi-threaded runtime.
+///
+/// The wire format and method routing are identical to [`AgentSideConnection`], which this
+/// type dereferences to. The difference is that incoming requests are handled by a
+/// [`SendAgent`], handler futures are spawned as [`Send`] futures, and the returned
+/// I/O future is [`Send`], so everything can be spawned with e.g. `tokio::spawn`.
+/// Requests to the client go through [`SendClient`], whose futures are [`Send`] as well.
+///
+/// Only available with the `send` feature.
+#[cfg(feature = "send")]
+pub struct SendAgentSideConnection(AgentSideConnection);
+
+#[cfg(feature = "send")]
+impl SendAgentSideConnection {
+    /// Creates a new agent-side connection whose tasks can run on any thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `agent` - A handler that implements the [`SendAgent`] trait to process incoming client requests
+    /// * `outgoing_bytes` - The stream for sending data to the client
+    /// * `incoming_bytes` - The stream for receiving data from the client
+    /// * `spawn` - A function to spawn [`Send`] async tasks (e.g., `tokio::spawn`)
+    ///
+    /// # Returns
+    ///
+    /// Returns a tuple containing:
+    /// - The connection instance for making requests to the client
+    /// - An I/O future that must be spawned to handle the underlying communication
+    pub fn new(
+        agent: impl SendAgent + 'static,
+        outgoing_bytes: impl Unpin + AsyncWrite + Send,
+        incoming_bytes: impl Unpin + AsyncRead + Send,
+        spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) -> (Self, impl Future<Output = Result<()>> + Send) {
+        let client_capabilities = Arc::default();
+        let content_encoding = Arc::default();
+        let require_absolute_paths = Arc::default();
+        let pending_permissions = Arc::default();
+        let cancellations = Arc::default();
+        let agent = CancelSession {
+            handler: RequireAbsolutePaths {
+                handler: RecordClientCapabilities {
+                    agent: SendAgentHandler(agent),
+                    capabilities: Arc::clone(&client_capabilities),
+                    content_encoding: Arc::clone(&content_encoding),
+                },
+                enabled: Arc::clone(&require_absolute_paths),
+            },
+            pending: Arc::clone(&pending_permissions),
+            cancellations: Arc::clone(&cancellations),
+        };
+        let (conn, io_task) = RpcConnection::new_send(
+            agent,
+            outgoing_bytes,
+            incoming_bytes,
+            content_encoding,
+            spawn,
+        );
+        (
+            Self(AgentSideConnection {
+                conn,
+                client_capabilities,
+                require_absolute_paths,
+                read_coalescer: ReadCoalescer::default(),
+                pending_permissions,
+                cancellations,
+            }),
+            io_task,
         )
     }
 
-    async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
-        self.conn
-            .request(
-                format!("_{}", args.method),
-                Some(AgentRequest::ExtMethodRequest(args)),
-            )
-            .await
+    /// See [`AgentSideConnection::with_request_timeout`].
+    #[must_use]
+    pub fn with_request_timeout<F>(
+        self,
+        timeout: Duration,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self(self.0.with_request_timeout(timeout, timer))
+    }
+
+    /// See [`AgentSideConnection::with_keepalive`].
+    #[must_use]
+    pub fn with_keepalive<F>(
+        self,
+        interval: Duration,
+        max_missed: u32,
+        timer: impl Fn(Duration) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self(self.0.with_keepalive(interval, max_missed, timer))
     }
 
-    async fn ext_notification(&self, args: ExtNotification) -> Result<(), Error> {
-        self.conn.notify(
-            format!("_{}", args.method),
-            Some(AgentNotification::ExtNotification(args)),
+    /// See [`AgentSideConnection::with_max_concurrent_requests`].
+    #[must_use]
+    pub fn with_max_concurrent_requests(self, max_concurrent: usize, max_queued: usize) -> Self {
+        Self(
+            self.0
+                .with_max_concurrent_requests(max_concurrent, max_queued),
         )
     }
+
+    /// See [`AgentSideConnection::with_framing`].
+    #[must_use]
+    pub fn with_framing(self, framing: Framing) -> Self {
+        Self(self.0.with_framing(framing))
+    }
+
+    /// See [`AgentSideConnection::with_max_message_size`].
+    #[must_use]
+    pub fn with_max_message_size(self, max_bytes: usize, policy: OversizedMessagePolicy) -> Self {
+        Self(self.0.with_max_message_size(max_bytes, policy))
+    }
+
+    /// See [`AgentSideConnection::with_absolute_paths_required`].
+    #[must_use]
+    pub fn with_absolute_paths_required(self, required: bool) -> Self {
+        Self(self.0.with_absolute_paths_required(required))
+    }
+
+    /// See [`AgentSideConnection::with_read_coalescing`].
+    #[must_use]
+    pub fn with_read_coalescing(self, enabled: bool) -> Self {
+        Self(self.0.with_read_coalescing(enabled))
+    }
+
+    /// See [`AgentSideConnection::with_cancellation_registry`].
+    #[must_use]
+    pub fn with_cancellation_registry(self, registry: CancellationRegistry) -> Self {
+        Self(self.0.with_cancellation_registry(registry))
+    }
+}
+
+#[cfg(feature = "send")]
+impl std::ops::Deref for SendAgentSideConnection {
+    type Target = AgentSideConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
 }
 
 /// Marker type representing the agent side of an ACP connection.
@@ -613,18 +1829,23 @@ impl Side for AgentSide {
             SESSION_SET_MODE_METHOD_NAME => serde_json::from_str(params.get())
                 .map(ClientRequest::SetSessionModeRequest)
                 .map_err(Into::into),
-            #[cfg(feature = "unstable")]
             SESSION_SET_MODEL_METHOD_NAME => serde_json::from_str(params.get())
                 .map(ClientRequest::SetSessionModelRequest)
                 .map_err(Into::into),
             SESSION_PROMPT_METHOD_NAME => serde_json::from_str(params.get())
                 .map(ClientRequest::PromptRequest)
                 .map_err(Into::into),
+            SESSION_LIST_COMMANDS_METHOD_NAME => serde_json::from_str(params.get())
+                .map(ClientRequest::ListCommandsRequest)
+                .map_err(Into::into),
+            SESSION_INVOKE_COMMAND_METHOD_NAME => serde_json::from_str(params.get())
+                .map(ClientRequest::InvokeCommandRequest)
+                .map_err(Into::into),
             _ => {
                 if let Some(custom_method) = method.strip_prefix('_') {
                     Ok(ClientRequest::ExtMethodRequest(ExtRequest {
                         method: custom_method.into(),
-                        params: RawValue::from_string(params.get().to_string())?.into(),
+                        params: params.to_owned().into(),
                     }))
                 } else {
                     Err(Error::method_not_found())
@@ -643,11 +1864,17 @@ impl Side for AgentSide {
             SESSION_CANCEL_METHOD_NAME => serde_json::from_str(params.get())
                 .map(ClientNotification::CancelNotification)
                 .map_err(Into::into),
+            SESSION_CANCEL_TOOL_CALL_METHOD_NAME => serde_json::from_str(params.get())
+                .map(ClientNotification::CancelToolCallNotification)
+                .map_err(Into::into),
+            TERMINAL_EXITED_NOTIFICATION => serde_json::from_str(params.get())
+                .map(ClientNotification::TerminalExitedNotification)
+                .map_err(Into::into),
             _ => {
                 if let Some(custom_method) = method.strip_prefix('_') {
                     Ok(ClientNotification::ExtNotification(ExtNotification {
                         method: custom_method.into(),
-                        params: RawValue::from_string(params.get().to_string())?.into(),
+                        params: params.to_owned().into(),
                     }))
                 } else {
                     Err(Error::method_not_found())
@@ -657,54 +1884,420 @@ impl Side for AgentSide {
     }
 }
 
-impl<T: Agent> MessageHandler<AgentSide> for T {
-    async fn handle_request(&self, request: ClientRequest) -> Result<AgentResponse, Error> {
-        match request {
-            ClientRequest::InitializeRequest(args) => {
-                let response = self.initialize(args).await?;
-                Ok(AgentResponse::InitializeResponse(response))
-            }
-            ClientRequest::AuthenticateRequest(args) => {
-                let response = self.authenticate(args).await?;
-                Ok(AgentResponse::AuthenticateResponse(response))
-            }
-            ClientRequest::NewSessionRequest(args) => {
-                let response = self.new_session(args).await?;
-                Ok(AgentResponse::NewSessionResponse(response))
-            }
-            ClientRequest::LoadSessionRequest(args) => {
-                let response = self.load_session(args).await?;
-                Ok(AgentResponse::LoadSessionResponse(response))
-            }
-            ClientRequest::PromptRequest(args) => {
-                let response = self.prompt(args).await?;
-                Ok(AgentResponse::PromptResponse(response))
-            }
-            ClientRequest::SetSessionModeRequest(args) => {
-                let response = self.set_session_mode(args).await?;
-                Ok(AgentResponse::SetSessionModeResponse(response))
+/// Remembers the client capabilities from a successful `initialize` request, so
+/// that [`AgentSideConnection`] can check them before sending requests, and
+/// negotiates the [`ContentEncoding`] of the connection from them.
+struct RecordClientCapabilities<A> {
+    agent: A,
+    capabilities: Arc<Mutex<Option<ClientCapabilities>>>,
+    content_encoding: Arc<Mutex<Option<ContentEncoding>>>,
+}
+
+impl<A: MessageHandler<AgentSide>> MessageHandler<AgentSide> for RecordClientCapabilities<A> {
+    fn handle_request(
+        &self,
+        request: ClientRequest,
+    ) -> impl Future<Output = Result<AgentResponse, Error>> {
+        let client_capabilities = match &request {
+            ClientRequest::InitializeRequest(args) => Some(args.client_capabilities.clone()),
+            _ => None,
+        };
+        let capabilities = self.capabilities.clone();
+        let content_encoding = self.content_encoding.clone();
+        let response = self.agent.handle_request(request);
+        async move {
+            let response = response.await?;
+            if let Some(client_capabilities) = client_capabilities {
+                if let AgentResponse::InitializeResponse(response) = &response {
+                    *content_encoding.lock() = ContentEncoding::negotiate(
+                        &client_capabilities.compression,
+                        &response.agent_capabilities.compression,
+                    );
+                }
+                *capabilities.lock() = Some(client_capabilities);
             }
-            #[cfg(feature = "unstable")]
-            ClientRequest::SetSessionModelRequest(args) => {
-                let response = self.set_session_model(args).await?;
-                Ok(AgentResponse::SetSessionModelResponse(response))
+            Ok(response)
+        }
+    }
+
+    fn handle_notification(
+        &self,
+        notification: ClientNotification,
+    ) -> impl Future<Output = Result<(), Error>> {
+        self.agent.handle_notification(notification)
+    }
+}
+
+type ReadKey = (
+    SessionId,
+    PathBuf,
+    Option<u32>,
+    Option<u32>,
+    Option<u64>,
+    Option<u64>,
+);
+type ReadWaiter = oneshot::Sender<Result<ReadTextFileResponse, Error>>;
+
+/// Shares the response to an `fs/read_text_file` request among identical reads
+/// made while it is in flight, when enabled.
+#[derive(Default)]
+struct ReadCoalescer {
+    enabled: AtomicBool,
+    in_flight: Mutex<HashMap<ReadKey, Vec<ReadWaiter>>>,
+}
+
+impl ReadCoalescer {
+    async fn read<F>(
+        &self,
+        args: ReadTextFileRequest,
+        send: impl FnOnce(ReadTextFileRequest) -> F,
+    ) -> Result<ReadTextFileResponse, Error>
+    where
+        F: Future<Output = Result<ReadTextFileResponse, Error>>,
+    {
+        if !self.enabled.load(Ordering::Relaxed) || args.meta.is_some() {
+            return send(args).await;
+        }
+
+        let key = (
+            args.session_id.clone(),
+            args.path.clone(),
+            args.line,
+            args.limit,
+            args.byte_offset,
+            args.byte_limit,
+        );
+        loop {
+            let response = match self.in_flight.lock().entry(key.clone()) {
+                Entry::Occupied(mut entry) => {
+                    let (tx, rx) = oneshot::channel();
+                    entry.get_mut().push(tx);
+                    rx
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Vec::new());
+                    break;
+                }
+            };
+            // If the read we joined was dropped before its response arrived, take
+            // its place.
+            if let Ok(response) = response.await {
+                return response;
             }
-            ClientRequest::ExtMethodRequest(args) => {
-                let response = self.ext_method(args).await?;
-                Ok(AgentResponse::ExtMethodResponse(response))
+        }
+
+        let mut in_flight = InFlightRead {
+            coalescer: self,
+            key: Some(key),
+        };
+        let response = send(args).await;
+        for waiter in in_flight.finish() {
+            waiter.send(response.clone()).ok();
+        }
+        response
+    }
+}
+
+/// Removes a read from [`ReadCoalescer::in_flight`] when it finishes or is
+/// dropped, so that waiting reads never outlive it.
+struct InFlightRead<'a> {
+    coalescer: &'a ReadCoalescer,
+    key: Option<ReadKey>,
+}
+
+impl InFlightRead<'_> {
+    fn finish(&mut self) -> Vec<ReadWaiter> {
+        self.key
+            .take()
+            .and_then(|key| self.coalescer.in_flight.lock().remove(&key))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for InFlightRead<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// The `session/request_permission` requests awaiting a response, by session, so
+/// that they can be resolved as cancelled when their session is.
+#[derive(Default)]
+struct PendingPermissions {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<SessionId, HashMap<u64, oneshot::Sender<()>>>>,
+}
+
+impl PendingPermissions {
+    /// Waits for `response`, unless the session is cancelled first.
+    async fn wait(
+        &self,
+        session_id: SessionId,
+        response: impl Future<Output = Result<RequestPermissionResponse, Error>>,
+    ) -> Result<RequestPermissionResponse, Error> {
+        let (tx, cancelled) = oneshot::channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions
+            .lock()
+            .entry(session_id.clone())
+            .or_default()
+            .insert(id, tx);
+        let _pending = PendingPermission {
+            pending: self,
+            session_id,
+            id,
+        };
+
+        let response = std::pin::pin!(response);
+        match future::select(response, cancelled).await {
+            Either::Left((response, _)) => response,
+            Either::Right((Ok(()), _)) => Ok(RequestPermissionResponse {
+                outcome: RequestPermissionOutcome::Cancelled,
+                meta: None,
+            }),
+            Either::Right((Err(_), response)) => response.await,
+        }
+    }
+
+    fn cancel(&self, session_id: &SessionId) {
+        let pending = self.sessions.lock().remove(session_id);
+        for (_, tx) in pending.into_iter().flatten() {
+            tx.send(()).ok();
+        }
+    }
+}
+
+/// Unregisters a permission request from [`PendingPermissions`] when it finishes
+/// or is dropped.
+struct PendingPermission<'a> {
+    pending: &'a PendingPermissions,
+    session_id: SessionId,
+    id: u64,
+}
+
+impl Drop for PendingPermission<'_> {
+    fn drop(&mut self) {
+        let mut sessions = self.pending.sessions.lock();
+        if let Entry::Occupied(mut entry) = sessions.entry(self.session_id.clone()) {
+            entry.get_mut().remove(&self.id);
+            if entry.get().is_empty() {
+                entry.remove();
             }
         }
     }
+}
+
+/// Resolves the pending permission requests of a session and trips the
+/// cancellation token of its prompt turn when the client cancels it, before
+/// passing the cancellation on.
+struct CancelSession<H> {
+    handler: H,
+    pending: Arc<PendingPermissions>,
+    cancellations: Arc<Mutex<CancellationRegistry>>,
+}
+
+impl<H: MessageHandler<AgentSide>> MessageHandler<AgentSide> for CancelSession<H> {
+    fn handle_request(
+        &self,
+        request: ClientRequest,
+    ) -> impl Future<Output = Result<AgentResponse, Error>> {
+        self.handler.handle_request(request)
+    }
+
+    fn handle_notification(
+        &self,
+        notification: ClientNotification,
+    ) -> impl Future<Output = Result<(), Error>> {
+        if let ClientNotification::CancelNotification(args) = &notification {
+            self.pending.cancel(&args.session_id);
+            self.cancellations.lock().cancel(&args.session_id);
+        }
+        self.handler.handle_notification(notification)
+    }
+}
+
+/// Rejects requests with relative paths when enabled, before passing them on.
+struct RequireAbsolutePaths<H> {
+    handler: H,
+    enabled: Arc<AtomicBool>,
+}
+
+impl<H> RequireAbsolutePaths<H> {
+    fn check<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> Result<(), Error> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        paths.into_iter().try_for_each(require_absolute)
+    }
+}
+
+impl<H: MessageHandler<AgentSide>> MessageHandler<AgentSide> for RequireAbsolutePaths<H> {
+    fn handle_request(
+        &self,
+        request: ClientRequest,
+    ) -> impl Future<Output = Result<AgentResponse, Error>> {
+        let cwd = match &request {
+            ClientRequest::NewSessionRequest(args) => Some(args.cwd.as_path()),
+            ClientRequest::LoadSessionRequest(args) => Some(args.cwd.as_path()),
+            _ => None,
+        };
+        let response = self
+            .check(cwd)
+            .map(|()| self.handler.handle_request(request));
+        async move { response?.await }
+    }
+
+    fn handle_notification(
+        &self,
+        notification: ClientNotification,
+    ) -> impl Future<Output = Result<(), Error>> {
+        self.handler.handle_notification(notification)
+    }
+}
+
+impl<H: MessageHandler<ClientSide>> MessageHandler<ClientSide> for RequireAbsolutePaths<H> {
+    fn handle_request(
+        &self,
+        request: AgentRequest,
+    ) -> impl Future<Output = Result<ClientResponse, Error>> {
+        let paths = match &request {
+            AgentRequest::WriteTextFileRequest(args) => vec![args.path.as_path()],
+            AgentRequest::ReadTextFileRequest(args) => vec![args.path.as_path()],
+            AgentRequest::ReadTextFileChunkRequest(args) => vec![args.path.as_path()],
+            AgentRequest::ListDirectoryRequest(args) => vec![args.path.as_path()],
+            AgentRequest::DeleteFileRequest(args) => vec![args.path.as_path()],
+            AgentRequest::StatRequest(args) => vec![args.path.as_path()],
+            AgentRequest::SearchFilesRequest(args) => vec![args.root.as_path()],
+            AgentRequest::GrepRequest(args) => vec![args.root.as_path()],
+            AgentRequest::RenameFileRequest(args) => {
+                vec![args.old_path.as_path(), args.new_path.as_path()]
+            }
+            AgentRequest::CreateTerminalRequest(args) => args.cwd.as_deref().into_iter().collect(),
+            _ => Vec::new(),
+        };
+        let response = self
+            .check(paths)
+            .map(|()| self.handler.handle_request(request));
+        async move { response?.await }
+    }
+
+    fn handle_notification(
+        &self,
+        notification: AgentNotification,
+    ) -> impl Future<Output = Result<(), Error>> {
+        self.handler.handle_notification(notification)
+    }
+}
+
+/// Routes incoming requests to a [`SendAgent`].
+#[cfg(feature = "send")]
+struct SendAgentHandler<T>(T);
 
-    async fn handle_notification(&self, notification: ClientNotification) -> Result<(), Error> {
-        match notification {
-            ClientNotification::CancelNotification(args) => {
-                self.cancel(args).await?;
+#[cfg(feature = "send")]
+impl<T> std::ops::Deref for SendAgentHandler<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// The handlers [`SendAgentSideConnection`] wraps its [`SendAgent`] in.
+#[cfg(feature = "send")]
+type SendAgentHandlers<T> =
+    CancelSession<RequireAbsolutePaths<RecordClientCapabilities<SendAgentHandler<T>>>>;
+
+#[cfg(feature = "send")]
+impl<T: SendAgent> SendMessageHandler<AgentSide> for SendAgentHandlers<T> {
+    fn handle_request(
+        &self,
+        request: ClientRequest,
+    ) -> impl Future<Output = Result<AgentResponse, Error>> + Send {
+        MessageHandler::handle_request(self, request)
+    }
+
+    fn handle_notification(
+        &self,
+        notification: ClientNotification,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        MessageHandler::handle_notification(self, notification)
+    }
+}
+
+/// Implements [`MessageHandler`] for an [`Agent`], or a [`SendAgent`] with the `send`
+/// feature, by routing every request and notification to its method.
+macro_rules! impl_agent_message_handler {
+    (impl<$t:ident: $bound:ident> for $handler:ty) => {
+        impl<$t: $bound> MessageHandler<AgentSide> for $handler {
+            async fn handle_request(&self, request: ClientRequest) -> Result<AgentResponse, Error> {
+                match request {
+                    ClientRequest::InitializeRequest(args) => {
+                        let response = self.initialize(args).await?;
+                        Ok(AgentResponse::InitializeResponse(response))
+                    }
+                    ClientRequest::AuthenticateRequest(args) => {
+                        let response = self.authenticate(args).await?;
+                        Ok(AgentResponse::AuthenticateResponse(response))
+                    }
+                    ClientRequest::NewSessionRequest(args) => {
+                        let response = self.new_session(args).await?;
+                        Ok(AgentResponse::NewSessionResponse(response))
+                    }
+                    ClientRequest::LoadSessionRequest(args) => {
+                        let response = self.load_session(args).await?;
+                        Ok(AgentResponse::LoadSessionResponse(response))
+                    }
+                    ClientRequest::PromptRequest(args) => {
+                        let response = self.prompt(args).await?;
+                        Ok(AgentResponse::PromptResponse(response))
+                    }
+                    ClientRequest::SetSessionModeRequest(args) => {
+                        let response = self.set_session_mode(args).await?;
+                        Ok(AgentResponse::SetSessionModeResponse(response))
+                    }
+                    ClientRequest::SetSessionModelRequest(args) => {
+                        let response = self.set_session_model(args).await?;
+                        Ok(AgentResponse::SetSessionModelResponse(response))
+                    }
+                    ClientRequest::ListCommandsRequest(args) => {
+                        let response = self.list_commands(args).await?;
+                        Ok(AgentResponse::ListCommandsResponse(response))
+                    }
+                    ClientRequest::InvokeCommandRequest(args) => {
+                        let response = self.invoke_command(args).await?;
+                        Ok(AgentResponse::PromptResponse(response))
+                    }
+                    ClientRequest::ExtMethodRequest(args) => {
+                        let response = self.ext_method(args).await?;
+                        Ok(AgentResponse::ExtMethodResponse(response))
+                    }
+                }
             }
-            ClientNotification::ExtNotification(args) => {
-                self.ext_notification(args).await?;
+
+            async fn handle_notification(
+                &self,
+                notification: ClientNotification,
+            ) -> Result<(), Error> {
+                match notification {
+                    ClientNotification::CancelNotification(args) => {
+                        self.cancel(args).await?;
+                    }
+                    ClientNotification::CancelToolCallNotification(args) => {
+                        self.cancel_tool_call(args).await?;
+                    }
+                    ClientNotification::TerminalExitedNotification(args) => {
+                        self.terminal_exited(args).await?;
+                    }
+                    ClientNotification::ExtNotification(args) => {
+                        self.ext_notification(args).await?;
+                    }
+                }
+                Ok(())
             }
         }
-        Ok(())
-    }
+    };
 }
+
+impl_agent_message_handler!(impl<T: Agent> for T);
+#[cfg(feature = "send")]
+impl_agent_message_handler!(impl<T: SendAgent> for SendAgentHandler<T>);
@@ -2,6 +2,11 @@
 //!
 //! This module defines the Agent trait and all associated types for implementing
 //! an AI coding agent that follows the Agent Client Protocol (ACP).
+//!
+//! Only the [`Agent`] methods every agent must support are required. The others,
+//! including `authenticate` for agents without authentication methods, fail with
+//! [`Error::method_not_found`] by default, so implement the ones backing the
+//! capabilities you advertise.
 
 use std::rc::Rc;
 use std::{path::PathBuf, sync::Arc};
@@ -13,8 +18,9 @@ use serde_json::value::RawValue;
 
 use crate::ext::ExtRequest;
 use crate::{
-    ClientCapabilities, ContentBlock, Error, ExtNotification, ExtResponse, ProtocolVersion,
-    SessionId,
+    AvailableCommand, ClientCapabilities, ContentBlock, ContentEncoding, Error, ExtNotification,
+    ExtResponse, Meta, PermissionOption, ProgressToken, ProtocolVersion, ResourceUri, SessionId,
+    TerminalExitStatus, TerminalId, ToolCallId, TurnId,
 };
 
 /// Defines the interface that all ACP-compliant agents must implement.
@@ -44,7 +50,12 @@ pub trait Agent {
     /// `new_session` without receiving an `auth_required` error.
     ///
     /// See protocol docs: [Initialization](https://agentclientprotocol.com/protocol/initialization)
-    async fn authenticate(&self, args: AuthenticateRequest) -> Result<AuthenticateResponse, Error>;
+    async fn authenticate(
+        &self,
+        _args: AuthenticateRequest,
+    ) -> Result<AuthenticateResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
     /// Creates a new conversation session with the agent.
     ///
@@ -79,13 +90,41 @@ pub trait Agent {
     ///
     /// Upon receiving this notification, the Agent SHOULD:
     /// - Stop all language model requests as soon as possible
-    /// - Abort all tool call invocations in progress
+    /// - Abort all tool call invocations in progress, and mark them as `cancelled`
     /// - Send any pending `session/update` notifications
     /// - Respond to the original `session/prompt` request with `StopReason::Cancelled`
     ///
     /// See protocol docs: [Cancellation](https://agentclientprotocol.com/protocol/prompt-turn#cancellation)
     async fn cancel(&self, args: CancelNotification) -> Result<(), Error>;
 
+    /// Cancels a single tool call, while letting the rest of the prompt turn continue.
+    ///
+    /// This is a notification sent by the client, for example when the user stops a
+    /// long-running terminal command.
+    ///
+    /// Upon receiving this notification, the Agent SHOULD abort the tool call and
+    /// report it as `cancelled` through a `tool_call_update`. Unlike `session/cancel`,
+    /// the prompt turn carries on, and the Agent can react to the cancelled tool call.
+    ///
+    /// Only available if the Agent supports the `cancelToolCall` capability.
+    ///
+    /// See protocol docs: [Cancelling Tool Calls](https://agentclientprotocol.com/protocol/tool-calls#cancelling-tool-calls)
+    async fn cancel_tool_call(&self, _args: CancelToolCallNotification) -> Result<(), Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Notifies the agent that a terminal's command has exited.
+    ///
+    /// Only sent for terminals created with `notifyOnExit`, and only by Clients that
+    /// support the `terminalExitNotification` capability. Lets the Agent learn about
+    /// the exit without holding a `terminal/wait_for_exit` request open for the
+    /// lifetime of the command.
+    ///
+    /// See protocol docs: [Exit Notifications](https://agentclientprotocol.com/protocol/terminals#exit-notifications)
+    async fn terminal_exited(&self, _args: TerminalExitedNotification) -> Result<(), Error> {
+        Err(Error::method_not_found())
+    }
+
     /// Loads an existing session to resume a previous conversation.
     ///
     /// This method is only available if the agent advertises the `loadSession` capability.
@@ -120,12 +159,44 @@ pub trait Agent {
         Err(Error::method_not_found())
     }
 
-    /// **UNSTABLE**
+    /// Lists the slash commands available in a session.
+    ///
+    /// Lets the Client discover commands on demand, for example when the user starts
+    /// typing a command, instead of relying on the most recent
+    /// `available_commands_update` notification.
+    ///
+    /// Only available if the Agent supports the `listCommands` capability.
+    ///
+    /// See protocol docs: [Slash Commands](https://agentclientprotocol.com/protocol/slash-commands)
+    async fn list_commands(
+        &self,
+        _args: ListCommandsRequest,
+    ) -> Result<ListCommandsResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Runs one of the slash commands available in a session.
+    ///
+    /// Invoking a command starts a prompt turn, just like `session/prompt`: the
+    /// Agent streams updates, may request permissions, can be cancelled with
+    /// `session/cancel`, and responds with a stop reason when the turn is complete.
+    /// Unlike a prompt containing `/name` text, the Client states explicitly that
+    /// the user ran a command, so it can show it as one in its interface and history.
+    ///
+    /// Only available if the Agent supports the `invokeCommand` capability.
+    ///
+    /// See protocol docs: [Slash Commands](https://agentclientprotocol.com/protocol/slash-commands)
+    async fn invoke_command(&self, _args: InvokeCommandRequest) -> Result<PromptResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Selects the model used by a session.
     ///
-    /// This capability is not part of the spec yet, and may be removed or changed at any point.
+    /// The model must be one of the models advertised in `availableModels` during
+    /// session creation or loading. Agents may also change models autonomously and
+    /// notify the client via `current_model_update` notifications.
     ///
-    /// Select a model for a given session.
-    #[cfg(feature = "unstable")]
+    /// See protocol docs: [Session Models](https://agentclientprotocol.com/protocol/session-models)
     async fn set_session_model(
         &self,
         _args: SetSessionModelRequest,
@@ -140,7 +211,7 @@ pub trait Agent {
     ///
     /// See protocol docs: [Extensibility](https://agentclientprotocol.com/protocol/extensibility)
     async fn ext_method(&self, _args: ExtRequest) -> Result<ExtResponse, Error> {
-        Ok(RawValue::NULL.to_owned().into())
+        Err(Error::method_not_found())
     }
 
     /// Handles extension notifications from the client.
@@ -150,7 +221,7 @@ pub trait Agent {
     ///
     /// See protocol docs: [Extensibility](https://agentclientprotocol.com/protocol/extensibility)
     async fn ext_notification(&self, _args: ExtNotification) -> Result<(), Error> {
-        Ok(())
+        Err(Error::method_not_found())
     }
 }
 
@@ -174,13 +245,27 @@ impl<T: Agent> Agent for Rc<T> {
     ) -> Result<SetSessionModeResponse, Error> {
         self.as_ref().set_session_mode(args).await
     }
+    async fn list_commands(
+        &self,
+        args: ListCommandsRequest,
+    ) -> Result<ListCommandsResponse, Error> {
+        self.as_ref().list_commands(args).await
+    }
+    async fn invoke_command(&self, args: InvokeCommandRequest) -> Result<PromptResponse, Error> {
+        self.as_ref().invoke_command(args).await
+    }
     async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error> {
         self.as_ref().prompt(args).await
     }
     async fn cancel(&self, args: CancelNotification) -> Result<(), Error> {
         self.as_ref().cancel(args).await
     }
-    #[cfg(feature = "unstable")]
+    async fn cancel_tool_call(&self, args: CancelToolCallNotification) -> Result<(), Error> {
+        self.as_ref().cancel_tool_call(args).await
+    }
+    async fn terminal_exited(&self, args: TerminalExitedNotification) -> Result<(), Error> {
+        self.as_ref().terminal_exited(args).await
+    }
     async fn set_session_model(
         &self,
         args: SetSessionModelRequest,
@@ -215,13 +300,168 @@ impl<T: Agent> Agent for Arc<T> {
     ) -> Result<SetSessionModeResponse, Error> {
         self.as_ref().set_session_mode(args).await
     }
+    async fn list_commands(
+        &self,
+        args: ListCommandsRequest,
+    ) -> Result<ListCommandsResponse, Error> {
+        self.as_ref().list_commands(args).await
+    }
+    async fn invoke_command(&self, args: InvokeCommandRequest) -> Result<PromptResponse, Error> {
+        self.as_ref().invoke_command(args).await
+    }
+    async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error> {
+        self.as_ref().prompt(args).await
+    }
+    async fn cancel(&self, args: CancelNotification) -> Result<(), Error> {
+        self.as_ref().cancel(args).await
+    }
+    async fn cancel_tool_call(&self, args: CancelToolCallNotification) -> Result<(), Error> {
+        self.as_ref().cancel_tool_call(args).await
+    }
+    async fn terminal_exited(&self, args: TerminalExitedNotification) -> Result<(), Error> {
+        self.as_ref().terminal_exited(args).await
+    }
+    async fn set_session_model(
+        &self,
+        args: SetSessionModelRequest,
+    ) -> Result<SetSessionModelResponse, Error> {
+        self.as_ref().set_session_model(args).await
+    }
+    async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
+        self.as_ref().ext_method(args).await
+    }
+    async fn ext_notification(&self, args: ExtNotification) -> Result<(), Error> {
+        self.as_ref().ext_notification(args).await
+    }
+}
+
+/// An [`Agent`] that can be served by a multi-threaded runtime.
+///
+/// The methods and their defaults are the same as [`Agent`]'s, but implementations
+/// must be `Send + Sync` and return [`Send`] futures, so that
+/// [`SendAgentSideConnection`](crate::SendAgentSideConnection) can handle requests on any thread.
+///
+/// Only available with the `send` feature.
+#[cfg(feature = "send")]
+#[async_trait::async_trait]
+pub trait SendAgent: Send + Sync {
+    /// See [`Agent::initialize`].
+    async fn initialize(&self, args: InitializeRequest) -> Result<InitializeResponse, Error>;
+
+    /// See [`Agent::authenticate`].
+    async fn authenticate(
+        &self,
+        _args: AuthenticateRequest,
+    ) -> Result<AuthenticateResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::new_session`].
+    async fn new_session(&self, args: NewSessionRequest) -> Result<NewSessionResponse, Error>;
+
+    /// See [`Agent::prompt`].
+    async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error>;
+
+    /// See [`Agent::cancel`].
+    async fn cancel(&self, args: CancelNotification) -> Result<(), Error>;
+
+    /// See [`Agent::cancel_tool_call`].
+    async fn cancel_tool_call(&self, _args: CancelToolCallNotification) -> Result<(), Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::terminal_exited`].
+    async fn terminal_exited(&self, _args: TerminalExitedNotification) -> Result<(), Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::load_session`].
+    async fn load_session(&self, _args: LoadSessionRequest) -> Result<LoadSessionResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::set_session_mode`].
+    async fn set_session_mode(
+        &self,
+        _args: SetSessionModeRequest,
+    ) -> Result<SetSessionModeResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::list_commands`].
+    async fn list_commands(
+        &self,
+        _args: ListCommandsRequest,
+    ) -> Result<ListCommandsResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::invoke_command`].
+    async fn invoke_command(&self, _args: InvokeCommandRequest) -> Result<PromptResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::set_session_model`].
+    async fn set_session_model(
+        &self,
+        _args: SetSessionModelRequest,
+    ) -> Result<SetSessionModelResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::ext_method`].
+    async fn ext_method(&self, _args: ExtRequest) -> Result<ExtResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Agent::ext_notification`].
+    async fn ext_notification(&self, _args: ExtNotification) -> Result<(), Error> {
+        Err(Error::method_not_found())
+    }
+}
+
+#[cfg(feature = "send")]
+#[async_trait::async_trait]
+impl<T: SendAgent> SendAgent for Arc<T> {
+    async fn initialize(&self, args: InitializeRequest) -> Result<InitializeResponse, Error> {
+        self.as_ref().initialize(args).await
+    }
+    async fn authenticate(&self, args: AuthenticateRequest) -> Result<AuthenticateResponse, Error> {
+        self.as_ref().authenticate(args).await
+    }
+    async fn new_session(&self, args: NewSessionRequest) -> Result<NewSessionResponse, Error> {
+        self.as_ref().new_session(args).await
+    }
+    async fn load_session(&self, args: LoadSessionRequest) -> Result<LoadSessionResponse, Error> {
+        self.as_ref().load_session(args).await
+    }
+    async fn set_session_mode(
+        &self,
+        args: SetSessionModeRequest,
+    ) -> Result<SetSessionModeResponse, Error> {
+        self.as_ref().set_session_mode(args).await
+    }
+    async fn list_commands(
+        &self,
+        args: ListCommandsRequest,
+    ) -> Result<ListCommandsResponse, Error> {
+        self.as_ref().list_commands(args).await
+    }
+    async fn invoke_command(&self, args: InvokeCommandRequest) -> Result<PromptResponse, Error> {
+        self.as_ref().invoke_command(args).await
+    }
     async fn prompt(&self, args: PromptRequest) -> Result<PromptResponse, Error> {
         self.as_ref().prompt(args).await
     }
     async fn cancel(&self, args: CancelNotification) -> Result<(), Error> {
         self.as_ref().cancel(args).await
     }
-    #[cfg(feature = "unstable")]
+    async fn cancel_tool_call(&self, args: CancelToolCallNotification) -> Result<(), Error> {
+        self.as_ref().cancel_tool_call(args).await
+    }
+    async fn terminal_exited(&self, args: TerminalExitedNotification) -> Result<(), Error> {
+        self.as_ref().terminal_exited(args).await
+    }
     async fn set_session_model(
         &self,
         args: SetSessionModelRequest,
@@ -252,6 +492,13 @@ pub struct InitializeRequest {
     /// Capabilities supported by the client.
     #[serde(default)]
     pub client_capabilities: ClientCapabilities,
+    /// Information about the client, such as the editor's name and version.
+    ///
+    /// Agents can use this for telemetry or to work around known issues in
+    /// specific clients, but must not rely on it for feature detection, which is
+    /// what capabilities are for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_info: Option<Implementation>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -277,6 +524,29 @@ pub struct InitializeResponse {
     /// Authentication methods supported by the agent.
     #[serde(default)]
     pub auth_methods: Vec<AuthMethod>,
+    /// Information about the agent, such as its name and version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_info: Option<Implementation>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// The name and version of a client or agent implementation.
+///
+/// Exchanged during initialization, like MCP's `clientInfo` and `serverInfo`.
+///
+/// See protocol docs: [Implementation Information](https://agentclientprotocol.com/protocol/initialization#implementation-information)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Implementation {
+    /// A programmatic name for the implementation, such as `zed`.
+    pub name: String,
+    /// A human-readable name for display, such as `Zed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The version of the implementation.
+    pub version: String,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -324,9 +594,42 @@ pub struct AuthMethod {
     pub name: String,
     /// Optional description providing more details about this authentication method.
     pub description: Option<String>,
+    /// How the user completes this authentication method.
+    ///
+    /// Clients can use this to render an appropriate UI, such as a text field for an
+    /// API key or a "Sign in" button for a browser flow. When absent, clients should
+    /// fall back to presenting the method by its name and description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<AuthMethodKind>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
-    pub meta: Option<serde_json::Value>,
+    pub meta: Option<Meta>,
+}
+
+/// The kind of interaction an authentication method requires from the user.
+///
+/// Provider-specific details belong in the `_meta` field of the [`AuthMethod`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthMethodKind {
+    /// The user enters an API key or similar secret.
+    ApiKey,
+    /// The user signs in through an OAuth flow in their browser.
+    #[serde(rename = "oauth", rename_all = "camelCase")]
+    OAuth {
+        /// The URL the flow is expected to start at, for display purposes.
+        ///
+        /// The Agent remains responsible for starting the flow when the Client calls
+        /// `authenticate`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        authorize_url_hint: Option<String>,
+    },
+    /// The user enters a code on another device, following the OAuth device
+    /// authorization flow.
+    DeviceCode,
+    /// The user authenticates outside of the Client, for example by running a
+    /// command in their terminal.
+    External,
 }
 
 // New session
@@ -342,11 +645,28 @@ pub struct NewSessionRequest {
     pub cwd: PathBuf,
     /// List of MCP (Model Context Protocol) servers the agent should connect to.
     pub mcp_servers: Vec<McpServer>,
+    /// Token the Agent can include in `progress` session updates while it sets up
+    /// the session.
+    ///
+    /// See protocol docs: [Progress](https://agentclientprotocol.com/protocol/session-setup#progress)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_token: Option<ProgressToken>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
+impl NewSessionRequest {
+    /// Checks that every requested MCP server uses a transport allowed by the
+    /// agent's advertised `capabilities`.
+    ///
+    /// Returns [`Error::invalid_params`] naming the first server with an
+    /// unsupported transport.
+    pub fn validate_mcp_servers(&self, capabilities: &McpCapabilities) -> Result<(), Error> {
+        validate_mcp_servers(&self.mcp_servers, capabilities)
+    }
+}
+
 /// Response from creating a new session.
 ///
 /// See protocol docs: [Creating a Session](https://agentclientprotocol.com/protocol/session-setup#creating-a-session)
@@ -363,14 +683,21 @@ pub struct NewSessionResponse {
     /// See protocol docs: [Session Modes](https://agentclientprotocol.com/protocol/session-modes)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub modes: Option<SessionModeState>,
-    /// **UNSTABLE**
-    ///
-    /// This capability is not part of the spec yet, and may be removed or changed at any point.
-    ///
     /// Initial model state if supported by the Agent
-    #[cfg(feature = "unstable")]
+    ///
+    /// See protocol docs: [Session Models](https://agentclientprotocol.com/protocol/session-models)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub models: Option<SessionModelState>,
+    /// The permission options the Agent will offer in `session/request_permission`
+    /// requests, unless a request says otherwise.
+    ///
+    /// Lets the Client prepare its permission UI, and apply saved decisions,
+    /// before the first tool call. Agents that vary the options per request
+    /// should omit this.
+    ///
+    /// See protocol docs: [Default Permission Options](https://agentclientprotocol.com/protocol/session-setup#default-permission-options)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_permission_options: Option<Vec<PermissionOption>>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -398,6 +725,17 @@ pub struct LoadSessionRequest {
     pub meta: Option<serde_json::Value>,
 }
 
+impl LoadSessionRequest {
+    /// Checks that every requested MCP server uses a transport allowed by the
+    /// agent's advertised `capabilities`.
+    ///
+    /// Returns [`Error::invalid_params`] naming the first server with an
+    /// unsupported transport.
+    pub fn validate_mcp_servers(&self, capabilities: &McpCapabilities) -> Result<(), Error> {
+        validate_mcp_servers(&self.mcp_servers, capabilities)
+    }
+}
+
 /// Response from loading an existing session.
 #[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[schemars(extend("x-side" = "agent", "x-method" = SESSION_LOAD_METHOD_NAME))]
@@ -408,12 +746,9 @@ pub struct LoadSessionResponse {
     /// See protocol docs: [Session Modes](https://agentclientprotocol.com/protocol/session-modes)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub modes: Option<SessionModeState>,
-    /// **UNSTABLE**
-    ///
-    /// This capability is not part of the spec yet, and may be removed or changed at any point.
-    ///
     /// Initial model state if supported by the Agent
-    #[cfg(feature = "unstable")]
+    ///
+    /// See protocol docs: [Session Models](https://agentclientprotocol.com/protocol/session-models)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub models: Option<SessionModelState>,
     /// Extension point for implementations
@@ -424,6 +759,11 @@ pub struct LoadSessionResponse {
 // Session modes
 
 /// The set of modes and the one currently active.
+///
+/// Returned when creating or loading a session so that clients can let users
+/// switch between the modes the agent supports.
+///
+/// See protocol docs: [Session Modes](https://agentclientprotocol.com/protocol/session-modes)
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionModeState {
@@ -442,8 +782,11 @@ pub struct SessionModeState {
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionMode {
+    /// Unique identifier for this mode, used with `session/set_mode`.
     pub id: SessionModeId,
+    /// Human-readable name of the mode (e.g., "Ask", "Code").
     pub name: String,
+    /// Optional description of how the agent behaves in this mode.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     /// Extension point for implementations
@@ -481,6 +824,71 @@ pub struct SetSessionModeRequest {
 #[schemars(extend("x-side" = "agent", "x-method" = SESSION_SET_MODE_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
 pub struct SetSessionModeResponse {
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Commands
+
+/// Request parameters for listing the commands available in a session.
+///
+/// See protocol docs: [Slash Commands](https://agentclientprotocol.com/protocol/slash-commands)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "agent", "x-method" = SESSION_LIST_COMMANDS_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ListCommandsRequest {
+    /// The ID of the session to list commands for.
+    pub session_id: SessionId,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response to `session/list_commands` method.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "agent", "x-method" = SESSION_LIST_COMMANDS_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ListCommandsResponse {
+    /// The commands currently available in the session.
+    pub commands: Vec<AvailableCommand>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Request parameters for running a slash command in a session.
+///
+/// The Agent responds with a `PromptResponse` once the turn started by the
+/// command is complete.
+///
+/// See protocol docs: [Slash Commands](https://agentclientprotocol.com/protocol/slash-commands)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "agent", "x-method" = SESSION_INVOKE_COMMAND_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct InvokeCommandRequest {
+    /// The ID of the session to run the command in.
+    pub session_id: SessionId,
+    /// The name of the command, without the leading `/`.
+    pub name: String,
+    /// The input for the command, as described by its `input`.
+    ///
+    /// Empty for commands that take no input.
+    #[serde(default)]
+    pub arguments: String,
+    /// Token the Agent can include in `progress` session updates while it
+    /// runs this command.
+    ///
+    /// See protocol docs: [Progress](https://agentclientprotocol.com/protocol/session-setup#progress)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_token: Option<ProgressToken>,
+    /// Identifies this turn in the session updates the Agent sends for it.
+    ///
+    /// See protocol docs: [Turn IDs](https://agentclientprotocol.com/protocol/prompt-turn#turn-ids)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_id: Option<TurnId>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
@@ -506,6 +914,9 @@ pub enum McpServer {
         url: String,
         /// HTTP headers to set when making requests to the MCP server.
         headers: Vec<HttpHeader>,
+        /// Extension point for implementations
+        #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+        meta: Option<Meta>,
     },
     /// SSE transport configuration
     ///
@@ -518,6 +929,9 @@ pub enum McpServer {
         url: String,
         /// HTTP headers to set when making requests to the MCP server.
         headers: Vec<HttpHeader>,
+        /// Extension point for implementations
+        #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+        meta: Option<Meta>,
     },
     /// Stdio transport configuration
     ///
@@ -532,9 +946,79 @@ pub enum McpServer {
         args: Vec<String>,
         /// Environment variables to set when launching the MCP server.
         env: Vec<EnvVariable>,
+        /// How `env` combines with the environment the Agent runs in.
+        #[serde(default, skip_serializing_if = "EnvPolicy::is_clear")]
+        env_policy: EnvPolicy,
+        /// Extension point for implementations
+        #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+        meta: Option<Meta>,
     },
 }
 
+/// How the environment of a stdio MCP server is built.
+///
+/// See protocol docs: [Stdio Transport](https://agentclientprotocol.com/protocol/session-setup#stdio-transport)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvPolicy {
+    /// Only the listed variables are set.
+    #[default]
+    Clear,
+    /// The Agent's own environment is passed on, and listed variables are only
+    /// added if they aren't already set.
+    Inherit,
+    /// The Agent's own environment is passed on, and listed variables replace
+    /// inherited ones with the same name.
+    InheritAndOverride,
+}
+
+impl EnvPolicy {
+    fn is_clear(&self) -> bool {
+        matches!(self, EnvPolicy::Clear)
+    }
+
+    /// Builds the environment to launch a server with from the listed variables
+    /// and the inherited environment, typically [`std::env::vars`].
+    ///
+    /// The result is sorted by name.
+    pub fn apply(
+        self,
+        env: &[EnvVariable],
+        inherited: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<(String, String)> {
+        let mut vars = std::collections::BTreeMap::new();
+        if self != EnvPolicy::Clear {
+            vars.extend(inherited);
+        }
+        for variable in env {
+            if self == EnvPolicy::Inherit {
+                vars.entry(variable.name.clone())
+                    .or_insert_with(|| variable.value.clone());
+            } else {
+                vars.insert(variable.name.clone(), variable.value.clone());
+            }
+        }
+        vars.into_iter().collect()
+    }
+}
+
+fn validate_mcp_servers(
+    servers: &[McpServer],
+    capabilities: &McpCapabilities,
+) -> Result<(), Error> {
+    for server in servers {
+        let (name, transport) = match server {
+            McpServer::Http { name, .. } if !capabilities.http => (name, "http"),
+            McpServer::Sse { name, .. } if !capabilities.sse => (name, "sse"),
+            _ => continue,
+        };
+        return Err(Error::invalid_params().with_data(format!(
+            "MCP server \"{name}\" uses the {transport} transport, which the agent does not support"
+        )));
+    }
+    Ok(())
+}
+
 /// An environment variable to set when launching an MCP server.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -588,11 +1072,70 @@ pub struct PromptRequest {
     /// as it avoids extra round-trips and allows the message to include
     /// pieces of context from sources the agent may not have access to.
     pub prompt: Vec<ContentBlock>,
+    /// Files the user attached to the message as context, such as open editors
+    /// or a selection.
+    ///
+    /// Unlike resources mentioned in [`Self::prompt`], these are not part of the
+    /// message itself. Only allowed when the Agent supports
+    /// [`PromptCapabilities::context`].
+    ///
+    /// See protocol docs: [Attached Context](https://agentclientprotocol.com/protocol/prompt-turn#attached-context)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<ContextItem>,
+    /// Token the Agent can include in `progress` session updates while it
+    /// processes this prompt.
+    ///
+    /// See protocol docs: [Progress](https://agentclientprotocol.com/protocol/session-setup#progress)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_token: Option<ProgressToken>,
+    /// Identifies this turn in the session updates the Agent sends for it.
+    ///
+    /// See protocol docs: [Turn IDs](https://agentclientprotocol.com/protocol/prompt-turn#turn-ids)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_id: Option<TurnId>,
+    /// Whether the Agent may call tools while processing this prompt.
+    ///
+    /// When `false`, the Client asks for an answer in text alone, without tool
+    /// calls or file edits, for this turn only. This is a hint: Agents that don't
+    /// support it may still call tools. Defaults to `true`.
+    ///
+    /// See protocol docs: [Disabling Tools](https://agentclientprotocol.com/protocol/prompt-turn#disabling-tools)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools_enabled: Option<bool>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
+/// A file, or part of a file, attached to a prompt as context.
+///
+/// See protocol docs: [Attached Context](https://agentclientprotocol.com/protocol/prompt-turn#attached-context)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextItem {
+    /// The URI of the file.
+    pub uri: ResourceUri,
+    /// The lines of the file that are relevant. The whole file if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_range: Option<LineRange>,
+    /// Why the file was attached, e.g. "open in editor" or "selection".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// A range of lines in a file, 1-based and inclusive at both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LineRange {
+    /// The first line of the range.
+    pub start: u32,
+    /// The last line of the range.
+    pub end: u32,
+}
+
 /// Response from processing a user prompt.
 ///
 /// See protocol docs: [Check for Completion](https://agentclientprotocol.com/protocol/prompt-turn#4-check-for-completion)
@@ -602,6 +1145,35 @@ pub struct PromptRequest {
 pub struct PromptResponse {
     /// Indicates why the agent stopped processing the turn.
     pub stop_reason: StopReason,
+    /// Token usage for the whole turn, if reported by the Agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    /// Why the agent refused to continue.
+    ///
+    /// Only set when the stop reason is [`StopReason::Refusal`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<RefusalInfo>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Token usage and cost of language model requests.
+///
+/// See protocol docs: [Usage](https://agentclientprotocol.com/protocol/prompt-turn#usage)
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    /// Number of tokens sent to the language model.
+    pub input_tokens: u64,
+    /// Number of tokens generated by the language model.
+    pub output_tokens: u64,
+    /// Number of input tokens served from the model provider's cache.
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    /// Total cost in US dollars, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_cost_usd: Option<f64>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -633,14 +1205,40 @@ pub enum StopReason {
     Cancelled,
 }
 
-// Model
-
-/// **UNSTABLE**
+/// Details about why the agent refused to continue a prompt turn.
 ///
-/// This capability is not part of the spec yet, and may be removed or changed at any point.
+/// See protocol docs: [Refusals](https://agentclientprotocol.com/protocol/prompt-turn#refusals)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RefusalInfo {
+    /// The broad reason for the refusal, which clients can use to pick an
+    /// appropriate explanation.
+    pub category: RefusalCategory,
+    /// A human-readable explanation of the refusal.
+    pub message: String,
+}
+
+/// The broad reason why the agent refused to continue.
 ///
+/// See protocol docs: [Refusals](https://agentclientprotocol.com/protocol/prompt-turn#refusals)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RefusalCategory {
+    /// The request was considered unsafe.
+    Safety,
+    /// The request goes against the usage policy of the agent or model provider.
+    Policy,
+    /// The agent isn't able to do what was requested.
+    Capability,
+    /// Any other reason.
+    Other,
+}
+
+// Model
+
 /// The set of models and the one currently active.
-#[cfg(feature = "unstable")]
+///
+/// See protocol docs: [Session Models](https://agentclientprotocol.com/protocol/session-models)
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionModelState {
@@ -653,29 +1251,18 @@ pub struct SessionModelState {
     pub meta: Option<serde_json::Value>,
 }
 
-/// **UNSTABLE**
-///
-/// This capability is not part of the spec yet, and may be removed or changed at any point.
-///
 /// A unique identifier for a model.
-#[cfg(feature = "unstable")]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
 #[serde(transparent)]
 pub struct ModelId(pub Arc<str>);
 
-#[cfg(feature = "unstable")]
 impl std::fmt::Display for ModelId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
-/// **UNSTABLE**
-///
-/// This capability is not part of the spec yet, and may be removed or changed at any point.
-///
 /// Information about a selectable model.
-#[cfg(feature = "unstable")]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ModelInfo {
@@ -691,12 +1278,7 @@ pub struct ModelInfo {
     pub meta: Option<serde_json::Value>,
 }
 
-/// **UNSTABLE**
-///
-/// This capability is not part of the spec yet, and may be removed or changed at any point.
-///
 /// Request parameters for setting a session model.
-#[cfg(feature = "unstable")]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[schemars(extend("x-side" = "agent", "x-method" = SESSION_SET_MODEL_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
@@ -710,12 +1292,7 @@ pub struct SetSessionModelRequest {
     pub meta: Option<serde_json::Value>,
 }
 
-/// **UNSTABLE**
-///
-/// This capability is not part of the spec yet, and may be removed or changed at any point.
-///
 /// Response to `session/set_model` method.
-#[cfg(feature = "unstable")]
 #[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[schemars(extend("x-side" = "agent", "x-method" = SESSION_SET_MODEL_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
@@ -745,6 +1322,21 @@ pub struct AgentCapabilities {
     /// MCP capabilities supported by the agent.
     #[serde(default)]
     pub mcp_capabilities: McpCapabilities,
+    /// Whether the agent supports `session/list_commands`.
+    #[serde(default)]
+    pub list_commands: bool,
+    /// Whether the agent supports `session/invoke_command`.
+    #[serde(default)]
+    pub invoke_command: bool,
+    /// Whether the agent supports `session/cancel_tool_call`.
+    #[serde(default)]
+    pub cancel_tool_call: bool,
+    /// Encodings the agent can decompress messages with.
+    ///
+    /// Only applies to transports that frame messages with a `Content-Length`
+    /// header. See [`ContentEncoding`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<ContentEncoding>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -777,6 +1369,23 @@ pub struct PromptCapabilities {
     /// in prompt requests for pieces of context that are referenced in the message.
     #[serde(default)]
     pub embedded_context: bool,
+    /// Agent supports structured attached context in `session/prompt` requests.
+    ///
+    /// When enabled, the Client is allowed to set [`PromptRequest::context`].
+    #[serde(default)]
+    pub context: bool,
+    /// The largest prompt, in tokens, the agent can handle.
+    ///
+    /// Advisory only: Clients can use it to warn the user or trim context before
+    /// sending a prompt, but the protocol doesn't enforce it. How tokens are counted
+    /// depends on the model. Omitted when there is no known limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// The largest prompt, in bytes of serialized content, the agent can handle.
+    ///
+    /// Advisory only, like `maxTokens`. Omitted when there is no known limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -819,8 +1428,15 @@ pub struct AgentMethodNames {
     /// Notification for cancelling operations.
     pub session_cancel: &'static str,
     /// Method for selecting a model for a given session.
-    #[cfg(feature = "unstable")]
     pub session_set_model: &'static str,
+    /// Method for listing the commands available in a session.
+    pub session_list_commands: &'static str,
+    /// Method for running a slash command in a session.
+    pub session_invoke_command: &'static str,
+    /// Notification for cancelling a single tool call.
+    pub session_cancel_tool_call: &'static str,
+    /// Notification that a terminal's command has exited.
+    pub terminal_exited: &'static str,
 }
 
 /// Constant containing all agent method names.
@@ -832,8 +1448,11 @@ pub const AGENT_METHOD_NAMES: AgentMethodNames = AgentMethodNames {
     session_set_mode: SESSION_SET_MODE_METHOD_NAME,
     session_prompt: SESSION_PROMPT_METHOD_NAME,
     session_cancel: SESSION_CANCEL_METHOD_NAME,
-    #[cfg(feature = "unstable")]
     session_set_model: SESSION_SET_MODEL_METHOD_NAME,
+    session_list_commands: SESSION_LIST_COMMANDS_METHOD_NAME,
+    session_invoke_command: SESSION_INVOKE_COMMAND_METHOD_NAME,
+    session_cancel_tool_call: SESSION_CANCEL_TOOL_CALL_METHOD_NAME,
+    terminal_exited: TERMINAL_EXITED_NOTIFICATION,
 };
 
 /// Method name for the initialize request.
@@ -851,8 +1470,15 @@ pub(crate) const SESSION_PROMPT_METHOD_NAME: &str = "session/prompt";
 /// Method name for the cancel notification.
 pub(crate) const SESSION_CANCEL_METHOD_NAME: &str = "session/cancel";
 /// Method name for selecting a model for a given session.
-#[cfg(feature = "unstable")]
 pub(crate) const SESSION_SET_MODEL_METHOD_NAME: &str = "session/set_model";
+/// Method name for listing the commands available in a session.
+pub(crate) const SESSION_LIST_COMMANDS_METHOD_NAME: &str = "session/list_commands";
+/// Method name for running a slash command in a session.
+pub(crate) const SESSION_INVOKE_COMMAND_METHOD_NAME: &str = "session/invoke_command";
+/// Method name for the cancel tool call notification.
+pub(crate) const SESSION_CANCEL_TOOL_CALL_METHOD_NAME: &str = "session/cancel_tool_call";
+/// Notification name for a terminal's command exiting.
+pub(crate) const TERMINAL_EXITED_NOTIFICATION: &str = "terminal/exited";
 
 /// All possible requests that a client can send to an agent.
 ///
@@ -870,8 +1496,9 @@ pub enum ClientRequest {
     LoadSessionRequest(LoadSessionRequest),
     SetSessionModeRequest(SetSessionModeRequest),
     PromptRequest(PromptRequest),
-    #[cfg(feature = "unstable")]
     SetSessionModelRequest(SetSessionModelRequest),
+    ListCommandsRequest(ListCommandsRequest),
+    InvokeCommandRequest(InvokeCommandRequest),
     ExtMethodRequest(ExtRequest),
 }
 
@@ -891,8 +1518,8 @@ pub enum AgentResponse {
     LoadSessionResponse(#[serde(default)] LoadSessionResponse),
     SetSessionModeResponse(#[serde(default)] SetSessionModeResponse),
     PromptResponse(PromptResponse),
-    #[cfg(feature = "unstable")]
     SetSessionModelResponse(SetSessionModelResponse),
+    ListCommandsResponse(ListCommandsResponse),
     ExtMethodResponse(#[schemars(with = "serde_json::Value")] Arc<RawValue>),
 }
 
@@ -907,6 +1534,8 @@ pub enum AgentResponse {
 #[schemars(extend("x-docs-ignore" = true))]
 pub enum ClientNotification {
     CancelNotification(CancelNotification),
+    CancelToolCallNotification(CancelToolCallNotification),
+    TerminalExitedNotification(TerminalExitedNotification),
     ExtNotification(ExtNotification),
 }
 
@@ -924,11 +1553,398 @@ pub struct CancelNotification {
     pub meta: Option<serde_json::Value>,
 }
 
+/// Notification to cancel a single tool call, without ending the prompt turn.
+///
+/// Only available if the Agent supports the `cancelToolCall` capability.
+///
+/// See protocol docs: [Cancelling Tool Calls](https://agentclientprotocol.com/protocol/tool-calls#cancelling-tool-calls)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "agent", "x-method" = SESSION_CANCEL_TOOL_CALL_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct CancelToolCallNotification {
+    /// The ID of the session the tool call belongs to.
+    pub session_id: SessionId,
+    /// The ID of the tool call to cancel.
+    pub tool_call_id: ToolCallId,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Notification that a terminal's command has exited.
+///
+/// Only sent for terminals created with `notifyOnExit`.
+///
+/// See protocol docs: [Exit Notifications](https://agentclientprotocol.com/protocol/terminals#exit-notifications)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "agent", "x-method" = TERMINAL_EXITED_NOTIFICATION))]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalExitedNotification {
+    /// The session ID the terminal belongs to.
+    pub session_id: SessionId,
+    /// The ID of the terminal whose command exited.
+    pub terminal_id: TerminalId,
+    /// How the command exited.
+    pub exit_status: TerminalExitStatus,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
 #[cfg(test)]
 mod test_serialization {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_auth_method_kind_serialization() {
+        let method: AuthMethod = serde_json::from_value(json!({
+            "id": "api-key",
+            "name": "API key",
+            "description": null
+        }))
+        .unwrap();
+        assert_eq!(method.kind, None);
+        assert!(serde_json::to_value(&method).unwrap().get("kind").is_none());
+
+        let method = AuthMethod {
+            id: AuthMethodId("github".into()),
+            name: "Sign in with GitHub".to_string(),
+            description: None,
+            kind: Some(AuthMethodKind::OAuth {
+                authorize_url_hint: Some("https://github.com/login/oauth/authorize".to_string()),
+            }),
+            meta: Some(Meta::from(
+                json!({ "example.com/scopes": ["repo"] })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            )),
+        };
+        let json = serde_json::to_value(&method).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "id": "github",
+                "name": "Sign in with GitHub",
+                "description": null,
+                "kind": {
+                    "type": "oauth",
+                    "authorizeUrlHint": "https://github.com/login/oauth/authorize"
+                },
+                "_meta": { "example.com/scopes": ["repo"] }
+            })
+        );
+        let method: AuthMethod = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            method.kind,
+            Some(AuthMethodKind::OAuth {
+                authorize_url_hint: Some("https://github.com/login/oauth/authorize".to_string()),
+            })
+        );
+
+        for (kind, json) in [
+            (AuthMethodKind::ApiKey, json!({ "type": "api_key" })),
+            (AuthMethodKind::DeviceCode, json!({ "type": "device_code" })),
+            (AuthMethodKind::External, json!({ "type": "external" })),
+            (
+                AuthMethodKind::OAuth {
+                    authorize_url_hint: None,
+                },
+                json!({ "type": "oauth" }),
+            ),
+        ] {
+            assert_eq!(serde_json::to_value(&kind).unwrap(), json);
+            assert_eq!(
+                serde_json::from_value::<AuthMethodKind>(json).unwrap(),
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_prompt_response_usage_serialization() {
+        let response: PromptResponse =
+            serde_json::from_value(json!({"stopReason": "end_turn"})).unwrap();
+        assert_eq!(response.usage, None);
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({"stopReason": "end_turn"})
+        );
+
+        let response = PromptResponse {
+            stop_reason: StopReason::EndTurn,
+            usage: Some(TokenUsage {
+                input_tokens: 1200,
+                output_tokens: 340,
+                cache_read_tokens: 800,
+                total_cost_usd: Some(0.0125),
+                meta: None,
+            }),
+            refusal: None,
+            meta: None,
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "stopReason": "end_turn",
+                "usage": {
+                    "inputTokens": 1200,
+                    "outputTokens": 340,
+                    "cacheReadTokens": 800,
+                    "totalCostUsd": 0.0125
+                }
+            })
+        );
+        let deserialized: PromptResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.usage, response.usage);
+
+        let usage: TokenUsage =
+            serde_json::from_value(json!({"inputTokens": 10, "outputTokens": 5})).unwrap();
+        assert_eq!(usage.cache_read_tokens, 0);
+        assert_eq!(usage.total_cost_usd, None);
+    }
+
+    #[test]
+    fn test_prompt_response_refusal_serialization() {
+        let json = json!({
+            "stopReason": "refusal",
+            "refusal": {
+                "category": "policy",
+                "message": "I can't help with that request."
+            }
+        });
+        let response: PromptResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(response.stop_reason, StopReason::Refusal);
+        assert_eq!(
+            response.refusal,
+            Some(RefusalInfo {
+                category: RefusalCategory::Policy,
+                message: "I can't help with that request.".into(),
+            })
+        );
+        assert_eq!(serde_json::to_value(&response).unwrap(), json);
+    }
+
+    #[test]
+    fn test_session_mode_state_serialization() {
+        let json = json!({
+            "sessionId": "sess_abc123",
+            "modes": {
+                "currentModeId": "ask",
+                "availableModes": [
+                    {
+                        "id": "ask",
+                        "name": "Ask",
+                        "description": "Request permission before making any changes"
+                    },
+                    {
+                        "id": "code",
+                        "name": "Code"
+                    }
+                ]
+            }
+        });
+
+        let response: NewSessionResponse = serde_json::from_value(json.clone()).unwrap();
+        let modes = response.modes.as_ref().unwrap();
+        assert_eq!(modes.current_mode_id, SessionModeId("ask".into()));
+        assert_eq!(modes.available_modes.len(), 2);
+        assert_eq!(modes.available_modes[1].description, None);
+        assert_eq!(serde_json::to_value(&response).unwrap(), json);
+    }
+
+    #[test]
+    fn test_default_permission_options_serialization() {
+        let json = json!({
+            "sessionId": "sess_abc123",
+            "defaultPermissionOptions": [
+                {"optionId": "allow", "name": "Allow", "kind": "allow_once"},
+                {"optionId": "reject", "name": "Reject", "kind": "reject_once"}
+            ]
+        });
+
+        let response: NewSessionResponse = serde_json::from_value(json.clone()).unwrap();
+        let options = response.default_permission_options.as_ref().unwrap();
+        assert_eq!(options.len(), 2);
+        assert_eq!(options[1].kind, crate::PermissionOptionKind::RejectOnce);
+        assert_eq!(serde_json::to_value(&response).unwrap(), json);
+
+        let response: NewSessionResponse =
+            serde_json::from_value(json!({"sessionId": "sess_abc123"})).unwrap();
+        assert!(response.default_permission_options.is_none());
+    }
+
+    #[test]
+    fn test_set_session_mode_serialization() {
+        let request = SetSessionModeRequest {
+            session_id: SessionId("sess_abc123".into()),
+            mode_id: SessionModeId("code".into()),
+            meta: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"sessionId": "sess_abc123", "modeId": "code"})
+        );
+        assert_eq!(
+            serde_json::to_value(SetSessionModeResponse::default()).unwrap(),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_progress_token_serialization() {
+        let request = NewSessionRequest {
+            cwd: PathBuf::from("/home/user/project"),
+            mcp_servers: vec![],
+            progress_token: None,
+            meta: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"cwd": "/home/user/project", "mcpServers": []})
+        );
+
+        let request = PromptRequest {
+            session_id: SessionId("sess_abc123".into()),
+            prompt: vec!["Hello".into()],
+            context: Vec::new(),
+            progress_token: Some(ProgressToken("prompt-1".into())),
+            turn_id: None,
+            tools_enabled: None,
+            meta: None,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["progressToken"], json!("prompt-1"));
+        let deserialized: PromptRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.progress_token, request.progress_token);
+    }
+
+    #[test]
+    fn test_prompt_context_serialization() {
+        let request = PromptRequest {
+            session_id: SessionId("sess_abc123".into()),
+            prompt: vec!["Why does this panic?".into()],
+            context: vec![ContextItem {
+                uri: "file:///home/user/project/src/main.rs".into(),
+                line_range: Some(LineRange { start: 10, end: 24 }),
+                reason: Some("selection".into()),
+                meta: None,
+            }],
+            progress_token: None,
+            turn_id: None,
+            tools_enabled: None,
+            meta: None,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["context"],
+            json!([{
+                "uri": "file:///home/user/project/src/main.rs",
+                "lineRange": {"start": 10, "end": 24},
+                "reason": "selection"
+            }])
+        );
+        let deserialized: PromptRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.context, request.context);
+
+        let deserialized: PromptRequest =
+            serde_json::from_value(json!({"sessionId": "sess_abc123", "prompt": []})).unwrap();
+        assert!(deserialized.context.is_empty());
+        assert!(
+            serde_json::to_value(&deserialized)
+                .unwrap()
+                .get("context")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_prompt_tools_enabled_serialization() {
+        let request: PromptRequest =
+            serde_json::from_value(json!({"sessionId": "sess_abc123", "prompt": []})).unwrap();
+        assert_eq!(request.tools_enabled, None);
+
+        let request = PromptRequest {
+            tools_enabled: Some(false),
+            ..request
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json,
+            json!({"sessionId": "sess_abc123", "prompt": [], "toolsEnabled": false})
+        );
+        let deserialized: PromptRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.tools_enabled, Some(false));
+    }
+
+    #[test]
+    fn test_list_commands_serialization() {
+        let request: ListCommandsRequest =
+            serde_json::from_value(json!({"sessionId": "sess_abc123"})).unwrap();
+        assert_eq!(request.session_id, SessionId("sess_abc123".into()));
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"sessionId": "sess_abc123"})
+        );
+
+        let json = json!({
+            "commands": [
+                {
+                    "name": "web",
+                    "description": "Search the web for information",
+                    "input": {"hint": "query to search for"}
+                },
+                {
+                    "name": "test",
+                    "description": "Run tests for the current project",
+                    "input": null
+                }
+            ]
+        });
+        let response: ListCommandsResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(response.commands.len(), 2);
+        assert_eq!(response.commands[1].name, "test");
+        assert!(response.commands[1].input.is_none());
+        assert_eq!(serde_json::to_value(&response).unwrap(), json);
+    }
+
+    #[test]
+    fn test_agent_capabilities_list_commands() {
+        let capabilities: AgentCapabilities = serde_json::from_value(json!({})).unwrap();
+        assert!(!capabilities.list_commands);
+
+        let capabilities: AgentCapabilities =
+            serde_json::from_value(json!({"listCommands": true})).unwrap();
+        assert!(capabilities.list_commands);
+        assert_eq!(
+            serde_json::to_value(&capabilities).unwrap()["listCommands"],
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn test_prompt_capabilities_limits() {
+        let capabilities: PromptCapabilities = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(capabilities.max_tokens, None);
+        assert_eq!(capabilities.max_bytes, None);
+        assert_eq!(
+            serde_json::to_value(&capabilities).unwrap(),
+            json!({
+                "image": false,
+                "audio": false,
+                "embeddedContext": false,
+                "context": false
+            })
+        );
+
+        let capabilities: PromptCapabilities =
+            serde_json::from_value(json!({"maxTokens": 200_000, "maxBytes": 4_194_304})).unwrap();
+        assert_eq!(capabilities.max_tokens, Some(200_000));
+        assert_eq!(capabilities.max_bytes, Some(4_194_304));
+    }
+
     #[test]
     fn test_mcp_server_stdio_serialization() {
         let server = McpServer::Stdio {
@@ -940,6 +1956,13 @@ mod test_serialization {
                 value: "secret123".to_string(),
                 meta: None,
             }],
+            env_policy: EnvPolicy::Clear,
+            meta: Some(Meta::from(
+                json!({ "example.com/sandbox": true })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            )),
         };
 
         let json = serde_json::to_value(&server).unwrap();
@@ -954,7 +1977,8 @@ mod test_serialization {
                         "name": "API_KEY",
                         "value": "secret123"
                     }
-                ]
+                ],
+                "_meta": { "example.com/sandbox": true }
             })
         );
 
@@ -965,6 +1989,8 @@ mod test_serialization {
                 command,
                 args,
                 env,
+                env_policy,
+                meta,
             } => {
                 assert_eq!(name, "test-server");
                 assert_eq!(command, PathBuf::from("/usr/bin/server"));
@@ -972,11 +1998,121 @@ mod test_serialization {
                 assert_eq!(env.len(), 1);
                 assert_eq!(env[0].name, "API_KEY");
                 assert_eq!(env[0].value, "secret123");
+                assert_eq!(env_policy, EnvPolicy::Clear);
+                assert_eq!(
+                    meta.unwrap()
+                        .get_ext::<bool>("example.com/sandbox")
+                        .unwrap(),
+                    Some(true)
+                );
             }
             _ => panic!("Expected Stdio variant"),
         }
     }
 
+    #[test]
+    fn test_mcp_server_stdio_env_policy() {
+        let server: McpServer = serde_json::from_value(json!({
+            "name": "test-server",
+            "command": "/usr/bin/server",
+            "args": [],
+            "env": [{ "name": "PATH", "value": "/opt/bin" }],
+            "envPolicy": "inherit_and_override"
+        }))
+        .unwrap();
+        let McpServer::Stdio {
+            env, env_policy, ..
+        } = &server
+        else {
+            panic!("Expected Stdio variant");
+        };
+        assert_eq!(*env_policy, EnvPolicy::InheritAndOverride);
+        assert_eq!(
+            serde_json::to_value(&server).unwrap()["envPolicy"],
+            json!("inherit_and_override")
+        );
+
+        let inherited = || {
+            [
+                ("HOME".to_string(), "/home/user".to_string()),
+                ("PATH".to_string(), "/usr/bin".to_string()),
+            ]
+        };
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            EnvPolicy::Clear.apply(env, inherited()),
+            vars(&[("PATH", "/opt/bin")])
+        );
+        assert_eq!(
+            EnvPolicy::Inherit.apply(env, inherited()),
+            vars(&[("HOME", "/home/user"), ("PATH", "/usr/bin")])
+        );
+        assert_eq!(
+            EnvPolicy::InheritAndOverride.apply(env, inherited()),
+            vars(&[("HOME", "/home/user"), ("PATH", "/opt/bin")])
+        );
+    }
+
+    #[test]
+    fn test_validate_mcp_servers() {
+        let request = NewSessionRequest {
+            cwd: PathBuf::from("/home/user/project"),
+            mcp_servers: vec![
+                McpServer::Stdio {
+                    name: "filesystem".to_string(),
+                    command: PathBuf::from("/usr/bin/server"),
+                    args: vec![],
+                    env: vec![],
+                    env_policy: EnvPolicy::Clear,
+                    meta: None,
+                },
+                McpServer::Http {
+                    name: "api".to_string(),
+                    url: "https://api.example.com".to_string(),
+                    headers: vec![],
+                    meta: None,
+                },
+            ],
+            progress_token: None,
+            meta: None,
+        };
+
+        let error = request
+            .validate_mcp_servers(&McpCapabilities::default())
+            .unwrap_err();
+        assert_eq!(error.code, crate::ErrorCode::INVALID_PARAMS.code);
+        assert_eq!(
+            error.data,
+            Some(json!(
+                "MCP server \"api\" uses the http transport, which the agent does not support"
+            ))
+        );
+
+        let capabilities = McpCapabilities {
+            http: true,
+            ..Default::default()
+        };
+        assert!(request.validate_mcp_servers(&capabilities).is_ok());
+
+        let request = LoadSessionRequest {
+            mcp_servers: vec![McpServer::Sse {
+                name: "events".to_string(),
+                url: "https://events.example.com".to_string(),
+                headers: vec![],
+                meta: None,
+            }],
+            cwd: PathBuf::from("/home/user/project"),
+            session_id: SessionId("sess_abc123".into()),
+            meta: None,
+        };
+        assert!(request.validate_mcp_servers(&capabilities).is_err());
+    }
+
     #[test]
     fn test_mcp_server_http_serialization() {
         let server = McpServer::Http {
@@ -994,6 +2130,7 @@ mod test_serialization {
                     meta: None,
                 },
             ],
+            meta: None,
         };
 
         let json = serde_json::to_value(&server).unwrap();
@@ -1018,7 +2155,9 @@ mod test_serialization {
 
         let deserialized: McpServer = serde_json::from_value(json).unwrap();
         match deserialized {
-            McpServer::Http { name, url, headers } => {
+            McpServer::Http {
+                name, url, headers, ..
+            } => {
                 assert_eq!(name, "http-server");
                 assert_eq!(url, "https://api.example.com");
                 assert_eq!(headers.len(), 2);
@@ -1041,6 +2180,7 @@ mod test_serialization {
                 value: "apikey456".to_string(),
                 meta: None,
             }],
+            meta: None,
         };
 
         let json = serde_json::to_value(&server).unwrap();
@@ -1061,7 +2201,9 @@ mod test_serialization {
 
         let deserialized: McpServer = serde_json::from_value(json).unwrap();
         match deserialized {
-            McpServer::Sse { name, url, headers } => {
+            McpServer::Sse {
+                name, url, headers, ..
+            } => {
                 assert_eq!(name, "sse-server");
                 assert_eq!(url, "https://sse.example.com/events");
                 assert_eq!(headers.len(), 1);
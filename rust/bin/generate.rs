@@ -792,6 +792,10 @@ mod markdown_generator {
                 "session/prompt" => self.agent_methods.get("prompt").unwrap(),
                 "session/cancel" => self.agent_methods.get("cancel").unwrap(),
                 "session/set_model" => self.agent_methods.get("set_session_model").unwrap(),
+                "session/list_commands" => self.agent_methods.get("list_commands").unwrap(),
+                "session/invoke_command" => self.agent_methods.get("invoke_command").unwrap(),
+                "session/cancel_tool_call" => self.agent_methods.get("cancel_tool_call").unwrap(),
+                "terminal/exited" => self.agent_methods.get("terminal_exited").unwrap(),
                 _ => panic!("Introduced a method? Add it here :)"),
             }
         }
@@ -801,8 +805,14 @@ mod markdown_generator {
                 "session/request_permission" => {
                     self.client_methods.get("request_permission").unwrap()
                 }
+                "session/request_user_input" => {
+                    self.client_methods.get("request_user_input").unwrap()
+                }
                 "fs/write_text_file" => self.client_methods.get("write_text_file").unwrap(),
                 "fs/read_text_file" => self.client_methods.get("read_text_file").unwrap(),
+                "fs/read_text_file_chunk" => {
+                    self.client_methods.get("read_text_file_chunk").unwrap()
+                }
                 "session/update" => self.client_methods.get("session_notification").unwrap(),
                 "terminal/create" => self.client_methods.get("create_terminal").unwrap(),
                 "terminal/output" => self.client_methods.get("terminal_output").unwrap(),
@@ -811,6 +821,14 @@ mod markdown_generator {
                     self.client_methods.get("wait_for_terminal_exit").unwrap()
                 }
                 "terminal/kill" => self.client_methods.get("kill_terminal_command").unwrap(),
+                "fs/list_directory" => self.client_methods.get("list_directory").unwrap(),
+                "fs/delete_file" => self.client_methods.get("delete_file").unwrap(),
+                "fs/rename_file" => self.client_methods.get("rename_file").unwrap(),
+                "fs/stat" => self.client_methods.get("stat").unwrap(),
+                "fs/search" => self.client_methods.get("search_files").unwrap(),
+                "fs/grep" => self.client_methods.get("grep").unwrap(),
+                "terminal/input" => self.client_methods.get("write_terminal_input").unwrap(),
+                "terminal/resize" => self.client_methods.get("resize_terminal").unwrap(),
                 _ => panic!("Introduced a method? Add it here :)"),
             }
         }
@@ -853,6 +871,11 @@ mod markdown_generator {
 
         if let Some(index) = doc["index"].as_object() {
             for (_, item) in index {
+                // Other items, such as enum variants, may share the trait names.
+                if !item["inner"]["trait"].is_object() {
+                    continue;
+                }
+
                 if item["name"].as_str() == Some("Agent") {
                     if let Some(docs) = item["docs"].as_str() {
                         side_docs.agent_trait = docs.to_string();
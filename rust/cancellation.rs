@@ -0,0 +1,231 @@
+//! Cancellation of in-flight prompt turns.
+//!
+//! When a client sends `session/cancel`, the agent is expected to stop the ongoing
+//! prompt turn for that session and respond to the original `session/prompt`
+//! request with [`StopReason::Cancelled`](crate::StopReason::Cancelled).
+//! [`CancellationRegistry`] connects the two: the prompt handler registers the turn
+//! and waits on the returned [`CancellationToken`], which is tripped by session ID.
+//!
+//! [`AgentSideConnection`](crate::AgentSideConnection) trips the tokens of its
+//! registry itself when `session/cancel` arrives, before the agent's `cancel`
+//! handler runs. Agents get tokens from it with
+//! [`AgentSideConnection::cancellation_token`](crate::AgentSideConnection::cancellation_token),
+//! or share a registry of their own with it through
+//! [`AgentSideConnection::with_cancellation_registry`](crate::AgentSideConnection::with_cancellation_registry).
+//!
+//! See protocol docs: [Cancellation](https://agentclientprotocol.com/protocol/prompt-turn#cancellation)
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::{
+    FutureExt as _,
+    channel::oneshot,
+    future::{self, Either, Shared},
+};
+use parking_lot::Mutex;
+
+use crate::SessionId;
+
+/// Tracks the cancellation tokens of the prompt turns currently running, by session.
+///
+/// Cloning the registry is cheap, and all clones share the same tokens, so it can be
+/// held by the agent and moved into spawned tasks alike.
+///
+/// # Example
+///
+/// ```no_run
+/// use agent_client_protocol::{
+///     AgentSideConnection, Error, PromptRequest, PromptResponse, StopReason,
+/// };
+///
+/// # async fn run_turn(_request: PromptRequest) -> Result<PromptResponse, Error> { todo!() }
+/// async fn prompt(
+///     conn: &AgentSideConnection,
+///     request: PromptRequest,
+/// ) -> Result<PromptResponse, Error> {
+///     // Tripped by the connection when the client sends `session/cancel`.
+///     let token = conn.cancellation_token(request.session_id.clone());
+///     match token.run_until_cancelled(run_turn(request)).await {
+///         Some(response) => response,
+///         None => Ok(PromptResponse {
+///             stop_reason: StopReason::Cancelled,
+///             usage: None,
+///             refusal: None,
+///             meta: None,
+///         }),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationRegistry {
+    state: Arc<Mutex<RegistryState>>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryState {
+    next_id: u64,
+    turns: HashMap<SessionId, RegisteredTurn>,
+}
+
+#[derive(Debug)]
+struct RegisteredTurn {
+    id: u64,
+    cancel: oneshot::Sender<()>,
+}
+
+impl CancellationRegistry {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new prompt turn for the given session and returns its token.
+    ///
+    /// The turn stays registered until the token is dropped, so the token should be
+    /// held for as long as the turn runs. Registering a turn for a session that
+    /// already has one replaces it; the previous token can then no longer be
+    /// cancelled through the registry.
+    pub fn register(&self, session_id: SessionId) -> CancellationToken {
+        let (cancel, cancelled) = oneshot::channel();
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state
+            .turns
+            .insert(session_id.clone(), RegisteredTurn { id, cancel });
+        CancellationToken {
+            registry: self.state.clone(),
+            session_id,
+            id,
+            cancelled: cancelled.shared(),
+        }
+    }
+
+    /// Cancels the prompt turn running for the given session.
+    ///
+    /// Returns `false` if no turn is registered for the session, for example because
+    /// it already completed.
+    pub fn cancel(&self, session_id: &SessionId) -> bool {
+        let turn = self.state.lock().turns.remove(session_id);
+        turn.is_some_and(|turn| turn.cancel.send(()).is_ok())
+    }
+
+    /// Returns `true` if a prompt turn is registered for the given session.
+    #[must_use]
+    pub fn is_running(&self, session_id: &SessionId) -> bool {
+        self.state.lock().turns.contains_key(session_id)
+    }
+}
+
+/// A handle to a single prompt turn registered with a [`CancellationRegistry`].
+///
+/// Dropping the token unregisters the turn.
+#[derive(Debug)]
+pub struct CancellationToken {
+    registry: Arc<Mutex<RegistryState>>,
+    session_id: SessionId,
+    id: u64,
+    cancelled: Shared<oneshot::Receiver<()>>,
+}
+
+impl CancellationToken {
+    /// The session this turn belongs to.
+    #[must_use]
+    pub fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+
+    /// Returns `true` if the turn has been cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.cancelled.clone().now_or_never(), Some(Ok(())))
+    }
+
+    /// Resolves once the turn is cancelled.
+    ///
+    /// If the turn is never cancelled, the future never resolves.
+    pub async fn cancelled(&self) {
+        if self.cancelled.clone().await.is_err() {
+            // The turn was replaced or the registry dropped, so it can no longer be cancelled.
+            future::pending::<()>().await;
+        }
+    }
+
+    /// Runs `future` until it completes or the turn is cancelled, whichever happens
+    /// first.
+    ///
+    /// Returns `None` if the turn was cancelled, in which case `future` is dropped.
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        let future = std::pin::pin!(future);
+        let cancelled = std::pin::pin!(self.cancelled());
+        match future::select(future, cancelled).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(((), _)) => None,
+        }
+    }
+}
+
+impl Drop for CancellationToken {
+    fn drop(&mut self) {
+        let mut state = self.registry.lock();
+        if state
+            .turns
+            .get(&self.session_id)
+            .is_some_and(|turn| turn.id == self.id)
+        {
+            state.turns.remove(&self.session_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str) -> SessionId {
+        SessionId(id.into())
+    }
+
+    #[test]
+    fn test_cancel_trips_token() {
+        let registry = CancellationRegistry::new();
+        let token = registry.register(session("s1"));
+        assert!(!token.is_cancelled());
+        assert!(registry.is_running(&session("s1")));
+
+        assert!(!registry.cancel(&session("s2")));
+        assert!(registry.cancel(&session("s1")));
+        assert!(token.is_cancelled());
+        assert!(!registry.is_running(&session("s1")));
+        assert!(!registry.cancel(&session("s1")));
+
+        let output =
+            futures::executor::block_on(token.run_until_cancelled(future::pending::<()>()));
+        assert_eq!(output, None);
+    }
+
+    #[test]
+    fn test_completed_turn_is_unregistered() {
+        let registry = CancellationRegistry::new();
+        let token = registry.register(session("s1"));
+        let output = futures::executor::block_on(token.run_until_cancelled(async { 42 }));
+        assert_eq!(output, Some(42));
+
+        drop(token);
+        assert!(!registry.is_running(&session("s1")));
+        assert!(!registry.cancel(&session("s1")));
+    }
+
+    #[test]
+    fn test_replaced_turn_keeps_new_registration() {
+        let registry = CancellationRegistry::new();
+        let first = registry.register(session("s1"));
+        let second = registry.register(session("s1"));
+
+        drop(first);
+        assert!(registry.is_running(&session("s1")));
+        assert!(registry.cancel(&session("s1")));
+        assert!(second.is_cancelled());
+    }
+}
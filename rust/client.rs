@@ -2,6 +2,10 @@
 //!
 //! This module defines the Client trait and all associated types for implementing
 //! a client that interacts with AI coding agents via the Agent Client Protocol (ACP).
+//!
+//! Only the [`Client`] methods every client must support are required. The others
+//! fail with [`Error::method_not_found`] by default, so implement the ones backing
+//! the capabilities you advertise.
 
 use std::rc::Rc;
 use std::{fmt, path::PathBuf, sync::Arc};
@@ -12,8 +16,11 @@ use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 
 use crate::ext::ExtRequest;
-use crate::{ContentBlock, Error, ExtNotification, Plan, SessionId, ToolCall, ToolCallUpdate};
-use crate::{ExtResponse, SessionModeId};
+use crate::{
+    ContentBlock, Diff, Error, ExtNotification, LineRange, Plan, PlanEntryUpdate, SessionId,
+    ToolCall, ToolCallId, ToolCallLocation, ToolCallUpdate,
+};
+use crate::{ExtResponse, Meta, ModelId, ProgressToken, SessionModeId, TokenUsage, TurnId};
 
 /// Defines the interface that ACP-compliant clients must implement.
 ///
@@ -50,11 +57,32 @@ pub trait Client {
     /// See protocol docs: [Agent Reports Output](https://agentclientprotocol.com/protocol/prompt-turn#3-agent-reports-output)
     async fn session_notification(&self, args: SessionNotification) -> Result<(), Error>;
 
+    /// Asks the user for input in the middle of a prompt turn.
+    ///
+    /// Only available if the client advertises the `requestUserInput` capability.
+    /// Called by the agent when it needs information only the user can provide,
+    /// such as which branch to target. The client should present the prompt to
+    /// the user and return their answer.
+    ///
+    /// If the client cancels the prompt turn via `session/cancel`, it MUST
+    /// respond to this request with a `null` value.
+    ///
+    /// See protocol docs: [Requesting User Input](https://agentclientprotocol.com/protocol/prompt-turn#requesting-user-input)
+    async fn request_user_input(
+        &self,
+        _args: RequestUserInputRequest,
+    ) -> Result<RequestUserInputResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
     /// Writes content to a text file in the client's file system.
     ///
     /// Only available if the client advertises the `fs.writeTextFile` capability.
     /// Allows the agent to create or modify files within the client's environment.
     ///
+    /// When `dry_run` is set, the file must be left untouched and the response
+    /// must carry the [`Diff`] the write would produce.
+    ///
     /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
     async fn write_text_file(
         &self,
@@ -76,6 +104,92 @@ pub trait Client {
         Err(Error::method_not_found())
     }
 
+    /// Reads a single chunk of a text file in the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.readTextFileChunk` capability.
+    /// Allows the agent to scan large files with bounded memory, one chunk at a time.
+    /// [`AgentSideConnection::read_text_file_stream`](crate::AgentSideConnection::read_text_file_stream)
+    /// reads a whole file this way.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn read_text_file_chunk(
+        &self,
+        _args: ReadTextFileChunkRequest,
+    ) -> Result<ReadTextFileChunkResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Lists the entries of a directory in the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.listDirectory` capability.
+    /// Allows the agent to explore the client's environment without running a terminal command.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn list_directory(
+        &self,
+        _args: ListDirectoryRequest,
+    ) -> Result<ListDirectoryResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Deletes a file from the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.deleteFile` capability.
+    /// If the file doesn't exist, the client should respond with a "Resource not found"
+    /// (`-32002`) error rather than a generic internal error.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn delete_file(&self, _args: DeleteFileRequest) -> Result<DeleteFileResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Renames or moves a file in the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.renameFile` capability.
+    /// The rename should be atomic where the underlying file system supports it.
+    /// If the source file doesn't exist, the client should respond with a "Resource not found"
+    /// (`-32002`) error rather than a generic internal error.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn rename_file(&self, _args: RenameFileRequest) -> Result<RenameFileResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Gets metadata about a path in the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.stat` capability.
+    /// Lets the agent check whether a file exists or changed before reading it.
+    /// A path that doesn't exist is not an error: the client should respond with
+    /// `exists` set to `false`.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn stat(&self, _args: StatRequest) -> Result<StatResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Finds files matching a glob pattern in the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.search` capability.
+    /// Results honor the client's ignore configuration (such as `.gitignore`), so
+    /// they match what the user sees in the editor, and are capped at `max_results`.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn search_files(&self, _args: SearchFilesRequest) -> Result<SearchFilesResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Searches the contents of files in the client's file system.
+    ///
+    /// Only available if the client advertises the `fs.grep` capability.
+    /// Lets the agent use the editor's search infrastructure instead of running a
+    /// search tool in a terminal. Like [`Client::search_files`], results honor the
+    /// client's ignore configuration and are capped at `max_results`.
+    ///
+    /// See protocol docs: [Client](https://agentclientprotocol.com/protocol/overview#client)
+    async fn grep(&self, _args: GrepRequest) -> Result<GrepResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
     /// Executes a command in a new terminal
     ///
     /// Only available if the `terminal` Client capability is set to `true`.
@@ -158,6 +272,41 @@ pub trait Client {
         Err(Error::method_not_found())
     }
 
+    /// Writes input to the terminal's standard input
+    ///
+    /// Allows the Agent to drive interactive commands, such as REPLs or prompts,
+    /// running in a terminal created with `terminal/create`. The data is written
+    /// as-is, so the Agent must include any trailing newline the command expects.
+    ///
+    /// Only available if the `terminalInput` Client capability is set to `true`.
+    ///
+    /// If the terminal's command has already exited, the Client should respond with
+    /// a "Terminal exited" (`-32009`) error.
+    ///
+    /// See protocol docs: [Terminals](https://agentclientprotocol.com/protocol/terminals)
+    async fn write_terminal_input(
+        &self,
+        _args: WriteTerminalInputRequest,
+    ) -> Result<WriteTerminalInputResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// Resizes the terminal to the given dimensions
+    ///
+    /// Full-screen programs such as `top` or `vim` rely on the terminal size to lay
+    /// out their output. The Client should apply the new size to the underlying
+    /// pseudo-terminal so the running command is notified of the change.
+    ///
+    /// Only available if the `terminalResize` Client capability is set to `true`.
+    ///
+    /// See protocol docs: [Terminals](https://agentclientprotocol.com/protocol/terminals)
+    async fn resize_terminal(
+        &self,
+        _args: ResizeTerminalRequest,
+    ) -> Result<ResizeTerminalResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
     /// Handles extension method requests from the agent.
     ///
     /// Allows the Agent to send an arbitrary request that is not part of the ACP spec.
@@ -166,7 +315,7 @@ pub trait Client {
     ///
     /// See protocol docs: [Extensibility](https://agentclientprotocol.com/protocol/extensibility)
     async fn ext_method(&self, _args: ExtRequest) -> Result<ExtResponse, Error> {
-        Ok(RawValue::NULL.to_owned().into())
+        Err(Error::method_not_found())
     }
 
     /// Handles extension notifications from the agent.
@@ -177,7 +326,7 @@ pub trait Client {
     ///
     /// See protocol docs: [Extensibility](https://agentclientprotocol.com/protocol/extensibility)
     async fn ext_notification(&self, _args: ExtNotification) -> Result<(), Error> {
-        Ok(())
+        Err(Error::method_not_found())
     }
 }
 
@@ -189,6 +338,12 @@ impl<T: Client> Client for Rc<T> {
     ) -> Result<RequestPermissionResponse, Error> {
         self.as_ref().request_permission(args).await
     }
+    async fn request_user_input(
+        &self,
+        args: RequestUserInputRequest,
+    ) -> Result<RequestUserInputResponse, Error> {
+        self.as_ref().request_user_input(args).await
+    }
     async fn write_text_file(
         &self,
         args: WriteTextFileRequest,
@@ -201,6 +356,33 @@ impl<T: Client> Client for Rc<T> {
     ) -> Result<ReadTextFileResponse, Error> {
         self.as_ref().read_text_file(args).await
     }
+    async fn read_text_file_chunk(
+        &self,
+        args: ReadTextFileChunkRequest,
+    ) -> Result<ReadTextFileChunkResponse, Error> {
+        self.as_ref().read_text_file_chunk(args).await
+    }
+    async fn list_directory(
+        &self,
+        args: ListDirectoryRequest,
+    ) -> Result<ListDirectoryResponse, Error> {
+        self.as_ref().list_directory(args).await
+    }
+    async fn delete_file(&self, args: DeleteFileRequest) -> Result<DeleteFileResponse, Error> {
+        self.as_ref().delete_file(args).await
+    }
+    async fn rename_file(&self, args: RenameFileRequest) -> Result<RenameFileResponse, Error> {
+        self.as_ref().rename_file(args).await
+    }
+    async fn stat(&self, args: StatRequest) -> Result<StatResponse, Error> {
+        self.as_ref().stat(args).await
+    }
+    async fn search_files(&self, args: SearchFilesRequest) -> Result<SearchFilesResponse, Error> {
+        self.as_ref().search_files(args).await
+    }
+    async fn grep(&self, args: GrepRequest) -> Result<GrepResponse, Error> {
+        self.as_ref().grep(args).await
+    }
     async fn session_notification(&self, args: SessionNotification) -> Result<(), Error> {
         self.as_ref().session_notification(args).await
     }
@@ -234,6 +416,18 @@ impl<T: Client> Client for Rc<T> {
     ) -> Result<KillTerminalCommandResponse, Error> {
         self.as_ref().kill_terminal_command(args).await
     }
+    async fn write_terminal_input(
+        &self,
+        args: WriteTerminalInputRequest,
+    ) -> Result<WriteTerminalInputResponse, Error> {
+        self.as_ref().write_terminal_input(args).await
+    }
+    async fn resize_terminal(
+        &self,
+        args: ResizeTerminalRequest,
+    ) -> Result<ResizeTerminalResponse, Error> {
+        self.as_ref().resize_terminal(args).await
+    }
     async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
         self.as_ref().ext_method(args).await
     }
@@ -250,6 +444,12 @@ impl<T: Client> Client for Arc<T> {
     ) -> Result<RequestPermissionResponse, Error> {
         self.as_ref().request_permission(args).await
     }
+    async fn request_user_input(
+        &self,
+        args: RequestUserInputRequest,
+    ) -> Result<RequestUserInputResponse, Error> {
+        self.as_ref().request_user_input(args).await
+    }
     async fn write_text_file(
         &self,
         args: WriteTextFileRequest,
@@ -262,6 +462,33 @@ impl<T: Client> Client for Arc<T> {
     ) -> Result<ReadTextFileResponse, Error> {
         self.as_ref().read_text_file(args).await
     }
+    async fn read_text_file_chunk(
+        &self,
+        args: ReadTextFileChunkRequest,
+    ) -> Result<ReadTextFileChunkResponse, Error> {
+        self.as_ref().read_text_file_chunk(args).await
+    }
+    async fn list_directory(
+        &self,
+        args: ListDirectoryRequest,
+    ) -> Result<ListDirectoryResponse, Error> {
+        self.as_ref().list_directory(args).await
+    }
+    async fn delete_file(&self, args: DeleteFileRequest) -> Result<DeleteFileResponse, Error> {
+        self.as_ref().delete_file(args).await
+    }
+    async fn rename_file(&self, args: RenameFileRequest) -> Result<RenameFileResponse, Error> {
+        self.as_ref().rename_file(args).await
+    }
+    async fn stat(&self, args: StatRequest) -> Result<StatResponse, Error> {
+        self.as_ref().stat(args).await
+    }
+    async fn search_files(&self, args: SearchFilesRequest) -> Result<SearchFilesResponse, Error> {
+        self.as_ref().search_files(args).await
+    }
+    async fn grep(&self, args: GrepRequest) -> Result<GrepResponse, Error> {
+        self.as_ref().grep(args).await
+    }
     async fn session_notification(&self, args: SessionNotification) -> Result<(), Error> {
         self.as_ref().session_notification(args).await
     }
@@ -295,6 +522,18 @@ impl<T: Client> Client for Arc<T> {
     ) -> Result<KillTerminalCommandResponse, Error> {
         self.as_ref().kill_terminal_command(args).await
     }
+    async fn write_terminal_input(
+        &self,
+        args: WriteTerminalInputRequest,
+    ) -> Result<WriteTerminalInputResponse, Error> {
+        self.as_ref().write_terminal_input(args).await
+    }
+    async fn resize_terminal(
+        &self,
+        args: ResizeTerminalRequest,
+    ) -> Result<ResizeTerminalResponse, Error> {
+        self.as_ref().resize_terminal(args).await
+    }
     async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
         self.as_ref().ext_method(args).await
     }
@@ -303,142 +542,724 @@ impl<T: Client> Client for Arc<T> {
     }
 }
 
-// Session updates
-
-/// Notification containing a session update from the agent.
+/// An [`Client`] that can be served by a multi-threaded runtime.
 ///
-/// Used to stream real-time progress and results during prompt processing.
+/// The methods and their defaults are the same as [`Client`]'s, but implementations
+/// must be `Send + Sync` and return [`Send`] futures, so that
+/// [`SendClientSideConnection`](crate::SendClientSideConnection) can handle requests on any thread.
 ///
-/// See protocol docs: [Agent Reports Output](https://agentclientprotocol.com/protocol/prompt-turn#3-agent-reports-output)
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[schemars(extend("x-side" = "client", "x-method" = SESSION_UPDATE_NOTIFICATION))]
-#[serde(rename_all = "camelCase")]
-pub struct SessionNotification {
-    /// The ID of the session this update pertains to.
-    pub session_id: SessionId,
-    /// The actual update content.
-    pub update: SessionUpdate,
-    /// Extension point for implementations
-    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
-    pub meta: Option<serde_json::Value>,
-}
+/// Only available with the `send` feature.
+#[cfg(feature = "send")]
+#[async_trait::async_trait]
+pub trait SendClient: Send + Sync {
+    /// See [`Client::request_permission`].
+    async fn request_permission(
+        &self,
+        args: RequestPermissionRequest,
+    ) -> Result<RequestPermissionResponse, Error>;
 
-/// Different types of updates that can be sent during session processing.
-///
-/// These updates provide real-time feedback about the agent's progress.
-///
-/// See protocol docs: [Agent Reports Output](https://agentclientprotocol.com/protocol/prompt-turn#3-agent-reports-output)
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "snake_case", tag = "sessionUpdate")]
-pub enum SessionUpdate {
-    /// A chunk of the user's message being streamed.
-    UserMessageChunk { content: ContentBlock },
-    /// A chunk of the agent's response being streamed.
-    AgentMessageChunk { content: ContentBlock },
-    /// A chunk of the agent's internal reasoning being streamed.
-    AgentThoughtChunk { content: ContentBlock },
-    /// Notification that a new tool call has been initiated.
-    ToolCall(ToolCall),
-    /// Update on the status or results of a tool call.
-    ToolCallUpdate(ToolCallUpdate),
-    /// The agent's execution plan for complex tasks.
-    /// See protocol docs: [Agent Plan](https://agentclientprotocol.com/protocol/agent-plan)
-    Plan(Plan),
-    /// Available commands are ready or have changed
-    #[serde(rename_all = "camelCase")]
-    AvailableCommandsUpdate {
-        available_commands: Vec<AvailableCommand>,
-    },
-    /// The current mode of the session has changed
-    ///
-    /// See protocol docs: [Session Modes](https://agentclientprotocol.com/protocol/session-modes)
-    #[serde(rename_all = "camelCase")]
-    CurrentModeUpdate { current_mode_id: SessionModeId },
-}
+    /// See [`Client::session_notification`].
+    async fn session_notification(&self, args: SessionNotification) -> Result<(), Error>;
 
-/// Information about a command.
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "camelCase")]
-pub struct AvailableCommand {
-    /// Command name (e.g., `create_plan`, `research_codebase`).
-    pub name: String,
-    /// Human-readable description of what the command does.
-    pub description: String,
-    /// Input for the command if required
-    pub input: Option<AvailableCommandInput>,
-    /// Extension point for implementations
-    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
-    pub meta: Option<serde_json::Value>,
-}
+    /// See [`Client::request_user_input`].
+    async fn request_user_input(
+        &self,
+        _args: RequestUserInputRequest,
+    ) -> Result<RequestUserInputResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
-/// The input specification for a command.
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(untagged, rename_all = "camelCase")]
-pub enum AvailableCommandInput {
-    /// All text that was typed after the command name is provided as input.
-    #[schemars(rename = "UnstructuredCommandInput")]
-    Unstructured {
-        /// A hint to display when the input hasn't been provided yet
-        hint: String,
-    },
-}
+    /// See [`Client::write_text_file`].
+    async fn write_text_file(
+        &self,
+        _args: WriteTextFileRequest,
+    ) -> Result<WriteTextFileResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
-// Permission
+    /// See [`Client::read_text_file`].
+    async fn read_text_file(
+        &self,
+        _args: ReadTextFileRequest,
+    ) -> Result<ReadTextFileResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
-/// Request for user permission to execute a tool call.
-///
-/// Sent when the agent needs authorization before performing a sensitive operation.
-///
-/// See protocol docs: [Requesting Permission](https://agentclientprotocol.com/protocol/tool-calls#requesting-permission)
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[schemars(extend("x-side" = "client", "x-method" = SESSION_REQUEST_PERMISSION_METHOD_NAME))]
-#[serde(rename_all = "camelCase")]
-pub struct RequestPermissionRequest {
-    /// The session ID for this request.
-    pub session_id: SessionId,
-    /// Details about the tool call requiring permission.
-    pub tool_call: ToolCallUpdate,
-    /// Available permission options for the user to choose from.
-    pub options: Vec<PermissionOption>,
-    /// Extension point for implementations
-    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
-    pub meta: Option<serde_json::Value>,
-}
+    /// See [`Client::read_text_file_chunk`].
+    async fn read_text_file_chunk(
+        &self,
+        _args: ReadTextFileChunkRequest,
+    ) -> Result<ReadTextFileChunkResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
-/// An option presented to the user when requesting permission.
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-pub struct PermissionOption {
-    /// Unique identifier for this permission option.
-    #[serde(rename = "optionId")]
-    pub id: PermissionOptionId,
-    /// Human-readable label to display to the user.
-    pub name: String,
-    /// Hint about the nature of this permission option.
-    pub kind: PermissionOptionKind,
-    /// Extension point for implementations
-    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
-    pub meta: Option<serde_json::Value>,
-}
+    /// See [`Client::list_directory`].
+    async fn list_directory(
+        &self,
+        _args: ListDirectoryRequest,
+    ) -> Result<ListDirectoryResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
-/// Unique identifier for a permission option.
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
-#[serde(transparent)]
-pub struct PermissionOptionId(pub Arc<str>);
+    /// See [`Client::delete_file`].
+    async fn delete_file(&self, _args: DeleteFileRequest) -> Result<DeleteFileResponse, Error> {
+        Err(Error::method_not_found())
+    }
 
-impl fmt::Display for PermissionOptionId {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+    /// See [`Client::rename_file`].
+    async fn rename_file(&self, _args: RenameFileRequest) -> Result<RenameFileResponse, Error> {
+        Err(Error::method_not_found())
     }
-}
 
-/// The type of permission option being presented to the user.
-///
-/// Helps clients choose appropriate icons and UI treatment.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum PermissionOptionKind {
-    /// Allow this operation only this time.
-    AllowOnce,
+    /// See [`Client::stat`].
+    async fn stat(&self, _args: StatRequest) -> Result<StatResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::search_files`].
+    async fn search_files(&self, _args: SearchFilesRequest) -> Result<SearchFilesResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::grep`].
+    async fn grep(&self, _args: GrepRequest) -> Result<GrepResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::create_terminal`].
+    async fn create_terminal(
+        &self,
+        _args: CreateTerminalRequest,
+    ) -> Result<CreateTerminalResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::terminal_output`].
+    async fn terminal_output(
+        &self,
+        _args: TerminalOutputRequest,
+    ) -> Result<TerminalOutputResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::release_terminal`].
+    async fn release_terminal(
+        &self,
+        _args: ReleaseTerminalRequest,
+    ) -> Result<ReleaseTerminalResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::wait_for_terminal_exit`].
+    async fn wait_for_terminal_exit(
+        &self,
+        _args: WaitForTerminalExitRequest,
+    ) -> Result<WaitForTerminalExitResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::kill_terminal_command`].
+    async fn kill_terminal_command(
+        &self,
+        _args: KillTerminalCommandRequest,
+    ) -> Result<KillTerminalCommandResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::write_terminal_input`].
+    async fn write_terminal_input(
+        &self,
+        _args: WriteTerminalInputRequest,
+    ) -> Result<WriteTerminalInputResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::resize_terminal`].
+    async fn resize_terminal(
+        &self,
+        _args: ResizeTerminalRequest,
+    ) -> Result<ResizeTerminalResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::ext_method`].
+    async fn ext_method(&self, _args: ExtRequest) -> Result<ExtResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    /// See [`Client::ext_notification`].
+    async fn ext_notification(&self, _args: ExtNotification) -> Result<(), Error> {
+        Err(Error::method_not_found())
+    }
+}
+
+#[cfg(feature = "send")]
+#[async_trait::async_trait]
+impl<T: SendClient> SendClient for Arc<T> {
+    async fn request_permission(
+        &self,
+        args: RequestPermissionRequest,
+    ) -> Result<RequestPermissionResponse, Error> {
+        self.as_ref().request_permission(args).await
+    }
+    async fn request_user_input(
+        &self,
+        args: RequestUserInputRequest,
+    ) -> Result<RequestUserInputResponse, Error> {
+        self.as_ref().request_user_input(args).await
+    }
+    async fn write_text_file(
+        &self,
+        args: WriteTextFileRequest,
+    ) -> Result<WriteTextFileResponse, Error> {
+        self.as_ref().write_text_file(args).await
+    }
+    async fn read_text_file(
+        &self,
+        args: ReadTextFileRequest,
+    ) -> Result<ReadTextFileResponse, Error> {
+        self.as_ref().read_text_file(args).await
+    }
+    async fn read_text_file_chunk(
+        &self,
+        args: ReadTextFileChunkRequest,
+    ) -> Result<ReadTextFileChunkResponse, Error> {
+        self.as_ref().read_text_file_chunk(args).await
+    }
+    async fn list_directory(
+        &self,
+        args: ListDirectoryRequest,
+    ) -> Result<ListDirectoryResponse, Error> {
+        self.as_ref().list_directory(args).await
+    }
+    async fn delete_file(&self, args: DeleteFileRequest) -> Result<DeleteFileResponse, Error> {
+        self.as_ref().delete_file(args).await
+    }
+    async fn rename_file(&self, args: RenameFileRequest) -> Result<RenameFileResponse, Error> {
+        self.as_ref().rename_file(args).await
+    }
+    async fn stat(&self, args: StatRequest) -> Result<StatResponse, Error> {
+        self.as_ref().stat(args).await
+    }
+    async fn search_files(&self, args: SearchFilesRequest) -> Result<SearchFilesResponse, Error> {
+        self.as_ref().search_files(args).await
+    }
+    async fn grep(&self, args: GrepRequest) -> Result<GrepResponse, Error> {
+        self.as_ref().grep(args).await
+    }
+    async fn session_notification(&self, args: SessionNotification) -> Result<(), Error> {
+        self.as_ref().session_notification(args).await
+    }
+    async fn create_terminal(
+        &self,
+        args: CreateTerminalRequest,
+    ) -> Result<CreateTerminalResponse, Error> {
+        self.as_ref().create_terminal(args).await
+    }
+    async fn terminal_output(
+        &self,
+        args: TerminalOutputRequest,
+    ) -> Result<TerminalOutputResponse, Error> {
+        self.as_ref().terminal_output(args).await
+    }
+    async fn release_terminal(
+        &self,
+        args: ReleaseTerminalRequest,
+    ) -> Result<ReleaseTerminalResponse, Error> {
+        self.as_ref().release_terminal(args).await
+    }
+    async fn wait_for_terminal_exit(
+        &self,
+        args: WaitForTerminalExitRequest,
+    ) -> Result<WaitForTerminalExitResponse, Error> {
+        self.as_ref().wait_for_terminal_exit(args).await
+    }
+    async fn kill_terminal_command(
+        &self,
+        args: KillTerminalCommandRequest,
+    ) -> Result<KillTerminalCommandResponse, Error> {
+        self.as_ref().kill_terminal_command(args).await
+    }
+    async fn write_terminal_input(
+        &self,
+        args: WriteTerminalInputRequest,
+    ) -> Result<WriteTerminalInputResponse, Error> {
+        self.as_ref().write_terminal_input(args).await
+    }
+    async fn resize_terminal(
+        &self,
+        args: ResizeTerminalRequest,
+    ) -> Result<ResizeTerminalResponse, Error> {
+        self.as_ref().resize_terminal(args).await
+    }
+    async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
+        self.as_ref().ext_method(args).await
+    }
+    async fn ext_notification(&self, args: ExtNotification) -> Result<(), Error> {
+        self.as_ref().ext_notification(args).await
+    }
+}
+
+// Session updates
+
+/// Notification containing a session update from the agent.
+///
+/// Used to stream real-time progress and results during prompt processing.
+///
+/// See protocol docs: [Agent Reports Output](https://agentclientprotocol.com/protocol/prompt-turn#3-agent-reports-output)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = SESSION_UPDATE_NOTIFICATION))]
+#[serde(rename_all = "camelCase")]
+pub struct SessionNotification {
+    /// The ID of the session this update pertains to.
+    pub session_id: SessionId,
+    /// The actual update content.
+    pub update: SessionUpdate,
+    /// The [`PromptRequest::turn_id`](crate::PromptRequest::turn_id) of the turn
+    /// this update belongs to, if the client set one and the agent tracks turns.
+    ///
+    /// See protocol docs: [Turn IDs](https://agentclientprotocol.com/protocol/prompt-turn#turn-ids)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_id: Option<TurnId>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Different types of updates that can be sent during session processing.
+///
+/// These updates provide real-time feedback about the agent's progress.
+///
+/// See protocol docs: [Agent Reports Output](https://agentclientprotocol.com/protocol/prompt-turn#3-agent-reports-output)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "sessionUpdate")]
+pub enum SessionUpdate {
+    /// A chunk of the user's message being streamed.
+    UserMessageChunk { content: ContentBlock },
+    /// A chunk of the agent's response being streamed.
+    AgentMessageChunk { content: ContentBlock },
+    /// A chunk of the agent's internal reasoning being streamed.
+    ///
+    /// See protocol docs: [Agent Reasoning](https://agentclientprotocol.com/protocol/prompt-turn#agent-reasoning)
+    #[serde(rename_all = "camelCase")]
+    AgentThoughtChunk {
+        content: ContentBlock,
+        /// An opaque signature the model attached to this reasoning, which must be
+        /// preserved verbatim along with the content.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+        /// Whether the content is redacted or encrypted reasoning, which must not be
+        /// displayed but should be preserved verbatim.
+        #[serde(default, skip_serializing_if = "is_false")]
+        redacted: bool,
+    },
+    /// Notification that a new tool call has been initiated.
+    ToolCall(ToolCall),
+    /// Update on the status or results of a tool call.
+    ToolCallUpdate(ToolCallUpdate),
+    /// A piece of a tool call's raw output, streamed as the tool produces it.
+    ///
+    /// Receivers merge it into the tool call's `rawOutput` with
+    /// [`ToolCall::apply_output_chunk`].
+    ///
+    /// See protocol docs: [Streaming Output](https://agentclientprotocol.com/protocol/tool-calls#streaming-output)
+    #[serde(rename_all = "camelCase")]
+    ToolCallOutputChunk {
+        /// The ID of the tool call that produced the output.
+        tool_call_id: ToolCallId,
+        /// The output produced since the previous chunk.
+        chunk: serde_json::Value,
+        /// Whether to append `chunk` to the current raw output rather than
+        /// replace it.
+        append: bool,
+    },
+    /// The agent's execution plan for complex tasks.
+    /// See protocol docs: [Agent Plan](https://agentclientprotocol.com/protocol/agent-plan)
+    Plan(Plan),
+    /// An update to a single entry of the agent's execution plan.
+    ///
+    /// Only valid after a full `plan` update has been sent for the session.
+    /// See protocol docs: [Agent Plan](https://agentclientprotocol.com/protocol/agent-plan#updating-plan-entries)
+    PlanEntryUpdate(PlanEntryUpdate),
+    /// Running token usage totals for the current prompt turn.
+    ///
+    /// Each update replaces the previous totals; the final totals can also be
+    /// reported in the `session/prompt` response.
+    /// See protocol docs: [Usage](https://agentclientprotocol.com/protocol/prompt-turn#usage)
+    UsageUpdate(TokenUsage),
+    /// Available commands are ready or have changed
+    #[serde(rename_all = "camelCase")]
+    AvailableCommandsUpdate {
+        available_commands: Vec<AvailableCommand>,
+    },
+    /// The current mode of the session has changed
+    ///
+    /// See protocol docs: [Session Modes](https://agentclientprotocol.com/protocol/session-modes)
+    #[serde(rename_all = "camelCase")]
+    CurrentModeUpdate { current_mode_id: SessionModeId },
+    /// The current model of the session has changed
+    ///
+    /// See protocol docs: [Session Models](https://agentclientprotocol.com/protocol/session-models)
+    #[serde(rename_all = "camelCase")]
+    CurrentModelUpdate { current_model_id: ModelId },
+    /// A chunk of output produced by a terminal, streamed as it's produced.
+    ///
+    /// Avoids polling `terminal/output` for long-running commands. Emitting these
+    /// updates is optional, and a full snapshot can still be retrieved via
+    /// `terminal/output` afterwards, so receivers must tolerate overlapping output.
+    ///
+    /// See protocol docs: [Terminals](https://agentclientprotocol.com/protocol/terminals)
+    #[serde(rename_all = "camelCase")]
+    TerminalOutputChunk {
+        /// The ID of the terminal that produced the output.
+        terminal_id: TerminalId,
+        /// The output produced since the previous chunk.
+        chunk: String,
+        /// Byte offset of this chunk within the terminal's full output.
+        offset: u64,
+    },
+    /// A problem the agent found in a file, such as a lint error or a compilation
+    /// failure, for the client to show alongside its own diagnostics.
+    ///
+    /// See protocol docs: [Diagnostics](https://agentclientprotocol.com/protocol/diagnostics)
+    Diagnostic {
+        /// Absolute path to the file the problem is in.
+        path: PathBuf,
+        /// The lines the problem spans. Omitted when it concerns the whole file.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range: Option<LineRange>,
+        /// How serious the problem is.
+        severity: DiagnosticSeverity,
+        /// A human-readable description of the problem.
+        message: String,
+        /// The tool that reported the problem, such as `rustc` or `eslint`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+    },
+    /// Prompts the user might want to send next, such as "Run the tests".
+    ///
+    /// Each update replaces the previous suggestions, and an empty list clears them.
+    /// Suggestions are advisory, and clients may ignore them.
+    ///
+    /// See protocol docs: [Suggested Prompts](https://agentclientprotocol.com/protocol/prompt-turn#suggested-prompts)
+    SuggestedPrompts { prompts: Vec<SuggestedPrompt> },
+    /// Progress of a long-running operation, such as setting up a session.
+    ///
+    /// See protocol docs: [Progress](https://agentclientprotocol.com/protocol/session-setup#progress)
+    Progress {
+        /// The `progressToken` of the request this progress belongs to.
+        token: ProgressToken,
+        /// A human-readable description of the current step.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        /// How much of the operation is complete, from 0 to 100.
+        ///
+        /// Omitted when the amount of remaining work is unknown.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        percent: Option<f32>,
+    },
+    /// The agent closed the session, for example because the model's context
+    /// expired.
+    ///
+    /// The client should stop using the session's ID, since further prompts will
+    /// fail with [`Error::session_closed`].
+    ///
+    /// See protocol docs: [Closed Sessions](https://agentclientprotocol.com/protocol/session-setup#closed-sessions)
+    SessionClosed {
+        /// A human-readable explanation of why the session was closed.
+        reason: String,
+    },
+}
+
+/// How serious a diagnostic is.
+///
+/// See protocol docs: [Diagnostics](https://agentclientprotocol.com/protocol/diagnostics)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticSeverity {
+    /// A problem that must be fixed, such as a compilation error.
+    Error,
+    /// A likely problem that doesn't prevent the code from working.
+    Warning,
+    /// Information about the code that isn't a problem.
+    Info,
+    /// A suggestion, such as a possible simplification.
+    Hint,
+}
+
+/// A follow-up prompt suggested by the agent.
+///
+/// See protocol docs: [Suggested Prompts](https://agentclientprotocol.com/protocol/prompt-turn#suggested-prompts)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestedPrompt {
+    /// A short label to display, such as "Run the tests?".
+    pub label: String,
+    /// The text to send as the next prompt when the user picks the suggestion.
+    pub prompt_text: String,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Information about a command.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableCommand {
+    /// Command name (e.g., `create_plan`, `research_codebase`).
+    pub name: String,
+    /// Human-readable description of what the command does.
+    pub description: String,
+    /// Input for the command if required.
+    ///
+    /// Commands without an input take no arguments.
+    pub input: Option<AvailableCommandInput>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl AvailableCommand {
+    /// Returns the arguments of this command if `prompt` invokes it.
+    ///
+    /// Commands are invoked by starting the first text block of a prompt with
+    /// `/name`, followed by whitespace and the arguments. Agents can use this
+    /// while handling `session/prompt` to recognize the commands they advertised.
+    #[must_use]
+    pub fn invocation<'a>(&self, prompt: &'a [ContentBlock]) -> Option<&'a str> {
+        let Some(ContentBlock::Text(text)) = prompt.first() else {
+            return None;
+        };
+        let rest = text
+            .text
+            .strip_prefix('/')?
+            .strip_prefix(self.name.as_str())?;
+        if rest.is_empty() {
+            Some(rest)
+        } else if rest.starts_with(char::is_whitespace) {
+            Some(rest.trim())
+        } else {
+            None
+        }
+    }
+
+    /// Checks that `arguments` are valid input for this command.
+    ///
+    /// Returns an `invalid_params` error otherwise.
+    pub fn validate_arguments(&self, arguments: &str) -> Result<(), Error> {
+        let arguments = arguments.trim();
+        match &self.input {
+            None if arguments.is_empty() => Ok(()),
+            None => Err(Error::invalid_params()
+                .with_data(format!("/{} doesn't take any arguments", self.name))),
+            Some(AvailableCommandInput::Unstructured { .. }) => Ok(()),
+            Some(AvailableCommandInput::Choice { options }) => {
+                if options.iter().any(|option| option.value == arguments) {
+                    Ok(())
+                } else {
+                    Err(Error::invalid_params().with_data(format!(
+                        "/{} expects one of: {}",
+                        self.name,
+                        options
+                            .iter()
+                            .map(|option| option.value.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// The input specification for a command.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged, rename_all = "camelCase")]
+pub enum AvailableCommandInput {
+    /// The input is one of a fixed set of options.
+    ///
+    /// Clients can offer the options for completion, and send the `value` of the
+    /// one the user picked as the command's input.
+    #[schemars(rename = "ChoiceCommandInput")]
+    Choice {
+        /// The options the user can pick from.
+        options: Vec<CommandInputOption>,
+    },
+    /// All text that was typed after the command name is provided as input.
+    #[schemars(rename = "UnstructuredCommandInput")]
+    Unstructured {
+        /// A hint to display when the input hasn't been provided yet
+        hint: String,
+    },
+}
+
+/// An option that can be picked as the input of a command.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandInputOption {
+    /// The text provided as input when this option is picked.
+    pub value: String,
+    /// Human-readable description of this option.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Permission
+
+/// Request for user permission to execute a tool call.
+///
+/// Sent when the agent needs authorization before performing a sensitive operation.
+///
+/// See protocol docs: [Requesting Permission](https://agentclientprotocol.com/protocol/tool-calls#requesting-permission)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = SESSION_REQUEST_PERMISSION_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct RequestPermissionRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Details about the tool call requiring permission.
+    pub tool_call: ToolCallUpdate,
+    /// Available permission options for the user to choose from.
+    pub options: Vec<PermissionOption>,
+    /// Structured details about the operation, which clients can use to render a
+    /// prompt tailored to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<PermissionContext>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// An option presented to the user when requesting permission.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PermissionOption {
+    /// Unique identifier for this permission option.
+    #[serde(rename = "optionId")]
+    pub id: PermissionOptionId,
+    /// Human-readable label to display to the user.
+    pub name: String,
+    /// Hint about the nature of this permission option.
+    pub kind: PermissionOptionKind,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<Meta>,
+}
+
+impl PermissionOption {
+    /// Creates an option with the given ID, label and kind.
+    pub fn new(
+        id: impl Into<Arc<str>>,
+        name: impl Into<String>,
+        kind: PermissionOptionKind,
+    ) -> Self {
+        Self {
+            id: PermissionOptionId(id.into()),
+            name: name.into(),
+            kind,
+            meta: None,
+        }
+    }
+
+    /// The conventional four options, in the order allow once, allow always,
+    /// reject once, reject always.
+    ///
+    /// Each option uses its kind's [`default_id`](PermissionOptionKind::default_id)
+    /// and [`default_name`](PermissionOptionKind::default_name). Use
+    /// [`PermissionOption::builder`] to offer fewer options or translated labels.
+    #[must_use]
+    pub fn standard_set() -> Vec<Self> {
+        Self::builder()
+            .allow_once(PermissionOptionKind::AllowOnce.default_name())
+            .allow_always(PermissionOptionKind::AllowAlways.default_name())
+            .reject_once(PermissionOptionKind::RejectOnce.default_name())
+            .reject_always(PermissionOptionKind::RejectAlways.default_name())
+            .build()
+    }
+
+    /// Starts building a list of options to present to the user.
+    pub fn builder() -> PermissionOptionsBuilder {
+        PermissionOptionsBuilder {
+            options: Vec::new(),
+        }
+    }
+}
+
+/// Builder for the options of a [`RequestPermissionRequest`], created with
+/// [`PermissionOption::builder`].
+///
+/// The methods named after a [`PermissionOptionKind`] add an option with that
+/// kind's [`default_id`](PermissionOptionKind::default_id), so that the IDs
+/// agree across agents while the labels can be localized.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct PermissionOptionsBuilder {
+    options: Vec<PermissionOption>,
+}
+
+impl PermissionOptionsBuilder {
+    /// Adds an [`PermissionOptionKind::AllowOnce`] option with the given label.
+    pub fn allow_once(self, name: impl Into<String>) -> Self {
+        self.standard(PermissionOptionKind::AllowOnce, name)
+    }
+
+    /// Adds an [`PermissionOptionKind::AllowAlways`] option with the given label.
+    pub fn allow_always(self, name: impl Into<String>) -> Self {
+        self.standard(PermissionOptionKind::AllowAlways, name)
+    }
+
+    /// Adds a [`PermissionOptionKind::RejectOnce`] option with the given label.
+    pub fn reject_once(self, name: impl Into<String>) -> Self {
+        self.standard(PermissionOptionKind::RejectOnce, name)
+    }
+
+    /// Adds a [`PermissionOptionKind::RejectAlways`] option with the given label.
+    pub fn reject_always(self, name: impl Into<String>) -> Self {
+        self.standard(PermissionOptionKind::RejectAlways, name)
+    }
+
+    /// Adds a custom option.
+    pub fn option(mut self, option: PermissionOption) -> Self {
+        self.options.push(option);
+        self
+    }
+
+    fn standard(self, kind: PermissionOptionKind, name: impl Into<String>) -> Self {
+        self.option(PermissionOption::new(kind.default_id(), name, kind))
+    }
+
+    /// Returns the built options.
+    #[must_use]
+    pub fn build(self) -> Vec<PermissionOption> {
+        self.options
+    }
+}
+
+/// Unique identifier for a permission option.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct PermissionOptionId(pub Arc<str>);
+
+impl fmt::Display for PermissionOptionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The type of permission option being presented to the user.
+///
+/// Helps clients choose appropriate icons and UI treatment.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionOptionKind {
+    /// Allow this operation only this time.
+    AllowOnce,
     /// Allow this operation and remember the choice.
     AllowAlways,
     /// Reject this operation only this time.
@@ -447,105 +1268,629 @@ pub enum PermissionOptionKind {
     RejectAlways,
 }
 
-/// Response to a permission request.
+impl PermissionOptionKind {
+    /// The conventional ID for an option of this kind, e.g. `"allow-once"`.
+    #[must_use]
+    pub fn default_id(self) -> &'static str {
+        match self {
+            Self::AllowOnce => "allow-once",
+            Self::AllowAlways => "allow-always",
+            Self::RejectOnce => "reject-once",
+            Self::RejectAlways => "reject-always",
+        }
+    }
+
+    /// An English label for an option of this kind, e.g. `"Allow once"`.
+    #[must_use]
+    pub fn default_name(self) -> &'static str {
+        match self {
+            Self::AllowOnce => "Allow once",
+            Self::AllowAlways => "Always allow",
+            Self::RejectOnce => "Reject once",
+            Self::RejectAlways => "Always reject",
+        }
+    }
+}
+
+/// Structured details about the operation a permission is requested for.
+///
+/// See protocol docs: [Requesting Permission](https://agentclientprotocol.com/protocol/tool-calls#requesting-permission)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PermissionContext {
+    /// Running a shell command.
+    Command {
+        /// The command to execute.
+        command: String,
+        /// Arguments passed to the command.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        /// Working directory for the command (absolute path).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cwd: Option<PathBuf>,
+    },
+    /// Modifying a file.
+    FileEdit {
+        /// The changes that would be made.
+        #[serde(flatten)]
+        diff: Diff,
+    },
+    /// Fetching resources over the network.
+    NetworkFetch {
+        /// The URLs that would be fetched.
+        urls: Vec<String>,
+    },
+    /// Calling a tool provided by an MCP server.
+    #[serde(rename_all = "camelCase")]
+    McpTool {
+        /// The name of the MCP server, as given when creating the session.
+        server_name: String,
+        /// The name of the tool on that server.
+        tool_name: String,
+    },
+}
+
+/// Response to a permission request.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = SESSION_REQUEST_PERMISSION_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct RequestPermissionResponse {
+    /// The user's decision on the permission request.
+    // This extra-level is unfortunately needed because the output must be an object
+    pub outcome: RequestPermissionOutcome,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// The outcome of a permission request.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RequestPermissionOutcome {
+    /// The prompt turn was cancelled before the user responded.
+    ///
+    /// When a client sends a `session/cancel` notification to cancel an ongoing
+    /// prompt turn, it MUST respond to all pending `session/request_permission`
+    /// requests with this `Cancelled` outcome.
+    ///
+    /// See protocol docs: [Cancellation](https://agentclientprotocol.com/protocol/prompt-turn#cancellation)
+    Cancelled,
+    /// The user selected one of the provided options.
+    #[serde(rename_all = "camelCase")]
+    Selected {
+        /// The ID of the option the user selected.
+        option_id: PermissionOptionId,
+    },
+}
+
+// Request user input
+
+/// Request for input from the user in the middle of a prompt turn.
+///
+/// Only available if the client supports the `requestUserInput` capability.
+///
+/// See protocol docs: [Requesting User Input](https://agentclientprotocol.com/protocol/prompt-turn#requesting-user-input)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = SESSION_REQUEST_USER_INPUT_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct RequestUserInputRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// The question to present to the user.
+    pub prompt: String,
+    /// The kind of answer expected from the user.
+    pub input_type: InputType,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// The kind of answer requested with `session/request_user_input`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputType {
+    /// Freeform text.
+    Text,
+    /// One of a fixed set of options. The answer is the chosen option.
+    Choice {
+        /// The options the user can choose from.
+        options: Vec<String>,
+    },
+    /// A yes or no answer, given as `"true"` or `"false"`.
+    Confirm,
+}
+
+/// Response to a user input request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[schemars(extend("x-side" = "client", "x-method" = SESSION_REQUEST_USER_INPUT_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct RequestUserInputResponse {
+    /// The user's answer, or `null` if the user dismissed the request or the
+    /// prompt turn was cancelled.
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Write text file
+
+/// Request to write content to a text file.
+///
+/// Only available if the client supports the `fs.writeTextFile` capability.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_WRITE_TEXT_FILE_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct WriteTextFileRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the file to write.
+    pub path: PathBuf,
+    /// The text content to write to the file.
+    pub content: String,
+    /// When `true`, the client must not modify the file, and instead responds with
+    /// the [`Diff`] the write would produce.
+    ///
+    /// Lets the agent show the user a real diff, for example when asking for
+    /// permission, before performing the write.
+    #[serde(default, skip_serializing_if = "is_false")]
+    #[schemars(extend("default" = false))]
+    pub dry_run: bool,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response to `fs/write_text_file`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = FS_WRITE_TEXT_FILE_METHOD_NAME))]
+#[serde(default)]
+pub struct WriteTextFileResponse {
+    /// The changes the write would make to the file.
+    ///
+    /// Set by clients when responding to a `dryRun` request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Diff>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Read text file
+
+/// Request to read content from a text file.
+///
+/// Only available if the client supports the `fs.readTextFile` capability.
+///
+/// A read selects either a range of lines, with `line` and `limit`, or a range of
+/// bytes, with `byteOffset` and `byteLimit`, but not both.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_READ_TEXT_FILE_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ReadTextFileRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the file to read.
+    pub path: PathBuf,
+    /// Line number to start reading from (1-based).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// Maximum number of lines to read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// Byte offset into the file's UTF-8 content to start reading from.
+    ///
+    /// Must fall on a character boundary. Only available if the client supports
+    /// the `fs.readTextFileByteRange` capability.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_offset: Option<u64>,
+    /// Maximum number of bytes to read.
+    ///
+    /// Only available if the client supports the `fs.readTextFileByteRange`
+    /// capability.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_limit: Option<u64>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl ReadTextFileRequest {
+    /// Whether this request reads a range of bytes rather than lines.
+    #[must_use]
+    pub fn is_byte_range(&self) -> bool {
+        self.byte_offset.is_some() || self.byte_limit.is_some()
+    }
+
+    /// Checks that the request doesn't select both a range of lines and a range
+    /// of bytes.
+    ///
+    /// Returns an `invalid_params` error otherwise.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.is_byte_range() && (self.line.is_some() || self.limit.is_some()) {
+            return Err(Error::invalid_params().with_data(
+                "`line` and `limit` can't be combined with `byteOffset` and `byteLimit`",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Response containing the contents of a text file.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_READ_TEXT_FILE_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ReadTextFileResponse {
+    pub content: String,
+    /// Byte offset at which the next range of the file starts.
+    ///
+    /// Set in response to a byte range read that stopped before the end of the
+    /// file, and omitted once the end of the file is reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_byte_offset: Option<u64>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl ReadTextFileResponse {
+    /// Builds the response to a byte range read of `text`.
+    ///
+    /// The content starts at `offset` and holds at most `limit` bytes, ending on
+    /// the last character boundary within the limit. If the limit is smaller than
+    /// the first character, that character is returned anyway, so that reads
+    /// before the end of the file always make progress. An `offset` past the end
+    /// of `text` returns empty content.
+    ///
+    /// Returns an `invalid_params` error if `offset` doesn't fall on a character
+    /// boundary.
+    pub fn from_byte_range(text: &str, offset: u64, limit: Option<u64>) -> Result<Self, Error> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(text.len());
+        if !text.is_char_boundary(start) {
+            return Err(Error::invalid_params().with_data(format!(
+                "byte offset {offset} is not on a character boundary"
+            )));
+        }
+        let limit = limit.map_or(usize::MAX, |limit| {
+            usize::try_from(limit).unwrap_or(usize::MAX)
+        });
+        let mut end = start.saturating_add(limit).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == start {
+            end = text[start..]
+                .chars()
+                .next()
+                .map_or(start, |c| start + c.len_utf8());
+        }
+        Ok(Self {
+            content: text[start..end].to_string(),
+            next_byte_offset: (end < text.len()).then_some(end as u64),
+            meta: None,
+        })
+    }
+}
+
+// Read text file chunk
+
+/// Request to read one chunk of a text file.
+///
+/// Only available if the client supports the `fs.readTextFileChunk` capability.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_READ_TEXT_FILE_CHUNK_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ReadTextFileChunkRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the file to read.
+    pub path: PathBuf,
+    /// Byte offset into the file's UTF-8 content to start reading from.
+    ///
+    /// Must fall on a character boundary. Defaults to the start of the file.
+    #[serde(default)]
+    pub offset: u64,
+    /// Maximum number of bytes to return. The client may return fewer bytes, and
+    /// picks its own chunk size if this is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u32>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response containing one chunk of a text file.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_READ_TEXT_FILE_CHUNK_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ReadTextFileChunkResponse {
+    /// The content starting at the requested offset.
+    ///
+    /// Always ends on a character boundary, so the next chunk starts at the
+    /// requested offset plus the UTF-8 length of this content.
+    pub content: String,
+    /// Whether this chunk reaches the end of the file.
+    ///
+    /// Chunks before the end of the file are never empty.
+    #[serde(default)]
+    pub eof: bool,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// List directory
+
+/// Request to list the entries of a directory.
+///
+/// Only available if the client supports the `fs.listDirectory` capability.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_LIST_DIRECTORY_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ListDirectoryRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the directory to list.
+    pub path: PathBuf,
+    /// Whether to include the contents of subdirectories.
+    #[serde(default)]
+    pub recursive: bool,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response containing the entries of a directory.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_LIST_DIRECTORY_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct ListDirectoryResponse {
+    /// The entries found in the directory.
+    ///
+    /// When the request is recursive, this includes the entries of all subdirectories.
+    pub entries: Vec<DirEntry>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// A single entry in a directory listing.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DirEntry {
+    /// The file or directory name, without its parent path.
+    pub name: String,
+    /// Absolute path to the entry.
+    pub path: PathBuf,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// Size of the file in bytes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Delete file
+
+/// Request to delete a file.
+///
+/// Only available if the client supports the `fs.deleteFile` capability.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_DELETE_FILE_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteFileRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the file to delete.
+    pub path: PathBuf,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response to `fs/delete_file`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = FS_DELETE_FILE_METHOD_NAME))]
+#[serde(default)]
+pub struct DeleteFileResponse {
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Rename file
+
+/// Request to rename or move a file.
+///
+/// Only available if the client supports the `fs.renameFile` capability.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_RENAME_FILE_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct RenameFileRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the file to rename.
+    pub old_path: PathBuf,
+    /// Absolute path the file should be moved to.
+    pub new_path: PathBuf,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response to `fs/rename_file`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = FS_RENAME_FILE_METHOD_NAME))]
+#[serde(default)]
+pub struct RenameFileResponse {
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+// Stat
+
+/// Request to get metadata about a path.
+///
+/// Only available if the client supports the `fs.stat` capability.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[schemars(extend("x-side" = "client", "x-method" = SESSION_REQUEST_PERMISSION_METHOD_NAME))]
+#[schemars(extend("x-side" = "client", "x-method" = FS_STAT_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
-pub struct RequestPermissionResponse {
-    /// The user's decision on the permission request.
-    // This extra-level is unfortunately needed because the output must be an object
-    pub outcome: RequestPermissionOutcome,
+pub struct StatRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// Absolute path to the file or directory.
+    pub path: PathBuf,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
-/// The outcome of a permission request.
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[serde(tag = "outcome", rename_all = "snake_case")]
-pub enum RequestPermissionOutcome {
-    /// The prompt turn was cancelled before the user responded.
-    ///
-    /// When a client sends a `session/cancel` notification to cancel an ongoing
-    /// prompt turn, it MUST respond to all pending `session/request_permission`
-    /// requests with this `Cancelled` outcome.
-    ///
-    /// See protocol docs: [Cancellation](https://agentclientprotocol.com/protocol/prompt-turn#cancellation)
-    Cancelled,
-    /// The user selected one of the provided options.
-    #[serde(rename_all = "camelCase")]
-    Selected {
-        /// The ID of the option the user selected.
-        option_id: PermissionOptionId,
-    },
+/// Response containing metadata about a path.
+///
+/// When the path doesn't exist, `exists` is `false` and the other fields are unset.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_STAT_METHOD_NAME))]
+#[serde(rename_all = "camelCase")]
+pub struct StatResponse {
+    /// Whether a file or directory exists at the path.
+    pub exists: bool,
+    /// Whether the path is a directory.
+    #[serde(default)]
+    pub is_dir: bool,
+    /// Size of the file in bytes, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// When the file was last modified, as an ISO 8601 timestamp, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
 }
 
-// Write text file
+// Search files
 
-/// Request to write content to a text file.
+/// Request to find files matching a glob pattern.
 ///
-/// Only available if the client supports the `fs.writeTextFile` capability.
+/// Only available if the client supports the `fs.search` capability.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[schemars(extend("x-side" = "client", "x-method" = FS_WRITE_TEXT_FILE_METHOD_NAME))]
+#[schemars(extend("x-side" = "client", "x-method" = FS_SEARCH_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
-pub struct WriteTextFileRequest {
+pub struct SearchFilesRequest {
     /// The session ID for this request.
     pub session_id: SessionId,
-    /// Absolute path to the file to write.
-    pub path: PathBuf,
-    /// The text content to write to the file.
-    pub content: String,
+    /// Absolute path to the directory to search in.
+    pub root: PathBuf,
+    /// The glob pattern to match, relative to `root` (e.g. `src/**/*.rs`).
+    pub glob: String,
+    /// Maximum number of paths to return.
+    ///
+    /// When omitted, the client may still apply a limit of its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<u32>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
-/// Response to `fs/write_text_file`
-#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+/// Response containing the files that matched a glob pattern.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(extend("x-side" = "client", "x-method" = FS_SEARCH_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
-#[schemars(extend("x-side" = "client", "x-method" = FS_WRITE_TEXT_FILE_METHOD_NAME))]
-#[serde(default)]
-pub struct WriteTextFileResponse {
+pub struct SearchFilesResponse {
+    /// Absolute paths of the matching files.
+    ///
+    /// Files excluded by the client's ignore configuration are never included.
+    pub paths: Vec<PathBuf>,
+    /// Whether more files matched than were returned.
+    #[serde(default)]
+    pub truncated: bool,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
-// Read text file
+// Grep
 
-/// Request to read content from a text file.
+/// Request to search the contents of files.
 ///
-/// Only available if the client supports the `fs.readTextFile` capability.
+/// Only available if the client supports the `fs.grep` capability.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[schemars(extend("x-side" = "client", "x-method" = FS_READ_TEXT_FILE_METHOD_NAME))]
+#[schemars(extend("x-side" = "client", "x-method" = FS_GREP_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
-pub struct ReadTextFileRequest {
+pub struct GrepRequest {
     /// The session ID for this request.
     pub session_id: SessionId,
-    /// Absolute path to the file to read.
-    pub path: PathBuf,
-    /// Line number to start reading from (1-based).
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub line: Option<u32>,
-    /// Maximum number of lines to read.
+    /// The text or regular expression to search for.
+    pub query: String,
+    /// Whether `query` is a regular expression rather than literal text.
+    ///
+    /// Clients should accept the regular expression syntax of ripgrep.
+    #[serde(default)]
+    pub is_regex: bool,
+    /// Absolute path to the directory to search in.
+    pub root: PathBuf,
+    /// Maximum number of matches to return.
+    ///
+    /// When omitted, the client may still apply a limit of its own.
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub limit: Option<u32>,
+    pub max_results: Option<u32>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
-/// Response containing the contents of a text file.
+/// Response containing the matches of a content search.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-#[schemars(extend("x-side" = "client", "x-method" = FS_READ_TEXT_FILE_METHOD_NAME))]
+#[schemars(extend("x-side" = "client", "x-method" = FS_GREP_METHOD_NAME))]
 #[serde(rename_all = "camelCase")]
-pub struct ReadTextFileResponse {
-    pub content: String,
+pub struct GrepResponse {
+    /// The matches found, at most one per line.
+    pub matches: Vec<GrepMatch>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// A line that matched a content search.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GrepMatch {
+    /// Absolute path to the file containing the match.
+    pub path: PathBuf,
+    /// The line of the match, 1-based.
+    pub line: u32,
+    /// The column the first match on the line starts at, 1-based and counted in
+    /// UTF-16 code units.
+    pub column: u32,
+    /// The full text of the matching line, without its line ending.
+    pub line_text: String,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
+impl GrepMatch {
+    /// The location of the match, for reporting it on a tool call.
+    #[must_use]
+    pub fn location(&self) -> ToolCallLocation {
+        ToolCallLocation {
+            line: Some(self.line),
+            column: Some(self.column),
+            ..ToolCallLocation::from(self.path.clone())
+        }
+    }
+}
+
 // Terminals
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
@@ -579,13 +1924,37 @@ pub struct CreateTerminalRequest {
     /// Maximum number of output bytes to retain.
     ///
     /// When the limit is exceeded, the Client truncates from the beginning of the output
-    /// to stay within the limit.
+    /// to stay within the limit, keeping the most recent output, and reports `truncated`
+    /// in the `terminal/output` response.
     ///
     /// The Client MUST ensure truncation happens at a character boundary to maintain valid
     /// string output, even if this means the retained output is slightly less than the
     /// specified limit.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub output_byte_limit: Option<u64>,
+    /// Initial width of the terminal in columns.
+    ///
+    /// When omitted, the Client chooses a default size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cols: Option<u16>,
+    /// Initial height of the terminal in rows.
+    ///
+    /// When omitted, the Client chooses a default size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u16>,
+    /// Whether to also capture stdout and stderr separately.
+    ///
+    /// When `true`, the Client includes `stdout` and `stderr` in `terminal/output`
+    /// responses alongside the merged `output`. Clients that can't tell the streams
+    /// apart, such as those running the command in a pseudo-terminal, omit them.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub separate_streams: bool,
+    /// Whether to send a `terminal/exited` notification when the command exits.
+    ///
+    /// Only honored by Clients that support the `terminalExitNotification`
+    /// capability. `terminal/wait_for_exit` keeps working either way.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub notify_on_exit: bool,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -625,7 +1994,25 @@ pub struct TerminalOutputResponse {
     /// The terminal output captured so far.
     pub output: String,
     /// Whether the output was truncated due to byte limits.
+    ///
+    /// When `true`, `output` holds the end of the output, and earlier output was dropped.
     pub truncated: bool,
+    /// The total number of bytes the command has written so far, including any that
+    /// were dropped due to the byte limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// The output the command has written to stdout.
+    ///
+    /// Only present if the terminal was created with `separateStreams` and the
+    /// Client supports it. Subject to the same `outputByteLimit` as `output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    /// The output the command has written to stderr.
+    ///
+    /// Only present if the terminal was created with `separateStreams` and the
+    /// Client supports it. Subject to the same `outputByteLimit` as `output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
     /// Exit status if the command has completed.
     pub exit_status: Option<TerminalExitStatus>,
     /// Extension point for implementations
@@ -721,6 +2108,60 @@ pub struct TerminalExitStatus {
     pub meta: Option<serde_json::Value>,
 }
 
+/// Request to write input to a terminal's standard input.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = TERMINAL_INPUT_METHOD_NAME))]
+pub struct WriteTerminalInputRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// The ID of the terminal to write to.
+    pub terminal_id: TerminalId,
+    /// The data to write to the terminal's standard input.
+    pub data: String,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response to terminal/input method
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = TERMINAL_INPUT_METHOD_NAME))]
+pub struct WriteTerminalInputResponse {
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Request to resize a terminal.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = TERMINAL_RESIZE_METHOD_NAME))]
+pub struct ResizeTerminalRequest {
+    /// The session ID for this request.
+    pub session_id: SessionId,
+    /// The ID of the terminal to resize.
+    pub terminal_id: TerminalId,
+    /// The new width of the terminal in columns.
+    pub cols: u16,
+    /// The new height of the terminal in rows.
+    pub rows: u16,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
+/// Response to terminal/resize method
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(extend("x-side" = "client", "x-method" = TERMINAL_RESIZE_METHOD_NAME))]
+pub struct ResizeTerminalResponse {
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
 // Capabilities
 
 /// Capabilities supported by the client.
@@ -739,11 +2180,78 @@ pub struct ClientCapabilities {
     /// Whether the Client support all `terminal/*` methods.
     #[serde(default)]
     pub terminal: bool,
+    /// Whether the Client supports `terminal/input` requests.
+    #[serde(default)]
+    pub terminal_input: bool,
+    /// Whether the Client supports `terminal/resize` requests.
+    #[serde(default)]
+    pub terminal_resize: bool,
+    /// Whether the Client sends `terminal/exited` notifications for terminals
+    /// created with `notifyOnExit`.
+    #[serde(default)]
+    pub terminal_exit_notification: bool,
+    /// Whether the Client supports `session/request_user_input` requests.
+    #[serde(default)]
+    pub request_user_input: bool,
+    /// Encodings the Client can decompress messages with, in order of preference.
+    ///
+    /// Only applies to transports that frame messages with a `Content-Length`
+    /// header. See [`ContentEncoding`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<ContentEncoding>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
+/// A compression algorithm for messages exchanged over a connection.
+///
+/// Both sides list the encodings they can decompress in their capabilities. When
+/// they have one in common, the first such encoding in the Client's list is used for
+/// large messages in both directions, and each compressed message carries a
+/// `Content-Encoding` header naming it. Otherwise messages are sent uncompressed.
+///
+/// Only applies to transports that frame messages with a `Content-Length` header,
+/// as newline-delimited messages have no room for headers.
+///
+/// In this crate, connections compress and decompress messages when the
+/// `compression` feature is enabled. Without it, don't advertise any encoding.
+///
+/// See protocol docs: [Compression](https://agentclientprotocol.com/protocol/initialization#compression)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentEncoding {
+    /// The gzip format ([RFC 1952](https://www.rfc-editor.org/rfc/rfc1952)).
+    Gzip,
+    /// The zlib format ([RFC 1950](https://www.rfc-editor.org/rfc/rfc1950)), as used
+    /// by the `deflate` encoding of HTTP.
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The value of the `Content-Encoding` header for this encoding.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+
+    /// Picks the encoding used on a connection, given the encodings each side
+    /// advertised.
+    ///
+    /// Returns the first encoding in the client's list that the agent also supports,
+    /// or `None` if messages should be sent uncompressed.
+    #[must_use]
+    pub fn negotiate(client: &[ContentEncoding], agent: &[ContentEncoding]) -> Option<Self> {
+        client
+            .iter()
+            .find(|encoding| agent.contains(encoding))
+            .copied()
+    }
+}
+
 /// File system capabilities that a client may support.
 ///
 /// See protocol docs: [FileSystem](https://agentclientprotocol.com/protocol/initialization#filesystem)
@@ -756,6 +2264,31 @@ pub struct FileSystemCapability {
     /// Whether the Client supports `fs/write_text_file` requests.
     #[serde(default)]
     pub write_text_file: bool,
+    /// Whether the Client supports `fs/read_text_file_chunk` requests.
+    #[serde(default)]
+    pub read_text_file_chunk: bool,
+    /// Whether the Client supports the `byteOffset` and `byteLimit` parameters of
+    /// `fs/read_text_file` requests.
+    #[serde(default)]
+    pub read_text_file_byte_range: bool,
+    /// Whether the Client supports `fs/list_directory` requests.
+    #[serde(default)]
+    pub list_directory: bool,
+    /// Whether the Client supports `fs/delete_file` requests.
+    #[serde(default)]
+    pub delete_file: bool,
+    /// Whether the Client supports `fs/rename_file` requests.
+    #[serde(default)]
+    pub rename_file: bool,
+    /// Whether the Client supports `fs/stat` requests.
+    #[serde(default)]
+    pub stat: bool,
+    /// Whether the Client supports `fs/search` requests.
+    #[serde(default)]
+    pub search: bool,
+    /// Whether the Client supports `fs/grep` requests.
+    #[serde(default)]
+    pub grep: bool,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -786,6 +2319,26 @@ pub struct ClientMethodNames {
     pub terminal_wait_for_exit: &'static str,
     /// Method for killing a terminal.
     pub terminal_kill: &'static str,
+    /// Method for listing directories.
+    pub fs_list_directory: &'static str,
+    /// Method for deleting files.
+    pub fs_delete_file: &'static str,
+    /// Method for renaming files.
+    pub fs_rename_file: &'static str,
+    /// Method for writing input to a terminal.
+    pub terminal_input: &'static str,
+    /// Method for resizing a terminal.
+    pub terminal_resize: &'static str,
+    /// Method for reading a chunk of a text file.
+    pub fs_read_text_file_chunk: &'static str,
+    /// Method for getting metadata about a path.
+    pub fs_stat: &'static str,
+    /// Method for finding files matching a glob pattern.
+    pub fs_search: &'static str,
+    /// Method for searching the contents of files.
+    pub fs_grep: &'static str,
+    /// Method for requesting input from the user.
+    pub session_request_user_input: &'static str,
 }
 
 /// Constant containing all client method names.
@@ -799,6 +2352,16 @@ pub const CLIENT_METHOD_NAMES: ClientMethodNames = ClientMethodNames {
     terminal_release: TERMINAL_RELEASE_METHOD_NAME,
     terminal_wait_for_exit: TERMINAL_WAIT_FOR_EXIT_METHOD_NAME,
     terminal_kill: TERMINAL_KILL_METHOD_NAME,
+    fs_list_directory: FS_LIST_DIRECTORY_METHOD_NAME,
+    fs_delete_file: FS_DELETE_FILE_METHOD_NAME,
+    fs_rename_file: FS_RENAME_FILE_METHOD_NAME,
+    terminal_input: TERMINAL_INPUT_METHOD_NAME,
+    terminal_resize: TERMINAL_RESIZE_METHOD_NAME,
+    fs_read_text_file_chunk: FS_READ_TEXT_FILE_CHUNK_METHOD_NAME,
+    fs_stat: FS_STAT_METHOD_NAME,
+    fs_search: FS_SEARCH_METHOD_NAME,
+    fs_grep: FS_GREP_METHOD_NAME,
+    session_request_user_input: SESSION_REQUEST_USER_INPUT_METHOD_NAME,
 };
 
 /// Notification name for session updates.
@@ -819,6 +2382,26 @@ pub(crate) const TERMINAL_RELEASE_METHOD_NAME: &str = "terminal/release";
 pub(crate) const TERMINAL_WAIT_FOR_EXIT_METHOD_NAME: &str = "terminal/wait_for_exit";
 /// Method for killing a terminal.
 pub(crate) const TERMINAL_KILL_METHOD_NAME: &str = "terminal/kill";
+/// Method name for listing directories.
+pub(crate) const FS_LIST_DIRECTORY_METHOD_NAME: &str = "fs/list_directory";
+/// Method name for deleting files.
+pub(crate) const FS_DELETE_FILE_METHOD_NAME: &str = "fs/delete_file";
+/// Method name for renaming files.
+pub(crate) const FS_RENAME_FILE_METHOD_NAME: &str = "fs/rename_file";
+/// Method name for writing input to a terminal.
+pub(crate) const TERMINAL_INPUT_METHOD_NAME: &str = "terminal/input";
+/// Method name for resizing a terminal.
+pub(crate) const TERMINAL_RESIZE_METHOD_NAME: &str = "terminal/resize";
+/// Method name for reading a chunk of a text file.
+pub(crate) const FS_READ_TEXT_FILE_CHUNK_METHOD_NAME: &str = "fs/read_text_file_chunk";
+/// Method name for getting metadata about a path.
+pub(crate) const FS_STAT_METHOD_NAME: &str = "fs/stat";
+/// Method name for finding files matching a glob pattern.
+pub(crate) const FS_SEARCH_METHOD_NAME: &str = "fs/search";
+/// Method name for searching the contents of files.
+pub(crate) const FS_GREP_METHOD_NAME: &str = "fs/grep";
+/// Method name for requesting input from the user.
+pub(crate) const SESSION_REQUEST_USER_INPUT_METHOD_NAME: &str = "session/request_user_input";
 
 /// All possible requests that an agent can send to a client.
 ///
@@ -838,6 +2421,16 @@ pub enum AgentRequest {
     ReleaseTerminalRequest(ReleaseTerminalRequest),
     WaitForTerminalExitRequest(WaitForTerminalExitRequest),
     KillTerminalCommandRequest(KillTerminalCommandRequest),
+    ListDirectoryRequest(ListDirectoryRequest),
+    DeleteFileRequest(DeleteFileRequest),
+    RenameFileRequest(RenameFileRequest),
+    WriteTerminalInputRequest(WriteTerminalInputRequest),
+    ResizeTerminalRequest(ResizeTerminalRequest),
+    ReadTextFileChunkRequest(ReadTextFileChunkRequest),
+    StatRequest(StatRequest),
+    SearchFilesRequest(SearchFilesRequest),
+    GrepRequest(GrepRequest),
+    RequestUserInputRequest(RequestUserInputRequest),
     ExtMethodRequest(ExtRequest),
 }
 
@@ -859,6 +2452,16 @@ pub enum ClientResponse {
     ReleaseTerminalResponse(#[serde(default)] ReleaseTerminalResponse),
     WaitForTerminalExitResponse(WaitForTerminalExitResponse),
     KillTerminalResponse(#[serde(default)] KillTerminalCommandResponse),
+    ListDirectoryResponse(ListDirectoryResponse),
+    DeleteFileResponse(#[serde(default)] DeleteFileResponse),
+    RenameFileResponse(#[serde(default)] RenameFileResponse),
+    WriteTerminalInputResponse(#[serde(default)] WriteTerminalInputResponse),
+    ResizeTerminalResponse(#[serde(default)] ResizeTerminalResponse),
+    ReadTextFileChunkResponse(ReadTextFileChunkResponse),
+    StatResponse(StatResponse),
+    SearchFilesResponse(SearchFilesResponse),
+    GrepResponse(GrepResponse),
+    RequestUserInputResponse(RequestUserInputResponse),
     ExtMethodResponse(#[schemars(with = "serde_json::Value")] Arc<RawValue>),
 }
 
@@ -876,3 +2479,804 @@ pub enum AgentNotification {
     SessionNotification(SessionNotification),
     ExtNotification(ExtNotification),
 }
+
+#[cfg(test)]
+mod test_serialization {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_thought_chunk_serialization() {
+        // Plain thought chunks are unchanged.
+        let json = json!({
+            "sessionUpdate": "agent_thought_chunk",
+            "content": { "type": "text", "text": "Let me think." }
+        });
+        let update: SessionUpdate = serde_json::from_value(json.clone()).unwrap();
+        let SessionUpdate::AgentThoughtChunk {
+            signature,
+            redacted,
+            ..
+        } = &update
+        else {
+            panic!("expected a thought chunk, got {update:?}");
+        };
+        assert_eq!((signature.as_deref(), *redacted), (None, false));
+        assert_eq!(serde_json::to_value(&update).unwrap(), json);
+
+        let update = SessionUpdate::AgentThoughtChunk {
+            content: ContentBlock::from("EuYBCkQYAiJA"),
+            signature: Some("sig_123".to_string()),
+            redacted: true,
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "sessionUpdate": "agent_thought_chunk",
+                "content": { "type": "text", "text": "EuYBCkQYAiJA" },
+                "signature": "sig_123",
+                "redacted": true
+            })
+        );
+    }
+
+    #[test]
+    fn test_turn_id_serialization() {
+        let notification = SessionNotification {
+            session_id: SessionId("sess_abc123".into()),
+            update: SessionUpdate::AgentMessageChunk {
+                content: ContentBlock::from("Hi"),
+            },
+            turn_id: Some(TurnId("turn-2".into())),
+            meta: None,
+        };
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(json["turnId"], json!("turn-2"));
+        let deserialized: SessionNotification = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.turn_id, notification.turn_id);
+
+        // Agents that don't track turns omit it.
+        let deserialized: SessionNotification = serde_json::from_value(json!({
+            "sessionId": "sess_abc123",
+            "update": {
+                "sessionUpdate": "agent_message_chunk",
+                "content": { "type": "text", "text": "Hi" }
+            }
+        }))
+        .unwrap();
+        assert_eq!(deserialized.turn_id, None);
+    }
+
+    #[test]
+    fn test_session_closed_serialization() {
+        let update = SessionUpdate::SessionClosed {
+            reason: "The model context expired".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "sessionUpdate": "session_closed",
+                "reason": "The model context expired"
+            })
+        );
+
+        let error = Error::session_closed("sess_abc123");
+        assert_eq!(error.code, -32010);
+        assert_eq!(error.kind(), Some(crate::ErrorKind::SessionClosed));
+        assert_eq!(error.data, Some(json!({"sessionId": "sess_abc123"})));
+    }
+
+    #[test]
+    fn test_progress_update_serialization() {
+        let update = SessionUpdate::Progress {
+            token: ProgressToken("index-1".into()),
+            message: Some("Indexing files".to_string()),
+            percent: Some(42.5),
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "sessionUpdate": "progress",
+                "token": "index-1",
+                "message": "Indexing files",
+                "percent": 42.5
+            })
+        );
+
+        let update: SessionUpdate = serde_json::from_value(json!({
+            "sessionUpdate": "progress",
+            "token": "index-1"
+        }))
+        .unwrap();
+        let SessionUpdate::Progress {
+            token,
+            message,
+            percent,
+        } = &update
+        else {
+            panic!("expected a progress update, got {update:?}");
+        };
+        assert_eq!(token, &ProgressToken("index-1".into()));
+        assert_eq!((message, percent), (&None, &None));
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({"sessionUpdate": "progress", "token": "index-1"})
+        );
+    }
+
+    #[test]
+    fn test_request_permission_context_serialization() {
+        let request = RequestPermissionRequest {
+            session_id: SessionId("sess_abc123".into()),
+            tool_call: ToolCallUpdate::builder(crate::ToolCallId("call_001".into())).build(),
+            options: vec![],
+            context: Some(PermissionContext::Command {
+                command: "cargo".to_string(),
+                args: vec!["test".to_string()],
+                cwd: Some(PathBuf::from("/home/user/project")),
+            }),
+            meta: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "sessionId": "sess_abc123",
+                "toolCall": { "toolCallId": "call_001" },
+                "options": [],
+                "context": {
+                    "type": "command",
+                    "command": "cargo",
+                    "args": ["test"],
+                    "cwd": "/home/user/project"
+                }
+            })
+        );
+
+        let context = PermissionContext::FileEdit {
+            diff: Diff {
+                path: PathBuf::from("/home/user/project/main.rs"),
+                old_text: None,
+                new_text: "fn main() {}".to_string(),
+                meta: None,
+            },
+        };
+        let json = serde_json::to_value(&context).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "type": "file_edit",
+                "path": "/home/user/project/main.rs",
+                "oldText": null,
+                "newText": "fn main() {}"
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<PermissionContext>(json).unwrap(),
+            context
+        );
+
+        let context: PermissionContext = serde_json::from_value(json!({
+            "type": "network_fetch",
+            "urls": ["https://example.com"]
+        }))
+        .unwrap();
+        assert_eq!(
+            context,
+            PermissionContext::NetworkFetch {
+                urls: vec!["https://example.com".to_string()]
+            }
+        );
+
+        let request: RequestPermissionRequest = serde_json::from_value(json!({
+            "sessionId": "sess_abc123",
+            "toolCall": { "toolCallId": "call_001" },
+            "options": []
+        }))
+        .unwrap();
+        assert_eq!(request.context, None);
+    }
+
+    #[test]
+    fn test_list_directory_request_serialization() {
+        let request = ListDirectoryRequest {
+            session_id: SessionId("sess_abc123".into()),
+            path: PathBuf::from("/home/user/project"),
+            recursive: true,
+            meta: None,
+        };
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "sessionId": "sess_abc123",
+                "path": "/home/user/project",
+                "recursive": true
+            })
+        );
+
+        let deserialized: ListDirectoryRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.session_id, request.session_id);
+        assert_eq!(deserialized.path, request.path);
+        assert!(deserialized.recursive);
+
+        let deserialized: ListDirectoryRequest = serde_json::from_value(json!({
+            "sessionId": "sess_abc123",
+            "path": "/home/user/project"
+        }))
+        .unwrap();
+        assert!(!deserialized.recursive);
+    }
+
+    #[test]
+    fn test_list_directory_response_serialization() {
+        let response = ListDirectoryResponse {
+            entries: vec![
+                DirEntry {
+                    name: "main.rs".to_string(),
+                    path: PathBuf::from("/home/user/project/main.rs"),
+                    is_dir: false,
+                    size: Some(1024),
+                    meta: None,
+                },
+                DirEntry {
+                    name: "src".to_string(),
+                    path: PathBuf::from("/home/user/project/src"),
+                    is_dir: true,
+                    size: None,
+                    meta: None,
+                },
+            ],
+            meta: None,
+        };
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "entries": [
+                    {
+                        "name": "main.rs",
+                        "path": "/home/user/project/main.rs",
+                        "isDir": false,
+                        "size": 1024
+                    },
+                    {
+                        "name": "src",
+                        "path": "/home/user/project/src",
+                        "isDir": true
+                    }
+                ]
+            })
+        );
+
+        let deserialized: ListDirectoryResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.entries, response.entries);
+    }
+
+    #[test]
+    fn test_read_text_file_chunk_serialization() {
+        let request: ReadTextFileChunkRequest = serde_json::from_value(json!({
+            "sessionId": "sess_abc123",
+            "path": "/var/log/build.log"
+        }))
+        .unwrap();
+        assert_eq!(request.offset, 0);
+        assert_eq!(request.max_bytes, None);
+
+        let request = ReadTextFileChunkRequest {
+            offset: 4096,
+            max_bytes: Some(1024),
+            ..request
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "sessionId": "sess_abc123",
+                "path": "/var/log/build.log",
+                "offset": 4096,
+                "maxBytes": 1024
+            })
+        );
+
+        let response: ReadTextFileChunkResponse =
+            serde_json::from_value(json!({ "content": "line 1\n" })).unwrap();
+        assert!(!response.eof);
+        assert_eq!(
+            serde_json::to_value(ReadTextFileChunkResponse {
+                content: String::new(),
+                eof: true,
+                meta: None,
+            })
+            .unwrap(),
+            json!({ "content": "", "eof": true })
+        );
+    }
+
+    #[test]
+    fn test_stat_response_serialization() {
+        let response = StatResponse {
+            exists: true,
+            is_dir: false,
+            size: Some(2048),
+            modified: Some("2025-01-15T10:30:00Z".to_string()),
+            meta: None,
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "exists": true,
+                "isDir": false,
+                "size": 2048,
+                "modified": "2025-01-15T10:30:00Z"
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<StatResponse>(json).unwrap(),
+            response
+        );
+
+        let missing: StatResponse = serde_json::from_value(json!({ "exists": false })).unwrap();
+        assert_eq!(missing, StatResponse::default());
+    }
+
+    #[test]
+    fn test_file_system_capability_list_directory() {
+        let capability: FileSystemCapability = serde_json::from_value(json!({
+            "readTextFile": true,
+            "listDirectory": true
+        }))
+        .unwrap();
+        assert!(capability.read_text_file);
+        assert!(!capability.write_text_file);
+        assert!(capability.list_directory);
+
+        let json = serde_json::to_value(&capability).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "readTextFile": true,
+                "writeTextFile": false,
+                "readTextFileChunk": false,
+                "readTextFileByteRange": false,
+                "listDirectory": true,
+                "deleteFile": false,
+                "renameFile": false,
+                "stat": false,
+                "search": false,
+                "grep": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_create_terminal_request_size() {
+        let request: CreateTerminalRequest = serde_json::from_value(json!({
+            "sessionId": "sess_abc123",
+            "command": "top"
+        }))
+        .unwrap();
+        assert_eq!(request.cols, None);
+        assert_eq!(request.rows, None);
+
+        let request = CreateTerminalRequest {
+            cols: Some(80),
+            rows: Some(24),
+            ..request
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "sessionId": "sess_abc123",
+                "command": "top",
+                "cols": 80,
+                "rows": 24
+            })
+        );
+    }
+
+    #[test]
+    fn test_terminal_output_separate_streams() {
+        let request: CreateTerminalRequest = serde_json::from_value(json!({
+            "sessionId": "sess_abc123",
+            "command": "cargo",
+            "separateStreams": true
+        }))
+        .unwrap();
+        assert!(request.separate_streams);
+
+        let response: TerminalOutputResponse = serde_json::from_value(json!({
+            "output": "warning: unused\nok\n",
+            "truncated": false,
+            "exitStatus": null
+        }))
+        .unwrap();
+        assert_eq!(response.stdout, None);
+        assert_eq!(response.stderr, None);
+
+        let response = TerminalOutputResponse {
+            stdout: Some("ok\n".into()),
+            stderr: Some("warning: unused\n".into()),
+            ..response
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "output": "warning: unused\nok\n",
+                "truncated": false,
+                "stdout": "ok\n",
+                "stderr": "warning: unused\n",
+                "exitStatus": null
+            })
+        );
+    }
+
+    #[test]
+    fn test_plan_entry_update_serialization() {
+        let update = SessionUpdate::PlanEntryUpdate(PlanEntryUpdate {
+            index: 1,
+            content: None,
+            priority: None,
+            status: Some(crate::PlanEntryStatus::InProgress),
+            meta: None,
+        });
+
+        let json = serde_json::to_value(&update).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "sessionUpdate": "plan_entry_update",
+                "index": 1,
+                "status": "in_progress"
+            })
+        );
+
+        match serde_json::from_value(json).unwrap() {
+            SessionUpdate::PlanEntryUpdate(update) => {
+                assert_eq!(update.index, 1);
+                assert_eq!(update.status, Some(crate::PlanEntryStatus::InProgress));
+                assert_eq!(update.priority, None);
+            }
+            _ => panic!("Expected PlanEntryUpdate variant"),
+        }
+    }
+
+    #[test]
+    fn test_terminal_output_chunk_serialization() {
+        let update = SessionUpdate::TerminalOutputChunk {
+            terminal_id: TerminalId("term_xyz789".into()),
+            chunk: "All tests passed\n".to_string(),
+            offset: 17,
+        };
+
+        let json = serde_json::to_value(&update).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "sessionUpdate": "terminal_output_chunk",
+                "terminalId": "term_xyz789",
+                "chunk": "All tests passed\n",
+                "offset": 17
+            })
+        );
+
+        match serde_json::from_value(json).unwrap() {
+            SessionUpdate::TerminalOutputChunk {
+                terminal_id,
+                chunk,
+                offset,
+            } => {
+                assert_eq!(terminal_id, TerminalId("term_xyz789".into()));
+                assert_eq!(chunk, "All tests passed\n");
+                assert_eq!(offset, 17);
+            }
+            _ => panic!("Expected TerminalOutputChunk variant"),
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_serialization() {
+        let update = SessionUpdate::Diagnostic {
+            path: PathBuf::from("/home/user/project/src/main.rs"),
+            range: Some(crate::LineRange { start: 12, end: 12 }),
+            severity: DiagnosticSeverity::Error,
+            message: "mismatched types".to_string(),
+            source: Some("rustc".to_string()),
+        };
+
+        let json = serde_json::to_value(&update).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "sessionUpdate": "diagnostic",
+                "path": "/home/user/project/src/main.rs",
+                "range": { "start": 12, "end": 12 },
+                "severity": "error",
+                "message": "mismatched types",
+                "source": "rustc"
+            })
+        );
+
+        match serde_json::from_value(json!({
+            "sessionUpdate": "diagnostic",
+            "path": "/home/user/project/README.md",
+            "severity": "hint",
+            "message": "Consider adding a usage section"
+        }))
+        .unwrap()
+        {
+            SessionUpdate::Diagnostic {
+                range,
+                severity,
+                source,
+                ..
+            } => {
+                assert_eq!(range, None);
+                assert_eq!(severity, DiagnosticSeverity::Hint);
+                assert_eq!(source, None);
+            }
+            _ => panic!("Expected Diagnostic variant"),
+        }
+    }
+
+    #[test]
+    fn test_suggested_prompts_serialization() {
+        let update = SessionUpdate::SuggestedPrompts {
+            prompts: vec![SuggestedPrompt {
+                label: "Run the tests?".to_string(),
+                prompt_text: "Run the test suite and fix any failures".to_string(),
+                meta: None,
+            }],
+        };
+
+        let json = serde_json::to_value(&update).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "sessionUpdate": "suggested_prompts",
+                "prompts": [
+                    {
+                        "label": "Run the tests?",
+                        "promptText": "Run the test suite and fix any failures"
+                    }
+                ]
+            })
+        );
+
+        match serde_json::from_value(json).unwrap() {
+            SessionUpdate::SuggestedPrompts { prompts } => {
+                assert_eq!(prompts.len(), 1);
+                assert_eq!(prompts[0].label, "Run the tests?");
+            }
+            _ => panic!("Expected SuggestedPrompts variant"),
+        }
+    }
+
+    #[test]
+    fn test_tool_call_output_chunk_serialization() {
+        let json = json!({
+            "sessionUpdate": "tool_call_output_chunk",
+            "toolCallId": "call_001",
+            "chunk": [{ "test": "parses_empty_input", "passed": true }],
+            "append": true
+        });
+        let update: SessionUpdate = serde_json::from_value(json.clone()).unwrap();
+        let SessionUpdate::ToolCallOutputChunk {
+            tool_call_id,
+            chunk,
+            append,
+        } = &update
+        else {
+            panic!("expected a tool call output chunk, got {update:?}");
+        };
+        assert_eq!(tool_call_id, &ToolCallId("call_001".into()));
+        assert_eq!(chunk[0]["passed"], true);
+        assert!(append);
+        assert_eq!(serde_json::to_value(&update).unwrap(), json);
+    }
+
+    #[test]
+    fn test_standard_permission_options() {
+        let options = PermissionOption::standard_set();
+        assert_eq!(
+            serde_json::to_value(&options).unwrap(),
+            json!([
+                { "optionId": "allow-once", "name": "Allow once", "kind": "allow_once" },
+                { "optionId": "allow-always", "name": "Always allow", "kind": "allow_always" },
+                { "optionId": "reject-once", "name": "Reject once", "kind": "reject_once" },
+                { "optionId": "reject-always", "name": "Always reject", "kind": "reject_always" },
+            ])
+        );
+
+        // Translated labels keep the standard IDs.
+        let options = PermissionOption::builder()
+            .allow_once("Einmal erlauben")
+            .reject_once("Ablehnen")
+            .option(PermissionOption::new(
+                "allow-session",
+                "Allow for this session",
+                PermissionOptionKind::AllowAlways,
+            ))
+            .build();
+        assert_eq!(
+            options
+                .iter()
+                .map(|option| (option.id.0.as_ref(), option.name.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("allow-once", "Einmal erlauben"),
+                ("reject-once", "Ablehnen"),
+                ("allow-session", "Allow for this session"),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+
+    fn command(name: &str, input: Option<AvailableCommandInput>) -> AvailableCommand {
+        AvailableCommand {
+            name: name.to_string(),
+            description: String::new(),
+            input,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_command_input_deserialization() {
+        let input: AvailableCommandInput =
+            serde_json::from_value(serde_json::json!({ "hint": "query to search for" })).unwrap();
+        assert!(
+            matches!(input, AvailableCommandInput::Unstructured { hint } if hint == "query to search for")
+        );
+
+        let input: AvailableCommandInput = serde_json::from_value(serde_json::json!({
+            "options": [{ "value": "fast", "description": "Fewer checks" }, { "value": "thorough" }]
+        }))
+        .unwrap();
+        let AvailableCommandInput::Choice { options } = input else {
+            panic!("expected a choice input");
+        };
+        assert_eq!(options.len(), 2);
+        assert_eq!(options[0].description.as_deref(), Some("Fewer checks"));
+        assert_eq!(options[1].value, "thorough");
+    }
+
+    #[test]
+    fn test_command_invocation() {
+        let web = command("web", None);
+        assert_eq!(web.invocation(&["/web".into()]), Some(""));
+        assert_eq!(
+            web.invocation(&["/web  agent client protocol ".into()]),
+            Some("agent client protocol")
+        );
+        assert_eq!(web.invocation(&["/website".into()]), None);
+        assert_eq!(web.invocation(&["search the /web".into()]), None);
+        assert_eq!(web.invocation(&[]), None);
+    }
+
+    #[test]
+    fn test_validate_command_arguments() {
+        assert!(command("test", None).validate_arguments(" ").is_ok());
+        assert!(command("test", None).validate_arguments("all").is_err());
+
+        let plan = command(
+            "plan",
+            Some(AvailableCommandInput::Unstructured {
+                hint: "what to plan".to_string(),
+            }),
+        );
+        assert!(plan.validate_arguments("anything at all").is_ok());
+
+        let mode = command(
+            "mode",
+            Some(AvailableCommandInput::Choice {
+                options: ["fast", "thorough"]
+                    .map(|value| CommandInputOption {
+                        value: value.to_string(),
+                        description: None,
+                        meta: None,
+                    })
+                    .to_vec(),
+            }),
+        );
+        assert!(mode.validate_arguments("thorough").is_ok());
+        let error = mode.validate_arguments("slow").unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+    }
+
+    #[test]
+    fn test_read_text_file_byte_range() {
+        let request: ReadTextFileRequest = serde_json::from_value(serde_json::json!({
+            "sessionId": "sess_abc123",
+            "path": "/var/log/build.log",
+            "byteOffset": 4096,
+            "byteLimit": 1024
+        }))
+        .unwrap();
+        assert!(request.is_byte_range());
+        assert!(request.validate().is_ok());
+
+        let error = ReadTextFileRequest {
+            line: Some(10),
+            ..request
+        }
+        .validate()
+        .unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+
+        // "é" takes two bytes, so the first read stops before it.
+        let text = "abé\n";
+        let response = ReadTextFileResponse::from_byte_range(text, 0, Some(3)).unwrap();
+        assert_eq!(response.content, "ab");
+        assert_eq!(response.next_byte_offset, Some(2));
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({ "content": "ab", "nextByteOffset": 2 })
+        );
+
+        // A limit smaller than the next character still returns it.
+        let response = ReadTextFileResponse::from_byte_range(text, 2, Some(1)).unwrap();
+        assert_eq!(response.content, "é");
+        assert_eq!(response.next_byte_offset, Some(4));
+
+        let response = ReadTextFileResponse::from_byte_range(text, 4, None).unwrap();
+        assert_eq!(response.content, "\n");
+        assert_eq!(response.next_byte_offset, None);
+
+        let response = ReadTextFileResponse::from_byte_range(text, 100, Some(10)).unwrap();
+        assert_eq!(response.content, "");
+        assert_eq!(response.next_byte_offset, None);
+
+        let error = ReadTextFileResponse::from_byte_range(text, 3, None).unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+    }
+
+    #[test]
+    fn test_permission_option_ids() {
+        let options = PermissionOption::builder()
+            .allow_once("Allow once")
+            .reject_once("Reject")
+            .option(PermissionOption::new(
+                "allow-edits",
+                "Allow all edits",
+                PermissionOptionKind::AllowAlways,
+            ))
+            .build();
+        assert_eq!(
+            serde_json::to_value(&options).unwrap(),
+            serde_json::json!([
+                { "optionId": "allow-once", "name": "Allow once", "kind": "allow_once" },
+                { "optionId": "reject-once", "name": "Reject", "kind": "reject_once" },
+                { "optionId": "allow-edits", "name": "Allow all edits", "kind": "allow_always" }
+            ])
+        );
+
+        let ids = PermissionOption::standard_set()
+            .into_iter()
+            .map(|option| (option.id.to_string(), option.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            [
+                ("allow-once".to_string(), PermissionOptionKind::AllowOnce),
+                (
+                    "allow-always".to_string(),
+                    PermissionOptionKind::AllowAlways
+                ),
+                ("reject-once".to_string(), PermissionOptionKind::RejectOnce),
+                (
+                    "reject-always".to_string(),
+                    PermissionOptionKind::RejectAlways
+                ),
+            ]
+        );
+    }
+}
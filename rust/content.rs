@@ -12,6 +12,8 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::{Error, ResourceUri};
+
 /// Content blocks represent displayable information in the Agent Client Protocol.
 ///
 /// They provide a structured way to handle various types of user-facing content—whether
@@ -64,14 +66,272 @@ pub struct TextContent {
     pub meta: Option<serde_json::Value>,
 }
 
-impl<T: Into<String>> From<T> for ContentBlock {
-    fn from(value: T) -> Self {
+impl ContentBlock {
+    /// Creates a text content block without annotations.
+    pub fn text(text: impl Into<String>) -> Self {
         Self::Text(TextContent {
             annotations: None,
-            text: value.into(),
+            text: text.into(),
+            meta: None,
+        })
+    }
+
+    /// Creates an image content block from base64-encoded `data` and its MIME type.
+    pub fn image(data: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        Self::Image(ImageContent {
+            annotations: None,
+            data: data.into(),
+            mime_type: mime_type.into(),
+            uri: None,
             meta: None,
         })
     }
+
+    /// Creates a link to the resource at `uri`, with no optional metadata.
+    pub fn resource_link(uri: impl Into<ResourceUri>, name: impl Into<String>) -> Self {
+        Self::ResourceLink(ResourceLink {
+            annotations: None,
+            description: None,
+            mime_type: None,
+            name: name.into(),
+            size: None,
+            title: None,
+            uri: uri.into(),
+            meta: None,
+        })
+    }
+
+    /// Creates a content block embedding the given resource contents.
+    pub fn resource(resource: impl Into<EmbeddedResourceResource>) -> Self {
+        Self::Resource(EmbeddedResource {
+            annotations: None,
+            resource: resource.into(),
+            summary: None,
+            meta: None,
+        })
+    }
+
+    /// The annotations of this block, if any.
+    #[must_use]
+    pub fn annotations(&self) -> Option<&Annotations> {
+        match self {
+            Self::Text(text) => text.annotations.as_ref(),
+            Self::Image(image) => image.annotations.as_ref(),
+            Self::Audio(audio) => audio.annotations.as_ref(),
+            Self::ResourceLink(link) => link.annotations.as_ref(),
+            Self::Resource(resource) => resource.annotations.as_ref(),
+        }
+    }
+
+    /// Returns `true` if this block is meant for `role`, according to the
+    /// `audience` of its annotations.
+    ///
+    /// Blocks without annotations, or whose audience is missing or empty, are
+    /// meant for everyone.
+    #[must_use]
+    pub fn is_for(&self, role: Role) -> bool {
+        self.annotations()
+            .and_then(|annotations| annotations.audience.as_deref())
+            .is_none_or(|audience| audience.is_empty() || audience.contains(&role))
+    }
+
+    /// Converts content from an MCP tool result into content blocks.
+    ///
+    /// Accepts either a whole `CallToolResult` or just its `content` array, as
+    /// returned by an MCP server. MCP and ACP share the same content shapes, so
+    /// text, image, audio, resource link, and embedded resource blocks convert
+    /// without loss, keeping their MIME types, annotations, and `_meta`. The
+    /// blocks can be forwarded as [`ToolCallContent`](crate::ToolCallContent)
+    /// through its `From<ContentBlock>` conversion.
+    ///
+    /// Returns an [`Error::invalid_params`] error if a block has a type ACP
+    /// doesn't support or is missing required fields.
+    pub fn from_mcp_content(content: serde_json::Value) -> Result<Vec<Self>, Error> {
+        let content = match content {
+            serde_json::Value::Object(mut result) if result.contains_key("content") => {
+                result.remove("content").unwrap_or_default()
+            }
+            content => content,
+        };
+        Ok(serde_json::from_value(content)?)
+    }
+
+    /// Checks that binary data in this block is valid base64 and that MIME types
+    /// are syntactically valid.
+    ///
+    /// Deserialization is deliberately lenient and doesn't perform these checks, so
+    /// implementations that want to reject malformed content at the boundary can
+    /// call this on incoming blocks.
+    ///
+    /// Returns an [`Error::invalid_params`] error naming the offending field in its
+    /// `data` on failure.
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Text(_) | Self::ResourceLink(_) => Ok(()),
+            Self::Image(image) => {
+                validate_base64("data", &image.data)?;
+                validate_mime_type("mimeType", &image.mime_type)
+            }
+            Self::Audio(audio) => {
+                validate_base64("data", &audio.data)?;
+                validate_mime_type("mimeType", &audio.mime_type)
+            }
+            Self::Resource(resource) => match &resource.resource {
+                EmbeddedResourceResource::TextResourceContents(text) => {
+                    if let Some(mime_type) = &text.mime_type {
+                        validate_mime_type("resource.mimeType", mime_type)?;
+                    }
+                    Ok(())
+                }
+                EmbeddedResourceResource::BlobResourceContents(blob) => {
+                    validate_base64("resource.blob", &blob.blob)?;
+                    if let Some(mime_type) = &blob.mime_type {
+                        validate_mime_type("resource.mimeType", mime_type)?;
+                    }
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+fn invalid_field(field: &str, message: &str) -> Error {
+    Error::invalid_params().with_data(serde_json::json!({
+        "field": field,
+        "message": message,
+    }))
+}
+
+/// Checks `value` is standard, padded or unpadded, base64.
+fn validate_base64(field: &str, value: &str) -> Result<(), Error> {
+    let unpadded = value.trim_end_matches('=');
+    let padding = value.len() - unpadded.len();
+    let valid = padding <= 2
+        && unpadded
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+        && if padding > 0 {
+            value.len() % 4 == 0
+        } else {
+            value.len() % 4 != 1
+        };
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid_field(field, "invalid base64 data"))
+    }
+}
+
+/// Checks `value` is a `type/subtype` MIME type, optionally followed by
+/// `;name=value` parameters.
+fn validate_mime_type(field: &str, value: &str) -> Result<(), Error> {
+    fn is_token(s: &str) -> bool {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+    }
+
+    fn is_parameter(s: &str) -> bool {
+        let Some((name, value)) = s.trim().split_once('=') else {
+            return false;
+        };
+        is_token(name)
+            && (is_token(value)
+                || (value.len() >= 2 && value.starts_with('"') && value.ends_with('"')))
+    }
+
+    let mut parts = value.split(';');
+    let essence = parts.next().unwrap_or_default().trim();
+    let valid = essence
+        .split_once('/')
+        .is_some_and(|(type_, subtype)| is_token(type_) && is_token(subtype))
+        && parts.all(is_parameter);
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid_field(field, "invalid MIME type"))
+    }
+}
+
+/// Splits `text` into text blocks of at most `max_chars` characters, for
+/// streaming a complete message as a series of
+/// [`SessionUpdate::AgentMessageChunk`](crate::SessionUpdate::AgentMessageChunk)
+/// updates.
+///
+/// Chunks end at the last sentence boundary within the limit, if it isn't too
+/// early, or otherwise at the last word boundary. Whitespace starts the next
+/// chunk, as tokens from a streaming model do. A word longer than `max_chars` is
+/// split between characters. Concatenating the chunks always yields `text`.
+///
+/// # Panics
+///
+/// Panics if `max_chars` is zero.
+pub fn chunk_text(text: &str, max_chars: usize) -> impl Iterator<Item = ContentBlock> + '_ {
+    assert!(max_chars > 0, "max_chars must be greater than zero");
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (chunk, remainder) = rest.split_at(chunk_len(rest, max_chars));
+        rest = remainder;
+        Some(ContentBlock::text(chunk))
+    })
+}
+
+/// The length in bytes of the next chunk of `text`.
+fn chunk_len(text: &str, max_chars: usize) -> usize {
+    let Some((limit, _)) = text.char_indices().nth(max_chars) else {
+        return text.len();
+    };
+
+    let mut word_end = None;
+    let mut sentence_end = None;
+    let mut previous = None;
+    // A chunk may end right before the character at `limit`, so it's included.
+    for (ix, c) in text.char_indices().take_while(|(ix, _)| *ix <= limit) {
+        if c.is_whitespace() && previous.is_some_and(|previous: char| !previous.is_whitespace()) {
+            word_end = Some(ix);
+            if c == '\n' || matches!(previous, Some('.' | '!' | '?')) {
+                sentence_end = Some(ix);
+            }
+        }
+        previous = Some(c);
+    }
+    sentence_end
+        .filter(|end| *end >= limit / 2)
+        .or(word_end)
+        .unwrap_or(limit)
+}
+
+/// Returns the blocks meant for `role`, as decided by [`ContentBlock::is_for`].
+///
+/// Clients can use this to hide content meant only for the model from the user,
+/// such as context attached to a tool result, or to leave content meant only for
+/// the user out of what they send to the agent.
+pub fn filter_for_audience(
+    blocks: &[ContentBlock],
+    role: Role,
+) -> impl Iterator<Item = &ContentBlock> {
+    blocks.iter().filter(move |block| block.is_for(role))
+}
+
+/// Converts a string into a [`ContentBlock::Text`] without annotations.
+///
+/// Equivalent to [`ContentBlock::text`].
+impl From<String> for ContentBlock {
+    fn from(value: String) -> Self {
+        Self::text(value)
+    }
+}
+
+/// Converts a string slice into a [`ContentBlock::Text`] without annotations.
+///
+/// Equivalent to [`ContentBlock::text`].
+impl From<&str> for ContentBlock {
+    fn from(value: &str) -> Self {
+        Self::text(value)
+    }
 }
 
 /// An image provided to or from an LLM.
@@ -108,11 +368,68 @@ pub struct EmbeddedResource {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
     pub resource: EmbeddedResourceResource,
+    /// The size of the resource, as computed by the sender.
+    ///
+    /// Lets clients render a collapsed preview of large resources without
+    /// scanning their contents. See [`EmbeddedResource::summary`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ResourceSummary>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
+impl EmbeddedResource {
+    /// The size of the resource, for displaying it collapsed.
+    ///
+    /// Returns the sender's [`summary`](Self::summary) if it included one, and
+    /// otherwise computes it from the contents. Binary resources only report their
+    /// decoded length in bytes.
+    #[must_use]
+    pub fn summary(&self) -> ResourceSummary {
+        if let Some(summary) = &self.summary {
+            return summary.clone();
+        }
+        match &self.resource {
+            EmbeddedResourceResource::TextResourceContents(text) => ResourceSummary {
+                byte_len: text.text.len() as u64,
+                line_count: Some(text.text.lines().count() as u64),
+                truncated: false,
+            },
+            EmbeddedResourceResource::BlobResourceContents(blob) => ResourceSummary {
+                byte_len: base64_decoded_len(&blob.blob),
+                line_count: None,
+                truncated: false,
+            },
+        }
+    }
+}
+
+/// The size of an embedded resource.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSummary {
+    /// The length of the full resource in bytes, after decoding for binary
+    /// resources.
+    pub byte_len: u64,
+    /// The number of lines in the full resource. Not set for binary resources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_count: Option<u64>,
+    /// Whether the embedded contents are only a prefix of the full resource.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub truncated: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// The number of bytes encoded by `value`, which is assumed to be base64.
+fn base64_decoded_len(value: &str) -> u64 {
+    let unpadded = value.trim_end_matches('=').len() as u64;
+    unpadded * 3 / 4
+}
+
 /// Resource content that can be embedded in a message.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 #[serde(untagged)]
@@ -121,13 +438,25 @@ pub enum EmbeddedResourceResource {
     BlobResourceContents(BlobResourceContents),
 }
 
+impl From<TextResourceContents> for EmbeddedResourceResource {
+    fn from(value: TextResourceContents) -> Self {
+        Self::TextResourceContents(value)
+    }
+}
+
+impl From<BlobResourceContents> for EmbeddedResourceResource {
+    fn from(value: BlobResourceContents) -> Self {
+        Self::BlobResourceContents(value)
+    }
+}
+
 /// Text-based resource contents.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct TextResourceContents {
     #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
     pub text: String,
-    pub uri: String,
+    pub uri: ResourceUri,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -139,7 +468,7 @@ pub struct BlobResourceContents {
     pub blob: String,
     #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
-    pub uri: String,
+    pub uri: ResourceUri,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -159,7 +488,7 @@ pub struct ResourceLink {
     pub size: Option<i64>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
-    pub uri: String,
+    pub uri: ResourceUri,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
@@ -184,10 +513,521 @@ pub struct Annotations {
 }
 
 /// The sender or recipient of messages and data in a conversation.
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 pub enum Role {
     #[serde(rename = "assistant")]
     Assistant,
     #[serde(rename = "user")]
     User,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn assert_round_trip(block: ContentBlock, manual: ContentBlock, expected: serde_json::Value) {
+        let json = serde_json::to_value(&block).unwrap();
+        assert_eq!(json, serde_json::to_value(&manual).unwrap());
+        assert_eq!(json, expected);
+        assert_eq!(
+            serde_json::from_value::<ContentBlock>(json).unwrap(),
+            manual
+        );
+    }
+
+    #[test]
+    fn test_text_constructor() {
+        let manual = ContentBlock::Text(TextContent {
+            annotations: None,
+            text: "hi".to_string(),
+            meta: None,
+        });
+        assert_round_trip(
+            ContentBlock::text("hi"),
+            manual.clone(),
+            json!({"type": "text", "text": "hi"}),
+        );
+        assert_eq!(ContentBlock::from("hi"), manual);
+        assert_eq!(ContentBlock::from("hi".to_string()), manual);
+    }
+
+    #[test]
+    fn test_image_constructor() {
+        assert_round_trip(
+            ContentBlock::image("iVBORw0KGgo=", "image/png"),
+            ContentBlock::Image(ImageContent {
+                annotations: None,
+                data: "iVBORw0KGgo=".to_string(),
+                mime_type: "image/png".to_string(),
+                uri: None,
+                meta: None,
+            }),
+            json!({"type": "image", "data": "iVBORw0KGgo=", "mimeType": "image/png"}),
+        );
+    }
+
+    #[test]
+    fn test_resource_link_constructor() {
+        assert_round_trip(
+            ContentBlock::resource_link("file:///home/user/main.rs", "main.rs"),
+            ContentBlock::ResourceLink(ResourceLink {
+                annotations: None,
+                description: None,
+                mime_type: None,
+                name: "main.rs".to_string(),
+                size: None,
+                title: None,
+                uri: "file:///home/user/main.rs".into(),
+                meta: None,
+            }),
+            json!({
+                "type": "resource_link",
+                "name": "main.rs",
+                "uri": "file:///home/user/main.rs"
+            }),
+        );
+    }
+
+    #[test]
+    fn test_resource_constructor() {
+        let contents = TextResourceContents {
+            mime_type: Some("text/x-rust".to_string()),
+            text: "fn main() {}".to_string(),
+            uri: "file:///home/user/main.rs".into(),
+            meta: None,
+        };
+        assert_round_trip(
+            ContentBlock::resource(contents.clone()),
+            ContentBlock::Resource(EmbeddedResource {
+                annotations: None,
+                resource: EmbeddedResourceResource::TextResourceContents(contents),
+                summary: None,
+                meta: None,
+            }),
+            json!({
+                "type": "resource",
+                "resource": {
+                    "mimeType": "text/x-rust",
+                    "text": "fn main() {}",
+                    "uri": "file:///home/user/main.rs"
+                }
+            }),
+        );
+    }
+
+    #[test]
+    fn test_validate_accepts_well_formed_content() {
+        for block in [
+            ContentBlock::text("not base64!"),
+            ContentBlock::image("iVBORw0KGgo=", "image/png"),
+            ContentBlock::image("iVBORw0KGgo", "image/svg+xml"),
+            ContentBlock::Audio(AudioContent {
+                annotations: None,
+                data: "UklGRg==".to_string(),
+                mime_type: "audio/wav; codecs=1".to_string(),
+                meta: None,
+            }),
+            ContentBlock::resource(BlobResourceContents {
+                blob: "".to_string(),
+                mime_type: Some("application/octet-stream".to_string()),
+                uri: "file:///data.bin".into(),
+                meta: None,
+            }),
+        ] {
+            assert!(block.validate().is_ok(), "{block:?}");
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_malformed_content() {
+        let cases = [
+            (ContentBlock::image("not base64!", "image/png"), "data"),
+            (ContentBlock::image("iVBORw0KGgo==", "image/png"), "data"),
+            (ContentBlock::image("iVBORw0KG", "image/png"), "data"),
+            (ContentBlock::image("iVBORw0KGgo=", "png"), "mimeType"),
+            (ContentBlock::image("iVBORw0KGgo=", "image/"), "mimeType"),
+            (
+                ContentBlock::image("iVBORw0KGgo=", "image/png; x"),
+                "mimeType",
+            ),
+            (
+                ContentBlock::resource(BlobResourceContents {
+                    blob: "%%%".to_string(),
+                    mime_type: None,
+                    uri: "file:///data.bin".into(),
+                    meta: None,
+                }),
+                "resource.blob",
+            ),
+            (
+                ContentBlock::resource(TextResourceContents {
+                    mime_type: Some("text plain".to_string()),
+                    text: "hello".to_string(),
+                    uri: "file:///hello.txt".into(),
+                    meta: None,
+                }),
+                "resource.mimeType",
+            ),
+        ];
+
+        for (block, field) in cases {
+            let error = block.validate().expect_err(field);
+            assert_eq!(error.code, crate::ErrorCode::INVALID_PARAMS.code);
+            assert_eq!(error.data.unwrap()["field"], field);
+        }
+    }
+
+    #[test]
+    fn test_resource_summary() {
+        let text = |text: &str| EmbeddedResource {
+            annotations: None,
+            resource: EmbeddedResourceResource::TextResourceContents(TextResourceContents {
+                mime_type: None,
+                text: text.to_string(),
+                uri: "file:///home/user/main.rs".into(),
+                meta: None,
+            }),
+            summary: None,
+            meta: None,
+        };
+        assert_eq!(
+            text("fn main() {\n}\n").summary(),
+            ResourceSummary {
+                byte_len: 14,
+                line_count: Some(2),
+                truncated: false,
+            }
+        );
+
+        // A summary sent along with the resource takes precedence.
+        let mut truncated = text("fn main() {\n");
+        truncated.summary = Some(ResourceSummary {
+            byte_len: 12_000,
+            line_count: Some(400),
+            truncated: true,
+        });
+        assert_eq!(truncated.summary().line_count, Some(400));
+        assert_eq!(
+            serde_json::to_value(&truncated).unwrap()["summary"],
+            json!({ "byteLen": 12000, "lineCount": 400, "truncated": true })
+        );
+
+        let blob = EmbeddedResource {
+            annotations: None,
+            resource: EmbeddedResourceResource::BlobResourceContents(BlobResourceContents {
+                blob: "aGVsbG8=".to_string(),
+                mime_type: None,
+                uri: "file:///home/user/hello.bin".into(),
+                meta: None,
+            }),
+            summary: None,
+            meta: None,
+        };
+        assert_eq!(
+            blob.summary(),
+            ResourceSummary {
+                byte_len: 5,
+                line_count: None,
+                truncated: false,
+            }
+        );
+    }
+
+    fn mcp_annotations() -> serde_json::Value {
+        json!({ "audience": ["user"], "priority": 0.5, "lastModified": "2025-01-12T15:00:58Z" })
+    }
+
+    fn assert_annotations(annotations: Option<&Annotations>) {
+        assert_eq!(
+            annotations,
+            Some(&Annotations {
+                audience: Some(vec![Role::User]),
+                last_modified: Some("2025-01-12T15:00:58Z".to_string()),
+                priority: Some(0.5),
+                meta: None,
+            })
+        );
+    }
+
+    fn from_mcp(block: serde_json::Value) -> ContentBlock {
+        let mut blocks = ContentBlock::from_mcp_content(json!([block])).unwrap();
+        assert_eq!(blocks.len(), 1);
+        blocks.remove(0)
+    }
+
+    #[test]
+    fn test_from_mcp_text() {
+        let ContentBlock::Text(text) = from_mcp(json!({
+            "type": "text",
+            "text": "3 files changed",
+            "annotations": mcp_annotations(),
+        })) else {
+            panic!("expected text");
+        };
+        assert_eq!(text.text, "3 files changed");
+        assert_annotations(text.annotations.as_ref());
+    }
+
+    #[test]
+    fn test_from_mcp_image() {
+        let ContentBlock::Image(image) = from_mcp(json!({
+            "type": "image",
+            "data": "iVBORw0KGgo=",
+            "mimeType": "image/png",
+            "annotations": mcp_annotations(),
+        })) else {
+            panic!("expected image");
+        };
+        assert_eq!(image.data, "iVBORw0KGgo=");
+        assert_eq!(image.mime_type, "image/png");
+        assert_annotations(image.annotations.as_ref());
+    }
+
+    #[test]
+    fn test_from_mcp_audio() {
+        let ContentBlock::Audio(audio) = from_mcp(json!({
+            "type": "audio",
+            "data": "UklGRg==",
+            "mimeType": "audio/wav",
+            "annotations": mcp_annotations(),
+        })) else {
+            panic!("expected audio");
+        };
+        assert_eq!(audio.data, "UklGRg==");
+        assert_eq!(audio.mime_type, "audio/wav");
+        assert_annotations(audio.annotations.as_ref());
+    }
+
+    #[test]
+    fn test_from_mcp_resource_link() {
+        let ContentBlock::ResourceLink(link) = from_mcp(json!({
+            "type": "resource_link",
+            "uri": "file:///project/src/main.rs",
+            "name": "main.rs",
+            "description": "Primary entry point",
+            "mimeType": "text/x-rust",
+            "size": 1024,
+            "annotations": mcp_annotations(),
+        })) else {
+            panic!("expected resource link");
+        };
+        assert_eq!(link.uri, "file:///project/src/main.rs");
+        assert_eq!(link.name, "main.rs");
+        assert_eq!(link.description.as_deref(), Some("Primary entry point"));
+        assert_eq!(link.mime_type.as_deref(), Some("text/x-rust"));
+        assert_eq!(link.size, Some(1024));
+        assert_annotations(link.annotations.as_ref());
+    }
+
+    #[test]
+    fn test_from_mcp_embedded_resource() {
+        let ContentBlock::Resource(text) = from_mcp(json!({
+            "type": "resource",
+            "resource": {
+                "uri": "file:///project/README.md",
+                "mimeType": "text/markdown",
+                "text": "# Project",
+            },
+            "annotations": mcp_annotations(),
+        })) else {
+            panic!("expected resource");
+        };
+        assert_eq!(
+            text.resource,
+            EmbeddedResourceResource::TextResourceContents(TextResourceContents {
+                mime_type: Some("text/markdown".to_string()),
+                text: "# Project".to_string(),
+                uri: "file:///project/README.md".into(),
+                meta: None,
+            })
+        );
+        assert_annotations(text.annotations.as_ref());
+
+        let ContentBlock::Resource(blob) = from_mcp(json!({
+            "type": "resource",
+            "resource": {
+                "uri": "file:///project/logo.png",
+                "mimeType": "image/png",
+                "blob": "iVBORw0KGgo=",
+            },
+        })) else {
+            panic!("expected resource");
+        };
+        assert_eq!(
+            blob.resource,
+            EmbeddedResourceResource::BlobResourceContents(BlobResourceContents {
+                blob: "iVBORw0KGgo=".to_string(),
+                mime_type: Some("image/png".to_string()),
+                uri: "file:///project/logo.png".into(),
+                meta: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_mcp_tool_result() {
+        let blocks = ContentBlock::from_mcp_content(json!({
+            "content": [
+                { "type": "text", "text": "Found 2 matches" },
+                { "type": "resource_link", "uri": "file:///project/lib.rs", "name": "lib.rs" },
+            ],
+            "structuredContent": { "matches": 2 },
+            "isError": false,
+        }))
+        .unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                ContentBlock::text("Found 2 matches"),
+                ContentBlock::resource_link("file:///project/lib.rs", "lib.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_mcp_unsupported_type() {
+        let error =
+            ContentBlock::from_mcp_content(json!([{ "type": "video", "data": "" }])).unwrap_err();
+        assert_eq!(error.code, crate::ErrorCode::INVALID_PARAMS.code);
+    }
+
+    fn chunks(text: &str, max_chars: usize) -> Vec<String> {
+        let chunks: Vec<String> = chunk_text(text, max_chars)
+            .map(|block| match block {
+                ContentBlock::Text(text) => text.text,
+                block => panic!("expected text, got {block:?}"),
+            })
+            .collect();
+        assert_eq!(chunks.concat(), text);
+        for chunk in &chunks {
+            assert!(!chunk.is_empty());
+            assert!(chunk.chars().count() <= max_chars, "{chunk:?} is too long");
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_chunk_text_at_word_boundaries() {
+        assert_eq!(
+            chunks("The quick brown fox jumps over the lazy dog", 12),
+            [
+                "The quick",
+                " brown fox",
+                " jumps over",
+                " the lazy",
+                " dog"
+            ]
+        );
+        // The limit may fall right before a space.
+        assert_eq!(chunks("hello world", 5), ["hello", " worl", "d"]);
+    }
+
+    #[test]
+    fn test_chunk_text_prefers_sentence_boundaries() {
+        assert_eq!(
+            chunks("I checked the tests. All of them pass now.", 30),
+            ["I checked the tests.", " All of them pass now."]
+        );
+        assert_eq!(
+            chunks("First line\nsecond line here", 20),
+            ["First line", "\nsecond line here"]
+        );
+        // A sentence boundary near the start of a chunk would make it too short.
+        assert_eq!(
+            chunks("Ok. Then we run the whole suite", 20),
+            ["Ok. Then we run the", " whole suite"]
+        );
+    }
+
+    #[test]
+    fn test_chunk_text_splits_long_words_between_characters() {
+        assert_eq!(chunks("abcdefgh", 3), ["abc", "def", "gh"]);
+        assert_eq!(
+            chunks("héllo wörld ✓✓✓", 4),
+            ["héll", "o", " wör", "ld", " ✓✓✓"]
+        );
+        assert_eq!(chunks("🦀🦀🦀🦀🦀", 2), ["🦀🦀", "🦀🦀", "🦀"]);
+    }
+
+    #[test]
+    fn test_chunk_text_short_and_empty() {
+        assert_eq!(chunks("short", 100), ["short"]);
+        assert_eq!(chunks("exact", 5), ["exact"]);
+        assert!(chunks("", 5).is_empty());
+    }
+
+    fn for_audience(text: &str, audience: Option<Vec<Role>>) -> ContentBlock {
+        ContentBlock::Text(TextContent {
+            annotations: Some(Annotations {
+                audience,
+                last_modified: None,
+                priority: None,
+                meta: None,
+            }),
+            text: text.to_string(),
+            meta: None,
+        })
+    }
+
+    #[test]
+    fn test_is_for() {
+        let everyone = ContentBlock::text("everyone");
+        assert!(everyone.is_for(Role::User));
+        assert!(everyone.is_for(Role::Assistant));
+
+        let user = for_audience("user", Some(vec![Role::User]));
+        assert!(user.is_for(Role::User));
+        assert!(!user.is_for(Role::Assistant));
+
+        let both = for_audience("both", Some(vec![Role::Assistant, Role::User]));
+        assert!(both.is_for(Role::User));
+        assert!(both.is_for(Role::Assistant));
+
+        for unrestricted in [
+            for_audience("none", None),
+            for_audience("empty", Some(vec![])),
+        ] {
+            assert!(unrestricted.is_for(Role::User));
+            assert!(unrestricted.is_for(Role::Assistant));
+        }
+    }
+
+    #[test]
+    fn test_filter_for_audience() {
+        let mut image = ContentBlock::image("iVBORw0KGgo=", "image/png");
+        if let ContentBlock::Image(image) = &mut image {
+            image.annotations = Some(Annotations {
+                audience: Some(vec![Role::Assistant]),
+                last_modified: None,
+                priority: None,
+                meta: None,
+            });
+        }
+        let message = vec![
+            ContentBlock::text("Here's the failing test."),
+            for_audience("Internal: retried twice", Some(vec![Role::Assistant])),
+            for_audience("Click to open the report", Some(vec![Role::User])),
+            image.clone(),
+            ContentBlock::resource_link("file:///project/report.html", "report.html"),
+        ];
+
+        assert_eq!(
+            filter_for_audience(&message, Role::User)
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![message[0].clone(), message[2].clone(), message[4].clone(),]
+        );
+        assert_eq!(
+            filter_for_audience(&message, Role::Assistant)
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![
+                message[0].clone(),
+                message[1].clone(),
+                image,
+                message[4].clone(),
+            ]
+        );
+    }
+}
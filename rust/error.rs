@@ -10,7 +10,7 @@
 //!
 //! See: [Error Handling](https://agentclientprotocol.com/protocol/overview#error-handling)
 
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -94,6 +94,12 @@ impl Error {
         Error::new(ErrorCode::AUTH_REQUIRED)
     }
 
+    /// The request did not receive a response within the allotted time.
+    #[must_use]
+    pub fn request_timeout() -> Self {
+        Error::new(ErrorCode::REQUEST_TIMEOUT)
+    }
+
     /// A given resource, such as a file, was not found.
     #[must_use]
     pub fn resource_not_found(uri: Option<String>) -> Self {
@@ -105,6 +111,115 @@ impl Error {
         }
     }
 
+    /// The terminal's command has already exited and can no longer accept input.
+    #[must_use]
+    pub fn terminal_exited(terminal_id: impl Into<String>) -> Self {
+        Error::new(ErrorCode::TERMINAL_EXITED)
+            .with_data(serde_json::json!({ "terminalId": terminal_id.into() }))
+    }
+
+    /// The peer has too many requests in flight and this one was rejected (`-32003`).
+    #[must_use]
+    pub fn too_many_requests() -> Self {
+        Error::new(ErrorCode::TOO_MANY_REQUESTS)
+    }
+
+    /// The request conflicts with the current state of the target resource, for
+    /// example because it was modified concurrently.
+    #[must_use]
+    pub fn conflict() -> Self {
+        Error::new(ErrorCode::CONFLICT)
+    }
+
+    /// An incoming message exceeded the receiver's size limit and was discarded.
+    #[must_use]
+    pub fn message_too_large(max_bytes: usize) -> Self {
+        Error::new(ErrorCode::MESSAGE_TOO_LARGE)
+            .with_data(serde_json::json!({ "maxBytes": max_bytes }))
+    }
+
+    /// The session was closed by the agent and can no longer be used.
+    #[must_use]
+    pub fn session_closed(session_id: impl Into<String>) -> Self {
+        Error::new(ErrorCode::SESSION_CLOSED)
+            .with_data(serde_json::json!({ "sessionId": session_id.into() }))
+    }
+
+    /// A prompt turn is already running for the session, and the agent doesn't
+    /// accept another one until it ends.
+    #[must_use]
+    pub fn prompt_in_progress(session_id: impl Into<String>) -> Self {
+        Error::new(ErrorCode::PROMPT_IN_PROGRESS)
+            .with_data(serde_json::json!({ "sessionId": session_id.into() }))
+    }
+
+    /// The session isn't known to the agent, for example because the agent
+    /// restarted since it was created.
+    #[must_use]
+    pub fn session_not_found(session_id: impl Into<String>) -> Self {
+        Error::new(ErrorCode::SESSION_NOT_FOUND)
+            .with_data(serde_json::json!({ "sessionId": session_id.into() }))
+    }
+
+    /// Creates an error with the stable code of the given kind.
+    #[must_use]
+    pub fn from_kind(kind: ErrorKind) -> Self {
+        Error::new(kind.code())
+    }
+
+    /// Returns the kind of this error, if its code is one of the ACP-specific codes.
+    #[must_use]
+    pub fn kind(&self) -> Option<ErrorKind> {
+        ErrorKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.code().code == self.code)
+    }
+
+    /// Attaches a hint telling the caller how long to wait before retrying.
+    ///
+    /// The hint is stored as `retryAfterMs` in the error's `data` object and can
+    /// be read back with [`Error::retry_after`]. Any existing object fields in
+    /// `data` are preserved; other `data` values are replaced.
+    #[must_use]
+    pub fn with_retry_after(mut self, delay: Duration) -> Self {
+        let millis = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+        match &mut self.data {
+            Some(serde_json::Value::Object(map)) => {
+                map.insert("retryAfterMs".into(), millis.into());
+            }
+            data => *data = Some(serde_json::json!({ "retryAfterMs": millis })),
+        }
+        self
+    }
+
+    /// Returns whether the failed request may succeed if it is sent again.
+    ///
+    /// Internal errors, timeouts and rate limiting are considered transient.
+    /// Everything else, such as invalid params or an unknown method, will keep
+    /// failing until the request itself changes.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        [
+            ErrorCode::INTERNAL_ERROR,
+            ErrorCode::REQUEST_TIMEOUT,
+            ErrorCode::TOO_MANY_REQUESTS,
+        ]
+        .iter()
+        .any(|code| code.code == self.code)
+    }
+
+    /// The delay the peer asked for before retrying, read from the
+    /// `retryAfterMs` field of the error's `data`.
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.data
+            .as_ref()?
+            .get("retryAfterMs")?
+            .as_u64()
+            .map(Duration::from_millis)
+    }
+
     /// Converts a standard error into an internal JSON-RPC error.
     ///
     /// The error's string representation is included as additional data.
@@ -165,12 +280,138 @@ impl ErrorCode {
         message: "Authentication required",
     };
 
+    /// The request did not receive a response within the allotted time.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const REQUEST_TIMEOUT: ErrorCode = ErrorCode {
+        code: -32001,
+        message: "Request timed out",
+    };
+
     /// A given resource, such as a file, was not found.
     /// This is an ACP-specific error code in the reserved range.
     pub const RESOURCE_NOT_FOUND: ErrorCode = ErrorCode {
         code: -32002,
         message: "Resource not found",
     };
+
+    /// The terminal's command has already exited.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const TERMINAL_EXITED: ErrorCode = ErrorCode {
+        code: -32009,
+        message: "Terminal exited",
+    };
+
+    /// The receiver is already handling as many requests as it allows.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const TOO_MANY_REQUESTS: ErrorCode = ErrorCode {
+        code: -32003,
+        message: "Too many requests",
+    };
+
+    /// The request conflicts with the current state of the target resource.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const CONFLICT: ErrorCode = ErrorCode {
+        code: -32005,
+        message: "Conflict",
+    };
+
+    /// The session was closed by the agent.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const SESSION_CLOSED: ErrorCode = ErrorCode {
+        code: -32010,
+        message: "Session closed",
+    };
+
+    /// An incoming message exceeded the receiver's size limit.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const MESSAGE_TOO_LARGE: ErrorCode = ErrorCode {
+        code: -32006,
+        message: "Message too large",
+    };
+
+    /// A prompt turn is already running for the session.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const PROMPT_IN_PROGRESS: ErrorCode = ErrorCode {
+        code: -32007,
+        message: "Prompt in progress",
+    };
+
+    /// The session isn't known to the agent.
+    /// This is an ACP-specific error code in the reserved range.
+    pub const SESSION_NOT_FOUND: ErrorCode = ErrorCode {
+        code: -32008,
+        message: "Session not found",
+    };
+}
+
+/// The ACP-specific kinds of errors, each with a stable error code.
+///
+/// Implementations can return these instead of a generic internal error so that
+/// the other side can react to the failure, for example by authenticating or
+/// retrying later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Authentication is required before the request can be handled (`-32000`).
+    AuthRequired,
+    /// No response arrived within the allotted time (`-32001`).
+    RequestTimeout,
+    /// A resource, such as a file, was not found (`-32002`).
+    ///
+    /// This is the code `Resource not found` had before `ErrorKind` existed, which
+    /// MCP uses as well, so it stays unchanged for compatibility with existing peers.
+    ResourceNotFound,
+    /// The terminal's command has already exited (`-32009`).
+    TerminalExited,
+    /// Too many requests were sent; retry later (`-32003`).
+    RateLimited,
+    /// The request conflicts with the current state of the resource (`-32005`).
+    Conflict,
+    /// The session was closed by the agent (`-32010`).
+    SessionClosed,
+    /// A message exceeded the receiver's size limit (`-32006`).
+    MessageTooLarge,
+    /// A prompt turn is already running for the session (`-32007`).
+    PromptInProgress,
+    /// The session isn't known to the agent (`-32008`).
+    SessionNotFound,
+}
+
+impl ErrorKind {
+    const ALL: &[ErrorKind] = &[
+        ErrorKind::AuthRequired,
+        ErrorKind::RequestTimeout,
+        ErrorKind::ResourceNotFound,
+        ErrorKind::TerminalExited,
+        ErrorKind::RateLimited,
+        ErrorKind::Conflict,
+        ErrorKind::SessionClosed,
+        ErrorKind::MessageTooLarge,
+        ErrorKind::PromptInProgress,
+        ErrorKind::SessionNotFound,
+    ];
+
+    /// The error code used on the wire for this kind.
+    #[must_use]
+    pub const fn code(self) -> ErrorCode {
+        match self {
+            ErrorKind::AuthRequired => ErrorCode::AUTH_REQUIRED,
+            ErrorKind::RequestTimeout => ErrorCode::REQUEST_TIMEOUT,
+            ErrorKind::ResourceNotFound => ErrorCode::RESOURCE_NOT_FOUND,
+            ErrorKind::TerminalExited => ErrorCode::TERMINAL_EXITED,
+            ErrorKind::RateLimited => ErrorCode::TOO_MANY_REQUESTS,
+            ErrorKind::Conflict => ErrorCode::CONFLICT,
+            ErrorKind::SessionClosed => ErrorCode::SESSION_CLOSED,
+            ErrorKind::MessageTooLarge => ErrorCode::MESSAGE_TOO_LARGE,
+            ErrorKind::PromptInProgress => ErrorCode::PROMPT_IN_PROGRESS,
+            ErrorKind::SessionNotFound => ErrorCode::SESSION_NOT_FOUND,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::from_kind(kind)
+    }
 }
 
 impl From<ErrorCode> for (i32, String) {
@@ -204,6 +445,8 @@ impl Display for Error {
     }
 }
 
+/// Errors that wrap an [`Error`] are unwrapped so that its code reaches the peer
+/// unchanged; any other error becomes an internal error.
 impl From<anyhow::Error> for Error {
     fn from(error: anyhow::Error) -> Self {
         match error.downcast::<Self>() {
@@ -218,3 +461,64 @@ impl From<serde_json::Error> for Error {
         Error::invalid_params().with_data(error.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(Error::internal_error().is_retryable());
+        assert!(Error::request_timeout().is_retryable());
+        assert!(Error::too_many_requests().is_retryable());
+
+        assert!(!Error::invalid_params().is_retryable());
+        assert!(!Error::method_not_found().is_retryable());
+        assert!(!Error::auth_required().is_retryable());
+        assert!(!Error::resource_not_found(None).is_retryable());
+    }
+
+    #[test]
+    fn test_error_kind_codes() {
+        let codes = [
+            (ErrorKind::AuthRequired, -32000),
+            (ErrorKind::ResourceNotFound, -32002),
+            (ErrorKind::RateLimited, -32003),
+            (ErrorKind::Conflict, -32005),
+        ];
+        for (kind, code) in codes {
+            assert_eq!(kind.code().code, code, "{kind:?}");
+            assert_eq!(Error::from_kind(kind).kind(), Some(kind));
+        }
+
+        let mut codes = ErrorKind::ALL
+            .iter()
+            .map(|kind| kind.code().code)
+            .collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), ErrorKind::ALL.len());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(Error::too_many_requests().retry_after(), None);
+
+        let error = Error::too_many_requests().with_retry_after(Duration::from_millis(1500));
+        assert_eq!(error.retry_after(), Some(Duration::from_millis(1500)));
+        assert_eq!(
+            error.data,
+            Some(serde_json::json!({ "retryAfterMs": 1500 }))
+        );
+
+        let error = Error::session_closed("sess_1").with_retry_after(Duration::from_secs(2));
+        assert_eq!(
+            error.data,
+            Some(serde_json::json!({ "sessionId": "sess_1", "retryAfterMs": 2000 }))
+        );
+
+        let error =
+            Error::internal_error().with_data(serde_json::json!({ "retryAfterMs": "soon" }));
+        assert_eq!(error.retry_after(), None);
+    }
+}
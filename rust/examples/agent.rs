@@ -25,15 +25,18 @@ use tokio_util::compat::{TokioAsyncReadCompatExt as _, TokioAsyncWriteCompatExt
 struct ExampleAgent {
     session_update_tx: mpsc::UnboundedSender<(acp::SessionNotification, oneshot::Sender<()>)>,
     next_session_id: Cell<u64>,
+    cancellation: acp::CancellationRegistry,
 }
 
 impl ExampleAgent {
     fn new(
         session_update_tx: mpsc::UnboundedSender<(acp::SessionNotification, oneshot::Sender<()>)>,
+        cancellation: acp::CancellationRegistry,
     ) -> Self {
         Self {
             session_update_tx,
             next_session_id: Cell::new(0),
+            cancellation,
         }
     }
 }
@@ -46,9 +49,18 @@ impl acp::Agent for ExampleAgent {
     ) -> Result<acp::InitializeResponse, acp::Error> {
         log::info!("Received initialize request {arguments:?}");
         Ok(acp::InitializeResponse {
-            protocol_version: acp::V1,
+            protocol_version: acp::ProtocolVersion::negotiate(
+                arguments.protocol_version,
+                acp::SUPPORTED_VERSIONS,
+            ),
             agent_capabilities: acp::AgentCapabilities::default(),
             auth_methods: Vec::new(),
+            agent_info: Some(acp::Implementation {
+                name: "example-agent".to_string(),
+                title: Some("Example Agent".to_string()),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                meta: None,
+            }),
             meta: None,
         })
     }
@@ -66,13 +78,14 @@ impl acp::Agent for ExampleAgent {
         arguments: acp::NewSessionRequest,
     ) -> Result<acp::NewSessionResponse, acp::Error> {
         log::info!("Received new session request {arguments:?}");
+        arguments.validate_mcp_servers(&acp::McpCapabilities::default())?;
         let session_id = self.next_session_id.get();
         self.next_session_id.set(session_id + 1);
         Ok(acp::NewSessionResponse {
             session_id: acp::SessionId(session_id.to_string().into()),
             modes: None,
-            #[cfg(feature = "unstable")]
             models: None,
+            default_permission_options: None,
             meta: None,
         })
     }
@@ -84,7 +97,6 @@ impl acp::Agent for ExampleAgent {
         log::info!("Received load session request {arguments:?}");
         Ok(acp::LoadSessionResponse {
             modes: None,
-            #[cfg(feature = "unstable")]
             models: None,
             meta: None,
         })
@@ -95,27 +107,41 @@ impl acp::Agent for ExampleAgent {
         arguments: acp::PromptRequest,
     ) -> Result<acp::PromptResponse, acp::Error> {
         log::info!("Received prompt request {arguments:?}");
-        for content in ["Client sent: ".into()].into_iter().chain(arguments.prompt) {
-            let (tx, rx) = oneshot::channel();
-            self.session_update_tx
-                .send((
-                    SessionNotification {
-                        session_id: arguments.session_id.clone(),
-                        update: acp::SessionUpdate::AgentMessageChunk { content },
-                        meta: None,
-                    },
-                    tx,
-                ))
-                .map_err(|_| acp::Error::internal_error())?;
-            rx.await.map_err(|_| acp::Error::internal_error())?;
-        }
+        // Held until the turn ends, so that the connection can stop it when the
+        // client sends `session/cancel` in the meantime.
+        let token = self.cancellation.register(arguments.session_id.clone());
+        let turn = async {
+            for content in ["Client sent: ".into()].into_iter().chain(arguments.prompt) {
+                let (tx, rx) = oneshot::channel();
+                self.session_update_tx
+                    .send((
+                        SessionNotification {
+                            session_id: arguments.session_id.clone(),
+                            update: acp::SessionUpdate::AgentMessageChunk { content },
+                            turn_id: arguments.turn_id.clone(),
+                            meta: None,
+                        },
+                        tx,
+                    ))
+                    .map_err(|_| acp::Error::internal_error())?;
+                rx.await.map_err(|_| acp::Error::internal_error())?;
+            }
+            Ok::<_, acp::Error>(acp::StopReason::EndTurn)
+        };
+        let stop_reason = token
+            .run_until_cancelled(turn)
+            .await
+            .unwrap_or(Ok(acp::StopReason::Cancelled))?;
         Ok(acp::PromptResponse {
-            stop_reason: acp::StopReason::EndTurn,
+            stop_reason,
+            usage: None,
+            refusal: None,
             meta: None,
         })
     }
 
     async fn cancel(&self, args: acp::CancelNotification) -> Result<(), acp::Error> {
+        // The connection has already tripped the turn's cancellation token.
         log::info!("Received cancel request {args:?}");
         Ok(())
     }
@@ -128,7 +154,6 @@ impl acp::Agent for ExampleAgent {
         Ok(SetSessionModeResponse::default())
     }
 
-    #[cfg(feature = "unstable")]
     async fn set_session_model(
         &self,
         args: acp::SetSessionModelRequest,
@@ -170,11 +195,18 @@ async fn main() -> anyhow::Result<()> {
     local_set
         .run_until(async move {
             let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            // The connection trips the agent's cancellation tokens on `session/cancel`.
+            let cancellation = acp::CancellationRegistry::new();
             // Start up the ExampleAgent connected to stdio.
-            let (conn, handle_io) =
-                acp::AgentSideConnection::new(ExampleAgent::new(tx), outgoing, incoming, |fut| {
+            let (conn, handle_io) = acp::AgentSideConnection::new(
+                ExampleAgent::new(tx, cancellation.clone()),
+                outgoing,
+                incoming,
+                |fut| {
                     tokio::task::spawn_local(fut);
-                });
+                },
+            );
+            let conn = conn.with_cancellation_registry(cancellation);
             // Kick off a background task to send the ExampleAgent's session notifications to the client.
             tokio::task::spawn_local(async move {
                 while let Some((session_notification, tx)) = rx.recv().await {
@@ -12,9 +12,7 @@
 //! cargo build --example agent && cargo run --example client -- target/debug/examples/agent
 //! ```
 
-use agent_client_protocol::{
-    self as acp, Agent, ExtNotification, ExtRequest, ExtResponse, KillTerminalCommandResponse,
-};
+use agent_client_protocol::{self as acp, Agent};
 use anyhow::bail;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
@@ -29,55 +27,6 @@ impl acp::Client for ExampleClient {
         Err(acp::Error::method_not_found())
     }
 
-    async fn write_text_file(
-        &self,
-        _args: acp::WriteTextFileRequest,
-    ) -> anyhow::Result<acp::WriteTextFileResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn read_text_file(
-        &self,
-        _args: acp::ReadTextFileRequest,
-    ) -> anyhow::Result<acp::ReadTextFileResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn create_terminal(
-        &self,
-        _args: acp::CreateTerminalRequest,
-    ) -> Result<acp::CreateTerminalResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn terminal_output(
-        &self,
-        _args: acp::TerminalOutputRequest,
-    ) -> anyhow::Result<acp::TerminalOutputResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn release_terminal(
-        &self,
-        _args: acp::ReleaseTerminalRequest,
-    ) -> anyhow::Result<acp::ReleaseTerminalResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn wait_for_terminal_exit(
-        &self,
-        _args: acp::WaitForTerminalExitRequest,
-    ) -> anyhow::Result<acp::WaitForTerminalExitResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn kill_terminal_command(
-        &self,
-        _args: acp::KillTerminalCommandRequest,
-    ) -> anyhow::Result<KillTerminalCommandResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
     async fn session_notification(
         &self,
         args: acp::SessionNotification,
@@ -88,29 +37,47 @@ impl acp::Client for ExampleClient {
                     acp::ContentBlock::Text(text_content) => text_content.text,
                     acp::ContentBlock::Image(_) => "<image>".into(),
                     acp::ContentBlock::Audio(_) => "<audio>".into(),
-                    acp::ContentBlock::ResourceLink(resource_link) => resource_link.uri,
+                    acp::ContentBlock::ResourceLink(resource_link) => resource_link.uri.to_string(),
                     acp::ContentBlock::Resource(_) => "<resource>".into(),
                 };
                 println!("| Agent: {text}");
             }
+            acp::SessionUpdate::TerminalOutputChunk { chunk, .. } => {
+                print!("{chunk}");
+            }
+            acp::SessionUpdate::Diagnostic {
+                path,
+                range,
+                severity,
+                message,
+                ..
+            } => {
+                let line = range.map(|range| range.start).unwrap_or(1);
+                println!("| {severity:?}: {}:{line}: {message}", path.display());
+            }
+            acp::SessionUpdate::SuggestedPrompts { prompts } => {
+                for prompt in prompts {
+                    println!("| Suggestion: {}", prompt.label);
+                }
+            }
+            acp::SessionUpdate::SessionClosed { reason } => {
+                println!("| Session closed: {reason}");
+            }
             acp::SessionUpdate::UserMessageChunk { .. }
             | acp::SessionUpdate::AgentThoughtChunk { .. }
             | acp::SessionUpdate::ToolCall(_)
             | acp::SessionUpdate::ToolCallUpdate(_)
+            | acp::SessionUpdate::ToolCallOutputChunk { .. }
             | acp::SessionUpdate::Plan(_)
+            | acp::SessionUpdate::PlanEntryUpdate(_)
+            | acp::SessionUpdate::UsageUpdate(_)
+            | acp::SessionUpdate::Progress { .. }
             | acp::SessionUpdate::CurrentModeUpdate { .. }
+            | acp::SessionUpdate::CurrentModelUpdate { .. }
             | acp::SessionUpdate::AvailableCommandsUpdate { .. } => {}
         }
         Ok(())
     }
-
-    async fn ext_method(&self, _args: ExtRequest) -> Result<ExtResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
-
-    async fn ext_notification(&self, _args: ExtNotification) -> Result<(), acp::Error> {
-        Err(acp::Error::method_not_found())
-    }
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -154,6 +121,12 @@ async fn main() -> anyhow::Result<()> {
             conn.initialize(acp::InitializeRequest {
                 protocol_version: acp::V1,
                 client_capabilities: acp::ClientCapabilities::default(),
+                client_info: Some(acp::Implementation {
+                    name: "example-client".to_string(),
+                    title: Some("Example Client".to_string()),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    meta: None,
+                }),
                 meta: None,
             })
             .await?;
@@ -161,6 +134,7 @@ async fn main() -> anyhow::Result<()> {
                 .new_session(acp::NewSessionRequest {
                     mcp_servers: Vec::new(),
                     cwd: std::env::current_dir()?,
+                    progress_token: None,
                     meta: None,
                 })
                 .await?;
@@ -172,6 +146,10 @@ async fn main() -> anyhow::Result<()> {
                     .prompt(acp::PromptRequest {
                         session_id: response.session_id.clone(),
                         prompt: vec![line.into()],
+                        context: Vec::new(),
+                        progress_token: None,
+                        turn_id: None,
+                        tools_enabled: None,
                         meta: None,
                     })
                     .await;
@@ -0,0 +1,376 @@
+//! A conformance check that drives an agent through a scripted session.
+//!
+//! The script is a JSON file listing the steps to run and what the agent is
+//! expected to answer. Each step is reported as passed or failed, along with the
+//! JSON that didn't match, so agents written in any language can be checked
+//! against this crate. Run it like this:
+//!
+//! ```bash
+//! cargo run --example conformance -- path/to/script.json path/to/agent --agent-arg
+//! ```
+//!
+//! To check the example agent from this crate against the example script:
+//!
+//! ```bash
+//! cargo build --example agent && cargo run --example conformance -- rust/examples/conformance.json target/debug/examples/agent
+//! ```
+//!
+//! A script looks like this:
+//!
+//! ```json
+//! {
+//!   "steps": [
+//!     { "step": "initialize", "expect": { "protocolVersion": 1 } },
+//!     { "step": "new_session" },
+//!     {
+//!       "step": "prompt",
+//!       "prompt": "Run the tests",
+//!       "permission": "allow-once",
+//!       "expectPermission": { "toolCall": { "kind": "execute" } },
+//!       "expectUpdates": [{ "sessionUpdate": "tool_call", "kind": "execute" }],
+//!       "expect": { "stopReason": "end_turn" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Expectations are partial: every field they list must be present with the same
+//! value, but other fields are ignored. Arrays must have the same length. Expected
+//! updates must be sent in the given order, with any other updates in between.
+//! Agent errors are matched as `{ "error": { "code": ..., "message": ... } }`.
+
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use agent_client_protocol::{
+    self as acp, Agent, StreamMessageDirection, StreamMessageInfo, StreamRecvError,
+};
+use anyhow::{Context as _, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Script {
+    /// The capabilities the client advertises in `initialize`.
+    #[serde(default)]
+    client_capabilities: acp::ClientCapabilities,
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+enum Step {
+    Initialize {
+        #[serde(default)]
+        expect: Option<Value>,
+    },
+    NewSession {
+        /// Defaults to the current directory.
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        #[serde(default)]
+        expect: Option<Value>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Prompt {
+        prompt: String,
+        /// The option to select when the agent requests permission, or `None` to
+        /// answer with the `cancelled` outcome.
+        #[serde(default)]
+        permission: Option<acp::PermissionOptionId>,
+        #[serde(default)]
+        expect_permission: Option<Value>,
+        #[serde(default)]
+        expect_updates: Vec<Value>,
+        #[serde(default)]
+        expect: Option<Value>,
+    },
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Initialize { .. } => "initialize",
+            Step::NewSession { .. } => "new_session",
+            Step::Prompt { .. } => "prompt",
+        }
+    }
+}
+
+/// Answers permission requests as the current step says, and records them.
+#[derive(Default)]
+struct ConformanceClient {
+    permission: RefCell<Option<acp::PermissionOptionId>>,
+    permission_requests: RefCell<Vec<Value>>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl acp::Client for ConformanceClient {
+    async fn request_permission(
+        &self,
+        args: acp::RequestPermissionRequest,
+    ) -> Result<acp::RequestPermissionResponse, acp::Error> {
+        self.permission_requests
+            .borrow_mut()
+            .push(serde_json::to_value(&args)?);
+        let outcome = match self.permission.borrow().clone() {
+            Some(option_id) => acp::RequestPermissionOutcome::Selected { option_id },
+            None => acp::RequestPermissionOutcome::Cancelled,
+        };
+        Ok(acp::RequestPermissionResponse {
+            outcome,
+            meta: None,
+        })
+    }
+
+    async fn session_notification(
+        &self,
+        _args: acp::SessionNotification,
+    ) -> Result<(), acp::Error> {
+        // Updates are checked through the message stream, as sent on the wire.
+        Ok(())
+    }
+}
+
+/// Returns a description of the first difference between `expected` and
+/// `actual`, or `None` if `actual` has everything `expected` lists.
+fn mismatch(expected: &Value, actual: &Value, path: &str) -> Option<String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.iter().find_map(|(key, expected)| {
+                let path = format!("{path}.{key}");
+                match actual.get(key) {
+                    Some(actual) => mismatch(expected, actual, &path),
+                    None => Some(format!("at `{path}`: expected {expected}, got nothing")),
+                }
+            })
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            expected
+                .iter()
+                .zip(actual)
+                .enumerate()
+                .find_map(|(index, (expected, actual))| {
+                    mismatch(expected, actual, &format!("{path}[{index}]"))
+                })
+        }
+        _ if expected == actual => None,
+        _ => Some(format!("at `{path}`: expected {expected}, got {actual}")),
+    }
+}
+
+/// Checks `actual` against an optional expectation.
+fn check(expected: Option<&Value>, actual: &Value) -> Result<(), String> {
+    match expected.and_then(|expected| mismatch(expected, actual, "$")) {
+        Some(mismatch) => Err(format!("{mismatch}\n  actual: {actual}")),
+        None => Ok(()),
+    }
+}
+
+/// The JSON of a response, or of the error the agent answered with.
+fn outcome<T: serde::Serialize>(result: &Result<T, acp::Error>) -> Value {
+    match result {
+        Ok(response) => serde_json::to_value(response).unwrap_or_default(),
+        Err(error) => json!({ "error": error }),
+    }
+}
+
+struct Runner {
+    conn: acp::ClientSideConnection,
+    client: Rc<ConformanceClient>,
+    updates: acp::StreamReceiver,
+    client_capabilities: acp::ClientCapabilities,
+    session_id: Option<acp::SessionId>,
+}
+
+impl Runner {
+    async fn run(&mut self, step: Step) -> Result<(), String> {
+        match step {
+            Step::Initialize { expect } => {
+                let result = self
+                    .conn
+                    .initialize(acp::InitializeRequest {
+                        protocol_version: acp::V1,
+                        client_capabilities: self.client_capabilities.clone(),
+                        client_info: None,
+                        meta: None,
+                    })
+                    .await;
+                check(expect.as_ref(), &outcome(&result))
+            }
+            Step::NewSession { cwd, expect } => {
+                let cwd = match cwd {
+                    Some(cwd) => cwd,
+                    None => std::env::current_dir().map_err(|error| error.to_string())?,
+                };
+                let result = self
+                    .conn
+                    .new_session(acp::NewSessionRequest {
+                        mcp_servers: Vec::new(),
+                        cwd,
+                        progress_token: None,
+                        meta: None,
+                    })
+                    .await;
+                if let Ok(response) = &result {
+                    self.session_id = Some(response.session_id.clone());
+                }
+                check(expect.as_ref(), &outcome(&result))
+            }
+            Step::Prompt {
+                prompt,
+                permission,
+                expect_permission,
+                expect_updates,
+                expect,
+            } => {
+                let session_id = self
+                    .session_id
+                    .clone()
+                    .ok_or("no session was created before this step")?;
+                *self.client.permission.borrow_mut() = permission;
+                self.client.permission_requests.borrow_mut().clear();
+
+                let (result, updates) = self
+                    .prompt(acp::PromptRequest {
+                        session_id,
+                        prompt: vec![prompt.into()],
+                        context: Vec::new(),
+                        progress_token: None,
+                        turn_id: None,
+                        tools_enabled: None,
+                        meta: None,
+                    })
+                    .await?;
+
+                if let Some(expected) = &expect_permission {
+                    let requests = self.client.permission_requests.borrow();
+                    let request = requests
+                        .first()
+                        .ok_or("expected a permission request, but none was sent")?;
+                    check(Some(expected), request)?;
+                }
+
+                let mut remaining = updates.iter();
+                for expected in &expect_updates {
+                    if !remaining.any(|update| mismatch(expected, update, "$").is_none()) {
+                        return Err(format!(
+                            "expected update {expected} was not sent\n  updates: {}",
+                            Value::Array(updates.clone())
+                        ));
+                    }
+                }
+
+                check(expect.as_ref(), &outcome(&result))
+            }
+        }
+    }
+
+    /// Sends a prompt and collects the updates the agent sent during the turn.
+    async fn prompt(
+        &mut self,
+        request: acp::PromptRequest,
+    ) -> Result<(Result<acp::PromptResponse, acp::Error>, Vec<Value>), String> {
+        let mut updates = Vec::new();
+        let mut record = |message: acp::StreamMessage| {
+            if let Some(update) = message
+                .message
+                .params()
+                .and_then(|params| params.value().ok()?.get("update"))
+            {
+                updates.push(update.clone());
+            }
+        };
+
+        let prompt = self.conn.prompt(request);
+        tokio::pin!(prompt);
+        let result = loop {
+            tokio::select! {
+                message = self.updates.recv() => match message {
+                    Ok(message) => record(message),
+                    Err(StreamRecvError::Lagged(skipped)) => {
+                        return Err(format!("missed {skipped} updates while recording the turn"));
+                    }
+                    Err(StreamRecvError::Closed) => break prompt.await,
+                },
+                result = &mut prompt => break result,
+            }
+        };
+        // Updates sent right before the response may still be buffered.
+        while !self.updates.is_empty() {
+            match self.updates.recv().await {
+                Ok(message) => record(message),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        Ok((result, updates))
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let command = std::env::args().collect::<Vec<_>>();
+    let (script, program, args) = match command.as_slice() {
+        [_, script, program, args @ ..] => (script, program, args),
+        _ => bail!("Usage: conformance SCRIPT AGENT_PROGRAM AGENT_ARG..."),
+    };
+    let script = std::fs::read_to_string(script).with_context(|| format!("reading {script}"))?;
+    let script: Script = serde_json::from_str(&script).context("parsing the script")?;
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args.iter())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let outgoing = child.stdin.take().unwrap().compat_write();
+    let incoming = child.stdout.take().unwrap().compat();
+
+    let local_set = tokio::task::LocalSet::new();
+    let failed = local_set
+        .run_until(async move {
+            let client = Rc::new(ConformanceClient::default());
+            let (conn, handle_io) =
+                acp::ClientSideConnection::new(client.clone(), outgoing, incoming, |fut| {
+                    tokio::task::spawn_local(fut);
+                });
+            tokio::task::spawn_local(handle_io);
+
+            let updates = conn.subscribe_filtered(|message: &StreamMessageInfo<'_>| {
+                message.direction == StreamMessageDirection::Incoming
+                    && message.method == Some("session/update")
+            });
+            let mut runner = Runner {
+                conn,
+                client,
+                updates,
+                client_capabilities: script.client_capabilities,
+                session_id: None,
+            };
+
+            let mut failed = 0;
+            let total = script.steps.len();
+            for (index, step) in script.steps.into_iter().enumerate() {
+                let name = step.name();
+                match runner.run(step).await {
+                    Ok(()) => println!("PASS {:>3} {name}", index + 1),
+                    Err(error) => {
+                        failed += 1;
+                        println!("FAIL {:>3} {name}: {error}", index + 1);
+                    }
+                }
+            }
+            println!("{} passed, {failed} failed", total - failed);
+            drop(child);
+            failed
+        })
+        .await;
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
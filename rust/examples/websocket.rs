@@ -0,0 +1,171 @@
+//! Running an ACP connection over a WebSocket instead of stdio.
+//!
+//! The example starts a small echo agent behind a WebSocket server on localhost,
+//! then connects a client to it and sends a single prompt:
+//!
+//! ```bash
+//! cargo run --example websocket --features websocket
+//! ```
+
+use std::{cell::OnceCell, rc::Rc};
+
+use agent_client_protocol::{self as acp, Agent as _, Client as _, WebSocketTransport};
+use tokio::net::TcpListener;
+
+struct EchoAgent {
+    conn: Rc<OnceCell<acp::AgentSideConnection>>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl acp::Agent for EchoAgent {
+    async fn initialize(
+        &self,
+        _args: acp::InitializeRequest,
+    ) -> Result<acp::InitializeResponse, acp::Error> {
+        Ok(acp::InitializeResponse {
+            protocol_version: acp::V1,
+            agent_capabilities: acp::AgentCapabilities::default(),
+            auth_methods: Vec::new(),
+            agent_info: None,
+            meta: None,
+        })
+    }
+
+    async fn authenticate(
+        &self,
+        _args: acp::AuthenticateRequest,
+    ) -> Result<acp::AuthenticateResponse, acp::Error> {
+        Ok(acp::AuthenticateResponse::default())
+    }
+
+    async fn new_session(
+        &self,
+        _args: acp::NewSessionRequest,
+    ) -> Result<acp::NewSessionResponse, acp::Error> {
+        Ok(acp::NewSessionResponse {
+            session_id: acp::SessionId("echo".into()),
+            modes: None,
+            models: None,
+            default_permission_options: None,
+            meta: None,
+        })
+    }
+
+    async fn prompt(&self, args: acp::PromptRequest) -> Result<acp::PromptResponse, acp::Error> {
+        let conn = self.conn.get().ok_or_else(acp::Error::internal_error)?;
+        for content in args.prompt {
+            conn.session_notification(acp::SessionNotification {
+                session_id: args.session_id.clone(),
+                update: acp::SessionUpdate::AgentMessageChunk { content },
+                turn_id: args.turn_id.clone(),
+                meta: None,
+            })
+            .await?;
+        }
+        Ok(acp::PromptResponse {
+            stop_reason: acp::StopReason::EndTurn,
+            usage: None,
+            refusal: None,
+            meta: None,
+        })
+    }
+
+    async fn cancel(&self, _args: acp::CancelNotification) -> Result<(), acp::Error> {
+        Ok(())
+    }
+}
+
+struct PrintClient;
+
+#[async_trait::async_trait(?Send)]
+impl acp::Client for PrintClient {
+    async fn request_permission(
+        &self,
+        _args: acp::RequestPermissionRequest,
+    ) -> Result<acp::RequestPermissionResponse, acp::Error> {
+        Err(acp::Error::method_not_found())
+    }
+
+    async fn session_notification(&self, args: acp::SessionNotification) -> Result<(), acp::Error> {
+        if let acp::SessionUpdate::AgentMessageChunk {
+            content: acp::ContentBlock::Text(text),
+        } = args.update
+        {
+            println!("| Agent: {}", text.text);
+        }
+        Ok(())
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("ws://{}", listener.local_addr()?);
+
+    // LocalSet and spawn_local are used because the futures from the
+    // agent-client-protocol crate are not Send.
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async move {
+            // Serve the EchoAgent to the first WebSocket client that connects.
+            tokio::task::spawn_local(async move {
+                let (stream, _) = listener.accept().await?;
+                let transport =
+                    WebSocketTransport::new(tokio_tungstenite::accept_async(stream).await?);
+                let cell = Rc::new(OnceCell::new());
+                let (conn, handle_io) = acp::AgentSideConnection::new(
+                    EchoAgent { conn: cell.clone() },
+                    transport.outgoing,
+                    transport.incoming,
+                    |fut| {
+                        tokio::task::spawn_local(fut);
+                    },
+                );
+                cell.set(conn).ok();
+                handle_io.await
+            });
+
+            // Connect the PrintClient to the agent over the same kind of transport.
+            let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+            let transport = WebSocketTransport::new(socket);
+            let (conn, handle_io) = acp::ClientSideConnection::new(
+                PrintClient,
+                transport.outgoing,
+                transport.incoming,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::task::spawn_local(handle_io);
+
+            conn.initialize(acp::InitializeRequest {
+                protocol_version: acp::V1,
+                client_capabilities: acp::ClientCapabilities::default(),
+                client_info: None,
+                meta: None,
+            })
+            .await?;
+            let session = conn
+                .new_session(acp::NewSessionRequest {
+                    mcp_servers: Vec::new(),
+                    cwd: std::env::current_dir()?,
+                    progress_token: None,
+                    meta: None,
+                })
+                .await?;
+            let response = conn
+                .prompt(acp::PromptRequest {
+                    session_id: session.session_id,
+                    prompt: vec!["Hello over WebSocket!".into()],
+                    context: Vec::new(),
+                    progress_token: None,
+                    turn_id: None,
+                    tools_enabled: None,
+                    meta: None,
+                })
+                .await?;
+            println!("Turn ended: {:?}", response.stop_reason);
+            Ok(())
+        })
+        .await
+}
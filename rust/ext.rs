@@ -1,10 +1,94 @@
 //! Extension types and constants for protocol extensibility.
 
+use std::collections::HashMap;
+
+use futures::Future;
+#[cfg(feature = "send")]
+use futures::future::BoxFuture;
+use futures::future::LocalBoxFuture;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::value::RawValue;
 use std::sync::Arc;
 
+use crate::Error;
+
+/// The contents of a `_meta` field, which every protocol type carries as an
+/// extension point.
+///
+/// Keys are expected to be namespaced by a domain the extension author controls,
+/// such as `zed.dev` or `zed.dev/debugMode`, so that extensions never collide.
+/// [`Meta::set_ext`] enforces this convention, while the map itself remains
+/// accessible for reading arbitrary keys.
+///
+/// See protocol docs: [Extensibility](https://agentclientprotocol.com/protocol/extensibility#the-_meta-field)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Meta(pub serde_json::Map<String, serde_json::Value>);
+
+impl Meta {
+    /// Creates an empty `_meta` object.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deserializes the value stored under an extension namespace.
+    ///
+    /// Returns `Ok(None)` if the namespace is absent, and [`Error::invalid_params`]
+    /// if its value doesn't match `T`.
+    pub fn get_ext<T: DeserializeOwned>(&self, namespace: &str) -> Result<Option<T>, Error> {
+        self.0
+            .get(namespace)
+            .map(|value| {
+                T::deserialize(value)
+                    .map_err(|err| Error::invalid_params().with_data(err.to_string()))
+            })
+            .transpose()
+    }
+
+    /// Stores a value under an extension namespace, replacing any previous value.
+    ///
+    /// The namespace must start with a domain name, optionally followed by a
+    /// `/`-separated path, e.g. `example.com` or `example.com/feature`. Fails with
+    /// [`Error::invalid_params`] otherwise.
+    pub fn set_ext(&mut self, namespace: &str, value: impl Serialize) -> Result<(), Error> {
+        if !is_domain_prefixed(namespace) {
+            return Err(Error::invalid_params().with_data(format!(
+                "_meta key {namespace:?} must start with a domain name, e.g. \"example.com/{namespace}\""
+            )));
+        }
+        let value = serde_json::to_value(value).map_err(Error::into_internal_error)?;
+        self.0.insert(namespace.to_string(), value);
+        Ok(())
+    }
+
+    /// Removes the value stored under an extension namespace, returning it.
+    pub fn remove_ext(&mut self, namespace: &str) -> Option<serde_json::Value> {
+        self.0.remove(namespace)
+    }
+}
+
+impl From<serde_json::Map<String, serde_json::Value>> for Meta {
+    fn from(map: serde_json::Map<String, serde_json::Value>) -> Self {
+        Self(map)
+    }
+}
+
+fn is_domain_prefixed(namespace: &str) -> bool {
+    let domain = namespace.split('/').next().unwrap_or_default();
+    let labels = domain.split('.').collect::<Vec<_>>();
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-')
+        })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(transparent)]
 #[schemars(with = "serde_json::Value")]
@@ -24,3 +108,268 @@ pub struct ExtNotification {
     pub method: Arc<str>,
     pub params: Arc<RawValue>,
 }
+
+type ExtHandler = Box<dyn Fn(&RawValue) -> LocalBoxFuture<'static, Result<ExtResponse, Error>>>;
+#[cfg(feature = "send")]
+type SendExtHandler =
+    Box<dyn Fn(&RawValue) -> BoxFuture<'static, Result<ExtResponse, Error>> + Send + Sync>;
+
+/// Routes extension requests to typed handlers.
+///
+/// Instead of matching on [`ExtRequest::method`] and (de)serializing raw params by hand,
+/// register a handler per method and forward `ext_method` calls to [`ExtRegistry::handle`]:
+///
+/// ```ignore
+/// let mut registry = ExtRegistry::new();
+/// registry.register("example.com/ping", |request: PingRequest| async move {
+///     Ok(PingResponse { message: request.message })
+/// });
+///
+/// // In your `Agent` or `Client` implementation:
+/// async fn ext_method(&self, args: ExtRequest) -> Result<ExtResponse, Error> {
+///     self.registry.handle(args).await
+/// }
+/// ```
+#[derive(Default)]
+pub struct ExtRegistry {
+    handlers: HashMap<Arc<str>, ExtHandler>,
+}
+
+impl ExtRegistry {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for the given extension method, replacing any existing one.
+    ///
+    /// The request params are deserialized into `Req` before calling the handler, and the
+    /// returned `Resp` is serialized into the [`ExtResponse`].
+    pub fn register<Req, Resp, F, Fut>(
+        &mut self,
+        method: impl Into<Arc<str>>,
+        handler: F,
+    ) -> &mut Self
+    where
+        Req: DeserializeOwned,
+        Resp: Serialize,
+        F: Fn(Req) -> Fut + 'static,
+        Fut: Future<Output = Result<Resp, Error>> + 'static,
+    {
+        self.handlers.insert(
+            method.into(),
+            Box::new(move |params| {
+                let response = serde_json::from_str::<Req>(params.get()).map(&handler);
+                Box::pin(async move {
+                    let response = response
+                        .map_err(|err| Error::invalid_params().with_data(err.to_string()))?
+                        .await?;
+                    serde_json::value::to_raw_value(&response)
+                        .map(Arc::from)
+                        .map_err(Error::into_internal_error)
+                })
+            }),
+        );
+        self
+    }
+
+    /// Routes an incoming extension request to its registered handler.
+    ///
+    /// Returns [`Error::method_not_found`] if no handler is registered for the method, and
+    /// [`Error::invalid_params`] if the params can't be deserialized into the handler's
+    /// request type.
+    pub async fn handle(&self, request: ExtRequest) -> Result<ExtResponse, Error> {
+        let Some(handler) = self.handlers.get(&request.method) else {
+            return Err(Error::method_not_found());
+        };
+        handler(&request.params).await
+    }
+}
+
+/// An [`ExtRegistry`] for [`SendAgent`](crate::SendAgent) and
+/// [`SendClient`](crate::SendClient) implementations, whose handlers must be
+/// `Send + Sync` and return [`Send`] futures.
+///
+/// Only available with the `send` feature.
+#[cfg(feature = "send")]
+#[derive(Default)]
+pub struct SendExtRegistry {
+    handlers: HashMap<Arc<str>, SendExtHandler>,
+}
+
+#[cfg(feature = "send")]
+impl SendExtRegistry {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See [`ExtRegistry::register`].
+    pub fn register<Req, Resp, F, Fut>(
+        &mut self,
+        method: impl Into<Arc<str>>,
+        handler: F,
+    ) -> &mut Self
+    where
+        Req: DeserializeOwned,
+        Resp: Serialize,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp, Error>> + Send + 'static,
+    {
+        self.handlers.insert(
+            method.into(),
+            Box::new(move |params| {
+                let response = serde_json::from_str::<Req>(params.get()).map(&handler);
+                Box::pin(async move {
+                    let response = response
+                        .map_err(|err| Error::invalid_params().with_data(err.to_string()))?
+                        .await?;
+                    serde_json::value::to_raw_value(&response)
+                        .map(Arc::from)
+                        .map_err(Error::into_internal_error)
+                })
+            }),
+        );
+        self
+    }
+
+    /// See [`ExtRegistry::handle`].
+    pub async fn handle(&self, request: ExtRequest) -> Result<ExtResponse, Error> {
+        let Some(handler) = self.handlers.get(&request.method) else {
+            return Err(Error::method_not_found());
+        };
+        handler(&request.params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct PingRequest {
+        message: String,
+    }
+
+    #[derive(Serialize)]
+    struct PingResponse {
+        echo: String,
+    }
+
+    fn registry() -> ExtRegistry {
+        let mut registry = ExtRegistry::new();
+        registry.register("example.com/ping", |request: PingRequest| async move {
+            Ok(PingResponse {
+                echo: request.message,
+            })
+        });
+        registry
+    }
+
+    fn request(method: &str, params: serde_json::Value) -> ExtRequest {
+        ExtRequest {
+            method: method.into(),
+            params: serde_json::value::to_raw_value(&params).unwrap().into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_to_registered_handler() {
+        let response = registry()
+            .handle(request("example.com/ping", json!({"message": "hello"})))
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(response.get()).unwrap(),
+            json!({"echo": "hello"})
+        );
+    }
+
+    #[cfg(feature = "send")]
+    #[tokio::test]
+    async fn test_send_registry() {
+        let mut registry = SendExtRegistry::new();
+        registry.register("example.com/ping", |request: PingRequest| async move {
+            Ok(PingResponse {
+                echo: request.message,
+            })
+        });
+        let response = tokio::spawn(async move {
+            registry
+                .handle(request("example.com/ping", json!({"message": "hello"})))
+                .await
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(response.get()).unwrap(),
+            json!({"echo": "hello"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unregistered_method() {
+        let error = registry()
+            .handle(request("example.com/unknown", json!({})))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND.code);
+    }
+
+    #[test]
+    fn test_meta_extensions() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Workspace {
+            file_notifications: bool,
+        }
+
+        let mut meta = Meta::new();
+        meta.set_ext(
+            "zed.dev",
+            Workspace {
+                file_notifications: true,
+            },
+        )
+        .unwrap();
+        meta.set_ext("zed.dev/debugMode", true).unwrap();
+        assert_eq!(
+            serde_json::to_value(&meta).unwrap(),
+            json!({
+                "zed.dev": { "fileNotifications": true },
+                "zed.dev/debugMode": true
+            })
+        );
+
+        assert_eq!(
+            meta.get_ext::<Workspace>("zed.dev").unwrap(),
+            Some(Workspace {
+                file_notifications: true
+            })
+        );
+        assert_eq!(meta.get_ext::<bool>("example.com").unwrap(), None);
+        let error = meta.get_ext::<Workspace>("zed.dev/debugMode").unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+
+        for namespace in ["debugMode", "", "/zed.dev", "zed..dev", "-zed.dev/x"] {
+            let error = meta.set_ext(namespace, true).unwrap_err();
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code, "{namespace}");
+        }
+        assert_eq!(meta.remove_ext("zed.dev/debugMode"), Some(json!(true)));
+        assert_eq!(meta.0.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_params() {
+        let error = registry()
+            .handle(request("example.com/ping", json!({"msg": "hello"})))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+    }
+}
@@ -0,0 +1,158 @@
+//! Complete prompt turns, for replaying a conversation when a session is loaded.
+//!
+//! When loading a session, the agent is expected to stream the entire conversation
+//! history back to the client through `session/update` notifications.
+//! [`ConversationHistory`] describes that history one [`ConversationTurn`] at a
+//! time and lowers it into the updates to send.
+//!
+//! See protocol docs: [Loading Sessions](https://agentclientprotocol.com/protocol/session-setup#loading-sessions)
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Client, ContentBlock, Error, SessionId, SessionUpdate, StopReason, ToolCall, replay_updates,
+};
+
+/// A single prompt turn: the user's prompt and everything the agent did in response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationTurn {
+    /// The content of the user's prompt.
+    #[serde(default)]
+    pub user: Vec<ContentBlock>,
+    /// The content of the agent's response.
+    #[serde(default)]
+    pub agent: Vec<ContentBlock>,
+    /// The tool calls the agent made during the turn, in their final state.
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+    /// Why the turn ended.
+    pub stop_reason: StopReason,
+}
+
+impl ConversationTurn {
+    /// The updates that replay this turn.
+    ///
+    /// The user's prompt comes first, followed by the tool calls and then the
+    /// agent's response. The stop reason isn't part of any update, so it isn't
+    /// replayed.
+    pub fn updates(&self) -> impl Iterator<Item = SessionUpdate> + '_ {
+        let user = self
+            .user
+            .iter()
+            .map(|content| SessionUpdate::UserMessageChunk {
+                content: content.clone(),
+            });
+        let tool_calls = self.tool_calls.iter().cloned().map(SessionUpdate::ToolCall);
+        let agent = self
+            .agent
+            .iter()
+            .map(|content| SessionUpdate::AgentMessageChunk {
+                content: content.clone(),
+            });
+        user.chain(tool_calls).chain(agent)
+    }
+}
+
+/// The complete history of a conversation, one turn after the other.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConversationHistory(pub Vec<ConversationTurn>);
+
+impl ConversationHistory {
+    /// Creates an empty history.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a completed turn.
+    pub fn push(&mut self, turn: ConversationTurn) {
+        self.0.push(turn);
+    }
+
+    /// The updates that replay the whole conversation, in order.
+    ///
+    /// See [`ConversationTurn::updates`] for how each turn is lowered.
+    pub fn updates(&self) -> impl Iterator<Item = SessionUpdate> + '_ {
+        self.0.iter().flat_map(ConversationTurn::updates)
+    }
+
+    /// Sends the whole conversation to the client as `session/update`
+    /// notifications, in order.
+    ///
+    /// Call this from [`Agent::load_session`](crate::Agent::load_session) before
+    /// responding, as the protocol requires the whole conversation to be streamed
+    /// first.
+    pub async fn replay(
+        &self,
+        session_id: &SessionId,
+        client: &(impl Client + ?Sized),
+    ) -> Result<(), Error> {
+        replay_updates(session_id, client, self.updates()).await
+    }
+}
+
+impl From<Vec<ConversationTurn>> for ConversationHistory {
+    fn from(turns: Vec<ConversationTurn>) -> Self {
+        Self(turns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{ToolCallId, ToolCallStatus};
+
+    #[test]
+    fn test_updates() {
+        let history = ConversationHistory::from(vec![
+            ConversationTurn {
+                user: vec!["Fix the build".into()],
+                agent: vec!["Done.".into()],
+                tool_calls: vec![
+                    ToolCall::builder(ToolCallId("call_1".into()), "Run cargo build")
+                        .status(ToolCallStatus::Completed)
+                        .build(),
+                ],
+                stop_reason: StopReason::EndTurn,
+            },
+            ConversationTurn {
+                user: vec!["Thanks".into()],
+                agent: Vec::new(),
+                tool_calls: Vec::new(),
+                stop_reason: StopReason::Cancelled,
+            },
+        ]);
+
+        let updates = history
+            .updates()
+            .map(|update| serde_json::to_value(update).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            updates,
+            [
+                json!({
+                    "sessionUpdate": "user_message_chunk",
+                    "content": { "type": "text", "text": "Fix the build" }
+                }),
+                json!({
+                    "sessionUpdate": "tool_call",
+                    "toolCallId": "call_1",
+                    "title": "Run cargo build",
+                    "status": "completed"
+                }),
+                json!({
+                    "sessionUpdate": "agent_message_chunk",
+                    "content": { "type": "text", "text": "Done." }
+                }),
+                json!({
+                    "sessionUpdate": "user_message_chunk",
+                    "content": { "type": "text", "text": "Thanks" }
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Reading the dimensions of images without decoding them.
+//!
+//! Clients often need the size of an [`ImageContent`] to lay out a placeholder
+//! before the image itself is decoded. [`ImageContent::dimensions`] reads it from
+//! the header of PNG, JPEG, GIF and WebP images, decoding only as much of the
+//! base64 data as the header takes up.
+
+use crate::ImageContent;
+
+impl ImageContent {
+    /// The width and height of the image in pixels, read from its header.
+    ///
+    /// Supports PNG, JPEG, GIF and WebP, detected from the data rather than the
+    /// MIME type. Returns `None` for other formats, and for data that isn't valid
+    /// base64 or whose header is malformed.
+    #[must_use]
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        let mut bytes = Base64Decoder::new(&self.data);
+        let header: Vec<u8> = bytes.by_ref().take(30).collect();
+        if header.starts_with(b"\x89PNG\r\n\x1a\n") && header.get(12..16) == Some(b"IHDR") {
+            let width = u32::from_be_bytes(header.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(header.get(20..24)?.try_into().ok()?);
+            Some((width, height))
+        } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+            Some((u16_le(&header, 6)?.into(), u16_le(&header, 8)?.into()))
+        } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+            webp_dimensions(&header[12..])
+        } else if let Some(rest) = header.strip_prefix(b"\xff\xd8") {
+            jpeg_dimensions(rest.iter().copied().chain(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads the dimensions from the first chunk of a WebP image.
+fn webp_dimensions(chunk: &[u8]) -> Option<(u32, u32)> {
+    match chunk.get(0..4)? {
+        // Lossy: 14-bit width and height in the key frame header, after its start code.
+        b"VP8 " if chunk.get(11..14) == Some(&[0x9d, 0x01, 0x2a]) => Some((
+            u32::from(u16_le(chunk, 14)? & 0x3fff),
+            u32::from(u16_le(chunk, 16)? & 0x3fff),
+        )),
+        // Lossless: 14-bit width and height minus one, after the signature byte.
+        b"VP8L" if chunk.get(8) == Some(&0x2f) => {
+            let bits = u32::from_le_bytes(chunk.get(9..13)?.try_into().ok()?);
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        // Extended: 24-bit canvas width and height minus one, after the flags.
+        b"VP8X" => {
+            let size = chunk.get(12..18)?;
+            let width = u32::from_le_bytes([size[0], size[1], size[2], 0]);
+            let height = u32::from_le_bytes([size[3], size[4], size[5], 0]);
+            Some((width + 1, height + 1))
+        }
+        _ => None,
+    }
+}
+
+/// Reads the dimensions from the first start-of-frame segment of a JPEG image,
+/// given the bytes after its start-of-image marker.
+fn jpeg_dimensions(mut bytes: impl Iterator<Item = u8>) -> Option<(u32, u32)> {
+    loop {
+        if bytes.next()? != 0xff {
+            return None;
+        }
+        let mut marker = bytes.next()?;
+        while marker == 0xff {
+            marker = bytes.next()?;
+        }
+        match marker {
+            // Markers without a segment.
+            0x01 | 0xd0..=0xd7 => continue,
+            // The image data or its end came before any frame.
+            0xd9 | 0xda => return None,
+            // Start of frame, except for the DHT, JPG and DAC markers in that range.
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let segment: Vec<u8> = bytes.take(7).collect();
+                let height = u16::from_be_bytes(segment.get(3..5)?.try_into().ok()?);
+                let width = u16::from_be_bytes(segment.get(5..7)?.try_into().ok()?);
+                return Some((width.into(), height.into()));
+            }
+            _ => {
+                let length = u16::from_be_bytes([bytes.next()?, bytes.next()?]);
+                // The length includes its own two bytes.
+                let skip = usize::from(length).checked_sub(2)?;
+                if skip > 0 {
+                    bytes.nth(skip - 1)?;
+                }
+            }
+        }
+    }
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// Lazily decodes standard base64, ending at padding or the first invalid byte.
+struct Base64Decoder<'a> {
+    input: std::slice::Iter<'a, u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl<'a> Base64Decoder<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes().iter(),
+            buffer: 0,
+            bits: 0,
+        }
+    }
+}
+
+impl Iterator for Base64Decoder<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        while self.bits < 8 {
+            let value = match *self.input.next()? {
+                b @ b'A'..=b'Z' => b - b'A',
+                b @ b'a'..=b'z' => b - b'a' + 26,
+                b @ b'0'..=b'9' => b - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            self.buffer = ((self.buffer << 6) | u32::from(value)) & 0x3fff;
+            self.bits += 6;
+        }
+        self.bits -= 8;
+        Some((self.buffer >> self.bits) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ContentBlock;
+
+    fn dimensions(data: &str) -> Option<(u32, u32)> {
+        let ContentBlock::Image(image) = ContentBlock::image(data, "image/png") else {
+            unreachable!()
+        };
+        image.dimensions()
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        assert_eq!(
+            dimensions("iVBORw0KGgoAAAANSUhEUgAAAoAAAAHgCAYAAAA10dzk"),
+            Some((640, 480))
+        );
+        assert_eq!(dimensions("R0lGODlhIAAQAAAAAA=="), Some((32, 16)));
+        // The frame header comes after an APP0 segment, which is skipped.
+        assert_eq!(
+            dimensions("/9j/4AAQSkZJRgABAQAAAQABAAD/wAALCADIASwBAREA"),
+            Some((300, 200))
+        );
+        assert_eq!(
+            dimensions("UklGRhYAAABXRUJQVlA4WAoAAAAAAAAAfwcANwQA"),
+            Some((1920, 1080))
+        );
+
+        // Unknown formats, truncated headers and invalid base64.
+        assert_eq!(dimensions("aGVsbG8gd29ybGQ="), None);
+        assert_eq!(dimensions("iVBORw0KGgo="), None);
+        assert_eq!(dimensions("/9j/4AAQ"), None);
+        assert_eq!(dimensions("not base64!"), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Coalescing of streamed message chunks into complete messages.
+//!
+//! Agents stream their output as many small `session/update` notifications, often a
+//! few tokens at a time. [`MessageAccumulator`] groups those chunks back into whole
+//! messages so that UIs can render complete paragraphs instead of fragments.
+
+use std::collections::HashMap;
+
+use crate::{ContentBlock, SessionId, SessionNotification, SessionUpdate, TextContent};
+
+/// The kind of chunks an [`AccumulatedMessage`] was built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    /// Built from [`SessionUpdate::UserMessageChunk`] updates.
+    User,
+    /// Built from [`SessionUpdate::AgentMessageChunk`] updates.
+    Agent,
+    /// Built from [`SessionUpdate::AgentThoughtChunk`] updates.
+    Thought,
+}
+
+/// A message coalesced from consecutive chunks of the same kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccumulatedMessage {
+    /// The kind of chunks this message was built from.
+    pub kind: MessageKind,
+    /// The message content.
+    ///
+    /// Adjacent text chunks are merged into a single text block; other content,
+    /// such as images and resources, is kept as separate blocks in arrival order.
+    pub content: Vec<ContentBlock>,
+}
+
+/// Groups streamed message chunks into complete messages, per session.
+///
+/// Feed every [`SessionNotification`] received by the client into
+/// [`MessageAccumulator::push`]. Consecutive chunks of the same [`MessageKind`]
+/// are appended to the same message, while a chunk of a different kind starts a
+/// new one. Redacted thought chunks are skipped, as they aren't meant to be
+/// displayed. Tool calls, tool call updates and plan updates end the current
+/// message, so chunks sent after them start a new message even if they are of
+/// the same kind.
+///
+/// The accumulated messages are kept until [`MessageAccumulator::end_turn`] is
+/// called, which should happen once the `session/prompt` request completes.
+#[derive(Debug, Default)]
+pub struct MessageAccumulator {
+    sessions: HashMap<SessionId, SessionMessages>,
+}
+
+#[derive(Debug, Default)]
+struct SessionMessages {
+    messages: Vec<AccumulatedMessage>,
+    /// Whether the last message may still receive chunks.
+    open: bool,
+}
+
+impl MessageAccumulator {
+    /// Creates an empty accumulator.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a session update.
+    ///
+    /// Updates that neither carry message chunks nor end the current message,
+    /// such as mode or available command changes, are ignored.
+    pub fn push(&mut self, notification: &SessionNotification) {
+        let (kind, content) = match &notification.update {
+            SessionUpdate::UserMessageChunk { content } => (MessageKind::User, content),
+            SessionUpdate::AgentMessageChunk { content } => (MessageKind::Agent, content),
+            SessionUpdate::AgentThoughtChunk {
+                content,
+                redacted: false,
+                ..
+            } => (MessageKind::Thought, content),
+            // Redacted reasoning isn't meant to be displayed.
+            SessionUpdate::AgentThoughtChunk { redacted: true, .. } => return,
+            SessionUpdate::ToolCall(_)
+            | SessionUpdate::ToolCallUpdate(_)
+            | SessionUpdate::ToolCallOutputChunk { .. }
+            | SessionUpdate::Plan(_)
+            | SessionUpdate::PlanEntryUpdate(_) => {
+                if let Some(session) = self.sessions.get_mut(&notification.session_id) {
+                    session.open = false;
+                }
+                return;
+            }
+            SessionUpdate::AvailableCommandsUpdate { .. }
+            | SessionUpdate::CurrentModeUpdate { .. }
+            | SessionUpdate::CurrentModelUpdate { .. }
+            | SessionUpdate::TerminalOutputChunk { .. }
+            | SessionUpdate::Diagnostic { .. }
+            | SessionUpdate::SuggestedPrompts { .. }
+            | SessionUpdate::UsageUpdate(_)
+            | SessionUpdate::Progress { .. }
+            | SessionUpdate::SessionClosed { .. } => return,
+        };
+
+        let session = self
+            .sessions
+            .entry(notification.session_id.clone())
+            .or_default();
+        match session.messages.last_mut() {
+            Some(message) if session.open && message.kind == kind => {
+                append_content(&mut message.content, content.clone());
+            }
+            _ => session.messages.push(AccumulatedMessage {
+                kind,
+                content: vec![content.clone()],
+            }),
+        }
+        session.open = true;
+    }
+
+    /// Returns the messages accumulated for the given session during the current turn.
+    ///
+    /// The last message may still grow as more chunks arrive.
+    #[must_use]
+    pub fn messages(&self, session_id: &SessionId) -> &[AccumulatedMessage] {
+        self.sessions
+            .get(session_id)
+            .map_or(&[], |session| session.messages.as_slice())
+    }
+
+    /// Ends the current prompt turn for the given session, returning and clearing
+    /// its accumulated messages.
+    pub fn end_turn(&mut self, session_id: &SessionId) -> Vec<AccumulatedMessage> {
+        self.sessions
+            .remove(session_id)
+            .map(|session| session.messages)
+            .unwrap_or_default()
+    }
+}
+
+fn append_content(content: &mut Vec<ContentBlock>, block: ContentBlock) {
+    match (content.last_mut(), block) {
+        (
+            Some(ContentBlock::Text(last)),
+            ContentBlock::Text(TextContent {
+                annotations,
+                text,
+                meta,
+            }),
+        ) if last.annotations == annotations && last.meta == meta => {
+            last.text.push_str(&text);
+        }
+        (_, block) => content.push(block),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Plan, ToolCall, ToolCallId};
+
+    fn notification(session_id: &str, update: SessionUpdate) -> SessionNotification {
+        SessionNotification {
+            session_id: SessionId(session_id.into()),
+            update,
+            turn_id: None,
+            meta: None,
+        }
+    }
+
+    fn agent_chunk(session_id: &str, content: impl Into<ContentBlock>) -> SessionNotification {
+        notification(
+            session_id,
+            SessionUpdate::AgentMessageChunk {
+                content: content.into(),
+            },
+        )
+    }
+
+    fn thought_chunk(session_id: &str, content: impl Into<ContentBlock>) -> SessionNotification {
+        notification(
+            session_id,
+            SessionUpdate::AgentThoughtChunk {
+                content: content.into(),
+                signature: None,
+                redacted: false,
+            },
+        )
+    }
+
+    #[test]
+    fn test_coalesces_consecutive_chunks() {
+        let mut accumulator = MessageAccumulator::new();
+        accumulator.push(&thought_chunk("s1", "Let me "));
+        accumulator.push(&notification(
+            "s1",
+            SessionUpdate::AgentThoughtChunk {
+                content: ContentBlock::from("EuYBCkQYAiJA"),
+                signature: Some("sig_123".to_string()),
+                redacted: true,
+            },
+        ));
+        accumulator.push(&thought_chunk("s1", "think."));
+        accumulator.push(&agent_chunk("s1", "Hello, "));
+        accumulator.push(&agent_chunk("s1", "world!"));
+        accumulator.push(&agent_chunk(
+            "s1",
+            ContentBlock::image("iVBORw0KGgo=", "image/png"),
+        ));
+        accumulator.push(&agent_chunk("s1", "Done."));
+
+        assert_eq!(
+            accumulator.messages(&SessionId("s1".into())),
+            &[
+                AccumulatedMessage {
+                    kind: MessageKind::Thought,
+                    content: vec![ContentBlock::text("Let me think.")],
+                },
+                AccumulatedMessage {
+                    kind: MessageKind::Agent,
+                    content: vec![
+                        ContentBlock::text("Hello, world!"),
+                        ContentBlock::image("iVBORw0KGgo=", "image/png"),
+                        ContentBlock::text("Done."),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_calls_and_plans_flush_messages() {
+        let mut accumulator = MessageAccumulator::new();
+        accumulator.push(&agent_chunk("s1", "Reading the file."));
+        accumulator.push(&notification(
+            "s1",
+            SessionUpdate::ToolCall(
+                ToolCall::builder(ToolCallId("call_1".into()), "Read file").build(),
+            ),
+        ));
+        accumulator.push(&agent_chunk("s1", "Making a plan."));
+        accumulator.push(&notification(
+            "s1",
+            SessionUpdate::Plan(Plan {
+                entries: Vec::new(),
+                meta: None,
+            }),
+        ));
+        accumulator.push(&agent_chunk("s1", "All done."));
+
+        let messages = accumulator.messages(&SessionId("s1".into()));
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.content.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                vec![ContentBlock::text("Reading the file.")],
+                vec![ContentBlock::text("Making a plan.")],
+                vec![ContentBlock::text("All done.")],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sessions_are_independent_and_cleared_on_end_turn() {
+        let mut accumulator = MessageAccumulator::new();
+        accumulator.push(&agent_chunk("s1", "one"));
+        accumulator.push(&agent_chunk("s2", "two"));
+
+        let ended = accumulator.end_turn(&SessionId("s1".into()));
+        assert_eq!(
+            ended,
+            vec![AccumulatedMessage {
+                kind: MessageKind::Agent,
+                content: vec![ContentBlock::text("one")],
+            }]
+        );
+        assert!(accumulator.messages(&SessionId("s1".into())).is_empty());
+        assert_eq!(accumulator.messages(&SessionId("s2".into())).len(), 1);
+    }
+}
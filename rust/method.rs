@@ -0,0 +1,255 @@
+//! Typed names of the core protocol methods.
+//!
+//! [`AcpMethod`] lets proxies and other middleware that observe raw messages, for
+//! example through [`StreamReceiver`](crate::StreamReceiver), match on methods
+//! without hardcoding their wire names.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    Error,
+    agent::{
+        AUTHENTICATE_METHOD_NAME, INITIALIZE_METHOD_NAME, SESSION_CANCEL_METHOD_NAME,
+        SESSION_CANCEL_TOOL_CALL_METHOD_NAME, SESSION_INVOKE_COMMAND_METHOD_NAME,
+        SESSION_LIST_COMMANDS_METHOD_NAME, SESSION_LOAD_METHOD_NAME, SESSION_NEW_METHOD_NAME,
+        SESSION_PROMPT_METHOD_NAME, SESSION_SET_MODE_METHOD_NAME, SESSION_SET_MODEL_METHOD_NAME,
+        TERMINAL_EXITED_NOTIFICATION,
+    },
+    client::{
+        FS_DELETE_FILE_METHOD_NAME, FS_GREP_METHOD_NAME, FS_LIST_DIRECTORY_METHOD_NAME,
+        FS_READ_TEXT_FILE_CHUNK_METHOD_NAME, FS_READ_TEXT_FILE_METHOD_NAME,
+        FS_RENAME_FILE_METHOD_NAME, FS_SEARCH_METHOD_NAME, FS_STAT_METHOD_NAME,
+        FS_WRITE_TEXT_FILE_METHOD_NAME, SESSION_REQUEST_PERMISSION_METHOD_NAME,
+        SESSION_REQUEST_USER_INPUT_METHOD_NAME, SESSION_UPDATE_NOTIFICATION,
+        TERMINAL_CREATE_METHOD_NAME, TERMINAL_INPUT_METHOD_NAME, TERMINAL_KILL_METHOD_NAME,
+        TERMINAL_OUTPUT_METHOD_NAME, TERMINAL_RELEASE_METHOD_NAME, TERMINAL_RESIZE_METHOD_NAME,
+        TERMINAL_WAIT_FOR_EXIT_METHOD_NAME,
+    },
+};
+
+/// A core protocol method, handled either by the agent or by the client.
+///
+/// Extension methods, whose names start with an underscore, are not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AcpMethod {
+    // Agent methods
+    /// `initialize`
+    Initialize,
+    /// `authenticate`
+    Authenticate,
+    /// `session/new`
+    SessionNew,
+    /// `session/load`
+    SessionLoad,
+    /// `session/set_mode`
+    SessionSetMode,
+    /// `session/prompt`
+    SessionPrompt,
+    /// `session/cancel` (notification)
+    SessionCancel,
+    /// `session/set_model`
+    SessionSetModel,
+    /// `session/list_commands`
+    SessionListCommands,
+    /// `session/invoke_command`
+    SessionInvokeCommand,
+    /// `session/cancel_tool_call` (notification)
+    SessionCancelToolCall,
+    /// `terminal/exited` (notification)
+    TerminalExited,
+
+    // Client methods
+    /// `session/request_permission`
+    SessionRequestPermission,
+    /// `session/request_user_input`
+    SessionRequestUserInput,
+    /// `session/update` (notification)
+    SessionUpdate,
+    /// `fs/write_text_file`
+    FsWriteTextFile,
+    /// `fs/read_text_file`
+    FsReadTextFile,
+    /// `fs/read_text_file_chunk`
+    FsReadTextFileChunk,
+    /// `fs/list_directory`
+    FsListDirectory,
+    /// `fs/delete_file`
+    FsDeleteFile,
+    /// `fs/rename_file`
+    FsRenameFile,
+    /// `fs/stat`
+    FsStat,
+    /// `fs/search`
+    FsSearch,
+    /// `fs/grep`
+    FsGrep,
+    /// `terminal/create`
+    TerminalCreate,
+    /// `terminal/output`
+    TerminalOutput,
+    /// `terminal/release`
+    TerminalRelease,
+    /// `terminal/wait_for_exit`
+    TerminalWaitForExit,
+    /// `terminal/kill`
+    TerminalKill,
+    /// `terminal/input`
+    TerminalInput,
+    /// `terminal/resize`
+    TerminalResize,
+}
+
+impl AcpMethod {
+    /// Every core method, agent methods first.
+    pub const ALL: &[AcpMethod] = &[
+        AcpMethod::Initialize,
+        AcpMethod::Authenticate,
+        AcpMethod::SessionNew,
+        AcpMethod::SessionLoad,
+        AcpMethod::SessionSetMode,
+        AcpMethod::SessionPrompt,
+        AcpMethod::SessionCancel,
+        AcpMethod::SessionSetModel,
+        AcpMethod::SessionListCommands,
+        AcpMethod::SessionInvokeCommand,
+        AcpMethod::SessionCancelToolCall,
+        AcpMethod::TerminalExited,
+        AcpMethod::SessionRequestPermission,
+        AcpMethod::SessionRequestUserInput,
+        AcpMethod::SessionUpdate,
+        AcpMethod::FsWriteTextFile,
+        AcpMethod::FsReadTextFile,
+        AcpMethod::FsReadTextFileChunk,
+        AcpMethod::FsListDirectory,
+        AcpMethod::FsDeleteFile,
+        AcpMethod::FsRenameFile,
+        AcpMethod::FsStat,
+        AcpMethod::FsSearch,
+        AcpMethod::FsGrep,
+        AcpMethod::TerminalCreate,
+        AcpMethod::TerminalOutput,
+        AcpMethod::TerminalRelease,
+        AcpMethod::TerminalWaitForExit,
+        AcpMethod::TerminalKill,
+        AcpMethod::TerminalInput,
+        AcpMethod::TerminalResize,
+    ];
+
+    /// The method name used on the wire, e.g. `"session/prompt"`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            AcpMethod::Initialize => INITIALIZE_METHOD_NAME,
+            AcpMethod::Authenticate => AUTHENTICATE_METHOD_NAME,
+            AcpMethod::SessionNew => SESSION_NEW_METHOD_NAME,
+            AcpMethod::SessionLoad => SESSION_LOAD_METHOD_NAME,
+            AcpMethod::SessionSetMode => SESSION_SET_MODE_METHOD_NAME,
+            AcpMethod::SessionPrompt => SESSION_PROMPT_METHOD_NAME,
+            AcpMethod::SessionCancel => SESSION_CANCEL_METHOD_NAME,
+            AcpMethod::SessionSetModel => SESSION_SET_MODEL_METHOD_NAME,
+            AcpMethod::SessionListCommands => SESSION_LIST_COMMANDS_METHOD_NAME,
+            AcpMethod::SessionInvokeCommand => SESSION_INVOKE_COMMAND_METHOD_NAME,
+            AcpMethod::SessionCancelToolCall => SESSION_CANCEL_TOOL_CALL_METHOD_NAME,
+            AcpMethod::TerminalExited => TERMINAL_EXITED_NOTIFICATION,
+            AcpMethod::SessionRequestPermission => SESSION_REQUEST_PERMISSION_METHOD_NAME,
+            AcpMethod::SessionRequestUserInput => SESSION_REQUEST_USER_INPUT_METHOD_NAME,
+            AcpMethod::SessionUpdate => SESSION_UPDATE_NOTIFICATION,
+            AcpMethod::FsWriteTextFile => FS_WRITE_TEXT_FILE_METHOD_NAME,
+            AcpMethod::FsReadTextFile => FS_READ_TEXT_FILE_METHOD_NAME,
+            AcpMethod::FsReadTextFileChunk => FS_READ_TEXT_FILE_CHUNK_METHOD_NAME,
+            AcpMethod::FsListDirectory => FS_LIST_DIRECTORY_METHOD_NAME,
+            AcpMethod::FsDeleteFile => FS_DELETE_FILE_METHOD_NAME,
+            AcpMethod::FsRenameFile => FS_RENAME_FILE_METHOD_NAME,
+            AcpMethod::FsStat => FS_STAT_METHOD_NAME,
+            AcpMethod::FsSearch => FS_SEARCH_METHOD_NAME,
+            AcpMethod::FsGrep => FS_GREP_METHOD_NAME,
+            AcpMethod::TerminalCreate => TERMINAL_CREATE_METHOD_NAME,
+            AcpMethod::TerminalOutput => TERMINAL_OUTPUT_METHOD_NAME,
+            AcpMethod::TerminalRelease => TERMINAL_RELEASE_METHOD_NAME,
+            AcpMethod::TerminalWaitForExit => TERMINAL_WAIT_FOR_EXIT_METHOD_NAME,
+            AcpMethod::TerminalKill => TERMINAL_KILL_METHOD_NAME,
+            AcpMethod::TerminalInput => TERMINAL_INPUT_METHOD_NAME,
+            AcpMethod::TerminalResize => TERMINAL_RESIZE_METHOD_NAME,
+        }
+    }
+
+    /// Returns `true` if the method is sent as a notification rather than a request.
+    #[must_use]
+    pub const fn is_notification(self) -> bool {
+        matches!(
+            self,
+            AcpMethod::SessionCancel
+                | AcpMethod::SessionCancelToolCall
+                | AcpMethod::TerminalExited
+                | AcpMethod::SessionUpdate
+        )
+    }
+}
+
+impl Display for AcpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses a wire method name, failing with [`Error::method_not_found`] for names
+/// that aren't core methods, including extension methods.
+impl FromStr for AcpMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AcpMethod::ALL
+            .iter()
+            .copied()
+            .find(|method| method.as_str() == s)
+            .ok_or_else(Error::method_not_found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AGENT_METHOD_NAMES, CLIENT_METHOD_NAMES, ErrorCode};
+
+    #[test]
+    fn test_round_trip() {
+        for method in AcpMethod::ALL {
+            assert_eq!(method.as_str().parse::<AcpMethod>().unwrap(), *method);
+            assert_eq!(method.to_string(), method.as_str());
+        }
+        assert_eq!(
+            "session/prompt".parse::<AcpMethod>().unwrap(),
+            AcpMethod::SessionPrompt
+        );
+    }
+
+    #[test]
+    fn test_covers_method_names() {
+        let names = serde_json::to_value(AGENT_METHOD_NAMES)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .values()
+            .chain(
+                serde_json::to_value(CLIENT_METHOD_NAMES)
+                    .unwrap()
+                    .as_object()
+                    .unwrap()
+                    .values(),
+            )
+            .map(|name| name.as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), AcpMethod::ALL.len());
+        for name in names {
+            assert!(name.parse::<AcpMethod>().is_ok(), "{name} is not covered");
+        }
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let error = "_example.com/ping".parse::<AcpMethod>().unwrap_err();
+        assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND.code);
+        assert!(AcpMethod::SessionUpdate.is_notification());
+        assert!(!AcpMethod::SessionPrompt.is_notification());
+    }
+}
@@ -8,6 +8,8 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::{Error, Meta};
+
 /// An execution plan for accomplishing complex tasks.
 ///
 /// Plans consist of multiple entries representing individual tasks or goals.
@@ -22,12 +24,72 @@ pub struct Plan {
     ///
     /// When updating a plan, the agent must send a complete list of all entries
     /// with their current status. The client replaces the entire plan with each update.
+    /// Individual entries can instead be updated with a [`PlanEntryUpdate`].
     pub entries: Vec<PlanEntry>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
 
+impl Plan {
+    /// Applies a [`PlanEntryUpdate`] to the entry at its index.
+    ///
+    /// Fields that are not set in the update are left unchanged. Returns an
+    /// invalid params error if the index is out of range, in which case the plan
+    /// is left unchanged.
+    pub fn apply_entry_update(&mut self, update: PlanEntryUpdate) -> Result<(), Error> {
+        let len = self.entries.len();
+        let entry = usize::try_from(update.index)
+            .ok()
+            .and_then(|index| self.entries.get_mut(index))
+            .ok_or_else(|| {
+                Error::invalid_params().with_data(serde_json::json!({
+                    "index": update.index,
+                    "entries": len,
+                }))
+            })?;
+
+        if let Some(content) = update.content {
+            entry.content = content;
+        }
+        if let Some(priority) = update.priority {
+            entry.priority = priority;
+        }
+        if let Some(status) = update.status {
+            entry.status = status;
+        }
+        Ok(())
+    }
+}
+
+/// An update to a single entry of the current plan.
+///
+/// Allows agents to report progress on one task without resending the whole plan.
+/// Only the fields being changed need to be included.
+///
+/// Clients must have received a full plan through a `plan` session update before
+/// any entry updates; the index refers to the position of the entry in that plan.
+///
+/// See protocol docs: [Updating Plan Entries](https://agentclientprotocol.com/protocol/agent-plan#updating-plan-entries)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanEntryUpdate {
+    /// Zero-based position of the entry in the current plan.
+    pub index: u32,
+    /// Update the description of the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Update the priority of the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<PlanEntryPriority>,
+    /// Update the execution status of the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<PlanEntryStatus>,
+    /// Extension point for implementations
+    #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
+    pub meta: Option<serde_json::Value>,
+}
+
 /// A single entry in the execution plan.
 ///
 /// Represents a task or goal that the assistant intends to accomplish
@@ -45,7 +107,7 @@ pub struct PlanEntry {
     pub status: PlanEntryStatus,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
-    pub meta: Option<serde_json::Value>,
+    pub meta: Option<Meta>,
 }
 
 /// Priority levels for plan entries.
@@ -53,7 +115,7 @@ pub struct PlanEntry {
 /// Used to indicate the relative importance or urgency of different
 /// tasks in the execution plan.
 /// See protocol docs: [Plan Entries](https://agentclientprotocol.com/protocol/agent-plan#plan-entries)
-#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum PlanEntryPriority {
     /// High priority task - critical to the overall goal.
@@ -68,7 +130,7 @@ pub enum PlanEntryPriority {
 ///
 /// Tracks the lifecycle of each task from planning through completion.
 /// See protocol docs: [Plan Entries](https://agentclientprotocol.com/protocol/agent-plan#plan-entries)
-#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum PlanEntryStatus {
     /// The task has not started yet.
@@ -78,3 +140,58 @@ pub enum PlanEntryStatus {
     /// The task has been successfully completed.
     Completed,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> Plan {
+        Plan {
+            entries: ["Read the code", "Fix the bug"]
+                .into_iter()
+                .map(|content| PlanEntry {
+                    content: content.to_string(),
+                    priority: PlanEntryPriority::Medium,
+                    status: PlanEntryStatus::Pending,
+                    meta: None,
+                })
+                .collect(),
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_entry_update() {
+        let mut plan = plan();
+        let update: PlanEntryUpdate = serde_json::from_value(serde_json::json!({
+            "index": 1,
+            "status": "in_progress"
+        }))
+        .unwrap();
+        plan.apply_entry_update(update).unwrap();
+
+        assert_eq!(plan.entries[0].status, PlanEntryStatus::Pending);
+        assert_eq!(plan.entries[1].status, PlanEntryStatus::InProgress);
+        assert_eq!(plan.entries[1].priority, PlanEntryPriority::Medium);
+        assert_eq!(plan.entries[1].content, "Fix the bug");
+    }
+
+    #[test]
+    fn test_apply_entry_update_out_of_range() {
+        let mut plan = plan();
+        let error = plan
+            .apply_entry_update(PlanEntryUpdate {
+                index: 2,
+                content: None,
+                priority: None,
+                status: Some(PlanEntryStatus::Completed),
+                meta: None,
+            })
+            .unwrap_err();
+        assert_eq!(error.code, crate::ErrorCode::INVALID_PARAMS.code);
+        assert_eq!(
+            error.data,
+            Some(serde_json::json!({"index": 2, "entries": 2}))
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Handling of prompts sent while another prompt turn is still running.
+//!
+//! The protocol expects a single prompt turn per session at a time, but clients may
+//! send a follow-up `session/prompt` before the previous turn ended. A [`PromptQueue`]
+//! lets the agent decide what happens then, according to its [`PromptQueuePolicy`].
+//!
+//! See protocol docs: [Concurrent Prompts](https://agentclientprotocol.com/protocol/prompt-turn#concurrent-prompts)
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+};
+use parking_lot::Mutex;
+
+use crate::{Error, PromptResponse, SessionId, StopReason};
+
+/// What a [`PromptQueue`] does with a prompt that arrives while another prompt
+/// turn is running for the same session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PromptQueuePolicy {
+    /// Fail the new prompt with [`Error::prompt_in_progress`].
+    #[default]
+    Reject,
+    /// Run the new prompt once every earlier prompt for the session has ended, in
+    /// the order they arrived.
+    Queue,
+    /// End the running turn with [`StopReason::Cancelled`] and run the new prompt
+    /// in its place. Prompts still waiting for their turn are cancelled as well.
+    Interrupt,
+}
+
+/// Runs the prompt turns of each session one at a time, according to a
+/// [`PromptQueuePolicy`].
+///
+/// Cloning the queue is cheap, and all clones share the same state, so it can be
+/// held by the agent and moved into spawned tasks alike.
+///
+/// # Example
+///
+/// ```no_run
+/// use agent_client_protocol::{
+///     Error, PromptQueue, PromptQueuePolicy, PromptRequest, PromptResponse,
+/// };
+///
+/// # async fn run_turn(_request: PromptRequest) -> Result<PromptResponse, Error> { todo!() }
+/// async fn prompt(queue: &PromptQueue, request: PromptRequest) -> Result<PromptResponse, Error> {
+///     queue.run(request.session_id.clone(), run_turn(request)).await
+/// }
+///
+/// let queue = PromptQueue::new(PromptQueuePolicy::Queue);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PromptQueue {
+    policy: PromptQueuePolicy,
+    sessions: Arc<Mutex<HashMap<SessionId, SessionTurns>>>,
+}
+
+#[derive(Debug, Default)]
+struct SessionTurns {
+    /// Interrupts the running turn.
+    interrupt: Option<oneshot::Sender<()>>,
+    /// Prompts waiting for the running turn to end, each woken with the receiver
+    /// that interrupts it once it runs.
+    waiting: VecDeque<oneshot::Sender<oneshot::Receiver<()>>>,
+}
+
+impl PromptQueue {
+    /// Creates a queue that handles concurrent prompts according to `policy`.
+    #[must_use]
+    pub fn new(policy: PromptQueuePolicy) -> Self {
+        Self {
+            policy,
+            sessions: Arc::default(),
+        }
+    }
+
+    /// The policy applied to concurrent prompts.
+    #[must_use]
+    pub fn policy(&self) -> PromptQueuePolicy {
+        self.policy
+    }
+
+    /// Returns `true` if a prompt turn is running for the given session.
+    #[must_use]
+    pub fn is_running(&self, session_id: &SessionId) -> bool {
+        self.sessions.lock().contains_key(session_id)
+    }
+
+    /// Runs `turn` as the prompt turn of the given session once the policy allows
+    /// it.
+    ///
+    /// If the turn is interrupted, or cancelled while waiting, `turn` is dropped
+    /// and the prompt is answered with [`StopReason::Cancelled`].
+    pub async fn run(
+        &self,
+        session_id: SessionId,
+        turn: impl Future<Output = Result<PromptResponse, Error>>,
+    ) -> Result<PromptResponse, Error> {
+        let wait = {
+            let mut sessions = self.sessions.lock();
+            match sessions.get_mut(&session_id) {
+                None => {
+                    let (interrupt, interrupted) = oneshot::channel();
+                    sessions.insert(
+                        session_id.clone(),
+                        SessionTurns {
+                            interrupt: Some(interrupt),
+                            waiting: VecDeque::new(),
+                        },
+                    );
+                    Either::Left(interrupted)
+                }
+                Some(_) if self.policy == PromptQueuePolicy::Reject => {
+                    return Err(Error::prompt_in_progress(session_id.0.as_ref()));
+                }
+                Some(turns) => {
+                    if self.policy == PromptQueuePolicy::Interrupt {
+                        // Dropping the senders cancels the prompts still waiting.
+                        turns.waiting.clear();
+                        if let Some(interrupt) = turns.interrupt.take() {
+                            interrupt.send(()).ok();
+                        }
+                    }
+                    let (wake, woken) = oneshot::channel();
+                    turns.waiting.push_back(wake);
+                    Either::Right(woken)
+                }
+            }
+        };
+
+        let interrupted = match wait {
+            Either::Left(interrupted) => interrupted,
+            Either::Right(woken) => match woken.await {
+                Ok(interrupted) => interrupted,
+                Err(oneshot::Canceled) => return Ok(cancelled()),
+            },
+        };
+        let _running = RunningTurn {
+            sessions: &self.sessions,
+            session_id: &session_id,
+        };
+
+        let turn = std::pin::pin!(turn);
+        match future::select(turn, interrupted).await {
+            Either::Left((response, _)) => response,
+            Either::Right((Ok(()), _)) => Ok(cancelled()),
+            // The interrupt is only dropped once the turn ended.
+            Either::Right((Err(oneshot::Canceled), turn)) => turn.await,
+        }
+    }
+}
+
+/// Hands the session over to the next waiting prompt when the running turn ends.
+struct RunningTurn<'a> {
+    sessions: &'a Mutex<HashMap<SessionId, SessionTurns>>,
+    session_id: &'a SessionId,
+}
+
+impl Drop for RunningTurn<'_> {
+    fn drop(&mut self) {
+        let mut sessions = self.sessions.lock();
+        let Some(turns) = sessions.get_mut(self.session_id) else {
+            return;
+        };
+        while let Some(wake) = turns.waiting.pop_front() {
+            let (interrupt, interrupted) = oneshot::channel();
+            // Prompts whose request was dropped while waiting are skipped.
+            if wake.send(interrupted).is_ok() {
+                turns.interrupt = Some(interrupt);
+                return;
+            }
+        }
+        sessions.remove(self.session_id);
+    }
+}
+
+fn cancelled() -> PromptResponse {
+    PromptResponse {
+        stop_reason: StopReason::Cancelled,
+        usage: None,
+        refusal: None,
+        meta: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::ErrorKind;
+
+    fn session(id: &str) -> SessionId {
+        SessionId(id.into())
+    }
+
+    fn end_turn() -> PromptResponse {
+        PromptResponse {
+            stop_reason: StopReason::EndTurn,
+            usage: None,
+            refusal: None,
+            meta: None,
+        }
+    }
+
+    /// A turn that ends once `release` is sent, logging when it starts and ends.
+    async fn turn(
+        log: &Mutex<Vec<String>>,
+        name: &str,
+        release: oneshot::Receiver<()>,
+    ) -> Result<PromptResponse, Error> {
+        log.lock().push(format!("{name} started"));
+        release.await.ok();
+        log.lock().push(format!("{name} ended"));
+        Ok(end_turn())
+    }
+
+    #[test]
+    fn test_reject() {
+        let queue = PromptQueue::new(PromptQueuePolicy::Reject);
+        let log = Mutex::new(Vec::new());
+        let (release, released) = oneshot::channel();
+
+        let (first, second, other, ()) = block_on(async {
+            futures::join!(
+                queue.run(session("s1"), turn(&log, "first", released)),
+                queue.run(session("s1"), async { Ok(end_turn()) }),
+                queue.run(session("s2"), async { Ok(end_turn()) }),
+                async { release.send(()).unwrap() },
+            )
+        });
+        assert_eq!(first.unwrap().stop_reason, StopReason::EndTurn);
+        assert_eq!(
+            second.unwrap_err().kind(),
+            Some(ErrorKind::PromptInProgress)
+        );
+        assert_eq!(other.unwrap().stop_reason, StopReason::EndTurn);
+        assert!(!queue.is_running(&session("s1")));
+    }
+
+    #[test]
+    fn test_queue() {
+        let queue = PromptQueue::new(PromptQueuePolicy::Queue);
+        let log = Mutex::new(Vec::new());
+        let (release_first, first_released) = oneshot::channel();
+        let (release_second, second_released) = oneshot::channel();
+
+        let (first, second, ()) = block_on(async {
+            futures::join!(
+                queue.run(session("s1"), turn(&log, "first", first_released)),
+                queue.run(session("s1"), turn(&log, "second", second_released)),
+                async {
+                    assert_eq!(*log.lock(), ["first started"]);
+                    release_first.send(()).unwrap();
+                    release_second.send(()).unwrap();
+                },
+            )
+        });
+        assert_eq!(first.unwrap().stop_reason, StopReason::EndTurn);
+        assert_eq!(second.unwrap().stop_reason, StopReason::EndTurn);
+        assert_eq!(
+            *log.lock(),
+            [
+                "first started",
+                "first ended",
+                "second started",
+                "second ended"
+            ]
+        );
+        assert!(!queue.is_running(&session("s1")));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let queue = PromptQueue::new(PromptQueuePolicy::Interrupt);
+        let log = Mutex::new(Vec::new());
+        let (_release_first, first_released) = oneshot::channel();
+        let (_release_second, second_released) = oneshot::channel();
+        let (release_third, third_released) = oneshot::channel();
+
+        let (first, second, third, ()) = block_on(async {
+            futures::join!(
+                queue.run(session("s1"), turn(&log, "first", first_released)),
+                queue.run(session("s1"), turn(&log, "second", second_released)),
+                queue.run(session("s1"), turn(&log, "third", third_released)),
+                async { release_third.send(()).unwrap() },
+            )
+        });
+        assert_eq!(first.unwrap().stop_reason, StopReason::Cancelled);
+        assert_eq!(second.unwrap().stop_reason, StopReason::Cancelled);
+        assert_eq!(third.unwrap().stop_reason, StopReason::EndTurn);
+        assert_eq!(
+            *log.lock(),
+            ["first started", "third started", "third ended"]
+        );
+        assert!(!queue.is_running(&session("s1")));
+    }
+}
@@ -0,0 +1,135 @@
+//! Spans for the requests a connection sends and handles.
+//!
+//! With the `tracing` feature, every request gets a `tracing` span recording its
+//! method, ID, session ID and duration, and an event once it's answered. Without
+//! the feature, [`RequestSpan`] does nothing and connections only emit the usual
+//! `log` lines.
+
+use std::future::Future;
+
+use serde::Serialize;
+use serde_json::value::RawValue;
+
+use crate::Error;
+
+/// The span of a single request, sent to the peer or received from it.
+///
+/// Outgoing requests are traced from the moment they're sent until their response
+/// is delivered to the caller. Incoming requests are traced from the moment they're
+/// received, including any time spent waiting for a slot in the request queue,
+/// until their response is sent.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    started: Option<std::time::Instant>,
+}
+
+#[cfg(feature = "tracing")]
+impl RequestSpan {
+    pub(crate) fn outgoing(id: i32, method: &str, params: Option<&impl Serialize>) -> Self {
+        let span = tracing::debug_span!(
+            "acp.request",
+            direction = "outgoing",
+            method,
+            id,
+            session_id = tracing::field::Empty,
+        );
+        // Serializing the params is only worth it if the span is recorded.
+        if !span.is_disabled()
+            && let Some(session_id) = params
+                .and_then(|params| serde_json::to_value(params).ok())
+                .as_ref()
+                .and_then(|params| params.get("sessionId")?.as_str())
+        {
+            span.record("session_id", session_id);
+        }
+        Self::started(span)
+    }
+
+    pub(crate) fn incoming(id: i32, method: &str, params: Option<&RawValue>) -> Self {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params {
+            session_id: Option<String>,
+        }
+
+        let span = tracing::debug_span!(
+            "acp.request",
+            direction = "incoming",
+            method,
+            id,
+            session_id = tracing::field::Empty,
+        );
+        if !span.is_disabled()
+            && let Some(session_id) = params
+                .and_then(|params| serde_json::from_str::<Params>(params.get()).ok())
+                .and_then(|params| params.session_id)
+        {
+            span.record("session_id", session_id);
+        }
+        Self::started(span)
+    }
+
+    fn started(span: tracing::Span) -> Self {
+        Self {
+            started: (!span.is_disabled()).then(std::time::Instant::now),
+            span,
+        }
+    }
+
+    /// Records that the response to an outgoing request arrived and was matched to it.
+    pub(crate) fn response_matched(&self, is_error: bool) {
+        tracing::debug!(parent: &self.span, is_error, "matched response to pending request");
+    }
+
+    /// Runs `future` within the span, then records how the request finished.
+    pub(crate) async fn run<T>(
+        self,
+        future: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let result = tracing::Instrument::instrument(future, self.span.clone()).await;
+        self.finished(result.as_ref().map(|_| ()));
+        result
+    }
+
+    /// Records that the request finished, along with how long it took.
+    pub(crate) fn finished(&self, result: Result<(), &Error>) {
+        let duration_ms = self.started.map_or(0, |started| {
+            u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX)
+        });
+        match result {
+            Ok(()) => tracing::debug!(parent: &self.span, duration_ms, "request finished"),
+            Err(error) => tracing::debug!(
+                parent: &self.span,
+                duration_ms,
+                error.code = error.code,
+                error.message = %error.message,
+                "request failed"
+            ),
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl RequestSpan {
+    pub(crate) fn outgoing(_id: i32, _method: &str, _params: Option<&impl Serialize>) -> Self {
+        Self {}
+    }
+
+    pub(crate) fn incoming(_id: i32, _method: &str, _params: Option<&RawValue>) -> Self {
+        Self {}
+    }
+
+    pub(crate) fn response_matched(&self, _is_error: bool) {}
+
+    pub(crate) async fn run<T>(
+        self,
+        future: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        future.await
+    }
+
+    pub(crate) fn finished(&self, _result: Result<(), &Error>) {}
+}
@@ -0,0 +1,316 @@
+//! URIs identifying resources in content and context.
+//!
+//! Resources are identified by URIs like `file:///home/user/main.rs` or
+//! `zed://project/notes`. Peers regularly send bare paths instead, or file URIs
+//! whose paths aren't percent-encoded, so [`ResourceUri`] keeps the string it was
+//! given as-is and offers [`ResourceUri::parse`] to reject malformed ones.
+//!
+//! Deserialization is lenient so that existing payloads keep working. Check the
+//! URI with [`ResourceUri::is_valid`], or deserialize your own types with
+//! [`ResourceUri::deserialize_strict`] to reject invalid ones at the boundary.
+//!
+//! See: [Resource URIs](https://agentclientprotocol.com/protocol/content#resource-uris)
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::Error;
+
+/// A URI identifying a resource, such as `file:///home/user/main.rs`.
+///
+/// Local files are identified by `file` URIs with an absolute, percent-encoded
+/// path. Malformed URIs sent by older implementations are preserved as-is.
+///
+/// See protocol docs: [Resource URIs](https://agentclientprotocol.com/protocol/content#resource-uris)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct ResourceUri(Arc<str>);
+
+impl ResourceUri {
+    /// Parses a URI, failing with an `invalid_params` error if it is malformed.
+    ///
+    /// A valid URI starts with a scheme followed by `:`, and contains no
+    /// whitespace, control characters, or incomplete percent-encodings. `file` URIs
+    /// must also have an absolute path, as in `file:///home/user/main.rs`.
+    /// Single-letter schemes are rejected, since they're almost always Windows
+    /// drive letters.
+    pub fn parse(uri: impl Into<Arc<str>>) -> Result<Self, Error> {
+        let uri = Self(uri.into());
+        match uri.validate() {
+            Ok(()) => Ok(uri),
+            Err(reason) => Err(Error::invalid_params()
+                .with_data(format!("invalid resource URI `{}`: {reason}", uri.0))),
+        }
+    }
+
+    /// Builds a `file` URI from an absolute path, percent-encoding it as needed.
+    ///
+    /// Returns `None` if the path is relative or isn't valid UTF-8.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        if !path.is_absolute() {
+            return None;
+        }
+        let path = path.to_str()?;
+        #[cfg(windows)]
+        let path = format!("/{}", path.replace('\\', "/"));
+
+        let mut uri = String::from("file://");
+        for byte in path.bytes() {
+            if byte.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@".contains(&byte) {
+                uri.push(char::from(byte));
+            } else {
+                uri.push_str(&format!("%{byte:02X}"));
+            }
+        }
+        Some(Self(uri.into()))
+    }
+
+    /// Deserializes a URI, failing if it isn't valid according to [`ResourceUri::parse`].
+    ///
+    /// Use it with `#[serde(deserialize_with = "ResourceUri::deserialize_strict")]`.
+    pub fn deserialize_strict<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let uri = Arc::<str>::deserialize(deserializer)?;
+        Self::parse(uri).map_err(serde::de::Error::custom)
+    }
+
+    /// The URI exactly as it was given.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the URI is valid according to [`ResourceUri::parse`].
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// The scheme of the URI, such as `file` or `https`, or `None` if it doesn't
+    /// start with one.
+    #[must_use]
+    pub fn scheme(&self) -> Option<&str> {
+        let (scheme, _) = self.0.split_once(':')?;
+        let mut chars = scheme.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+            && scheme.len() > 1;
+        valid.then_some(scheme)
+    }
+
+    /// The local path a `file` URI refers to, with percent-encodings decoded.
+    ///
+    /// Returns `None` for other schemes and for files on remote hosts.
+    #[must_use]
+    pub fn as_path(&self) -> Option<PathBuf> {
+        if !self.scheme()?.eq_ignore_ascii_case("file") {
+            return None;
+        }
+        let rest = self.0.split_once(':')?.1.strip_prefix("//")?;
+        let (host, path) = rest.split_at(rest.find('/')?);
+        if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+            return None;
+        }
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let path = percent_decode(path)?;
+        #[cfg(windows)]
+        let path = match path.strip_prefix('/') {
+            Some(stripped) if stripped.as_bytes().get(1) == Some(&b':') => stripped.to_string(),
+            _ => path,
+        };
+        Some(PathBuf::from(path))
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        let Some(scheme) = self.scheme() else {
+            return Err("missing scheme");
+        };
+        if self.0.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err("contains whitespace or control characters");
+        }
+        let bytes = self.0.as_bytes();
+        for (index, _) in self.0.match_indices('%') {
+            let is_encoded = bytes
+                .get(index + 1..index + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+            if !is_encoded {
+                return Err("incomplete percent-encoding");
+            }
+        }
+        if scheme.eq_ignore_ascii_case("file") {
+            let has_path = self.0[scheme.len() + 1..]
+                .strip_prefix("//")
+                .is_some_and(|rest| rest.contains('/'));
+            if !has_path {
+                return Err("file URIs must have an absolute path");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes `%XX` sequences, returning `None` if the result isn't valid UTF-8.
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match (byte, tail) {
+            (b'%', [high, low, ..]) => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl fmt::Display for ResourceUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for ResourceUri {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ResourceUri {
+    fn from(uri: String) -> Self {
+        Self(uri.into())
+    }
+}
+
+impl From<&str> for ResourceUri {
+    fn from(uri: &str) -> Self {
+        Self(uri.into())
+    }
+}
+
+impl PartialEq<str> for ResourceUri {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for ResourceUri {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid() {
+        for uri in [
+            "file:///home/user/main.rs",
+            "file://localhost/home/user/main.rs",
+            "file://server/share/main.rs",
+            "file:///home/user/my%20notes.md",
+            "https://example.com/docs?page=2#intro",
+            "zed://project/notes",
+        ] {
+            assert!(ResourceUri::parse(uri).is_ok(), "{uri} should be valid");
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for uri in [
+            "",
+            "/home/user/main.rs",
+            "main.rs",
+            "C:\\Users\\me\\main.rs",
+            "file:///home/user/my notes.md",
+            "file:///home/user/100%.md",
+            "file:main.rs",
+            "file://",
+        ] {
+            assert!(
+                ResourceUri::parse(uri).is_err(),
+                "{uri:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_scheme() {
+        assert_eq!(ResourceUri::from("file:///a").scheme(), Some("file"));
+        assert_eq!(ResourceUri::from("zed://project").scheme(), Some("zed"));
+        assert_eq!(ResourceUri::from("/home/user").scheme(), None);
+        assert_eq!(ResourceUri::from("C:/Users").scheme(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_as_path() {
+        assert_eq!(
+            ResourceUri::from("file:///home/user/my%20notes.md").as_path(),
+            Some(PathBuf::from("/home/user/my notes.md"))
+        );
+        assert_eq!(
+            ResourceUri::from("file://localhost/tmp/a.rs#L10").as_path(),
+            Some(PathBuf::from("/tmp/a.rs"))
+        );
+        assert_eq!(
+            ResourceUri::from("file://server/share/a.rs").as_path(),
+            None
+        );
+        assert_eq!(
+            ResourceUri::from("https://example.com/a.rs").as_path(),
+            None
+        );
+        assert_eq!(ResourceUri::from("/home/user/main.rs").as_path(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_path_round_trip() {
+        let path = Path::new("/home/user/my notes/100%.md");
+        let uri = ResourceUri::from_path(path).unwrap();
+        assert_eq!(uri, "file:///home/user/my%20notes/100%25.md");
+        assert!(uri.is_valid());
+        assert_eq!(uri.as_path().as_deref(), Some(path));
+        assert_eq!(ResourceUri::from_path(Path::new("relative.rs")), None);
+    }
+
+    #[test]
+    fn test_deserialization() {
+        #[derive(Deserialize)]
+        struct Strict {
+            #[serde(deserialize_with = "ResourceUri::deserialize_strict")]
+            uri: ResourceUri,
+        }
+
+        let lenient: ResourceUri = serde_json::from_str(r#""/home/user/main.rs""#).unwrap();
+        assert_eq!(lenient, "/home/user/main.rs");
+        assert!(!lenient.is_valid());
+
+        let strict: Strict =
+            serde_json::from_str(r#"{ "uri": "file:///home/user/main.rs" }"#).unwrap();
+        assert_eq!(strict.uri, "file:///home/user/main.rs");
+        assert!(serde_json::from_str::<Strict>(r#"{ "uri": "/home/user/main.rs" }"#).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Retrying requests that failed with a transient error.
+//!
+//! Requests sent over a flaky transport can fail even though the peer could handle
+//! them, for example because the peer was momentarily overloaded. [`with_retry`]
+//! sends such a request again with exponential backoff for as long as the error is
+//! [retryable](Error::is_retryable), waiting for the [`retry_after`](Error::retry_after)
+//! hint instead whenever the peer provides one.
+//!
+//! Only requests that merely read state, such as reading a file or a terminal's
+//! output, can be retried. Sending a request that changes state again could apply
+//! the change twice if the first attempt succeeded but its response got lost.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher as _, Hasher as _},
+    time::Duration,
+};
+
+use crate::{
+    Error, GrepResponse, ListDirectoryResponse, ReadTextFileChunkResponse, ReadTextFileResponse,
+    SearchFilesResponse, StatResponse, TerminalOutputResponse, rpc::is_connection_closed,
+};
+
+/// How often and how long to wait before retrying a failed request.
+///
+/// The delay before the `n`th retry is `base_delay * 2^(n - 1)`, capped at
+/// `max_delay`, of which up to a `jitter` fraction is randomly taken off so that
+/// many callers failing at once don't retry in lockstep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryConfig {
+    /// The maximum number of times the request is sent, including the first attempt.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub base_delay: Duration,
+    /// The longest delay between two attempts, including any delay the peer asks
+    /// for with [`Error::retry_after`].
+    pub max_delay: Duration,
+    /// The fraction of each delay, between `0.0` and `1.0`, that is randomized.
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// The backoff delay before the given retry, where `1` is the first retry.
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(retry);
+        #[allow(clippy::cast_precision_loss)]
+        let random = hasher.finish() as f64 / u64::MAX as f64;
+        delay.mul_f64(1.0 - jitter * random)
+    }
+}
+
+/// Sends a request with `request` and retries it according to `config` while it
+/// fails with a [retryable](Error::is_retryable) error.
+///
+/// The `sleep` function must return a future that resolves after the given
+/// duration (e.g., `tokio::time::sleep`), which keeps this crate independent of
+/// any particular async runtime.
+///
+/// Only [idempotent reads](RetryableResponse) can be retried, and never once the
+/// connection has been closed. Notifications are never retried: they can only fail
+/// locally, and resending them could deliver them twice.
+///
+/// # Example
+///
+/// ```no_run
+/// use agent_client_protocol::{
+///     AgentSideConnection, Client, Error, ReadTextFileRequest, ReadTextFileResponse,
+///     RetryConfig, with_retry,
+/// };
+///
+/// async fn read(
+///     conn: &AgentSideConnection,
+///     request: ReadTextFileRequest,
+/// ) -> Result<ReadTextFileResponse, Error> {
+///     with_retry(&RetryConfig::default(), tokio::time::sleep, || {
+///         conn.read_text_file(request.clone())
+///     })
+///     .await
+/// }
+/// ```
+pub async fn with_retry<T, F, S>(
+    config: &RetryConfig,
+    sleep: impl Fn(Duration) -> S,
+    mut request: impl FnMut() -> F,
+) -> Result<T, Error>
+where
+    T: RetryableResponse,
+    F: Future<Output = Result<T, Error>>,
+    S: Future<Output = ()>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(error)
+                if error.is_retryable()
+                    && !is_connection_closed(&error)
+                    && attempt < config.max_attempts =>
+            {
+                sleep(error.retry_after().map_or_else(
+                    || config.backoff(attempt),
+                    |delay| delay.min(config.max_delay),
+                ))
+                .await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The response to a request that [`with_retry`] can send again.
+///
+/// This is implemented for the responses of the requests that only read state on
+/// the client: reading a text file or a chunk of it, [`stat`](crate::Client::stat),
+/// [`search_files`](crate::Client::search_files), [`grep`](crate::Client::grep),
+/// [`terminal_output`](crate::Client::terminal_output) and
+/// [`list_directory`](crate::Client::list_directory). It can't be implemented
+/// outside of this crate.
+pub trait RetryableResponse: sealed::Sealed {}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_retryable_response {
+    ($($response:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $response {}
+            impl RetryableResponse for $response {}
+        )*
+    };
+}
+
+impl_retryable_response!(
+    ReadTextFileResponse,
+    ReadTextFileChunkResponse,
+    StatResponse,
+    SearchFilesResponse,
+    GrepResponse,
+    TerminalOutputResponse,
+    ListDirectoryResponse,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+
+    #[test]
+    fn test_backoff() {
+        let config = RetryConfig {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            jitter: 0.0,
+        };
+        assert_eq!(config.backoff(1), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(200));
+        assert_eq!(config.backoff(3), Duration::from_millis(300));
+        assert_eq!(config.backoff(40), Duration::from_millis(300));
+
+        let config = RetryConfig {
+            jitter: 0.5,
+            ..config
+        };
+        for retry in 1..10 {
+            let delay = config.backoff(retry);
+            let max = Duration::from_millis(100 * 2u64.pow(retry - 1)).min(config.max_delay);
+            assert!(delay <= max && delay >= max / 2, "{delay:?} for {max:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_capped() {
+        let config = RetryConfig {
+            max_attempts: 2,
+            max_delay: Duration::from_secs(1),
+            ..RetryConfig::default()
+        };
+        let delays = std::cell::RefCell::new(Vec::new());
+        let mut attempts = 0;
+        let result = with_retry(
+            &config,
+            |delay| {
+                delays.borrow_mut().push(delay);
+                std::future::ready(())
+            },
+            || {
+                attempts += 1;
+                std::future::ready(Err::<StatResponse, _>(
+                    Error::too_many_requests().with_retry_after(Duration::from_secs(60)),
+                ))
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap_err().code, ErrorCode::TOO_MANY_REQUESTS.code);
+        assert_eq!(attempts, 2);
+        assert_eq!(*delays.borrow(), [Duration::from_secs(1)]);
+    }
+}
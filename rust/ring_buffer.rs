@@ -0,0 +1,212 @@
+//! Bounded storage for terminal output.
+//!
+//! Clients that support `terminal/create` must honor the request's
+//! `outputByteLimit` by keeping only the most recent output. [`RingBuffer`]
+//! implements those semantics so that every client truncates the same way.
+//!
+//! See: [Terminals](https://agentclientprotocol.com/protocol/terminals)
+
+use std::collections::VecDeque;
+
+use crate::{TerminalExitStatus, TerminalOutputResponse};
+
+/// Retains the last bytes written to a terminal, up to a byte limit.
+///
+/// Output is kept from the tail: once more than `limit` bytes have been
+/// written, the oldest bytes are dropped first. The end of a command's output
+/// usually holds what an agent needs most, such as the final error or the test
+/// summary, while the beginning is mostly progress noise.
+///
+/// Truncation always happens at a UTF-8 character boundary, so the retained
+/// output may be up to three bytes shorter than the limit.
+#[derive(Debug, Clone, Default)]
+pub struct RingBuffer {
+    bytes: VecDeque<u8>,
+    limit: Option<usize>,
+    total_bytes: u64,
+}
+
+impl RingBuffer {
+    /// Creates a buffer that keeps at most `limit` bytes, or all output if
+    /// `limit` is `None`.
+    ///
+    /// Pass the `output_byte_limit` of the [`CreateTerminalRequest`](crate::CreateTerminalRequest).
+    #[must_use]
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            bytes: VecDeque::new(),
+            limit: limit.map(|limit| usize::try_from(limit).unwrap_or(usize::MAX)),
+            total_bytes: 0,
+        }
+    }
+
+    /// Appends output from the terminal, dropping the oldest bytes if the limit
+    /// is exceeded.
+    ///
+    /// Output may be split anywhere, including in the middle of a character.
+    pub fn write(&mut self, output: &[u8]) {
+        self.total_bytes = self.total_bytes.saturating_add(output.len() as u64);
+        let Some(limit) = self.limit else {
+            self.bytes.extend(output);
+            return;
+        };
+
+        if output.len() >= limit {
+            self.bytes.clear();
+            self.bytes.extend(&output[output.len() - limit..]);
+        } else {
+            let excess = (self.bytes.len() + output.len()).saturating_sub(limit);
+            self.bytes.drain(..excess);
+            self.bytes.extend(output);
+        }
+
+        if self.truncated() {
+            // Don't start in the middle of a character that was cut in two.
+            while self
+                .bytes
+                .front()
+                .is_some_and(|byte| is_continuation(*byte))
+            {
+                self.bytes.pop_front();
+            }
+        }
+    }
+
+    /// The retained output.
+    ///
+    /// Invalid UTF-8, including a character whose remaining bytes haven't been
+    /// written yet, is replaced with U+FFFD.
+    #[must_use]
+    pub fn output(&self) -> String {
+        let (front, back) = self.bytes.as_slices();
+        if back.is_empty() {
+            String::from_utf8_lossy(front).into_owned()
+        } else {
+            String::from_utf8_lossy(&[front, back].concat()).into_owned()
+        }
+    }
+
+    /// The number of bytes currently retained.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether no output is retained.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The number of bytes written since the buffer was created, including the
+    /// ones that were dropped.
+    #[must_use]
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Whether any output has been dropped to stay within the limit.
+    #[must_use]
+    pub fn truncated(&self) -> bool {
+        self.total_bytes > self.bytes.len() as u64
+    }
+
+    /// Builds the `terminal/output` response for the current contents.
+    #[must_use]
+    pub fn to_response(&self, exit_status: Option<TerminalExitStatus>) -> TerminalOutputResponse {
+        TerminalOutputResponse {
+            output: self.output(),
+            truncated: self.truncated(),
+            total_bytes: Some(self.total_bytes),
+            stdout: None,
+            stderr: None,
+            exit_status,
+            meta: None,
+        }
+    }
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(limit: Option<u64>, chunks: &[&str]) -> RingBuffer {
+        let mut buffer = RingBuffer::new(limit);
+        for chunk in chunks {
+            buffer.write(chunk.as_bytes());
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let buffer = written(None, &["hello ", "world"]);
+        assert_eq!(buffer.output(), "hello world");
+        assert!(!buffer.truncated());
+        assert_eq!(buffer.total_bytes(), 11);
+    }
+
+    #[test]
+    fn test_exactly_at_limit() {
+        let buffer = written(Some(11), &["hello ", "world"]);
+        assert_eq!(buffer.output(), "hello world");
+        assert!(!buffer.truncated());
+    }
+
+    #[test]
+    fn test_one_byte_over_limit_keeps_tail() {
+        let buffer = written(Some(10), &["hello ", "world"]);
+        assert_eq!(buffer.output(), "ello world");
+        assert!(buffer.truncated());
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.total_bytes(), 11);
+    }
+
+    #[test]
+    fn test_single_write_larger_than_limit() {
+        let buffer = written(Some(3), &["abcdef"]);
+        assert_eq!(buffer.output(), "def");
+        assert!(buffer.truncated());
+    }
+
+    #[test]
+    fn test_zero_limit() {
+        let buffer = written(Some(0), &["abc"]);
+        assert!(buffer.is_empty());
+        assert!(buffer.truncated());
+        assert_eq!(buffer.total_bytes(), 3);
+    }
+
+    #[test]
+    fn test_truncates_at_character_boundary() {
+        // "é" is two bytes, so keeping the last four bytes would start inside it.
+        let buffer = written(Some(4), &["aé✓"]);
+        assert_eq!(buffer.output(), "✓");
+        assert_eq!(buffer.len(), 3);
+        assert!(buffer.truncated());
+    }
+
+    #[test]
+    fn test_character_split_across_writes() {
+        let check = "✓".as_bytes();
+        let mut buffer = RingBuffer::new(Some(8));
+        buffer.write(b"ok ");
+        buffer.write(&check[..1]);
+        assert_eq!(buffer.output(), "ok \u{FFFD}");
+        buffer.write(&check[1..]);
+        assert_eq!(buffer.output(), "ok ✓");
+        assert!(!buffer.truncated());
+    }
+
+    #[test]
+    fn test_to_response() {
+        let response = written(Some(4), &["line 1\n", "line 2\n"]).to_response(None);
+        assert_eq!(response.output, "e 2\n");
+        assert!(response.truncated);
+        assert_eq!(response.total_bytes, Some(14));
+    }
+}
@@ -1,22 +1,23 @@
 use std::{
     any::Any,
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     rc::Rc,
     sync::{
         Arc,
-        atomic::{AtomicI32, Ordering},
+        atomic::{AtomicI32, AtomicU64, Ordering},
     },
+    time::Duration,
 };
 
 use anyhow::Result;
 use futures::{
-    AsyncBufReadExt as _, AsyncRead, AsyncWrite, AsyncWriteExt as _, FutureExt as _,
-    StreamExt as _,
+    AsyncBufRead, AsyncBufReadExt as _, AsyncRead, AsyncReadExt as _, AsyncWrite,
+    AsyncWriteExt as _, FutureExt as _, Stream, StreamExt as _,
     channel::{
         mpsc::{self, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
-    future::LocalBoxFuture,
+    future::{self, BoxFuture, Either, Fuse, LocalBoxFuture},
     io::BufReader,
     select_biased,
 };
@@ -24,19 +25,33 @@ use parking_lot::Mutex;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::value::RawValue;
 
-use crate::stream_broadcast::{StreamBroadcast, StreamSender};
-use crate::{Error, StreamReceiver};
+use crate::request_span::RequestSpan;
+use crate::stream_broadcast::{StreamBroadcast, StreamMessageInfo, StreamSender};
+use crate::{ContentEncoding, Error, StreamMessageDirection, StreamReceiver};
 
 pub struct RpcConnection<Local: Side, Remote: Side> {
-    outgoing_tx: UnboundedSender<OutgoingMessage<Local, Remote>>,
+    outgoing_tx: UnboundedSender<OutgoingFrame<Local, Remote>>,
     pending_responses: Arc<Mutex<HashMap<i32, PendingResponse>>>,
     next_id: AtomicI32,
     broadcast: StreamBroadcast,
+    request_timeout: Option<(Duration, Timer)>,
+    request_queue: Arc<Mutex<RequestQueue<Local>>>,
+    keepalive: Arc<Mutex<Option<Keepalive>>>,
+    framing: Arc<Mutex<Framing>>,
+    content_encoding: Arc<Mutex<Option<ContentEncoding>>>,
+    message_limit: Arc<Mutex<MessageLimit>>,
+    stats: Arc<StatsCounters>,
 }
 
+/// Produces a future that resolves once the given duration has elapsed.
+///
+/// Supplied by the caller so that request timeouts don't depend on a specific runtime.
+pub type Timer = Arc<dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync>;
+
 struct PendingResponse {
     deserialize: fn(&serde_json::value::RawValue) -> Result<Box<dyn Any + Send>, Error>,
     respond: oneshot::Sender<Result<Box<dyn Any + Send>, Error>>,
+    span: RequestSpan,
 }
 
 impl<Local, Remote> RpcConnection<Local, Remote>
@@ -48,60 +63,214 @@ where
         handler: Handler,
         outgoing_bytes: impl Unpin + AsyncWrite,
         incoming_bytes: impl Unpin + AsyncRead,
+        content_encoding: Arc<Mutex<Option<ContentEncoding>>>,
         spawn: impl Fn(LocalBoxFuture<'static, ()>) + 'static,
     ) -> (Self, impl futures::Future<Output = Result<()>>)
     where
         Handler: MessageHandler<Local> + 'static,
     {
+        let (this, incoming_rx, io_task) =
+            Self::with_io(outgoing_bytes, incoming_bytes, content_encoding);
+        Self::handle_incoming(
+            this.outgoing_tx.clone(),
+            incoming_rx,
+            this.request_queue.clone(),
+            handler,
+            spawn,
+        );
+        (this, io_task)
+    }
+
+    /// Like [`Self::new`], but spawns handler futures as [`Send`] futures and returns a
+    /// [`Send`] IO task, so that the connection can be driven by a multi-threaded runtime.
+    #[cfg(feature = "send")]
+    pub fn new_send<Handler>(
+        handler: Handler,
+        outgoing_bytes: impl Unpin + AsyncWrite + Send,
+        incoming_bytes: impl Unpin + AsyncRead + Send,
+        content_encoding: Arc<Mutex<Option<ContentEncoding>>>,
+        spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) -> (Self, impl futures::Future<Output = Result<()>> + Send)
+    where
+        Handler: SendMessageHandler<Local> + 'static,
+    {
+        let (this, incoming_rx, io_task) =
+            Self::with_io(outgoing_bytes, incoming_bytes, content_encoding);
+        Self::handle_incoming_send(
+            this.outgoing_tx.clone(),
+            incoming_rx,
+            this.request_queue.clone(),
+            handler,
+            spawn,
+        );
+        (this, io_task)
+    }
+
+    fn with_io<Out: Unpin + AsyncWrite, In: Unpin + AsyncRead>(
+        outgoing_bytes: Out,
+        incoming_bytes: In,
+        content_encoding: Arc<Mutex<Option<ContentEncoding>>>,
+    ) -> (
+        Self,
+        UnboundedReceiver<IncomingMessage<Local>>,
+        impl futures::Future<Output = Result<()>>,
+    ) {
         let (incoming_tx, incoming_rx) = mpsc::unbounded();
         let (outgoing_tx, outgoing_rx) = mpsc::unbounded();
 
         let pending_responses = Arc::new(Mutex::new(HashMap::default()));
         let (broadcast_tx, broadcast) = StreamBroadcast::new();
+        let keepalive = Arc::new(Mutex::new(None));
+        let framing = Arc::new(Mutex::new(Framing::default()));
+        let message_limit = Arc::new(Mutex::new(MessageLimit::default()));
+        let stats = Arc::new(StatsCounters::default());
 
         let io_task = {
             let pending_responses = pending_responses.clone();
+            let keepalive = keepalive.clone();
+            let framing = framing.clone();
+            let content_encoding = content_encoding.clone();
+            let message_limit = message_limit.clone();
+            let stats = stats.clone();
             async move {
+                // Read when the task first runs, so that they can still be configured
+                // after the connection is constructed.
+                let keepalive = keepalive.lock().clone();
+                let framing = *framing.lock();
+                let message_limit = *message_limit.lock();
+                broadcast_tx.connection_opened();
                 let result = Self::handle_io(
                     incoming_tx,
                     outgoing_rx,
-                    outgoing_bytes,
-                    incoming_bytes,
+                    FrameWriter {
+                        bytes: outgoing_bytes,
+                        framing,
+                        content_encoding,
+                    },
+                    framing.read_frames(incoming_bytes, message_limit),
                     pending_responses.clone(),
-                    broadcast_tx,
+                    &broadcast_tx,
+                    &stats,
+                    keepalive,
                 )
                 .await;
                 pending_responses.lock().clear();
-                result
+                broadcast_tx.connection_closed(&result);
+                result.map(|_| ())
             }
         };
 
-        Self::handle_incoming(outgoing_tx.clone(), incoming_rx, handler, spawn);
-
         let this = Self {
             outgoing_tx,
             pending_responses,
             next_id: AtomicI32::new(0),
             broadcast,
+            request_timeout: None,
+            request_queue: Arc::new(Mutex::new(RequestQueue::default())),
+            keepalive,
+            framing,
+            content_encoding,
+            message_limit,
+            stats,
         };
 
-        (this, io_task)
+        (this, incoming_rx, io_task)
     }
 
     pub fn subscribe(&self) -> StreamReceiver {
         self.broadcast.receiver()
     }
 
+    pub fn subscribe_filtered(
+        &self,
+        filter: impl Fn(&StreamMessageInfo<'_>) -> bool + Send + Sync + 'static,
+    ) -> StreamReceiver {
+        self.broadcast.filtered_receiver(filter)
+    }
+
+    /// Counts of the messages exchanged so far.
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats
+            .snapshot(self.pending_responses.lock().len() as u64)
+    }
+
+    /// Applies `timeout` to every request sent through [`Self::request`].
+    pub fn set_request_timeout(&mut self, timeout: Duration, timer: Timer) {
+        self.request_timeout = Some((timeout, timer));
+    }
+
+    /// Makes the IO task send a `$/ping` request every `interval`, and fail with
+    /// [`PeerUnreachable`] once `max_missed` consecutive pings went unanswered.
+    ///
+    /// Only takes effect if called before the IO task is first polled.
+    pub fn set_keepalive(&self, interval: Duration, max_missed: u32, timer: Timer) {
+        *self.keepalive.lock() = Some(Keepalive {
+            interval,
+            max_missed: max_missed.max(1),
+            timer,
+        });
+    }
+
+    /// Sets how messages are delimited on the byte streams.
+    ///
+    /// Only takes effect if called before the IO task is first polled.
+    pub fn set_framing(&self, framing: Framing) {
+        *self.framing.lock() = framing;
+    }
+
+    /// Rejects incoming messages larger than `max_bytes`, handling them according
+    /// to `policy`.
+    ///
+    /// Only takes effect if called before the IO task is first polled.
+    pub fn set_max_message_size(&self, max_bytes: usize, policy: OversizedMessagePolicy) {
+        *self.message_limit.lock() = MessageLimit { max_bytes, policy };
+    }
+
+    /// Compresses large outgoing messages with `encoding` from now on, or stops
+    /// compressing them if `None`.
+    ///
+    /// Only applies with [`Framing::ContentLength`].
+    pub fn set_content_encoding(&self, encoding: Option<ContentEncoding>) {
+        *self.content_encoding.lock() = encoding;
+    }
+
+    /// Bounds how many incoming requests are handled at once.
+    ///
+    /// Requests received while `max_concurrent` are in flight wait in a queue of up to
+    /// `max_queued` entries, and are rejected with [`Error::too_many_requests`]
+    /// (`-32003`) once that queue is full. A batch counts as a single request. Notifications are
+    /// never limited.
+    pub fn set_max_concurrent_requests(&self, max_concurrent: usize, max_queued: usize) {
+        self.request_queue.lock().limit = Some(RequestLimit {
+            max_concurrent: max_concurrent.max(1),
+            max_queued,
+        });
+    }
+
+    /// Closes the outgoing channel, which lets the IO task finish, and fails every
+    /// request that is still awaiting a response.
+    ///
+    /// Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        self.outgoing_tx.close_channel();
+        for (_, pending_response) in self.pending_responses.lock().drain() {
+            pending_response.respond.send(Err(connection_closed())).ok();
+        }
+    }
+
     pub fn notify(
         &self,
         method: impl Into<Arc<str>>,
         params: Option<Remote::InNotification>,
     ) -> Result<(), Error> {
         self.outgoing_tx
-            .unbounded_send(OutgoingMessage::Notification {
-                method: method.into(),
-                params,
-            })
+            .unbounded_send(
+                OutgoingMessage::Notification {
+                    method: method.into(),
+                    params,
+                }
+                .into(),
+            )
             .map_err(|_| Error::internal_error().with_data("failed to send notification"))
     }
 
@@ -109,9 +278,22 @@ where
         &self,
         method: impl Into<Arc<str>>,
         params: Option<Remote::InRequest>,
+    ) -> impl Future<Output = Result<Out, Error>> {
+        self.request_with_timeout(method, params, self.request_timeout.clone())
+    }
+
+    /// Sends a request that resolves with [`Error::request_timeout`] if no response
+    /// arrives before the timeout elapses, as measured by the paired [`Timer`].
+    pub fn request_with_timeout<Out: DeserializeOwned + Send + 'static>(
+        &self,
+        method: impl Into<Arc<str>>,
+        params: Option<Remote::InRequest>,
+        timeout: Option<(Duration, Timer)>,
     ) -> impl Future<Output = Result<Out, Error>> {
         let (tx, rx) = oneshot::channel();
         let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let method = method.into();
+        let span = RequestSpan::outgoing(id, &method, params.as_ref());
         self.pending_responses.lock().insert(
             id,
             PendingResponse {
@@ -123,136 +305,302 @@ where
                         })
                 },
                 respond: tx,
+                span: span.clone(),
             },
         );
 
         if self
             .outgoing_tx
-            .unbounded_send(OutgoingMessage::Request {
-                id,
-                method: method.into(),
-                params,
-            })
+            .unbounded_send(OutgoingMessage::Request { id, method, params }.into())
             .is_err()
+            && let Some(pending_response) = self.pending_responses.lock().remove(&id)
         {
-            self.pending_responses.lock().remove(&id);
+            pending_response.respond.send(Err(connection_closed())).ok();
         }
-        async move {
-            let result = rx
-                .await
-                .map_err(|_| Error::internal_error().with_data("server shut down unexpectedly"))??
+
+        let pending_responses = self.pending_responses.clone();
+        span.run(async move {
+            let response = match timeout {
+                Some((duration, timer)) => match future::select(rx, timer(duration)).await {
+                    Either::Left((response, _)) => response,
+                    Either::Right(((), rx)) => {
+                        if pending_responses.lock().remove(&id).is_some() {
+                            return Err(Error::request_timeout());
+                        }
+                        // The response arrived just as the timer fired.
+                        rx.await
+                    }
+                },
+                None => rx.await,
+            };
+
+            let result = response
+                .map_err(|_| Error::internal_error().with_data(SERVER_SHUT_DOWN))??
                 .downcast::<Out>()
                 .map_err(|_| Error::internal_error().with_data("failed to deserialize response"))?;
 
             Ok(*result)
-        }
+        })
     }
 
+    /// Exchanges messages until either side closes the connection, and returns
+    /// which side did.
+    #[allow(clippy::too_many_arguments)]
     async fn handle_io(
         incoming_tx: UnboundedSender<IncomingMessage<Local>>,
-        mut outgoing_rx: UnboundedReceiver<OutgoingMessage<Local, Remote>>,
-        mut outgoing_bytes: impl Unpin + AsyncWrite,
-        incoming_bytes: impl Unpin + AsyncRead,
+        mut outgoing_rx: UnboundedReceiver<OutgoingFrame<Local, Remote>>,
+        mut outgoing_bytes: FrameWriter<impl Unpin + AsyncWrite>,
+        incoming_frames: impl Stream<Item = std::io::Result<IncomingFrame>>,
         pending_responses: Arc<Mutex<HashMap<i32, PendingResponse>>>,
-        broadcast: StreamSender,
-    ) -> Result<()> {
+        broadcast: &StreamSender,
+        stats: &StatsCounters,
+        keepalive: Option<Keepalive>,
+    ) -> Result<StreamMessageDirection> {
         // TODO: Create nicer abstraction for broadcast
-        let mut input_reader = BufReader::new(incoming_bytes);
+        let mut incoming_frames = std::pin::pin!(incoming_frames.fuse());
         let mut outgoing_line = Vec::new();
-        let mut incoming_line = String::new();
+        let next_tick = || match &keepalive {
+            Some(keepalive) => (keepalive.timer)(keepalive.interval).fuse(),
+            None => Fuse::terminated(),
+        };
+        let mut keepalive_tick = next_tick();
+        // Pings use negative IDs so they never collide with regular requests.
+        let mut next_ping_id = -1;
+        let mut unanswered_pings = HashSet::new();
         loop {
             select_biased! {
-                message = outgoing_rx.next() => {
-                    if let Some(message) = message {
+                frame = outgoing_rx.next() => {
+                    if let Some(frame) = frame {
                         outgoing_line.clear();
-                        serde_json::to_writer(&mut outgoing_line, &JsonRpcMessage::wrap(&message)).map_err(Error::into_internal_error)?;
+                        match &frame {
+                            OutgoingFrame::Message(message) => {
+                                serde_json::to_writer(&mut outgoing_line, &JsonRpcMessage::wrap(message)).map_err(Error::into_internal_error)?;
+                            }
+                            OutgoingFrame::Batch { responses, replies } => {
+                                let mut messages = responses
+                                    .iter()
+                                    .map(|message| serde_json::to_value(JsonRpcMessage::wrap(message)))
+                                    .collect::<Result<Vec<_>, _>>()
+                                    .map_err(Error::into_internal_error)?;
+                                messages.extend(replies.iter().cloned());
+                                serde_json::to_writer(&mut outgoing_line, &messages).map_err(Error::into_internal_error)?;
+                            }
+                        }
                         log::trace!("send: {}", String::from_utf8_lossy(&outgoing_line));
-                        outgoing_line.push(b'\n');
-                        outgoing_bytes.write_all(&outgoing_line).await.ok();
-                        broadcast.outgoing(&message);
+                        for message in frame.messages() {
+                            stats.outgoing(message);
+                        }
+                        outgoing_bytes.write(&mut outgoing_line).await;
+                        for message in frame.messages() {
+                            broadcast.outgoing(message);
+                        }
                     } else {
-                        break;
+                        break Ok(StreamMessageDirection::Outgoing);
                     }
                 }
-                bytes_read = input_reader.read_line(&mut incoming_line).fuse() => {
-                    if bytes_read.map_err(Error::into_internal_error)? == 0 {
-                        break
+                () = keepalive_tick => {
+                    if let Some(keepalive) = &keepalive
+                        && unanswered_pings.len() >= keepalive.max_missed as usize
+                    {
+                        return Err(PeerUnreachable {
+                            missed_pings: keepalive.max_missed,
+                        }
+                        .into());
                     }
+                    unanswered_pings.insert(next_ping_id);
+                    let ping = serde_json::json!({
+                        "jsonrpc": JsonRpcMessage::<()>::VERSION,
+                        "id": next_ping_id,
+                        "method": PING_METHOD_NAME,
+                    });
+                    next_ping_id -= 1;
+                    Self::write_internal(&mut outgoing_bytes, &mut outgoing_line, &ping).await?;
+                    keepalive_tick = next_tick();
+                }
+                incoming_frame = incoming_frames.next() => {
+                    let incoming_line = match incoming_frame.transpose().map_err(Error::into_internal_error)? {
+                        Some(IncomingFrame::Message(line)) => line,
+                        Some(IncomingFrame::TooLarge(limit)) => {
+                            let error = Error::message_too_large(limit.max_bytes);
+                            log::error!("discarded incoming message: {error}");
+                            // The message wasn't parsed, so its ID is unknown.
+                            let response = serde_json::json!({
+                                "jsonrpc": JsonRpcMessage::<()>::VERSION,
+                                "id": null,
+                                "error": &error,
+                            });
+                            Self::write_internal(&mut outgoing_bytes, &mut outgoing_line, &response).await?;
+                            match limit.policy {
+                                OversizedMessagePolicy::Skip => continue,
+                                OversizedMessagePolicy::Close => return Err(error.into()),
+                            }
+                        }
+                        None => break Ok(StreamMessageDirection::Incoming),
+                    };
                     log::trace!("recv: {}", &incoming_line);
 
-                    match serde_json::from_str::<RawIncomingMessage>(&incoming_line) {
-                        Ok(message) => {
-                            if let Some(id) = message.id {
-                                if let Some(method) = message.method {
-                                    // Request
-                                    match Local::decode_request(method, message.params) {
-                                        Ok(request) => {
-                                            broadcast.incoming_request(id, method, &request);
-                                            incoming_tx.unbounded_send(IncomingMessage::Request { id, request }).ok();
+                    if incoming_line.trim_start().starts_with('[') {
+                        // Batch
+                        match serde_json::from_str::<Vec<&RawValue>>(&incoming_line) {
+                            Ok(elements) if elements.is_empty() => {
+                                // An empty batch is answered with a single error, not an array.
+                                log::error!("received empty batch");
+                                Self::write_internal(&mut outgoing_bytes, &mut outgoing_line, &invalid_request()).await?;
+                            }
+                            Ok(elements) => {
+                                let mut replies = Vec::new();
+                                let mut requests = Vec::new();
+                                for element in elements {
+                                    match serde_json::from_str::<RawIncomingMessage>(element.get()) {
+                                        Ok(RawIncomingMessage { id: Some(id), method: Some(PING_METHOD_NAME), .. }) => {
+                                            replies.push(pong(id));
                                         }
-                                        Err(err) => {
-                                            outgoing_line.clear();
-                                            let error_response = OutgoingMessage::<Local, Remote>::Response {
-                                                id,
-                                                result: ResponseResult::Error(err),
-                                            };
-
-                                            serde_json::to_writer(&mut outgoing_line, &JsonRpcMessage::wrap(&error_response))?;
-                                            log::trace!("send: {}", String::from_utf8_lossy(&outgoing_line));
-                                            outgoing_line.push(b'\n');
-                                            outgoing_bytes.write_all(&outgoing_line).await.ok();
-                                            broadcast.outgoing(&error_response);
+                                        Ok(RawIncomingMessage { id: None, method: None, result: None, error: None, .. }) | Err(_) => {
+                                            // Only this element is invalid; the rest of the batch is still handled.
+                                            log::error!("invalid message in batch: {element}");
+                                            replies.push(invalid_request());
+                                        }
+                                        Ok(message) => {
+                                            requests.extend(Self::handle_incoming_message(message, &incoming_tx, &pending_responses, broadcast, stats));
                                         }
                                     }
-                                } else if let Some(pending_response) = pending_responses.lock().remove(&id) {
-                                    // Response
-                                    if let Some(result_value) = message.result {
-                                        broadcast.incoming_response(id, Ok(Some(result_value)));
-
-                                        let result = (pending_response.deserialize)(result_value);
-                                        pending_response.respond.send(result).ok();
-                                    } else if let Some(error) = message.error {
-                                        broadcast.incoming_response(id, Err(&error));
-
-                                        pending_response.respond.send(Err(error)).ok();
-                                    } else {
-                                        broadcast.incoming_response(id, Ok(None));
-
-                                        let result = (pending_response.deserialize)(&RawValue::from_string("null".into()).unwrap());
-                                        pending_response.respond.send(result).ok();
-                                    }
-                                } else {
-                                    log::error!("received response for unknown request id: {id}");
                                 }
-                            } else if let Some(method) = message.method {
-                                // Notification
-                                match Local::decode_notification(method, message.params) {
-                                    Ok(notification) => {
-                                        broadcast.incoming_notification(method, &notification);
-                                        incoming_tx.unbounded_send(IncomingMessage::Notification { notification }).ok();
+                                // A batch made up only of notifications and responses gets no reply.
+                                if !requests.is_empty() {
+                                    incoming_tx.unbounded_send(IncomingMessage::Batch { requests, replies }).ok();
+                                } else if !replies.is_empty() {
+                                    Self::write_internal(&mut outgoing_bytes, &mut outgoing_line, &replies.into()).await?;
+                                }
+                            }
+                            Err(error) => {
+                                log::error!("failed to parse incoming batch: {error}. Raw: {incoming_line}");
+                            }
+                        }
+                    } else {
+                        match serde_json::from_str::<RawIncomingMessage>(&incoming_line) {
+                            Ok(RawIncomingMessage { id: Some(id), method: Some(PING_METHOD_NAME), .. }) => {
+                                Self::write_internal(&mut outgoing_bytes, &mut outgoing_line, &pong(id)).await?;
+                            }
+                            Ok(RawIncomingMessage { id: Some(id), method: None, .. })
+                                if unanswered_pings.contains(&id) =>
+                            {
+                                // Any answer, even an error, shows that the peer is alive.
+                                unanswered_pings.clear();
+                            }
+                            Ok(message) => {
+                                match Self::handle_incoming_message(message, &incoming_tx, &pending_responses, broadcast, stats) {
+                                    Some((id, Ok(request), span)) => {
+                                        incoming_tx.unbounded_send(IncomingMessage::Request { id, request, span }).ok();
                                     }
-                                    Err(err) => {
-                                        log::error!("failed to decode {:?}: {err}", message.params);
+                                    Some((id, Err(err), span)) => {
+                                        span.finished(Err(&err));
+                                        outgoing_line.clear();
+                                        let error_response = OutgoingMessage::<Local, Remote>::Response {
+                                            id,
+                                            result: ResponseResult::Error(err),
+                                        };
+
+                                        serde_json::to_writer(&mut outgoing_line, &JsonRpcMessage::wrap(&error_response))?;
+                                        log::trace!("send: {}", String::from_utf8_lossy(&outgoing_line));
+                                        stats.outgoing(&error_response);
+                                        outgoing_bytes.write(&mut outgoing_line).await;
+                                        broadcast.outgoing(&error_response);
                                     }
+                                    None => {}
                                 }
-                            } else {
-                                log::error!("received message with neither id nor method");
                             }
-                        }
-                        Err(error) => {
-                            log::error!("failed to parse incoming message: {error}. Raw: {incoming_line}");
+                            Err(error) => {
+                                log::error!("failed to parse incoming message: {error}. Raw: {incoming_line}");
+                            }
                         }
                     }
-                    incoming_line.clear();
                 }
             }
         }
+    }
+
+    /// Writes a message that is handled by the connection itself, such as keepalive
+    /// pings, without broadcasting it.
+    async fn write_internal(
+        outgoing_bytes: &mut FrameWriter<impl Unpin + AsyncWrite>,
+        outgoing_line: &mut Vec<u8>,
+        message: &serde_json::Value,
+    ) -> Result<()> {
+        outgoing_line.clear();
+        serde_json::to_writer(&mut *outgoing_line, message)?;
+        log::trace!("send: {}", String::from_utf8_lossy(outgoing_line));
+        outgoing_bytes.write(outgoing_line).await;
         Ok(())
     }
 
+    /// Routes responses and notifications, returning any request (or the error
+    /// produced while decoding it) for the caller to dispatch.
+    fn handle_incoming_message(
+        message: RawIncomingMessage,
+        incoming_tx: &UnboundedSender<IncomingMessage<Local>>,
+        pending_responses: &Mutex<HashMap<i32, PendingResponse>>,
+        broadcast: &StreamSender,
+        stats: &StatsCounters,
+    ) -> Option<(i32, Result<Local::InRequest, Error>, RequestSpan)> {
+        stats.incoming(&message);
+        if let Some(id) = message.id {
+            if let Some(method) = message.method {
+                // Request
+                let span = RequestSpan::incoming(id, method, message.params);
+                let request = Local::decode_request(method, message.params);
+                if request.is_ok() {
+                    broadcast.incoming_request(id, method, message.params);
+                }
+                return Some((id, request, span));
+            } else if let Some(pending_response) = pending_responses.lock().remove(&id) {
+                // Response
+                pending_response
+                    .span
+                    .response_matched(message.error.is_some());
+                if let Some(result_value) = message.result {
+                    broadcast.incoming_response(id, Ok(Some(result_value)));
+
+                    let result = (pending_response.deserialize)(result_value);
+                    pending_response.respond.send(result).ok();
+                } else if let Some(error) = message.error {
+                    broadcast.incoming_response(id, Err(&error));
+
+                    pending_response.respond.send(Err(error)).ok();
+                } else {
+                    broadcast.incoming_response(id, Ok(None));
+
+                    let result = (pending_response.deserialize)(
+                        &RawValue::from_string("null".into()).unwrap(),
+                    );
+                    pending_response.respond.send(result).ok();
+                }
+            } else {
+                // This is expected for requests that timed out before the response arrived.
+                log::warn!("received response for unknown request id: {id}");
+            }
+        } else if let Some(method) = message.method {
+            // Notification
+            match Local::decode_notification(method, message.params) {
+                Ok(notification) => {
+                    broadcast.incoming_notification(method, message.params);
+                    incoming_tx
+                        .unbounded_send(IncomingMessage::Notification { notification })
+                        .ok();
+                }
+                Err(err) => {
+                    log::error!("failed to decode {:?}: {err}", message.params);
+                }
+            }
+        } else {
+            log::error!("received message with neither id nor method");
+        }
+        None
+    }
+
     fn handle_incoming<Handler: MessageHandler<Local> + 'static>(
-        outgoing_tx: UnboundedSender<OutgoingMessage<Local, Remote>>,
+        outgoing_tx: UnboundedSender<OutgoingFrame<Local, Remote>>,
         mut incoming_rx: UnboundedReceiver<IncomingMessage<Local>>,
+        request_queue: Arc<Mutex<RequestQueue<Local>>>,
         handler: Handler,
         spawn: impl Fn(LocalBoxFuture<'static, ()>) + 'static,
     ) {
@@ -262,41 +610,646 @@ where
             let spawn = spawn.clone();
             async move {
                 while let Some(message) = incoming_rx.next().await {
-                    match message {
-                        IncomingMessage::Request { id, request } => {
-                            let outgoing_tx = outgoing_tx.clone();
-                            let handler = handler.clone();
-                            spawn(
-                                async move {
-                                    let result = handler.handle_request(request).await.into();
-                                    outgoing_tx
-                                        .unbounded_send(OutgoingMessage::Response { id, result })
-                                        .ok();
+                    let Some(message) = Self::admit(&request_queue, &outgoing_tx, message) else {
+                        continue;
+                    };
+                    let outgoing_tx = outgoing_tx.clone();
+                    let request_queue = request_queue.clone();
+                    let handler = handler.clone();
+                    spawn(
+                        async move {
+                            Self::handle_admitted(&*handler, &outgoing_tx, &request_queue, message)
+                                .await;
+                        }
+                        .boxed_local(),
+                    );
+                }
+            }
+            .boxed_local()
+        });
+    }
+
+    #[cfg(feature = "send")]
+    fn handle_incoming_send<Handler: SendMessageHandler<Local> + 'static>(
+        outgoing_tx: UnboundedSender<OutgoingFrame<Local, Remote>>,
+        mut incoming_rx: UnboundedReceiver<IncomingMessage<Local>>,
+        request_queue: Arc<Mutex<RequestQueue<Local>>>,
+        handler: Handler,
+        spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    ) {
+        let spawn = Arc::new(spawn);
+        let handler = Arc::new(SendHandler(handler));
+        spawn({
+            let spawn = spawn.clone();
+            async move {
+                while let Some(message) = incoming_rx.next().await {
+                    let Some(message) = Self::admit(&request_queue, &outgoing_tx, message) else {
+                        continue;
+                    };
+                    let outgoing_tx = outgoing_tx.clone();
+                    let request_queue = request_queue.clone();
+                    let handler = handler.clone();
+                    spawn(
+                        async move {
+                            Self::handle_admitted(&*handler, &outgoing_tx, &request_queue, message)
+                                .await;
+                        }
+                        .boxed(),
+                    );
+                }
+            }
+            .boxed()
+        });
+    }
+
+    /// Returns the message if it can be handled right away. Otherwise the message is
+    /// either queued until a running request finishes, or rejected if the queue is full.
+    fn admit(
+        request_queue: &Mutex<RequestQueue<Local>>,
+        outgoing_tx: &UnboundedSender<OutgoingFrame<Local, Remote>>,
+        message: IncomingMessage<Local>,
+    ) -> Option<IncomingMessage<Local>> {
+        if matches!(message, IncomingMessage::Notification { .. }) {
+            return Some(message);
+        }
+
+        let mut queue = request_queue.lock();
+        match queue.limit {
+            Some(limit) if queue.in_flight >= limit.max_concurrent => {
+                if queue.queued.len() < limit.max_queued {
+                    queue.queued.push_back(message);
+                } else {
+                    drop(queue);
+                    Self::reject(outgoing_tx, message);
+                }
+                None
+            }
+            _ => {
+                queue.in_flight += 1;
+                Some(message)
+            }
+        }
+    }
+
+    fn reject(
+        outgoing_tx: &UnboundedSender<OutgoingFrame<Local, Remote>>,
+        message: IncomingMessage<Local>,
+    ) {
+        let frame = match message {
+            IncomingMessage::Request { id, span, .. } => {
+                let error = Error::too_many_requests();
+                span.finished(Err(&error));
+                OutgoingMessage::Response {
+                    id,
+                    result: ResponseResult::Error(error),
+                }
+                .into()
+            }
+            IncomingMessage::Batch { requests, replies } => OutgoingFrame::Batch {
+                responses: requests
+                    .into_iter()
+                    .map(|(id, _, span)| {
+                        let error = Error::too_many_requests();
+                        span.finished(Err(&error));
+                        OutgoingMessage::Response {
+                            id,
+                            result: ResponseResult::Error(error),
+                        }
+                    })
+                    .collect(),
+                replies,
+            },
+            IncomingMessage::Notification { .. } => return,
+        };
+        outgoing_tx.unbounded_send(frame).ok();
+    }
+
+    /// Handles an admitted message, then keeps handling queued requests until the
+    /// queue is empty so that the number of in-flight requests stays bounded.
+    async fn handle_admitted<Handler: MessageHandler<Local>>(
+        handler: &Handler,
+        outgoing_tx: &UnboundedSender<OutgoingFrame<Local, Remote>>,
+        request_queue: &Mutex<RequestQueue<Local>>,
+        mut message: IncomingMessage<Local>,
+    ) {
+        if matches!(message, IncomingMessage::Notification { .. }) {
+            Self::handle_message(handler, outgoing_tx, message).await;
+            return;
+        }
+
+        loop {
+            Self::handle_message(handler, outgoing_tx, message).await;
+            let mut queue = request_queue.lock();
+            match queue.queued.pop_front() {
+                Some(next) => message = next,
+                None => {
+                    queue.in_flight -= 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn handle_message<Handler: MessageHandler<Local>>(
+        handler: &Handler,
+        outgoing_tx: &UnboundedSender<OutgoingFrame<Local, Remote>>,
+        message: IncomingMessage<Local>,
+    ) {
+        match message {
+            IncomingMessage::Request { id, request, span } => {
+                let result = span.run(handler.handle_request(request)).await.into();
+                outgoing_tx
+                    .unbounded_send(OutgoingMessage::Response { id, result }.into())
+                    .ok();
+            }
+            IncomingMessage::Notification { notification } => {
+                match handler.handle_notification(notification).await {
+                    Ok(()) => {}
+                    // Unrecognized notifications are ignored rather than reported.
+                    Err(err) if err.code == crate::ErrorCode::METHOD_NOT_FOUND.code => {
+                        log::debug!("ignored notification: {err:?}");
+                    }
+                    Err(err) => log::error!("failed to handle notification: {err:?}"),
+                }
+            }
+            IncomingMessage::Batch { requests, replies } => {
+                let responses =
+                    future::join_all(requests.into_iter().map(|(id, request, span)| async move {
+                        let result = span
+                            .run(async move {
+                                match request {
+                                    Ok(request) => handler.handle_request(request).await,
+                                    Err(err) => Err(err),
                                 }
-                                .boxed_local(),
-                            );
+                            })
+                            .await;
+                        OutgoingMessage::Response {
+                            id,
+                            result: result.into(),
                         }
-                        IncomingMessage::Notification { notification } => {
-                            let handler = handler.clone();
-                            spawn(
-                                async move {
-                                    if let Err(err) =
-                                        handler.handle_notification(notification).await
-                                    {
-                                        log::error!("failed to handle notification: {err:?}");
-                                    }
+                    }))
+                    .await;
+                outgoing_tx
+                    .unbounded_send(OutgoingFrame::Batch { responses, replies })
+                    .ok();
+            }
+        }
+    }
+}
+
+/// Incoming requests that are being handled or waiting to be handled.
+struct RequestQueue<Local: Side> {
+    limit: Option<RequestLimit>,
+    in_flight: usize,
+    queued: VecDeque<IncomingMessage<Local>>,
+}
+
+impl<Local: Side> Default for RequestQueue<Local> {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            in_flight: 0,
+            queued: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RequestLimit {
+    max_concurrent: usize,
+    max_queued: usize,
+}
+
+/// How messages are delimited on the byte streams of a connection.
+///
+/// Both sides of a connection must use the same framing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Each message is a single line of JSON terminated by `\n`, as required by the
+    /// stdio transport of the protocol.
+    #[default]
+    NewlineDelimited,
+    /// Each message is preceded by a `Content-Length: N\r\n\r\n` header giving its
+    /// size in bytes, as in the Language Server Protocol.
+    ///
+    /// Messages may then contain raw newlines, and large messages are read in one
+    /// go. Other headers, such as `Content-Type`, are ignored.
+    ContentLength,
+}
+
+impl Framing {
+    /// Splits the incoming bytes into messages, ending at the end of the stream.
+    ///
+    /// Messages exceeding `limit` are discarded without being buffered, and
+    /// reported as [`IncomingFrame::TooLarge`].
+    fn read_frames(
+        self,
+        incoming_bytes: impl Unpin + AsyncRead,
+        limit: MessageLimit,
+    ) -> impl Stream<Item = std::io::Result<IncomingFrame>> {
+        let max_bytes = limit.max_bytes;
+        let invalid_data =
+            |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let into_string = move |bytes: Vec<u8>| {
+            String::from_utf8(bytes).map_err(|error| invalid_data(error.to_string()))
+        };
+        futures::stream::try_unfold(
+            BufReader::new(incoming_bytes),
+            move |mut reader| async move {
+                let mut frame = Vec::new();
+                let frame = match self {
+                    Framing::NewlineDelimited => {
+                        match read_line(&mut reader, &mut frame, max_bytes).await? {
+                            ReadLine::Eof => return Ok(None),
+                            ReadLine::Line => IncomingFrame::Message(into_string(frame)?),
+                            ReadLine::TooLarge => IncomingFrame::TooLarge(limit),
+                        }
+                    }
+                    Framing::ContentLength => {
+                        let mut content_length = None;
+                        let mut content_encoding = None;
+                        let mut saw_header = false;
+                        loop {
+                            frame.clear();
+                            match read_line(&mut reader, &mut frame, max_bytes).await? {
+                                ReadLine::Eof => return Ok(None),
+                                ReadLine::Line => {}
+                                ReadLine::TooLarge => {
+                                    return Err(invalid_data("header too long".into()));
                                 }
-                                .boxed_local(),
-                            );
+                            }
+                            let frame = into_string(std::mem::take(&mut frame))?;
+                            let header = frame.trim_end_matches(['\r', '\n']);
+                            if header.is_empty() {
+                                if saw_header {
+                                    break;
+                                }
+                                // Tolerate blank lines between messages.
+                                continue;
+                            }
+                            saw_header = true;
+                            let Some((name, value)) = header.split_once(':') else {
+                                continue;
+                            };
+                            let name = name.trim();
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length =
+                                    Some(value.trim().parse::<usize>().map_err(|_| {
+                                        invalid_data(format!("invalid header: {header}"))
+                                    })?);
+                            } else if name.eq_ignore_ascii_case("content-encoding") {
+                                content_encoding = Some(value.trim().to_ascii_lowercase());
+                            }
+                        }
+                        let content_length = content_length
+                            .ok_or_else(|| invalid_data("missing Content-Length header".into()))?;
+                        if content_length > max_bytes {
+                            futures::io::copy(
+                                (&mut reader).take(content_length as u64),
+                                &mut futures::io::sink(),
+                            )
+                            .await?;
+                            return Ok(Some((IncomingFrame::TooLarge(limit), reader)));
+                        }
+                        let mut body = vec![0; content_length];
+                        reader.read_exact(&mut body).await?;
+                        let body = match content_encoding.as_deref() {
+                            None | Some("identity") => Some(body),
+                            Some(encoding) => decompress(encoding, &body, max_bytes)?,
+                        };
+                        match body {
+                            Some(body) => IncomingFrame::Message(into_string(body)?),
+                            None => IncomingFrame::TooLarge(limit),
                         }
                     }
+                };
+                Ok(Some((frame, reader)))
+            },
+        )
+    }
+}
+
+/// A message read from the incoming byte stream.
+enum IncomingFrame {
+    Message(String),
+    /// A message exceeding the connection's size limit, which was discarded.
+    TooLarge(MessageLimit),
+}
+
+enum ReadLine {
+    Eof,
+    Line,
+    TooLarge,
+}
+
+/// Reads a line into `line`, including its terminating newline, unless it is longer
+/// than `max_bytes`, in which case the rest of it is skipped without buffering it.
+async fn read_line(
+    reader: &mut (impl Unpin + AsyncBufRead),
+    line: &mut Vec<u8>,
+    max_bytes: usize,
+) -> std::io::Result<ReadLine> {
+    let mut too_large = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(if too_large {
+                ReadLine::TooLarge
+            } else if line.is_empty() {
+                ReadLine::Eof
+            } else {
+                ReadLine::Line
+            });
+        }
+        let (len, done) = match available.iter().position(|byte| *byte == b'\n') {
+            Some(end) => (end + 1, true),
+            None => (available.len(), false),
+        };
+        if !too_large {
+            let content_len = line.len() + len - usize::from(done);
+            if content_len > max_bytes {
+                too_large = true;
+                line.clear();
+            } else {
+                line.extend_from_slice(&available[..len]);
+            }
+        }
+        reader.consume_unpin(len);
+        if done {
+            return Ok(if too_large {
+                ReadLine::TooLarge
+            } else {
+                ReadLine::Line
+            });
+        }
+    }
+}
+
+/// Writes messages to the outgoing byte stream with the connection's [`Framing`].
+struct FrameWriter<W> {
+    bytes: W,
+    framing: Framing,
+    content_encoding: Arc<Mutex<Option<ContentEncoding>>>,
+}
+
+impl<W: Unpin + AsyncWrite> FrameWriter<W> {
+    /// Writes the message in `payload`, which may be modified in the process.
+    ///
+    /// Write errors are ignored; a closed peer is noticed by the read side.
+    async fn write(&mut self, payload: &mut Vec<u8>) {
+        match self.framing {
+            Framing::NewlineDelimited => {
+                payload.push(b'\n');
+            }
+            Framing::ContentLength => {
+                let mut header = String::new();
+                if let Some(encoding) = *self.content_encoding.lock()
+                    && payload.len() >= MIN_COMPRESSED_SIZE
+                    && let Some(compressed) = compress(encoding, payload)
+                {
+                    *payload = compressed;
+                    header = format!("Content-Encoding: {}\r\n", encoding.as_str());
                 }
+                let header = format!("Content-Length: {}\r\n{header}\r\n", payload.len());
+                payload.splice(0..0, header.into_bytes());
             }
-            .boxed_local()
-        });
+        }
+        self.bytes.write_all(payload).await.ok();
+    }
+}
+
+/// Messages smaller than this are never compressed, as it would barely make them
+/// any smaller.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// Compresses an outgoing message, returning `None` if it should be sent as is.
+#[cfg(feature = "compression")]
+fn compress(encoding: ContentEncoding, payload: &[u8]) -> Option<Vec<u8>> {
+    use flate2::{
+        Compression,
+        write::{GzEncoder, ZlibEncoder},
+    };
+    use std::io::Write as _;
+
+    let compressed = match encoding {
+        ContentEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(payload).and_then(|()| encoder.finish())
+        }
+        ContentEncoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(payload).and_then(|()| encoder.finish())
+        }
+    };
+    compressed
+        .inspect_err(|error| log::error!("failed to compress message: {error}"))
+        .ok()
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_encoding: ContentEncoding, _payload: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+/// Decompresses the body of an incoming message sent with a `Content-Encoding` header.
+///
+/// Returns `None` if it decompresses to more than `max_bytes`.
+fn decompress(encoding: &str, body: &[u8], max_bytes: usize) -> std::io::Result<Option<Vec<u8>>> {
+    #[cfg(feature = "compression")]
+    {
+        use flate2::read::{GzDecoder, ZlibDecoder};
+        use std::io::Read as _;
+
+        // Stop one byte past the limit, so that compressed messages can't make the
+        // connection buffer more than it would otherwise.
+        let limit = u64::try_from(max_bytes).map_or(u64::MAX, |max| max.saturating_add(1));
+        let mut decompressed = Vec::new();
+        match encoding {
+            "gzip" => GzDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decompressed)?,
+            "deflate" => ZlibDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decompressed)?,
+            _ => return Err(unsupported_encoding(encoding)),
+        };
+        Ok((decompressed.len() <= max_bytes).then_some(decompressed))
+    }
+    #[cfg(not(feature = "compression"))]
+    {
+        let _ = (body, max_bytes);
+        Err(unsupported_encoding(encoding))
+    }
+}
+
+fn unsupported_encoding(encoding: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("unsupported Content-Encoding: {encoding}"),
+    )
+}
+
+/// Method name of the keepalive requests exchanged by connections.
+const PING_METHOD_NAME: &str = "$/ping";
+
+/// The answer to a `$/ping` request, which the connection sends itself.
+fn pong(id: i32) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": JsonRpcMessage::<()>::VERSION,
+        "id": id,
+        "result": null,
+    })
+}
+
+/// The answer to an empty batch or to an element of a batch that isn't a valid
+/// message, whose ID is unknown.
+fn invalid_request() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": JsonRpcMessage::<()>::VERSION,
+        "id": null,
+        "error": Error::invalid_request(),
+    })
+}
+
+/// What a connection does with an incoming message that exceeds its size limit.
+///
+/// In both cases, the message is discarded without being parsed, and the peer is
+/// sent an [`Error::message_too_large`] response with a `null` ID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizedMessagePolicy {
+    /// Keep the connection open and continue with the next message.
+    #[default]
+    Skip,
+    /// Close the connection, failing the IO task with the error.
+    Close,
+}
+
+#[derive(Clone, Copy)]
+struct MessageLimit {
+    max_bytes: usize,
+    policy: OversizedMessagePolicy,
+}
+
+impl Default for MessageLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: usize::MAX,
+            policy: OversizedMessagePolicy::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Keepalive {
+    interval: Duration,
+    max_missed: u32,
+    timer: Timer,
+}
+
+/// The error the IO task fails with when keepalive pings go unanswered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerUnreachable {
+    /// The number of consecutive pings that received no answer.
+    pub missed_pings: u32,
+}
+
+impl std::error::Error for PeerUnreachable {}
+
+impl std::fmt::Display for PeerUnreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "peer unreachable: {} keepalive pings went unanswered",
+            self.missed_pings
+        )
     }
 }
 
+/// Counts of the messages a connection exchanged, as returned by `stats()` on
+/// either connection type.
+///
+/// Messages the connection handles itself, such as keepalive pings, aren't counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Requests sent to the peer.
+    pub requests_sent: u64,
+    /// Requests received from the peer.
+    pub requests_received: u64,
+    /// Responses sent to the peer, including error responses.
+    pub responses_sent: u64,
+    /// Error responses, both sent and received.
+    pub errors: u64,
+    /// Notifications, both sent and received.
+    pub notifications: u64,
+    /// Requests sent to the peer that are still awaiting a response.
+    pub in_flight: u64,
+}
+
+#[derive(Default)]
+struct StatsCounters {
+    requests_sent: AtomicU64,
+    requests_received: AtomicU64,
+    responses_sent: AtomicU64,
+    errors: AtomicU64,
+    notifications: AtomicU64,
+}
+
+impl StatsCounters {
+    fn outgoing<Local: Side, Remote: Side>(&self, message: &OutgoingMessage<Local, Remote>) {
+        match message {
+            OutgoingMessage::Request { .. } => Self::increment(&self.requests_sent),
+            OutgoingMessage::Response { result, .. } => {
+                Self::increment(&self.responses_sent);
+                if matches!(result, ResponseResult::Error(_)) {
+                    Self::increment(&self.errors);
+                }
+            }
+            OutgoingMessage::Notification { .. } => Self::increment(&self.notifications),
+        }
+    }
+
+    fn incoming(&self, message: &RawIncomingMessage) {
+        match (message.id, message.method) {
+            (Some(_), Some(_)) => Self::increment(&self.requests_received),
+            (None, Some(_)) => Self::increment(&self.notifications),
+            (_, None) if message.error.is_some() => Self::increment(&self.errors),
+            (_, None) => {}
+        }
+    }
+
+    fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, in_flight: u64) -> ConnectionStats {
+        ConnectionStats {
+            requests_sent: self.requests_sent.load(Ordering::Relaxed),
+            requests_received: self.requests_received.load(Ordering::Relaxed),
+            responses_sent: self.responses_sent.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            notifications: self.notifications.load(Ordering::Relaxed),
+            in_flight,
+        }
+    }
+}
+
+const CONNECTION_CLOSED: &str = "connection closed";
+const SERVER_SHUT_DOWN: &str = "server shut down unexpectedly";
+
+fn connection_closed() -> Error {
+    Error::internal_error().with_data(CONNECTION_CLOSED)
+}
+
+/// Returns whether a request failed because its connection is gone, in which case
+/// sending it again can't succeed.
+pub(crate) fn is_connection_closed(error: &Error) -> bool {
+    error.code == crate::ErrorCode::INTERNAL_ERROR.code
+        && matches!(
+            error.data.as_ref().and_then(serde_json::Value::as_str),
+            Some(CONNECTION_CLOSED | SERVER_SHUT_DOWN)
+        )
+}
+
 #[derive(Deserialize)]
 struct RawIncomingMessage<'a> {
     id: Option<i32>,
@@ -307,8 +1260,53 @@ struct RawIncomingMessage<'a> {
 }
 
 enum IncomingMessage<Local: Side> {
-    Request { id: i32, request: Local::InRequest },
-    Notification { notification: Local::InNotification },
+    Request {
+        id: i32,
+        request: Local::InRequest,
+        span: RequestSpan,
+    },
+    Notification {
+        notification: Local::InNotification,
+    },
+    /// Requests received together in a [JSON-RPC batch][1], answered with a single array
+    /// once all of them have been handled. Requests that failed to decode carry their error.
+    ///
+    /// [1]: https://www.jsonrpc.org/specification#batch
+    ///
+    /// `$/ping` requests and invalid elements in the batch never reach the handler,
+    /// but the connection's own replies to them are sent in the same array.
+    Batch {
+        requests: Vec<(i32, Result<Local::InRequest, Error>, RequestSpan)>,
+        replies: Vec<serde_json::Value>,
+    },
+}
+
+/// A single line written to the output stream.
+enum OutgoingFrame<Local: Side, Remote: Side> {
+    Message(OutgoingMessage<Local, Remote>),
+    /// The responses to a batch, along with the replies the connection made itself,
+    /// such as answers to `$/ping` requests and Invalid Request errors.
+    Batch {
+        responses: Vec<OutgoingMessage<Local, Remote>>,
+        replies: Vec<serde_json::Value>,
+    },
+}
+
+impl<Local: Side, Remote: Side> OutgoingFrame<Local, Remote> {
+    fn messages(&self) -> &[OutgoingMessage<Local, Remote>] {
+        match self {
+            OutgoingFrame::Message(message) => std::slice::from_ref(message),
+            OutgoingFrame::Batch { responses, .. } => responses,
+        }
+    }
+}
+
+impl<Local: Side, Remote: Side> From<OutgoingMessage<Local, Remote>>
+    for OutgoingFrame<Local, Remote>
+{
+    fn from(message: OutgoingMessage<Local, Remote>) -> Self {
+        OutgoingFrame::Message(message)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -377,9 +1375,9 @@ impl<T> From<Result<T, Error>> for ResponseResult<T> {
 }
 
 pub trait Side: Clone {
-    type InRequest: Clone + Serialize + DeserializeOwned + 'static;
-    type OutResponse: Clone + Serialize + DeserializeOwned + 'static;
-    type InNotification: Clone + Serialize + DeserializeOwned + 'static;
+    type InRequest: Clone + Serialize + DeserializeOwned + Send + 'static;
+    type OutResponse: Clone + Serialize + DeserializeOwned + Send + 'static;
+    type InNotification: Clone + Serialize + DeserializeOwned + Send + 'static;
 
     fn decode_request(method: &str, params: Option<&RawValue>) -> Result<Self::InRequest, Error>;
 
@@ -400,3 +1398,38 @@ pub trait MessageHandler<Local: Side> {
         notification: Local::InNotification,
     ) -> impl Future<Output = Result<(), Error>>;
 }
+
+/// A [`MessageHandler`] that can be shared across threads and whose futures are [`Send`].
+#[cfg(feature = "send")]
+pub trait SendMessageHandler<Local: Side>: Send + Sync {
+    fn handle_request(
+        &self,
+        request: Local::InRequest,
+    ) -> impl Future<Output = Result<Local::OutResponse, Error>> + Send;
+
+    fn handle_notification(
+        &self,
+        notification: Local::InNotification,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// Adapts a [`SendMessageHandler`] to the [`MessageHandler`] the connection drives.
+#[cfg(feature = "send")]
+struct SendHandler<H>(H);
+
+#[cfg(feature = "send")]
+impl<Local: Side, H: SendMessageHandler<Local>> MessageHandler<Local> for SendHandler<H> {
+    fn handle_request(
+        &self,
+        request: Local::InRequest,
+    ) -> impl Future<Output = Result<Local::OutResponse, Error>> {
+        self.0.handle_request(request)
+    }
+
+    fn handle_notification(
+        &self,
+        notification: Local::InNotification,
+    ) -> impl Future<Output = Result<(), Error>> {
+        self.0.handle_notification(notification)
+    }
+}
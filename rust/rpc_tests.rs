@@ -11,6 +11,12 @@ struct TestClient {
     written_files: Arc<Mutex<Vec<(std::path::PathBuf, String)>>>,
     session_notifications: Arc<Mutex<Vec<SessionNotification>>>,
     extension_notifications: Arc<Mutex<Vec<(String, ExtNotification)>>>,
+    terminal_inputs: Arc<Mutex<Vec<(TerminalId, String)>>>,
+    exited_terminals: Arc<Mutex<std::collections::HashSet<TerminalId>>>,
+    terminal_sizes: Arc<Mutex<Vec<(TerminalId, u16, u16)>>>,
+    requests_in_flight: Arc<Mutex<(usize, usize)>>,
+    failing_reads: Arc<Mutex<usize>>,
+    user_inputs: Arc<Mutex<Vec<RequestUserInputRequest>>>,
 }
 
 impl TestClient {
@@ -21,6 +27,12 @@ impl TestClient {
             written_files: Arc::new(Mutex::new(Vec::new())),
             session_notifications: Arc::new(Mutex::new(Vec::new())),
             extension_notifications: Arc::new(Mutex::new(Vec::new())),
+            terminal_inputs: Arc::new(Mutex::new(Vec::new())),
+            exited_terminals: Arc::new(Mutex::new(std::collections::HashSet::new())),
+            terminal_sizes: Arc::new(Mutex::new(Vec::new())),
+            requests_in_flight: Arc::new(Mutex::new((0, 0))),
+            failing_reads: Arc::new(Mutex::new(0)),
+            user_inputs: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -28,6 +40,10 @@ impl TestClient {
         self.permission_responses.lock().unwrap().push(outcome);
     }
 
+    fn fail_next_reads(&self, count: usize) {
+        *self.failing_reads.lock().unwrap() = count;
+    }
+
     fn add_file_content(&self, path: std::path::PathBuf, content: String) {
         self.file_contents.lock().unwrap().insert(path, content);
     }
@@ -57,10 +73,40 @@ impl Client for TestClient {
         })
     }
 
+    async fn request_user_input(
+        &self,
+        args: RequestUserInputRequest,
+    ) -> Result<RequestUserInputResponse, Error> {
+        let value = match &args.input_type {
+            InputType::Text => Some("main".to_string()),
+            InputType::Choice { options } => options.first().cloned(),
+            InputType::Confirm => None,
+        };
+        self.user_inputs.lock().unwrap().push(args);
+        Ok(RequestUserInputResponse { value, meta: None })
+    }
+
     async fn write_text_file(
         &self,
         arguments: WriteTextFileRequest,
     ) -> Result<WriteTextFileResponse, Error> {
+        if arguments.dry_run {
+            let old_text = self
+                .file_contents
+                .lock()
+                .unwrap()
+                .get(&arguments.path)
+                .cloned();
+            return Ok(WriteTextFileResponse {
+                diff: Some(Diff {
+                    path: arguments.path,
+                    old_text,
+                    new_text: arguments.content,
+                    meta: None,
+                }),
+                meta: None,
+            });
+        }
         self.written_files
             .lock()
             .unwrap()
@@ -72,13 +118,144 @@ impl Client for TestClient {
         &self,
         arguments: ReadTextFileRequest,
     ) -> Result<ReadTextFileResponse, Error> {
+        {
+            let mut failing_reads = self.failing_reads.lock().unwrap();
+            if *failing_reads > 0 {
+                *failing_reads -= 1;
+                return Err(Error::internal_error().with_data("transient failure"));
+            }
+        }
         let contents = self.file_contents.lock().unwrap();
         let content = contents
             .get(&arguments.path)
             .cloned()
             .unwrap_or_else(|| "default content".to_string());
+        if arguments.is_byte_range() {
+            return ReadTextFileResponse::from_byte_range(
+                &content,
+                arguments.byte_offset.unwrap_or_default(),
+                arguments.byte_limit,
+            );
+        }
         Ok(ReadTextFileResponse {
             content,
+            next_byte_offset: None,
+            meta: None,
+        })
+    }
+
+    async fn read_text_file_chunk(
+        &self,
+        args: ReadTextFileChunkRequest,
+    ) -> Result<ReadTextFileChunkResponse, Error> {
+        let contents = self.file_contents.lock().unwrap();
+        let content = contents
+            .get(&args.path)
+            .ok_or_else(|| Error::resource_not_found(Some(args.path.display().to_string())))?;
+        let start = usize::try_from(args.offset)
+            .unwrap_or(usize::MAX)
+            .min(content.len());
+        if !content.is_char_boundary(start) {
+            return Err(Error::invalid_params());
+        }
+        let mut end = content
+            .len()
+            .min(start + args.max_bytes.unwrap_or(4) as usize);
+        while !content.is_char_boundary(end) {
+            end += 1;
+        }
+        Ok(ReadTextFileChunkResponse {
+            content: content[start..end].to_string(),
+            eof: end == content.len(),
+            meta: None,
+        })
+    }
+
+    async fn delete_file(&self, args: DeleteFileRequest) -> Result<DeleteFileResponse, Error> {
+        match self.file_contents.lock().unwrap().remove(&args.path) {
+            Some(_) => Ok(DeleteFileResponse::default()),
+            None => Err(Error::resource_not_found(Some(
+                args.path.display().to_string(),
+            ))),
+        }
+    }
+
+    async fn rename_file(&self, args: RenameFileRequest) -> Result<RenameFileResponse, Error> {
+        let mut contents = self.file_contents.lock().unwrap();
+        let content = contents
+            .remove(&args.old_path)
+            .ok_or_else(|| Error::resource_not_found(Some(args.old_path.display().to_string())))?;
+        contents.insert(args.new_path, content);
+        Ok(RenameFileResponse::default())
+    }
+
+    async fn stat(&self, args: StatRequest) -> Result<StatResponse, Error> {
+        Ok(match self.file_contents.lock().unwrap().get(&args.path) {
+            Some(content) => StatResponse {
+                exists: true,
+                size: Some(content.len() as u64),
+                ..Default::default()
+            },
+            None => StatResponse::default(),
+        })
+    }
+
+    async fn search_files(&self, args: SearchFilesRequest) -> Result<SearchFilesResponse, Error> {
+        // Only `*.ext` patterns are supported by this test client.
+        let extension = args.glob.trim_start_matches("*.");
+        let mut paths: Vec<_> = self
+            .file_contents
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|path| {
+                path.starts_with(&args.root) && path.extension().is_some_and(|ext| ext == extension)
+            })
+            .cloned()
+            .collect();
+        paths.sort();
+        let max_results = args.max_results.map_or(usize::MAX, |max| max as usize);
+        let truncated = paths.len() > max_results;
+        paths.truncate(max_results);
+        Ok(SearchFilesResponse {
+            paths,
+            truncated,
+            meta: None,
+        })
+    }
+
+    async fn grep(&self, args: GrepRequest) -> Result<GrepResponse, Error> {
+        let pattern = if args.is_regex {
+            args.query
+        } else {
+            regex::escape(&args.query)
+        };
+        let regex = regex::Regex::new(&pattern)
+            .map_err(|error| Error::invalid_params().with_data(error.to_string()))?;
+        let contents = self.file_contents.lock().unwrap();
+        let mut files: Vec<_> = contents
+            .iter()
+            .filter(|(path, _)| path.starts_with(&args.root))
+            .collect();
+        files.sort();
+        let matches = files
+            .into_iter()
+            .flat_map(|(path, content)| {
+                content.lines().enumerate().filter_map(|(ix, line)| {
+                    let start = regex.find(line)?.start();
+                    Some(GrepMatch {
+                        path: path.clone(),
+                        line: ix as u32 + 1,
+                        column: line[..start].encode_utf16().count() as u32 + 1,
+                        line_text: line.to_string(),
+                        meta: None,
+                    })
+                })
+            })
+            .take(args.max_results.map_or(usize::MAX, |max| max as usize))
+            .collect();
+        Ok(GrepResponse {
+            matches,
             meta: None,
         })
     }
@@ -109,6 +286,36 @@ impl Client for TestClient {
         unimplemented!()
     }
 
+    async fn write_terminal_input(
+        &self,
+        args: WriteTerminalInputRequest,
+    ) -> Result<WriteTerminalInputResponse, Error> {
+        if self
+            .exited_terminals
+            .lock()
+            .unwrap()
+            .contains(&args.terminal_id)
+        {
+            return Err(Error::terminal_exited(args.terminal_id.to_string()));
+        }
+        self.terminal_inputs
+            .lock()
+            .unwrap()
+            .push((args.terminal_id, args.data));
+        Ok(WriteTerminalInputResponse::default())
+    }
+
+    async fn resize_terminal(
+        &self,
+        args: ResizeTerminalRequest,
+    ) -> Result<ResizeTerminalResponse, Error> {
+        self.terminal_sizes
+            .lock()
+            .unwrap()
+            .push((args.terminal_id, args.cols, args.rows));
+        Ok(ResizeTerminalResponse::default())
+    }
+
     async fn release_terminal(
         &self,
         _args: ReleaseTerminalRequest,
@@ -129,6 +336,16 @@ impl Client for TestClient {
                 "response": "pong",
                 "params": args.params
             })),
+            "example.com/slow" => {
+                {
+                    let mut requests = self.requests_in_flight.lock().unwrap();
+                    requests.0 += 1;
+                    requests.1 = requests.1.max(requests.0);
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                self.requests_in_flight.lock().unwrap().0 -= 1;
+                Ok(raw_json!(null))
+            }
             _ => Err(Error::method_not_found()),
         }
     }
@@ -144,10 +361,13 @@ impl Client for TestClient {
 
 #[derive(Clone)]
 struct TestAgent {
-    sessions: Arc<Mutex<std::collections::HashSet<SessionId>>>,
+    sessions: SessionRegistry,
     prompts_received: Arc<Mutex<Vec<PromptReceived>>>,
     cancellations_received: Arc<Mutex<Vec<SessionId>>>,
+    tool_call_cancellations_received: Arc<Mutex<Vec<(SessionId, ToolCallId)>>>,
+    terminal_exits_received: Arc<Mutex<Vec<(TerminalId, TerminalExitStatus)>>>,
     extension_notifications: Arc<Mutex<Vec<(String, ExtNotification)>>>,
+    client_info: Arc<Mutex<Option<Implementation>>>,
 }
 
 type PromptReceived = (SessionId, Vec<ContentBlock>);
@@ -155,10 +375,13 @@ type PromptReceived = (SessionId, Vec<ContentBlock>);
 impl TestAgent {
     fn new() -> Self {
         Self {
-            sessions: Arc::new(Mutex::new(std::collections::HashSet::new())),
+            sessions: SessionRegistry::new(),
             prompts_received: Arc::new(Mutex::new(Vec::new())),
             cancellations_received: Arc::new(Mutex::new(Vec::new())),
+            tool_call_cancellations_received: Arc::new(Mutex::new(Vec::new())),
+            terminal_exits_received: Arc::new(Mutex::new(Vec::new())),
             extension_notifications: Arc::new(Mutex::new(Vec::new())),
+            client_info: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -166,19 +389,33 @@ impl TestAgent {
 #[async_trait::async_trait(?Send)]
 impl Agent for TestAgent {
     async fn initialize(&self, arguments: InitializeRequest) -> Result<InitializeResponse, Error> {
+        *self.client_info.lock().unwrap() = arguments.client_info;
         Ok(InitializeResponse {
-            protocol_version: arguments.protocol_version,
+            protocol_version: ProtocolVersion::negotiate(
+                arguments.protocol_version,
+                SUPPORTED_VERSIONS,
+            ),
             agent_capabilities: AgentCapabilities::default(),
             auth_methods: vec![],
+            agent_info: Some(Implementation {
+                name: "test-agent".to_string(),
+                title: None,
+                version: "1.0.0".to_string(),
+                meta: None,
+            }),
             meta: None,
         })
     }
 
     async fn authenticate(
         &self,
-        _arguments: AuthenticateRequest,
+        arguments: AuthenticateRequest,
     ) -> Result<AuthenticateResponse, Error> {
-        Ok(AuthenticateResponse::default())
+        match &*arguments.method_id.0 {
+            "expired" => Err(Error::auth_required().with_data("token expired")),
+            "conflict" => Err(anyhow::Error::from(Error::from_kind(ErrorKind::Conflict)).into()),
+            _ => Ok(AuthenticateResponse::default()),
+        }
     }
 
     async fn new_session(
@@ -186,12 +423,12 @@ impl Agent for TestAgent {
         _arguments: NewSessionRequest,
     ) -> Result<NewSessionResponse, Error> {
         let session_id = SessionId(Arc::from("test-session-123"));
-        self.sessions.lock().unwrap().insert(session_id.clone());
+        self.sessions.insert(session_id.clone(), ());
         Ok(NewSessionResponse {
             session_id,
             modes: None,
-            #[cfg(feature = "unstable")]
             models: None,
+            default_permission_options: None,
             meta: None,
         })
     }
@@ -199,7 +436,6 @@ impl Agent for TestAgent {
     async fn load_session(&self, _: LoadSessionRequest) -> Result<LoadSessionResponse, Error> {
         Ok(LoadSessionResponse {
             modes: None,
-            #[cfg(feature = "unstable")]
             models: None,
             meta: None,
         })
@@ -207,18 +443,67 @@ impl Agent for TestAgent {
 
     async fn set_session_mode(
         &self,
-        _arguments: SetSessionModeRequest,
+        arguments: SetSessionModeRequest,
     ) -> Result<SetSessionModeResponse, Error> {
+        self.sessions.get_or_err(&arguments.session_id)?;
         Ok(SetSessionModeResponse { meta: None })
     }
 
+    async fn list_commands(
+        &self,
+        _arguments: ListCommandsRequest,
+    ) -> Result<ListCommandsResponse, Error> {
+        Ok(ListCommandsResponse {
+            commands: vec![AvailableCommand {
+                name: "web".to_string(),
+                description: "Search the web for information".to_string(),
+                input: Some(AvailableCommandInput::Unstructured {
+                    hint: "query to search for".to_string(),
+                }),
+                meta: None,
+            }],
+            meta: None,
+        })
+    }
+
+    async fn invoke_command(
+        &self,
+        arguments: InvokeCommandRequest,
+    ) -> Result<PromptResponse, Error> {
+        let commands = self
+            .list_commands(ListCommandsRequest {
+                session_id: arguments.session_id,
+                meta: None,
+            })
+            .await?
+            .commands;
+        let command = commands
+            .iter()
+            .find(|command| command.name == arguments.name)
+            .ok_or_else(|| {
+                Error::invalid_params().with_data(format!("unknown command /{}", arguments.name))
+            })?;
+        command.validate_arguments(&arguments.arguments)?;
+        Ok(PromptResponse {
+            stop_reason: StopReason::EndTurn,
+            usage: None,
+            refusal: None,
+            meta: None,
+        })
+    }
+
     async fn prompt(&self, arguments: PromptRequest) -> Result<PromptResponse, Error> {
         self.prompts_received
             .lock()
             .unwrap()
-            .push((arguments.session_id, arguments.prompt));
+            .push((arguments.session_id.clone(), arguments.prompt));
+        if &*arguments.session_id.0 == "unauthenticated" {
+            return Err(Error::auth_required());
+        }
         Ok(PromptResponse {
             stop_reason: StopReason::EndTurn,
+            usage: None,
+            refusal: None,
             meta: None,
         })
     }
@@ -231,7 +516,22 @@ impl Agent for TestAgent {
         Ok(())
     }
 
-    #[cfg(feature = "unstable")]
+    async fn cancel_tool_call(&self, args: CancelToolCallNotification) -> Result<(), Error> {
+        self.tool_call_cancellations_received
+            .lock()
+            .unwrap()
+            .push((args.session_id, args.tool_call_id));
+        Ok(())
+    }
+
+    async fn terminal_exited(&self, args: TerminalExitedNotification) -> Result<(), Error> {
+        self.terminal_exits_received
+            .lock()
+            .unwrap()
+            .push((args.terminal_id, args.exit_status));
+        Ok(())
+    }
+
     async fn set_session_model(
         &self,
         args: SetSessionModelRequest,
@@ -267,8 +567,10 @@ fn create_connection_pair(
     client: &TestClient,
     agent: &TestAgent,
 ) -> (ClientSideConnection, AgentSideConnection) {
-    let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
-    let (agent_to_client_rx, agent_to_client_tx) = piper::pipe(1024);
+    // Large enough that tests flooding the connection with requests don't stall
+    // on a full pipe in both directions at once.
+    let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1 << 20);
+    let (agent_to_client_rx, agent_to_client_tx) = piper::pipe(1 << 20);
 
     let (agent_conn, agent_io_task) = ClientSideConnection::new(
         client.clone(),
@@ -305,10 +607,17 @@ async fn test_initialize() {
 
             let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
 
+            let client_info = Implementation {
+                name: "test-client".to_string(),
+                title: Some("Test Client".to_string()),
+                version: "0.1.0".to_string(),
+                meta: None,
+            };
             let result = agent_conn
                 .initialize(InitializeRequest {
                     protocol_version: VERSION,
                     client_capabilities: ClientCapabilities::default(),
+                    client_info: Some(client_info.clone()),
                     meta: None,
                 })
                 .await;
@@ -316,6 +625,129 @@ async fn test_initialize() {
             assert!(result.is_ok());
             let response = result.unwrap();
             assert_eq!(response.protocol_version, VERSION);
+            assert_eq!(
+                response.agent_info.map(|info| (info.name, info.version)),
+                Some(("test-agent".to_string(), "1.0.0".to_string()))
+            );
+            assert_eq!(*agent.client_info.lock().unwrap(), Some(client_info));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_capability_guards() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let read_request = ReadTextFileRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from("/test/file.txt"),
+                line: None,
+                limit: None,
+                byte_offset: None,
+                byte_limit: None,
+                meta: None,
+            };
+            let image_prompt = PromptRequest {
+                session_id: SessionId("test-session".into()),
+                prompt: vec![ContentBlock::Image(ImageContent {
+                    annotations: None,
+                    data: "aGVsbG8=".to_string(),
+                    mime_type: "image/png".to_string(),
+                    uri: None,
+                    meta: None,
+                })],
+                context: Vec::new(),
+                progress_token: None,
+                turn_id: None,
+                tools_enabled: None,
+                meta: None,
+            };
+
+            // Nothing is checked before the capabilities are known.
+            assert!(client_conn.client_capabilities().is_none());
+            assert!(agent_conn.agent_capabilities().is_none());
+            client_conn
+                .read_text_file(read_request.clone())
+                .await
+                .expect("read_text_file failed");
+            agent_conn
+                .prompt(image_prompt.clone())
+                .await
+                .expect("prompt failed");
+
+            agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities {
+                        fs: FileSystemCapability {
+                            write_text_file: true,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    client_info: None,
+                    meta: None,
+                })
+                .await
+                .expect("initialize failed");
+            assert!(
+                client_conn
+                    .client_capabilities()
+                    .unwrap()
+                    .fs
+                    .write_text_file
+            );
+            assert!(agent_conn.agent_capabilities().is_some());
+
+            let error = client_conn
+                .read_text_file(read_request)
+                .await
+                .expect_err("read_text_file should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_REQUEST.code);
+            assert_eq!(
+                error.data,
+                Some(json!("client does not support fs/read_text_file"))
+            );
+            client_conn
+                .write_text_file(WriteTextFileRequest {
+                    session_id: SessionId("test-session".into()),
+                    path: std::path::PathBuf::from("/test/file.txt"),
+                    content: "content".to_string(),
+                    dry_run: false,
+                    meta: None,
+                })
+                .await
+                .expect("write_text_file failed");
+
+            let error = agent_conn
+                .prompt(image_prompt)
+                .await
+                .expect_err("image prompt should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+            assert_eq!(
+                error.data,
+                Some(json!("agent does not support image content in prompts"))
+            );
+            agent_conn
+                .prompt(PromptRequest {
+                    session_id: SessionId("test-session".into()),
+                    prompt: vec!["Hello".into()],
+                    context: Vec::new(),
+                    progress_token: None,
+                    turn_id: None,
+                    tools_enabled: None,
+                    meta: None,
+                })
+                .await
+                .expect("prompt failed");
+
+            // Rejected requests never reach the other side.
+            assert_eq!(agent.prompts_received.lock().unwrap().len(), 2);
         })
         .await;
 }
@@ -334,6 +766,7 @@ async fn test_basic_session_creation() {
                 .new_session(NewSessionRequest {
                     mcp_servers: vec![],
                     cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
                     meta: None,
                 })
                 .await
@@ -343,103 +776,145 @@ async fn test_basic_session_creation() {
 }
 
 #[tokio::test]
-async fn test_bidirectional_file_operations() {
+async fn test_absolute_paths_required() {
     let local_set = tokio::task::LocalSet::new();
     local_set
         .run_until(async {
             let client = TestClient::new();
             let agent = TestAgent::new();
 
-            // Add test file content
-            let test_path = std::path::PathBuf::from("/test/file.txt");
-            client.add_file_content(test_path.clone(), "Hello, World!".to_string());
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let new_session = |cwd: &str| NewSessionRequest {
+                mcp_servers: vec![],
+                cwd: std::path::PathBuf::from(cwd),
+                progress_token: None,
+                meta: None,
+            };
 
-            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            // Relative paths are passed on by default.
+            agent_conn
+                .new_session(new_session("relative/dir"))
+                .await
+                .expect("new_session failed");
 
-            // Test reading a file
-            let session_id = SessionId(Arc::from("test-session"));
-            let read_result = client_conn
-                .read_text_file(ReadTextFileRequest {
-                    session_id: session_id.clone(),
-                    path: test_path.clone(),
-                    line: None,
-                    limit: None,
+            let agent_conn = agent_conn.with_absolute_paths_required(true);
+            let client_conn = client_conn.with_absolute_paths_required(true);
+
+            let error = agent_conn
+                .new_session(new_session("relative/dir"))
+                .await
+                .expect_err("relative cwd should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+            assert_eq!(error.data.unwrap()["path"], "relative/dir");
+            agent_conn
+                .new_session(new_session("/test"))
+                .await
+                .expect("new_session failed");
+
+            let error = client_conn
+                .write_text_file(WriteTextFileRequest {
+                    session_id: SessionId("test-session".into()),
+                    path: std::path::PathBuf::from("file.txt"),
+                    content: "content".to_string(),
+                    dry_run: false,
                     meta: None,
                 })
                 .await
-                .expect("read_text_file failed");
+                .expect_err("relative path should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+            assert!(client.written_files.lock().unwrap().is_empty());
+        })
+        .await;
+}
 
-            assert_eq!(read_result.content, "Hello, World!");
+#[tokio::test]
+async fn test_write_text_file_dry_run() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+            client.add_file_content("/test/file.txt".into(), "old\n".to_string());
 
-            // Test writing a file
-            let write_result = client_conn
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+
+            let response = client_conn
                 .write_text_file(WriteTextFileRequest {
-                    session_id: session_id.clone(),
-                    path: test_path.clone(),
-                    content: "Updated content".to_string(),
+                    session_id: SessionId("test-session".into()),
+                    path: std::path::PathBuf::from("/test/file.txt"),
+                    content: "new\n".to_string(),
+                    dry_run: true,
                     meta: None,
                 })
-                .await;
+                .await
+                .expect("write_text_file failed");
 
-            assert!(write_result.is_ok());
+            let diff = response.diff.expect("dry run should return a diff");
+            assert_eq!(diff.old_text.as_deref(), Some("old\n"));
+            assert_eq!(diff.new_text, "new\n");
+            assert_eq!(diff.line_stats(), (1, 1));
+            assert!(client.written_files.lock().unwrap().is_empty());
         })
         .await;
 }
 
 #[tokio::test]
-async fn test_session_notifications() {
+async fn test_session_modes() {
     let local_set = tokio::task::LocalSet::new();
     local_set
         .run_until(async {
             let client = TestClient::new();
             let agent = TestAgent::new();
 
-            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
 
-            let session_id = SessionId(Arc::from("test-session"));
-            // Send various session updates
-            client_conn
-                .session_notification(SessionNotification {
-                    session_id: session_id.clone(),
-                    update: SessionUpdate::UserMessageChunk {
-                        content: ContentBlock::Text(TextContent {
-                            annotations: None,
-                            text: "Hello from user".to_string(),
-                            meta: None,
-                        }),
-                    },
+            let session_id = agent_conn
+                .new_session(NewSessionRequest {
+                    mcp_servers: vec![],
+                    cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
                     meta: None,
                 })
                 .await
-                .expect("session_notification failed");
+                .expect("new_session failed")
+                .session_id;
+            agent_conn
+                .set_session_mode(SetSessionModeRequest {
+                    session_id,
+                    mode_id: SessionModeId("code".into()),
+                    meta: None,
+                })
+                .await
+                .expect("set_session_mode failed");
 
             client_conn
                 .session_notification(SessionNotification {
-                    session_id: session_id.clone(),
-                    update: SessionUpdate::AgentMessageChunk {
-                        content: ContentBlock::Text(TextContent {
-                            annotations: None,
-                            text: "Hello from agent".to_string(),
-                            meta: None,
-                        }),
+                    session_id: SessionId("test-session".into()),
+                    update: SessionUpdate::CurrentModeUpdate {
+                        current_mode_id: SessionModeId("code".into()),
                     },
+                    turn_id: None,
                     meta: None,
                 })
                 .await
                 .expect("session_notification failed");
 
             tokio::task::yield_now().await;
-
             let notifications = client.session_notifications.lock().unwrap();
-            assert_eq!(notifications.len(), 2);
-            assert_eq!(notifications[0].session_id, session_id);
-            assert_eq!(notifications[1].session_id, session_id);
+            assert!(matches!(
+                &notifications[..],
+                [SessionNotification {
+                    update: SessionUpdate::CurrentModeUpdate { current_mode_id },
+                    turn_id: None,
+                    ..
+                }] if current_mode_id.0.as_ref() == "code"
+            ));
         })
         .await;
 }
 
 #[tokio::test]
-async fn test_cancel_notification() {
+async fn test_unknown_session() {
     let local_set = tokio::task::LocalSet::new();
     local_set
         .run_until(async {
@@ -448,450 +923,3124 @@ async fn test_cancel_notification() {
 
             let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
 
-            let session_id = SessionId(Arc::from("test-session"));
-            // Send cancel notification
-            agent_conn
-                .cancel(CancelNotification {
-                    session_id: session_id.clone(),
+            // The agent doesn't know the session, for example after a restart.
+            let error = agent_conn
+                .set_session_mode(SetSessionModeRequest {
+                    session_id: SessionId("sess_before_restart".into()),
+                    mode_id: SessionModeId("code".into()),
                     meta: None,
                 })
                 .await
-                .expect("cancel failed");
-
-            tokio::task::yield_now().await;
+                .expect_err("set_session_mode should fail for an unknown session");
 
-            let cancelled = agent.cancellations_received.lock().unwrap();
-            assert_eq!(cancelled.len(), 1);
-            assert_eq!(cancelled[0], session_id);
+            assert_eq!(error.code, -32008);
+            assert_eq!(error.kind(), Some(ErrorKind::SessionNotFound));
+            assert_eq!(
+                error.data,
+                Some(json!({ "sessionId": "sess_before_restart" }))
+            );
         })
         .await;
 }
 
 #[tokio::test]
-async fn test_concurrent_operations() {
+async fn test_session_models() {
     let local_set = tokio::task::LocalSet::new();
     local_set
         .run_until(async {
             let client = TestClient::new();
             let agent = TestAgent::new();
 
-            // Add multiple file contents
-            for i in 0..5 {
-                let path = std::path::PathBuf::from(format!("/test/file{i}.txt"));
-                client.add_file_content(path, format!("Content {i}"));
-            }
-
-            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
 
-            let session_id = SessionId(Arc::from("test-session"));
+            agent_conn
+                .set_session_model(SetSessionModelRequest {
+                    session_id: SessionId("test-session".into()),
+                    model_id: ModelId("sonnet".into()),
+                    meta: None,
+                })
+                .await
+                .expect("set_session_model failed");
 
-            // Launch multiple concurrent read operations
-            let mut read_futures = vec![];
-            for i in 0..5 {
-                let path = std::path::PathBuf::from(format!("/test/file{i}.txt"));
-                let future = client_conn.read_text_file(ReadTextFileRequest {
-                    session_id: session_id.clone(),
-                    path,
-                    line: None,
-                    limit: None,
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: SessionId("test-session".into()),
+                    update: SessionUpdate::CurrentModelUpdate {
+                        current_model_id: ModelId("sonnet".into()),
+                    },
+                    turn_id: None,
                     meta: None,
-                });
-                read_futures.push(future);
-            }
-
-            // Wait for all reads to complete
-            let results = futures::future::join_all(read_futures).await;
+                })
+                .await
+                .expect("session_notification failed");
 
-            // Verify all reads succeeded
-            for (i, result) in results.into_iter().enumerate() {
-                let output = result.expect("read failed");
-                assert_eq!(output.content, format!("Content {i}"));
-            }
+            tokio::task::yield_now().await;
+            let notifications = client.session_notifications.lock().unwrap();
+            assert!(matches!(
+                &notifications[..],
+                [SessionNotification {
+                    update: SessionUpdate::CurrentModelUpdate { current_model_id },
+                    ..
+                }] if current_model_id.0.as_ref() == "sonnet"
+            ));
         })
         .await;
 }
 
 #[tokio::test]
-async fn test_full_conversation_flow() {
+async fn test_list_commands() {
     let local_set = tokio::task::LocalSet::new();
     local_set
         .run_until(async {
             let client = TestClient::new();
             let agent = TestAgent::new();
 
-            // Set up permission to approve the tool call
-            client.add_permission_response(RequestPermissionOutcome::Selected {
-                option_id: PermissionOptionId(Arc::from("allow-once")),
-            });
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
 
-            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
-            // 1. Start new session
-            let new_session_result = agent_conn
-                .new_session(NewSessionRequest {
-                    mcp_servers: vec![],
-                    cwd: std::path::PathBuf::from("/test"),
+            let response = agent_conn
+                .list_commands(ListCommandsRequest {
+                    session_id: SessionId("test-session".into()),
                     meta: None,
                 })
                 .await
-                .expect("new_session failed");
+                .expect("list_commands failed");
+
+            assert_eq!(response.commands.len(), 1);
+            assert_eq!(response.commands[0].name, "web");
+            assert!(matches!(
+                &response.commands[0].input,
+                Some(AvailableCommandInput::Unstructured { hint }) if hint == "query to search for"
+            ));
+        })
+        .await;
+}
 
-            let session_id = new_session_result.session_id;
+#[tokio::test]
+async fn test_invoke_command() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
 
-            // 2. Send user message
-            let user_prompt = vec![ContentBlock::Text(TextContent {
-                annotations: None,
-                text: "Please analyze the file and summarize it".to_string(),
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+
+            let invoke = |name: &str| InvokeCommandRequest {
+                session_id: SessionId("test-session".into()),
+                name: name.to_string(),
+                arguments: "agent client protocol".to_string(),
+                progress_token: None,
+                turn_id: None,
                 meta: None,
-            })];
+            };
 
-            agent_conn
-                .prompt(PromptRequest {
-                    session_id: session_id.clone(),
-                    prompt: user_prompt,
-                    meta: None,
-                })
+            let response = agent_conn
+                .invoke_command(invoke("web"))
                 .await
-                .expect("prompt failed");
+                .expect("invoke_command failed");
+            assert_eq!(response.stop_reason, StopReason::EndTurn);
 
-            // 3. Agent starts responding
-            client_conn
-                .session_notification(SessionNotification {
-                    session_id: session_id.clone(),
-                    update: SessionUpdate::AgentMessageChunk {
-                        content: ContentBlock::Text(TextContent {
-                            annotations: None,
-                            text: "I'll analyze the file for you. ".to_string(),
-                            meta: None,
-                        }),
-                    },
-                    meta: None,
-                })
+            let error = agent_conn
+                .invoke_command(invoke("unknown"))
                 .await
-                .expect("session_notification failed");
+                .expect_err("unknown commands should fail");
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+        })
+        .await;
+}
 
-            // 4. Agent creates a tool call
-            let tool_call_id = ToolCallId(Arc::from("read-file-001"));
-            client_conn
-                .session_notification(SessionNotification {
+#[tokio::test]
+async fn test_bidirectional_file_operations() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            // Add test file content
+            let test_path = std::path::PathBuf::from("/test/file.txt");
+            client.add_file_content(test_path.clone(), "Hello, World!".to_string());
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+
+            // Test reading a file
+            let session_id = SessionId(Arc::from("test-session"));
+            let read_result = client_conn
+                .read_text_file(ReadTextFileRequest {
                     session_id: session_id.clone(),
-                    update: SessionUpdate::ToolCall(ToolCall {
-                        id: tool_call_id.clone(),
-                        title: "Reading file".to_string(),
-                        kind: ToolKind::Read,
-                        status: ToolCallStatus::Pending,
-                        content: vec![],
-                        locations: vec![ToolCallLocation {
-                            path: std::path::PathBuf::from("/test/data.txt"),
-                            line: None,
-                            meta: None,
-                        }],
-                        raw_input: None,
-                        raw_output: None,
-                        meta: None,
-                    }),
+                    path: test_path.clone(),
+                    line: None,
+                    limit: None,
+                    byte_offset: None,
+                    byte_limit: None,
                     meta: None,
                 })
                 .await
-                .expect("session_notification failed");
+                .expect("read_text_file failed");
 
-            // 5. Agent requests permission for the tool call
-            let permission_result = client_conn
-                .request_permission(RequestPermissionRequest {
+            assert_eq!(read_result.content, "Hello, World!");
+
+            // Test writing a file
+            let write_result = client_conn
+                .write_text_file(WriteTextFileRequest {
                     session_id: session_id.clone(),
-                    tool_call: ToolCallUpdate {
-                        id: tool_call_id.clone(),
-                        fields: ToolCallUpdateFields {
-                            title: Some("Read /test/data.txt".to_string()),
-                            locations: Some(vec![ToolCallLocation {
-                                path: std::path::PathBuf::from("/test/data.txt"),
-                                line: None,
-                                meta: None,
-                            }]),
-                            ..Default::default()
-                        },
-                        meta: None,
-                    },
-                    options: vec![
-                        PermissionOption {
-                            id: PermissionOptionId(Arc::from("allow-once")),
-                            name: "Allow once".to_string(),
-                            kind: PermissionOptionKind::AllowOnce,
-                            meta: None,
-                        },
-                        PermissionOption {
-                            id: PermissionOptionId(Arc::from("reject-once")),
-                            name: "Reject".to_string(),
-                            kind: PermissionOptionKind::RejectOnce,
-                            meta: None,
-                        },
-                    ],
+                    path: test_path.clone(),
+                    content: "Updated content".to_string(),
+                    dry_run: false,
                     meta: None,
                 })
-                .await
-                .expect("request_permission failed");
+                .await;
 
-            // Verify permission was granted
-            match permission_result.outcome {
-                RequestPermissionOutcome::Selected { option_id } => {
-                    assert_eq!(option_id.0.as_ref(), "allow-once");
-                }
-                RequestPermissionOutcome::Cancelled => panic!("Expected permission to be granted"),
-            }
+            assert!(write_result.is_ok());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_delete_and_rename_files() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            client.add_file_content("/test/old.txt".into(), "content".to_string());
+            client.add_file_content("/test/generated.txt".into(), "artifact".to_string());
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
 
-            // 6. Update tool call status
             client_conn
-                .session_notification(SessionNotification {
-                    session_id: session_id.clone(),
-                    update: SessionUpdate::ToolCallUpdate(ToolCallUpdate {
-                        id: tool_call_id.clone(),
-                        fields: ToolCallUpdateFields {
-                            status: Some(ToolCallStatus::InProgress),
-                            ..Default::default()
-                        },
-                        meta: None,
-                    }),
+                .rename_file(RenameFileRequest {
+                    session_id: SessionId("test-session".into()),
+                    old_path: std::path::PathBuf::from("/test/old.txt"),
+                    new_path: std::path::PathBuf::from("/test/new.txt"),
                     meta: None,
                 })
                 .await
-                .expect("session_notification failed");
-
-            // 7. Tool call completes with content
+                .expect("rename_file failed");
             client_conn
-                .session_notification(SessionNotification {
-                    session_id: session_id.clone(),
-                    update: SessionUpdate::ToolCallUpdate(ToolCallUpdate {
-                        id: tool_call_id.clone(),
-                        fields: ToolCallUpdateFields {
-                            status: Some(ToolCallStatus::Completed),
-                            content: Some(vec![ToolCallContent::Content {
-                                content: ContentBlock::Text(TextContent {
-                                    annotations: None,
-                                    text: "File contents: Lorem ipsum dolor sit amet".to_string(),
-                                    meta: None,
-                                }),
-                            }]),
-                            ..Default::default()
-                        },
-                        meta: None,
-                    }),
+                .delete_file(DeleteFileRequest {
+                    session_id: SessionId("test-session".into()),
+                    path: std::path::PathBuf::from("/test/generated.txt"),
                     meta: None,
                 })
                 .await
-                .expect("session_notification failed");
+                .expect("delete_file failed");
+
+            {
+                let contents = client.file_contents.lock().unwrap();
+                assert_eq!(contents.len(), 1);
+                assert_eq!(
+                    contents.get(std::path::Path::new("/test/new.txt")),
+                    Some(&"content".to_string())
+                );
+            }
 
-            // 8. Agent sends more text after tool completion
-            client_conn
-                .session_notification(SessionNotification {
-                    session_id: session_id.clone(),
-                    update: SessionUpdate::AgentMessageChunk {
-                        content: ContentBlock::Text(TextContent {
-                            annotations: None,
-                            text: "Based on the file contents, here's my summary: The file contains placeholder text commonly used in the printing industry.".to_string(),
-                            meta: None,
-                        }),
-                    },
+            let error = client_conn
+                .delete_file(DeleteFileRequest {
+                    session_id: SessionId("test-session".into()),
+                    path: std::path::PathBuf::from("/test/missing.txt"),
                     meta: None,
                 })
                 .await
-                .expect("session_notification failed");
+                .expect_err("deleting a missing file should fail");
+            assert_eq!(error.code, ErrorCode::RESOURCE_NOT_FOUND.code);
+            assert_eq!(error.data, Some(json!({"uri": "/test/missing.txt"})));
+
+            let error = client_conn
+                .rename_file(RenameFileRequest {
+                    session_id: SessionId("test-session".into()),
+                    old_path: std::path::PathBuf::from("/test/missing.txt"),
+                    new_path: std::path::PathBuf::from("/test/other.txt"),
+                    meta: None,
+                })
+                .await
+                .expect_err("renaming a missing file should fail");
+            assert_eq!(error.code, ErrorCode::RESOURCE_NOT_FOUND.code);
+        })
+        .await;
+}
 
-            for _ in 0..10 {
-                tokio::task::yield_now().await;
-            }
+#[tokio::test]
+async fn test_stat() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
 
-            // Verify we received all the updates
-            let updates = client.session_notifications.lock().unwrap();
-            assert!(updates.len() >= 5); // At least 5 updates sent
+            client.add_file_content("/test/file.txt".into(), "content".to_string());
 
-            // Verify the sequence of updates
-            let mut found_agent_message = false;
-            let mut found_tool_call = false;
-            let mut found_tool_update = false;
-            let mut found_final_message = false;
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let stat = |path: &str| StatRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from(path),
+                meta: None,
+            };
 
-            for notification in updates.iter() {
-                match &notification.update {
-                    SessionUpdate::AgentMessageChunk { content : ContentBlock::Text(text)} => {
-                        if text.text.contains("I'll analyze") {
-                            found_agent_message = true;
-                        } else if text.text.contains("Based on the file") {
-                            found_final_message = true;
-                        }
-                    }
-                    SessionUpdate::ToolCall(_) => {
-                        found_tool_call = true;
-                    }
-                    SessionUpdate::ToolCallUpdate(update) => {
-                        if let Some(ToolCallStatus::Completed) = update.fields.status {
-                            found_tool_update = true;
-                        }
-                    }
-                    _ => {}
-                }
+            let response = client_conn
+                .stat(stat("/test/file.txt"))
+                .await
+                .expect("stat failed");
+            assert!(response.exists);
+            assert!(!response.is_dir);
+            assert_eq!(response.size, Some(7));
+
+            // A missing path is reported in the response rather than as an error.
+            let response = client_conn
+                .stat(stat("/test/missing.txt"))
+                .await
+                .expect("stat of a missing path should succeed");
+            assert_eq!(response, StatResponse::default());
+            assert!(!response.exists);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_search_files() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            for path in ["/test/src/a.rs", "/test/src/b.rs", "/test/src/c.rs"] {
+                client.add_file_content(path.into(), String::new());
             }
+            client.add_file_content("/test/README.md".into(), String::new());
+            client.add_file_content("/other/d.rs".into(), String::new());
 
-            assert!(found_agent_message, "Should have initial agent message");
-            assert!(found_tool_call, "Should have tool call");
-            assert!(found_tool_update, "Should have tool call completion");
-            assert!(found_final_message, "Should have final agent message");
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let search = |max_results| SearchFilesRequest {
+                session_id: SessionId("test-session".into()),
+                root: std::path::PathBuf::from("/test"),
+                glob: "*.rs".to_string(),
+                max_results,
+                meta: None,
+            };
+
+            let response = client_conn
+                .search_files(search(None))
+                .await
+                .expect("search_files failed");
+            assert_eq!(
+                response.paths,
+                ["/test/src/a.rs", "/test/src/b.rs", "/test/src/c.rs"]
+                    .map(std::path::PathBuf::from)
+            );
+            assert!(!response.truncated);
+
+            let response = client_conn
+                .search_files(search(Some(2)))
+                .await
+                .expect("search_files failed");
+            assert_eq!(response.paths.len(), 2);
+            assert!(response.truncated);
         })
         .await;
 }
 
 #[tokio::test]
-async fn test_notification_wire_format() {
-    use crate::{
-        AgentNotification, AgentSide, CancelNotification, ClientNotification, ClientSide,
-        ContentBlock, SessionNotification, SessionUpdate, TextContent,
-        rpc::{JsonRpcMessage, OutgoingMessage},
-    };
-    use serde_json::{Value, json};
+async fn test_grep() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
 
-    // Test client -> agent notification wire format
-    let outgoing_msg =
-        JsonRpcMessage::wrap(OutgoingMessage::<ClientSide, AgentSide>::Notification {
-            method: "cancel".into(),
-            params: Some(ClientNotification::CancelNotification(CancelNotification {
-                session_id: SessionId("test-123".into()),
+            client.add_file_content(
+                "/test/src/main.rs".into(),
+                "fn main() {\n    let café = run(1 + 2);\n}\n".to_string(),
+            );
+            client.add_file_content(
+                "/test/src/lib.rs".into(),
+                "pub fn run(n: u32) -> u32 {\n    n + 2\n}\n".to_string(),
+            );
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let grep = |query: &str, is_regex| GrepRequest {
+                session_id: SessionId("test-session".into()),
+                query: query.to_string(),
+                is_regex,
+                root: std::path::PathBuf::from("/test"),
+                max_results: None,
                 meta: None,
-            })),
-        });
+            };
 
-    let serialized: Value = serde_json::to_value(&outgoing_msg).unwrap();
-    assert_eq!(
-        serialized,
-        json!({
-            "jsonrpc": "2.0",
-            "method": "cancel",
-            "params": {
-                "sessionId": "test-123"
-            },
+            // In literal mode, `+` has no special meaning.
+            let response = client_conn
+                .grep(grep("1 + 2", false))
+                .await
+                .expect("grep failed");
+            assert_eq!(
+                response.matches,
+                [GrepMatch {
+                    path: "/test/src/main.rs".into(),
+                    line: 2,
+                    column: 20,
+                    line_text: "    let café = run(1 + 2);".to_string(),
+                    meta: None,
+                }]
+            );
+            assert_eq!(
+                response.matches[0].location(),
+                ToolCallLocation {
+                    line: Some(2),
+                    column: Some(20),
+                    ..ToolCallLocation::from("/test/src/main.rs")
+                }
+            );
+
+            let response = client_conn
+                .grep(grep(r"fn \w+\(", true))
+                .await
+                .expect("grep failed");
+            let matches: Vec<_> = response
+                .matches
+                .iter()
+                .map(|m| (m.path.to_str().unwrap(), m.line, m.column))
+                .collect();
+            assert_eq!(
+                matches,
+                [("/test/src/lib.rs", 1, 5), ("/test/src/main.rs", 1, 1)]
+            );
+
+            let response = client_conn
+                .grep(GrepRequest {
+                    max_results: Some(1),
+                    ..grep(r"\d", true)
+                })
+                .await
+                .expect("grep failed");
+            assert_eq!(response.matches.len(), 1);
         })
-    );
+        .await;
+}
 
-    // Test agent -> client notification wire format
-    let outgoing_msg =
-        JsonRpcMessage::wrap(OutgoingMessage::<AgentSide, ClientSide>::Notification {
-            method: "sessionUpdate".into(),
-            params: Some(AgentNotification::SessionNotification(
-                SessionNotification {
-                    session_id: SessionId("test-456".into()),
-                    update: SessionUpdate::AgentMessageChunk {
-                        content: ContentBlock::Text(TextContent {
-                            annotations: None,
-                            text: "Hello".to_string(),
-                            meta: None,
-                        }),
+#[tokio::test]
+async fn test_request_user_input() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let request = |input_type| RequestUserInputRequest {
+                session_id: SessionId("test-session".into()),
+                prompt: "Which branch should I target?".to_string(),
+                input_type,
+                meta: None,
+            };
+
+            agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities {
+                        request_user_input: true,
+                        ..Default::default()
                     },
+                    client_info: None,
                     meta: None,
-                },
-            )),
-        });
+                })
+                .await
+                .expect("initialize failed");
 
-    let serialized: Value = serde_json::to_value(&outgoing_msg).unwrap();
-    assert_eq!(
-        serialized,
-        json!({
-            "jsonrpc": "2.0",
-            "method": "sessionUpdate",
-            "params": {
-                "sessionId": "test-456",
-                "update": {
-                    "sessionUpdate": "agent_message_chunk",
-                    "content": {
-                        "type": "text",
-                        "text": "Hello"
-                    }
-                }
-            }
+            let response = client_conn
+                .request_user_input(request(InputType::Text))
+                .await
+                .expect("request_user_input failed");
+            assert_eq!(response.value.as_deref(), Some("main"));
+
+            let response = client_conn
+                .request_user_input(request(InputType::Choice {
+                    options: vec!["develop".to_string(), "main".to_string()],
+                }))
+                .await
+                .expect("request_user_input failed");
+            assert_eq!(response.value.as_deref(), Some("develop"));
+
+            // A dismissed request has no value.
+            let response = client_conn
+                .request_user_input(request(InputType::Confirm))
+                .await
+                .expect("request_user_input failed");
+            assert_eq!(response.value, None);
+
+            let inputs = client.user_inputs.lock().unwrap().clone();
+            assert_eq!(inputs.len(), 3);
+            assert_eq!(inputs[0].prompt, "Which branch should I target?");
+            assert_eq!(inputs[2].input_type, InputType::Confirm);
+
+            agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities::default(),
+                    client_info: None,
+                    meta: None,
+                })
+                .await
+                .expect("initialize failed");
+            let error = client_conn
+                .request_user_input(request(InputType::Text))
+                .await
+                .expect_err("request_user_input should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_REQUEST.code);
+            assert_eq!(
+                error.data,
+                Some(json!("client does not support session/request_user_input"))
+            );
         })
-    );
+        .await;
 }
 
 #[tokio::test]
-async fn test_extension_methods_and_notifications() {
+async fn test_read_text_file_stream() {
+    use futures::TryStreamExt as _;
+
     let local_set = tokio::task::LocalSet::new();
     local_set
         .run_until(async {
             let client = TestClient::new();
             let agent = TestAgent::new();
 
-            // Store references to the client and agent to check notifications later
-            let client_ref = client.clone();
-            let agent_ref = agent.clone();
+            let log = "line 1\nünïcödé\nline 3\n".to_string();
+            client.add_file_content("/test/build.log".into(), log.clone());
 
-            let (client_conn, agent_conn) = create_connection_pair(&client, &agent);
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
 
-            // Test agent calling client extension method
-            let client_response = agent_conn
-                .ext_method(ExtRequest {
-                    method: "example.com/ping".into(),
-                    params: raw_json!({"data": "test"}),
-                })
+            let request = ReadTextFileChunkRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from("/test/build.log"),
+                offset: 0,
+                max_bytes: Some(5),
+                meta: None,
+            };
+            let chunks = client_conn
+                .read_text_file_stream(request.clone())
+                .try_collect::<Vec<_>>()
                 .await
-                .unwrap();
-
+                .expect("read_text_file_stream failed");
+            assert!(chunks.len() > 1);
+            assert!(chunks.iter().all(|chunk| chunk.content.len() <= 6));
+            assert!(chunks.last().unwrap().eof);
+            assert!(chunks[..chunks.len() - 1].iter().all(|chunk| !chunk.eof));
             assert_eq!(
-                serde_json::to_value(client_response).unwrap(),
-                serde_json::json!({
-                    "response": "pong",
-                    "params": {"data": "test"}
+                chunks
+                    .iter()
+                    .map(|chunk| chunk.content.as_str())
+                    .collect::<String>(),
+                log
+            );
+
+            let chunks = client_conn
+                .read_text_file_stream(ReadTextFileChunkRequest {
+                    offset: 7,
+                    max_bytes: None,
+                    ..request.clone()
                 })
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("read_text_file_stream failed");
+            assert_eq!(
+                chunks
+                    .iter()
+                    .map(|chunk| chunk.content.as_str())
+                    .collect::<String>(),
+                &log[7..]
             );
 
-            // Test client calling agent extension method
-            let agent_response = client_conn
-                .ext_method(ExtRequest {
-                    method: "example.com/echo".into(),
-                    params: raw_json!({"message": "hello"}),
+            let error = client_conn
+                .read_text_file_stream(ReadTextFileChunkRequest {
+                    path: std::path::PathBuf::from("/test/missing.log"),
+                    ..request
                 })
+                .try_collect::<Vec<_>>()
                 .await
-                .unwrap();
+                .expect_err("reading a missing file should fail");
+            assert_eq!(error.code, ErrorCode::RESOURCE_NOT_FOUND.code);
+        })
+        .await;
+}
 
-            assert_eq!(
-                serde_json::to_value(agent_response).unwrap(),
-                serde_json::json!({
-                    "echo": {"message": "hello"}
+#[tokio::test]
+async fn test_read_text_file_byte_range() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let text = "héllo wörld\n".to_string();
+            client.add_file_content("/test/notes.txt".into(), text.clone());
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let request = ReadTextFileRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from("/test/notes.txt"),
+                line: None,
+                limit: None,
+                byte_offset: Some(0),
+                byte_limit: Some(4),
+                meta: None,
+            };
+
+            let error = client_conn
+                .read_text_file(ReadTextFileRequest {
+                    line: Some(1),
+                    ..request.clone()
                 })
-            );
+                .await
+                .expect_err("mixing line and byte ranges should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+
+            let initialize = |read_text_file_byte_range| InitializeRequest {
+                protocol_version: VERSION,
+                client_capabilities: ClientCapabilities {
+                    fs: FileSystemCapability {
+                        read_text_file: true,
+                        read_text_file_byte_range,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                client_info: None,
+                meta: None,
+            };
+            agent_conn
+                .initialize(initialize(false))
+                .await
+                .expect("initialize failed");
+            let error = client_conn
+                .read_text_file(request.clone())
+                .await
+                .expect_err("byte range reads should be rejected");
+            assert_eq!(error.code, ErrorCode::INVALID_REQUEST.code);
 
-            // Test extension notifications
             agent_conn
-                .ext_notification(ExtNotification {
-                    method: "example.com/client/notify".into(),
-                    params: raw_json!({"info": "client notification"}),
-                })
+                .initialize(initialize(true))
                 .await
-                .unwrap();
+                .expect("initialize failed");
+            let mut content = String::new();
+            let mut byte_offset = Some(0);
+            while let Some(offset) = byte_offset {
+                let response = client_conn
+                    .read_text_file(ReadTextFileRequest {
+                        byte_offset: Some(offset),
+                        ..request.clone()
+                    })
+                    .await
+                    .expect("read_text_file failed");
+                assert!(!response.content.is_empty());
+                assert!(response.content.len() <= 4);
+                content.push_str(&response.content);
+                byte_offset = response.next_byte_offset;
+            }
+            assert_eq!(content, text);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_write_terminal_input() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            client
+                .exited_terminals
+                .lock()
+                .unwrap()
+                .insert(TerminalId("term-exited".into()));
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
 
             client_conn
-                .ext_notification(ExtNotification {
-                    method: "example.com/agent/notify".into(),
-                    params: raw_json!({"info": "agent notification"}),
+                .write_terminal_input(WriteTerminalInputRequest {
+                    session_id: SessionId("test-session".into()),
+                    terminal_id: TerminalId("term-1".into()),
+                    data: "print(1 + 1)\n".to_string(),
+                    meta: None,
                 })
                 .await
-                .unwrap();
-
-            // Yield to allow notifications to be processed
-            tokio::task::yield_now().await;
+                .expect("write_terminal_input failed");
 
-            // Verify client received the notification
-            let client_notifications = client_ref.extension_notifications.lock().unwrap();
-            assert_eq!(client_notifications.len(), 1);
-            assert_eq!(client_notifications[0].0, "example.com/client/notify");
             assert_eq!(
-                serde_json::to_value(&client_notifications[0].1).unwrap(),
-                serde_json::json!({"info": "client notification"})
+                *client.terminal_inputs.lock().unwrap(),
+                vec![(TerminalId("term-1".into()), "print(1 + 1)\n".to_string())]
             );
 
-            // Verify agent received the notification
-            let agent_notifications = agent_ref.extension_notifications.lock().unwrap();
-            assert_eq!(agent_notifications.len(), 1);
-            assert_eq!(agent_notifications[0].0, "example.com/agent/notify");
-            assert_eq!(
-                serde_json::to_value(&agent_notifications[0].1).unwrap(),
-                serde_json::json!({"info": "agent notification"})
-            );
-        })
+            let error = client_conn
+                .write_terminal_input(WriteTerminalInputRequest {
+                    session_id: SessionId("test-session".into()),
+                    terminal_id: TerminalId("term-exited".into()),
+                    data: "exit\n".to_string(),
+                    meta: None,
+                })
+                .await
+                .expect_err("writing to an exited terminal should fail");
+            assert_eq!(error.code, ErrorCode::TERMINAL_EXITED.code);
+            assert_eq!(error.data, Some(json!({"terminalId": "term-exited"})));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_resize_terminal() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+
+            client_conn
+                .resize_terminal(ResizeTerminalRequest {
+                    session_id: SessionId("test-session".into()),
+                    terminal_id: TerminalId("term-1".into()),
+                    cols: 120,
+                    rows: 40,
+                    meta: None,
+                })
+                .await
+                .expect("resize_terminal failed");
+
+            assert_eq!(
+                *client.terminal_sizes.lock().unwrap(),
+                vec![(TerminalId("term-1".into()), 120, 40)]
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_session_notifications() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+
+            let session_id = SessionId(Arc::from("test-session"));
+            // Send various session updates
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::UserMessageChunk {
+                        content: ContentBlock::Text(TextContent {
+                            annotations: None,
+                            text: "Hello from user".to_string(),
+                            meta: None,
+                        }),
+                    },
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::AgentMessageChunk {
+                        content: ContentBlock::Text(TextContent {
+                            annotations: None,
+                            text: "Hello from agent".to_string(),
+                            meta: None,
+                        }),
+                    },
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            tokio::task::yield_now().await;
+
+            let notifications = client.session_notifications.lock().unwrap();
+            assert_eq!(notifications.len(), 2);
+            assert_eq!(notifications[0].session_id, session_id);
+            assert_eq!(notifications[1].session_id, session_id);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_cancel_notification() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+
+            let session_id = SessionId(Arc::from("test-session"));
+            // Send cancel notification
+            agent_conn
+                .cancel(CancelNotification {
+                    session_id: session_id.clone(),
+                    meta: None,
+                })
+                .await
+                .expect("cancel failed");
+
+            tokio::task::yield_now().await;
+
+            let cancelled = agent.cancellations_received.lock().unwrap();
+            assert_eq!(cancelled.len(), 1);
+            assert_eq!(cancelled[0], session_id);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_cancel_trips_cancellation_token() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let registry = CancellationRegistry::new();
+            let client_conn = client_conn.with_cancellation_registry(registry.clone());
+
+            let session_id = SessionId(Arc::from("test-session"));
+            let token = client_conn.cancellation_token(session_id.clone());
+            let other = registry.register(SessionId(Arc::from("other-session")));
+            assert!(registry.is_running(&session_id));
+
+            agent_conn
+                .cancel(CancelNotification {
+                    session_id: session_id.clone(),
+                    meta: None,
+                })
+                .await
+                .expect("cancel failed");
+
+            tokio::time::timeout(std::time::Duration::from_secs(1), token.cancelled())
+                .await
+                .expect("token was not cancelled");
+            tokio::task::yield_now().await;
+            assert!(!other.is_cancelled());
+            assert!(!registry.is_running(&session_id));
+            assert_eq!(
+                *agent.cancellations_received.lock().unwrap(),
+                vec![session_id]
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_cancel_tool_call_notification() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+
+            let session_id = SessionId(Arc::from("test-session"));
+            let tool_call_id = ToolCallId(Arc::from("call_001"));
+            agent_conn
+                .cancel_tool_call(CancelToolCallNotification {
+                    session_id: session_id.clone(),
+                    tool_call_id: tool_call_id.clone(),
+                    meta: None,
+                })
+                .await
+                .expect("cancel_tool_call failed");
+
+            tokio::task::yield_now().await;
+
+            // Only the tool call is cancelled, not the whole turn.
+            assert!(agent.cancellations_received.lock().unwrap().is_empty());
+            assert_eq!(
+                *agent.tool_call_cancellations_received.lock().unwrap(),
+                vec![(session_id, tool_call_id)]
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_terminal_exited_notification() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+
+            let terminal_id = TerminalId(Arc::from("term_001"));
+            agent_conn
+                .terminal_exited(TerminalExitedNotification {
+                    session_id: SessionId(Arc::from("test-session")),
+                    terminal_id: terminal_id.clone(),
+                    exit_status: TerminalExitStatus {
+                        exit_code: Some(1),
+                        signal: None,
+                        meta: None,
+                    },
+                    meta: None,
+                })
+                .await
+                .expect("terminal_exited failed");
+
+            tokio::task::yield_now().await;
+
+            let exits = agent.terminal_exits_received.lock().unwrap();
+            assert_eq!(exits.len(), 1);
+            assert_eq!(exits[0].0, terminal_id);
+            assert_eq!(exits[0].1.exit_code, Some(1));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_concurrent_operations() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            // Add multiple file contents
+            for i in 0..5 {
+                let path = std::path::PathBuf::from(format!("/test/file{i}.txt"));
+                client.add_file_content(path, format!("Content {i}"));
+            }
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+
+            let session_id = SessionId(Arc::from("test-session"));
+
+            // Launch multiple concurrent read operations
+            let mut read_futures = vec![];
+            for i in 0..5 {
+                let path = std::path::PathBuf::from(format!("/test/file{i}.txt"));
+                let future = client_conn.read_text_file(ReadTextFileRequest {
+                    session_id: session_id.clone(),
+                    path,
+                    line: None,
+                    limit: None,
+                    byte_offset: None,
+                    byte_limit: None,
+                    meta: None,
+                });
+                read_futures.push(future);
+            }
+
+            // Wait for all reads to complete
+            let results = futures::future::join_all(read_futures).await;
+
+            // Verify all reads succeeded
+            for (i, result) in results.into_iter().enumerate() {
+                let output = result.expect("read failed");
+                assert_eq!(output.content, format!("Content {i}"));
+            }
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_read_coalescing() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+            client.add_file_content("/test/file.txt".into(), "Content".to_string());
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let client_conn = client_conn.with_read_coalescing(true);
+            let mut stream = client_conn.subscribe();
+
+            let read = |path: &str, line| ReadTextFileRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from(path),
+                line,
+                limit: None,
+                byte_offset: None,
+                byte_limit: None,
+                meta: None,
+            };
+            let results = futures::future::join_all([
+                client_conn.read_text_file(read("/test/file.txt", None)),
+                client_conn.read_text_file(read("/test/file.txt", None)),
+                client_conn.read_text_file(read("/test/file.txt", None)),
+                client_conn.read_text_file(read("/test/file.txt", Some(2))),
+                client_conn.read_text_file(read("/test/other.txt", None)),
+            ])
+            .await;
+            for result in results {
+                result.expect("read failed");
+            }
+
+            // Reads made after the first one finished are sent again.
+            client_conn
+                .read_text_file(read("/test/file.txt", None))
+                .await
+                .expect("read failed");
+
+            let mut sent = Vec::new();
+            while !stream.is_empty() {
+                let message = stream.recv().await.unwrap();
+                if let StreamMessageContent::Request { params, .. } = message.message {
+                    sent.push(
+                        params.unwrap().value().unwrap()["path"]
+                            .as_str()
+                            .unwrap()
+                            .to_string(),
+                    );
+                }
+            }
+            assert_eq!(
+                sent,
+                [
+                    "/test/file.txt",
+                    "/test/file.txt",
+                    "/test/other.txt",
+                    "/test/file.txt"
+                ]
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_with_retry() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+            client.add_file_content("/test/file.txt".into(), "Content".to_string());
+
+            let (_agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let request = ReadTextFileRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from("/test/file.txt"),
+                line: None,
+                limit: None,
+                byte_offset: None,
+                byte_limit: None,
+                meta: None,
+            };
+            let config = RetryConfig {
+                max_attempts: 3,
+                base_delay: std::time::Duration::from_millis(1),
+                max_delay: std::time::Duration::from_millis(5),
+                jitter: 0.0,
+            };
+            let delays = Arc::new(Mutex::new(Vec::new()));
+            let sleep = |delay| {
+                delays.lock().unwrap().push(delay);
+                tokio::time::sleep(delay)
+            };
+
+            client.fail_next_reads(2);
+            let response = with_retry(&config, sleep, || {
+                client_conn.read_text_file(request.clone())
+            })
+            .await
+            .expect("read should succeed on the third attempt");
+            assert_eq!(response.content, "Content");
+            assert_eq!(
+                *delays.lock().unwrap(),
+                [
+                    std::time::Duration::from_millis(1),
+                    std::time::Duration::from_millis(2)
+                ]
+            );
+
+            // Gives up once the attempts are exhausted.
+            client.fail_next_reads(3);
+            let error = with_retry(&config, sleep, || {
+                client_conn.read_text_file(request.clone())
+            })
+            .await
+            .expect_err("read should fail after three attempts");
+            assert_eq!(error.code, ErrorCode::INTERNAL_ERROR.code);
+            assert_eq!(*client.failing_reads.lock().unwrap(), 0);
+
+            // A closed connection is never retried.
+            delays.lock().unwrap().clear();
+            client_conn.shutdown();
+            let error = with_retry(&config, sleep, || {
+                client_conn.read_text_file(request.clone())
+            })
+            .await
+            .expect_err("read should fail on a closed connection");
+            assert_eq!(error.code, ErrorCode::INTERNAL_ERROR.code);
+            assert!(delays.lock().unwrap().is_empty());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_full_conversation_flow() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            // Set up permission to approve the tool call
+            client.add_permission_response(RequestPermissionOutcome::Selected {
+                option_id: PermissionOptionId(Arc::from("allow-once")),
+            });
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            // 1. Start new session
+            let new_session_result = agent_conn
+                .new_session(NewSessionRequest {
+                    mcp_servers: vec![],
+                    cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
+                    meta: None,
+                })
+                .await
+                .expect("new_session failed");
+
+            let session_id = new_session_result.session_id;
+
+            // 2. Send user message
+            let user_prompt = vec![ContentBlock::Text(TextContent {
+                annotations: None,
+                text: "Please analyze the file and summarize it".to_string(),
+                meta: None,
+            })];
+
+            agent_conn
+                .prompt(PromptRequest {
+                    session_id: session_id.clone(),
+                    prompt: user_prompt,
+                    context: Vec::new(),
+                    progress_token: None,
+                    turn_id: None,
+                    tools_enabled: None,
+                    meta: None,
+                })
+                .await
+                .expect("prompt failed");
+
+            // 3. Agent starts responding
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::AgentMessageChunk {
+                        content: ContentBlock::Text(TextContent {
+                            annotations: None,
+                            text: "I'll analyze the file for you. ".to_string(),
+                            meta: None,
+                        }),
+                    },
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            // 4. Agent creates a tool call
+            let tool_call_id = ToolCallId(Arc::from("read-file-001"));
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::ToolCall(ToolCall {
+                        id: tool_call_id.clone(),
+                        title: "Reading file".to_string(),
+                        kind: ToolKind::Read,
+                        status: ToolCallStatus::Pending,
+                        content: vec![],
+                        locations: vec![ToolCallLocation {
+                            path: std::path::PathBuf::from("/test/data.txt"),
+                            line: Some(3),
+                            column: Some(5),
+                            end_line: Some(7),
+                            end_column: Some(1),
+                            meta: None,
+                        }],
+                        raw_input: None,
+                        raw_output: None,
+                        meta: None,
+                    }),
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            // 5. Agent requests permission for the tool call
+            let permission_result = client_conn
+                .request_permission(RequestPermissionRequest {
+                    session_id: session_id.clone(),
+                    tool_call: ToolCallUpdate {
+                        id: tool_call_id.clone(),
+                        fields: ToolCallUpdateFields {
+                            title: Some("Read /test/data.txt".to_string()),
+                            locations: Some(vec![ToolCallLocation {
+                                path: std::path::PathBuf::from("/test/data.txt"),
+                                line: None,
+                                column: None,
+                                end_line: None,
+                                end_column: None,
+                                meta: None,
+                            }]),
+                            ..Default::default()
+                        },
+                        meta: None,
+                    },
+                    options: vec![
+                        PermissionOption {
+                            id: PermissionOptionId(Arc::from("allow-once")),
+                            name: "Allow once".to_string(),
+                            kind: PermissionOptionKind::AllowOnce,
+                            meta: None,
+                        },
+                        PermissionOption {
+                            id: PermissionOptionId(Arc::from("reject-once")),
+                            name: "Reject".to_string(),
+                            kind: PermissionOptionKind::RejectOnce,
+                            meta: None,
+                        },
+                    ],
+                    context: None,
+                    meta: None,
+                })
+                .await
+                .expect("request_permission failed");
+
+            // Verify permission was granted
+            match permission_result.outcome {
+                RequestPermissionOutcome::Selected { option_id } => {
+                    assert_eq!(option_id.0.as_ref(), "allow-once");
+                }
+                RequestPermissionOutcome::Cancelled => panic!("Expected permission to be granted"),
+            }
+
+            // 6. Update tool call status
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::ToolCallUpdate(ToolCallUpdate {
+                        id: tool_call_id.clone(),
+                        fields: ToolCallUpdateFields {
+                            status: Some(ToolCallStatus::InProgress),
+                            ..Default::default()
+                        },
+                        meta: None,
+                    }),
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            // 7. Tool call completes with content
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::ToolCallUpdate(ToolCallUpdate {
+                        id: tool_call_id.clone(),
+                        fields: ToolCallUpdateFields {
+                            status: Some(ToolCallStatus::Completed),
+                            content: Some(vec![ToolCallContent::Content {
+                                content: ContentBlock::Text(TextContent {
+                                    annotations: None,
+                                    text: "File contents: Lorem ipsum dolor sit amet".to_string(),
+                                    meta: None,
+                                }),
+                            }]),
+                            ..Default::default()
+                        },
+                        meta: None,
+                    }),
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            // 8. Agent sends more text after tool completion
+            client_conn
+                .session_notification(SessionNotification {
+                    session_id: session_id.clone(),
+                    update: SessionUpdate::AgentMessageChunk {
+                        content: ContentBlock::Text(TextContent {
+                            annotations: None,
+                            text: "Based on the file contents, here's my summary: The file contains placeholder text commonly used in the printing industry.".to_string(),
+                            meta: None,
+                        }),
+                    },
+                    turn_id: None,
+                    meta: None,
+                })
+                .await
+                .expect("session_notification failed");
+
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+
+            // Verify we received all the updates
+            let updates = client.session_notifications.lock().unwrap();
+            assert!(updates.len() >= 5); // At least 5 updates sent
+
+            // Verify the sequence of updates
+            let mut found_agent_message = false;
+            let mut found_tool_call = false;
+            let mut found_tool_update = false;
+            let mut found_final_message = false;
+
+            for notification in updates.iter() {
+                match &notification.update {
+                    SessionUpdate::AgentMessageChunk { content : ContentBlock::Text(text)} => {
+                        if text.text.contains("I'll analyze") {
+                            found_agent_message = true;
+                        } else if text.text.contains("Based on the file") {
+                            found_final_message = true;
+                        }
+                    }
+                    SessionUpdate::ToolCall(tool_call) => {
+                        found_tool_call = true;
+                        let location = &tool_call.locations[0];
+                        assert_eq!(
+                            (location.line, location.column, location.end_line, location.end_column),
+                            (Some(3), Some(5), Some(7), Some(1))
+                        );
+                    }
+                    SessionUpdate::ToolCallUpdate(update) => {
+                        if let Some(ToolCallStatus::Completed) = update.fields.status {
+                            found_tool_update = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            assert!(found_agent_message, "Should have initial agent message");
+            assert!(found_tool_call, "Should have tool call");
+            assert!(found_tool_update, "Should have tool call completion");
+            assert!(found_final_message, "Should have final agent message");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_notification_wire_format() {
+    use crate::{
+        AgentNotification, AgentSide, CancelNotification, ClientNotification, ClientSide,
+        ContentBlock, SessionNotification, SessionUpdate, TextContent,
+        rpc::{JsonRpcMessage, OutgoingMessage},
+    };
+    use serde_json::{Value, json};
+
+    // Test client -> agent notification wire format
+    let outgoing_msg =
+        JsonRpcMessage::wrap(OutgoingMessage::<ClientSide, AgentSide>::Notification {
+            method: "cancel".into(),
+            params: Some(ClientNotification::CancelNotification(CancelNotification {
+                session_id: SessionId("test-123".into()),
+                meta: None,
+            })),
+        });
+
+    let serialized: Value = serde_json::to_value(&outgoing_msg).unwrap();
+    assert_eq!(
+        serialized,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "cancel",
+            "params": {
+                "sessionId": "test-123"
+            },
+        })
+    );
+
+    // Test agent -> client notification wire format
+    let outgoing_msg =
+        JsonRpcMessage::wrap(OutgoingMessage::<AgentSide, ClientSide>::Notification {
+            method: "sessionUpdate".into(),
+            params: Some(AgentNotification::SessionNotification(
+                SessionNotification {
+                    session_id: SessionId("test-456".into()),
+                    update: SessionUpdate::AgentMessageChunk {
+                        content: ContentBlock::Text(TextContent {
+                            annotations: None,
+                            text: "Hello".to_string(),
+                            meta: None,
+                        }),
+                    },
+                    turn_id: None,
+                    meta: None,
+                },
+            )),
+        });
+
+    let serialized: Value = serde_json::to_value(&outgoing_msg).unwrap();
+    assert_eq!(
+        serialized,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "sessionUpdate",
+            "params": {
+                "sessionId": "test-456",
+                "update": {
+                    "sessionUpdate": "agent_message_chunk",
+                    "content": {
+                        "type": "text",
+                        "text": "Hello"
+                    }
+                }
+            }
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_extension_methods_and_notifications() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            // Store references to the client and agent to check notifications later
+            let client_ref = client.clone();
+            let agent_ref = agent.clone();
+
+            let (client_conn, agent_conn) = create_connection_pair(&client, &agent);
+
+            // Test agent calling client extension method
+            let client_response = agent_conn
+                .ext_method(ExtRequest {
+                    method: "example.com/ping".into(),
+                    params: raw_json!({"data": "test"}),
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(
+                serde_json::to_value(client_response).unwrap(),
+                serde_json::json!({
+                    "response": "pong",
+                    "params": {"data": "test"}
+                })
+            );
+
+            // Test client calling agent extension method
+            let agent_response = client_conn
+                .ext_method(ExtRequest {
+                    method: "example.com/echo".into(),
+                    params: raw_json!({"message": "hello"}),
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(
+                serde_json::to_value(agent_response).unwrap(),
+                serde_json::json!({
+                    "echo": {"message": "hello"}
+                })
+            );
+
+            // Test extension notifications
+            agent_conn
+                .ext_notification(ExtNotification {
+                    method: "example.com/client/notify".into(),
+                    params: raw_json!({"info": "client notification"}),
+                })
+                .await
+                .unwrap();
+
+            client_conn
+                .ext_notification(ExtNotification {
+                    method: "example.com/agent/notify".into(),
+                    params: raw_json!({"info": "agent notification"}),
+                })
+                .await
+                .unwrap();
+
+            // Yield to allow notifications to be processed
+            tokio::task::yield_now().await;
+
+            // Verify client received the notification
+            let client_notifications = client_ref.extension_notifications.lock().unwrap();
+            assert_eq!(client_notifications.len(), 1);
+            assert_eq!(client_notifications[0].0, "example.com/client/notify");
+            assert_eq!(
+                serde_json::to_value(&client_notifications[0].1).unwrap(),
+                serde_json::json!({"info": "client notification"})
+            );
+
+            // Verify agent received the notification
+            let agent_notifications = agent_ref.extension_notifications.lock().unwrap();
+            assert_eq!(agent_notifications.len(), 1);
+            assert_eq!(agent_notifications[0].0, "example.com/agent/notify");
+            assert_eq!(
+                serde_json::to_value(&agent_notifications[0].1).unwrap(),
+                serde_json::json!({"info": "agent notification"})
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_request_timeout() {
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+
+            // Nothing answers on the agent side, so requests can only resolve by timing out.
+            let (agent_conn, io_task) =
+                ClientSideConnection::new(client, client_to_agent_tx, agent_to_client_rx, |fut| {
+                    tokio::task::spawn_local(fut);
+                });
+            let agent_conn = agent_conn
+                .with_request_timeout(std::time::Duration::from_millis(10), tokio::time::sleep);
+            tokio::task::spawn_local(io_task);
+            let mut requests = futures::io::BufReader::new(client_to_agent_rx).lines();
+
+            let result = agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities::default(),
+                    client_info: None,
+                    meta: None,
+                })
+                .await;
+            let error = result.expect_err("initialize should time out");
+            assert_eq!(error.code, ErrorCode::REQUEST_TIMEOUT.code);
+
+            // A late response for the timed out request is dropped without affecting
+            // subsequent requests.
+            let late_response = json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "result": {"protocolVersion": 1}
+            });
+            agent_to_client_tx
+                .write_all(format!("{late_response}\n").as_bytes())
+                .await
+                .unwrap();
+
+            let second_request = agent_conn.initialize(InitializeRequest {
+                protocol_version: VERSION,
+                client_capabilities: ClientCapabilities::default(),
+                client_info: None,
+                meta: None,
+            });
+            let respond = async {
+                requests.next().await.unwrap().unwrap();
+                let second_request: serde_json::Value =
+                    serde_json::from_str(&requests.next().await.unwrap().unwrap()).unwrap();
+                assert_eq!(second_request["id"], 1);
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": {"protocolVersion": 1}
+                });
+                agent_to_client_tx
+                    .write_all(format!("{response}\n").as_bytes())
+                    .await
+                    .unwrap();
+            };
+            let (response, ()) = futures::join!(second_request, respond);
+            assert_eq!(response.unwrap().protocol_version, VERSION);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_batch_requests() {
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            client.add_file_content("/test/a.txt".into(), "contents of a".to_string());
+            client.add_file_content("/test/b.txt".into(), "contents of b".to_string());
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+
+            let (_agent_conn, io_task) = ClientSideConnection::new(
+                client.clone(),
+                client_to_agent_tx,
+                agent_to_client_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::task::spawn_local(io_task);
+            let mut responses = futures::io::BufReader::new(client_to_agent_rx).lines();
+
+            let batch = json!([
+                {
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "fs/read_text_file",
+                    "params": {"sessionId": "test-session", "path": "/test/a.txt"}
+                },
+                {
+                    "jsonrpc": "2.0",
+                    "method": "session/update",
+                    "params": {
+                        "sessionId": "test-session",
+                        "update": {
+                            "sessionUpdate": "agent_message_chunk",
+                            "content": {"type": "text", "text": "Reading files"}
+                        }
+                    }
+                },
+                {
+                    "jsonrpc": "2.0",
+                    "id": 2,
+                    "method": "fs/read_text_file",
+                    "params": {"sessionId": "test-session", "path": "/test/b.txt"}
+                }
+            ]);
+            agent_to_client_tx
+                .write_all(format!("{batch}\n").as_bytes())
+                .await
+                .unwrap();
+
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(
+                response,
+                json!([
+                    {"jsonrpc": "2.0", "id": 1, "result": {"content": "contents of a"}},
+                    {"jsonrpc": "2.0", "id": 2, "result": {"content": "contents of b"}}
+                ])
+            );
+            assert_eq!(client.session_notifications.lock().unwrap().len(), 1);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_ping_in_batch() {
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            client.add_file_content("/test/a.txt".into(), "contents of a".to_string());
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+
+            let (_agent_conn, io_task) = ClientSideConnection::new(
+                client.clone(),
+                client_to_agent_tx,
+                agent_to_client_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::task::spawn_local(io_task);
+            let mut responses = futures::io::BufReader::new(client_to_agent_rx).lines();
+
+            let batch = json!([
+                {"jsonrpc": "2.0", "id": 1, "method": "$/ping"},
+                {
+                    "jsonrpc": "2.0",
+                    "id": 2,
+                    "method": "fs/read_text_file",
+                    "params": {"sessionId": "test-session", "path": "/test/a.txt"}
+                }
+            ]);
+            agent_to_client_tx
+                .write_all(format!("{batch}\n").as_bytes())
+                .await
+                .unwrap();
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(
+                response,
+                json!([
+                    {"jsonrpc": "2.0", "id": 2, "result": {"content": "contents of a"}},
+                    {"jsonrpc": "2.0", "id": 1, "result": null}
+                ])
+            );
+
+            let batch = json!([
+                {"jsonrpc": "2.0", "id": 3, "method": "$/ping"},
+                {"jsonrpc": "2.0", "id": 4, "method": "$/ping"}
+            ]);
+            agent_to_client_tx
+                .write_all(format!("{batch}\n").as_bytes())
+                .await
+                .unwrap();
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(
+                response,
+                json!([
+                    {"jsonrpc": "2.0", "id": 3, "result": null},
+                    {"jsonrpc": "2.0", "id": 4, "result": null}
+                ])
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_invalid_batch() {
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            client.add_file_content("/test/a.txt".into(), "contents of a".to_string());
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+
+            let (_agent_conn, io_task) = ClientSideConnection::new(
+                client.clone(),
+                client_to_agent_tx,
+                agent_to_client_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::task::spawn_local(io_task);
+            let mut responses = futures::io::BufReader::new(client_to_agent_rx).lines();
+            let invalid_request = json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": -32600, "message": "Invalid Request"}
+            });
+
+            // An empty batch gets a single error rather than an array.
+            agent_to_client_tx.write_all(b"[]\n").await.unwrap();
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(response, invalid_request);
+
+            // Invalid elements get an error each, and valid requests are still answered.
+            let batch = json!([
+                1,
+                {
+                    "jsonrpc": "2.0",
+                    "id": 2,
+                    "method": "fs/read_text_file",
+                    "params": {"sessionId": "test-session", "path": "/test/a.txt"}
+                },
+                {"foo": "bar"},
+                {"jsonrpc": "2.0", "id": "not-a-number", "method": "fs/read_text_file"}
+            ]);
+            agent_to_client_tx
+                .write_all(format!("{batch}\n").as_bytes())
+                .await
+                .unwrap();
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(
+                response,
+                json!([
+                    {"jsonrpc": "2.0", "id": 2, "result": {"content": "contents of a"}},
+                    invalid_request,
+                    invalid_request,
+                    invalid_request
+                ])
+            );
+
+            // A batch of only invalid elements is answered right away.
+            agent_to_client_tx.write_all(b"[1, 2]\n").await.unwrap();
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(response, json!([invalid_request, invalid_request]));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_max_concurrent_requests() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let _agent_conn = agent_conn.with_max_concurrent_requests(8, 1000);
+
+            let results = futures::future::join_all((0..1000).map(|_| {
+                client_conn.ext_method(ExtRequest {
+                    method: "example.com/slow".into(),
+                    params: raw_json!({}),
+                })
+            }))
+            .await;
+
+            assert!(results.iter().all(Result::is_ok));
+            let (in_flight, max_in_flight) = *client.requests_in_flight.lock().unwrap();
+            assert_eq!(in_flight, 0);
+            assert!(max_in_flight <= 8, "{max_in_flight} requests ran at once");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_too_many_requests() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let _agent_conn = agent_conn.with_max_concurrent_requests(2, 3);
+
+            let results = futures::future::join_all((0..20).map(|_| {
+                client_conn.ext_method(ExtRequest {
+                    method: "example.com/slow".into(),
+                    params: raw_json!({}),
+                })
+            }))
+            .await;
+
+            let rejected = results
+                .iter()
+                .filter(|result| result.as_ref().is_err_and(|error| error.code == -32003))
+                .count();
+            assert!(rejected > 0);
+            assert_eq!(
+                results.iter().filter(|result| result.is_ok()).count() + rejected,
+                20
+            );
+            assert!(client.requests_in_flight.lock().unwrap().1 <= 2);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_keepalive() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let agent_conn = agent_conn.with_keepalive(
+                std::time::Duration::from_millis(5),
+                2,
+                tokio::time::sleep,
+            );
+            let mut agent_stream = agent_conn.subscribe();
+            let mut client_stream = client_conn.subscribe();
+
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+            // Pings are answered by the connection and never reach the handler or
+            // stream subscribers.
+            for stream in [&mut agent_stream, &mut client_stream] {
+                let message = stream.recv().await.unwrap();
+                assert!(matches!(
+                    message.message,
+                    StreamMessageContent::ConnectionOpened
+                ));
+                assert!(stream.is_empty());
+            }
+            agent_conn
+                .authenticate(AuthenticateRequest {
+                    method_id: AuthMethodId("test".into()),
+                    meta: None,
+                })
+                .await
+                .expect("connection should still be alive");
+            let message = client_stream.recv().await.unwrap();
+            assert_eq!(message.message.method(), Some("authenticate"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_prompt_error_code_preserved() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+
+            let error = agent_conn
+                .prompt(PromptRequest {
+                    session_id: SessionId("unauthenticated".into()),
+                    prompt: vec!["Hello".into()],
+                    context: Vec::new(),
+                    progress_token: None,
+                    turn_id: None,
+                    tools_enabled: None,
+                    meta: None,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, ErrorCode::AUTH_REQUIRED.code);
+            assert_eq!(error.message, ErrorCode::AUTH_REQUIRED.message);
+            assert_eq!(error.data, None);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_error_kind_reaches_peer() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+
+            let error = agent_conn
+                .authenticate(AuthenticateRequest {
+                    method_id: AuthMethodId("expired".into()),
+                    meta: None,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, -32000);
+            assert_eq!(error.message, "Authentication required");
+            assert_eq!(error.data, Some(json!("token expired")));
+            assert_eq!(error.kind(), Some(ErrorKind::AuthRequired));
+
+            // Errors wrapped in `anyhow` keep their code too.
+            let error = agent_conn
+                .authenticate(AuthenticateRequest {
+                    method_id: AuthMethodId("conflict".into()),
+                    meta: None,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, -32005);
+            assert_eq!(error.kind(), Some(ErrorKind::Conflict));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_keepalive_peer_unreachable() {
+    use futures::{AsyncBufReadExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, _agent_to_client_tx) = piper::pipe(1024);
+
+            // The agent side reads the pings but never answers.
+            let (agent_conn, io_task) =
+                ClientSideConnection::new(client, client_to_agent_tx, agent_to_client_rx, |fut| {
+                    tokio::task::spawn_local(fut);
+                });
+            let _agent_conn = agent_conn.with_keepalive(
+                std::time::Duration::from_millis(5),
+                2,
+                tokio::time::sleep,
+            );
+            let io_task = tokio::task::spawn_local(io_task);
+
+            let mut pings = futures::io::BufReader::new(client_to_agent_rx).lines();
+            let ping: serde_json::Value =
+                serde_json::from_str(&pings.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(
+                ping,
+                json!({"jsonrpc": "2.0", "id": -1, "method": "$/ping"})
+            );
+
+            let error = tokio::time::timeout(std::time::Duration::from_secs(5), io_task)
+                .await
+                .expect("io task should fail")
+                .unwrap()
+                .expect_err("io task should fail");
+            assert_eq!(
+                error.downcast_ref::<PeerUnreachable>(),
+                Some(&PeerUnreachable { missed_pings: 2 })
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_content_length_framing() {
+    use futures::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+
+            let (agent_conn, io_task) =
+                ClientSideConnection::new(client, client_to_agent_tx, agent_to_client_rx, |fut| {
+                    tokio::task::spawn_local(fut);
+                });
+            let agent_conn = agent_conn.with_framing(Framing::ContentLength);
+            tokio::task::spawn_local(io_task);
+            let response = tokio::task::spawn_local(async move {
+                agent_conn
+                    .authenticate(AuthenticateRequest {
+                        method_id: AuthMethodId("test".into()),
+                        meta: None,
+                    })
+                    .await
+            });
+
+            let mut reader = futures::io::BufReader::new(client_to_agent_rx);
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            let content_length: usize = header
+                .strip_prefix("Content-Length: ")
+                .and_then(|header| header.strip_suffix("\r\n"))
+                .unwrap()
+                .parse()
+                .unwrap();
+            header.clear();
+            reader.read_line(&mut header).await.unwrap();
+            assert_eq!(header, "\r\n");
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await.unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(request["method"], "authenticate");
+
+            // Bodies may span several lines, and unknown headers are ignored.
+            let body = serde_json::to_string_pretty(&json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": {}
+            }))
+            .unwrap();
+            assert!(body.contains('\n'));
+            agent_to_client_tx
+                .write_all(
+                    format!(
+                        "Content-Type: application/vscode-jsonrpc; charset=utf-8\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            response.await.unwrap().unwrap();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_max_message_size() {
+    use futures::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let update = |text: &str| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "session/update",
+                    "params": {
+                        "sessionId": "test-session",
+                        "update": {
+                            "sessionUpdate": "agent_message_chunk",
+                            "content": {"type": "text", "text": text}
+                        }
+                    }
+                })
+                .to_string()
+            };
+            let too_large = json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": -32006, "message": "Message too large", "data": {"maxBytes": 4096}}
+            });
+
+            // Oversized messages are skipped, and later ones are still handled.
+            let client = TestClient::new();
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+            let (agent_conn, io_task) = ClientSideConnection::new(
+                client.clone(),
+                client_to_agent_tx,
+                agent_to_client_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            let _agent_conn = agent_conn.with_max_message_size(4096, OversizedMessagePolicy::Skip);
+            tokio::task::spawn_local(io_task);
+            let mut responses = futures::io::BufReader::new(client_to_agent_rx).lines();
+
+            for text in ["x".repeat(1 << 20), "small".to_string()] {
+                agent_to_client_tx
+                    .write_all(format!("{}\n", update(&text)).as_bytes())
+                    .await
+                    .unwrap();
+            }
+            let response: serde_json::Value =
+                serde_json::from_str(&responses.next().await.unwrap().unwrap()).unwrap();
+            assert_eq!(response, too_large);
+            while client.session_notifications.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            let notifications = client.session_notifications.lock().unwrap().clone();
+            assert_eq!(notifications.len(), 1);
+            assert!(matches!(
+                &notifications[0].update,
+                SessionUpdate::AgentMessageChunk { content: ContentBlock::Text(text) } if text.text == "small"
+            ));
+
+            // With the close policy, the connection fails instead. Content-Length
+            // framing checks the declared length before reading the body.
+            let (mut client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1024);
+            let (agent_conn, io_task) = ClientSideConnection::new(
+                TestClient::new(),
+                client_to_agent_tx,
+                agent_to_client_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            let _agent_conn = agent_conn
+                .with_framing(Framing::ContentLength)
+                .with_max_message_size(4096, OversizedMessagePolicy::Close);
+            let io_task = tokio::task::spawn_local(io_task);
+
+            let body = update(&"x".repeat(1 << 20));
+            agent_to_client_tx
+                .write_all(format!("Content-Length: {}\r\n\r\n{body}", body.len()).as_bytes())
+                .await
+                .unwrap();
+            let error = io_task.await.unwrap().expect_err("connection should close");
+            assert_eq!(
+                error.downcast_ref::<Error>().and_then(Error::kind),
+                Some(ErrorKind::MessageTooLarge)
+            );
+
+            let mut written = String::new();
+            client_to_agent_rx
+                .read_to_string(&mut written)
+                .await
+                .unwrap();
+            let (_, body) = written.split_once("\r\n\r\n").unwrap();
+            assert_eq!(serde_json::from_str::<serde_json::Value>(body).unwrap(), too_large);
+        })
+        .await;
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn test_request_tracing() {
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let output = Output::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer({
+            let output = output.clone();
+            move || output.clone()
+        })
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+            agent_conn
+                .set_session_mode(SetSessionModeRequest {
+                    session_id: SessionId("sess_traced".into()),
+                    mode_id: SessionModeId("code".into()),
+                    meta: None,
+                })
+                .await
+                .expect_err("set_session_mode should fail for an unknown session");
+        })
+        .await;
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    for direction in ["outgoing", "incoming"] {
+        let span = format!(
+            "acp.request{{direction=\"{direction}\" method=\"session/set_mode\" id=0 session_id=\"sess_traced\"}}"
+        );
+        assert!(
+            output
+                .lines()
+                .any(|line| line.contains(&span) && line.contains("request failed")),
+            "missing {direction} span in:\n{output}"
+        );
+    }
+    assert!(output.contains("matched response to pending request is_error=true"));
+    assert!(output.contains("error.code=-32008"));
+}
+
+#[cfg(feature = "compression")]
+#[tokio::test]
+async fn test_compression() {
+    use futures::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _};
+    use std::io::{Read as _, Write as _};
+
+    async fn read_frame(
+        reader: &mut (impl futures::AsyncBufRead + Unpin),
+    ) -> (Option<String>, serde_json::Value) {
+        let mut content_length = 0;
+        let mut content_encoding = None;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            let Some((name, value)) = header.trim_end().split_once(": ") else {
+                break;
+            };
+            match name {
+                "Content-Length" => content_length = value.parse().unwrap(),
+                "Content-Encoding" => content_encoding = Some(value.to_string()),
+                _ => panic!("unexpected header {name}"),
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await.unwrap();
+        if content_encoding.is_some() {
+            let mut decompressed = Vec::new();
+            flate2::read::GzDecoder::new(&body[..])
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            body = decompressed;
+        }
+        (content_encoding, serde_json::from_slice(&body).unwrap())
+    }
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1 << 20);
+            let (agent_to_client_rx, mut agent_to_client_tx) = piper::pipe(1 << 20);
+
+            let (agent_conn, io_task) =
+                ClientSideConnection::new(client, client_to_agent_tx, agent_to_client_rx, |fut| {
+                    tokio::task::spawn_local(fut);
+                });
+            let agent_conn = std::rc::Rc::new(agent_conn.with_framing(Framing::ContentLength));
+            tokio::task::spawn_local(io_task);
+            let mut reader = futures::io::BufReader::new(client_to_agent_rx);
+            let large_method_id = AuthMethodId("x".repeat(4096).into());
+
+            // Nothing is compressed before both sides advertised an encoding.
+            tokio::task::spawn_local({
+                let agent_conn = agent_conn.clone();
+                let method_id = large_method_id.clone();
+                async move {
+                    agent_conn
+                        .authenticate(AuthenticateRequest {
+                            method_id,
+                            meta: None,
+                        })
+                        .await
+                }
+            });
+            let (content_encoding, request) = read_frame(&mut reader).await;
+            assert_eq!(content_encoding, None);
+            assert_eq!(request["method"], "authenticate");
+
+            let initialize = tokio::task::spawn_local({
+                let agent_conn = agent_conn.clone();
+                async move {
+                    agent_conn
+                        .initialize(InitializeRequest {
+                            protocol_version: VERSION,
+                            client_capabilities: ClientCapabilities {
+                                compression: vec![ContentEncoding::Gzip, ContentEncoding::Deflate],
+                                ..Default::default()
+                            },
+                            client_info: None,
+                            meta: None,
+                        })
+                        .await
+                }
+            });
+            let (_, request) = read_frame(&mut reader).await;
+            assert_eq!(
+                request["params"]["clientCapabilities"]["compression"],
+                json!(["gzip", "deflate"])
+            );
+
+            // Compressed messages from the agent are decompressed.
+            let body = serde_json::to_vec(&json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": {
+                    "protocolVersion": VERSION,
+                    "agentCapabilities": { "compression": ["gzip"] }
+                }
+            }))
+            .unwrap();
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&body).unwrap();
+            let body = encoder.finish().unwrap();
+            agent_to_client_tx
+                .write_all(
+                    format!(
+                        "Content-Length: {}\r\nContent-Encoding: gzip\r\n\r\n",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            agent_to_client_tx.write_all(&body).await.unwrap();
+            let response = initialize.await.unwrap().unwrap();
+            assert_eq!(
+                response.agent_capabilities.compression,
+                vec![ContentEncoding::Gzip]
+            );
+
+            // Large messages are now compressed with the negotiated encoding, small ones
+            // are not.
+            tokio::task::spawn_local({
+                let agent_conn = agent_conn.clone();
+                async move {
+                    agent_conn
+                        .authenticate(AuthenticateRequest {
+                            method_id: large_method_id,
+                            meta: None,
+                        })
+                        .await
+                }
+            });
+            let (content_encoding, request) = read_frame(&mut reader).await;
+            assert_eq!(content_encoding.as_deref(), Some("gzip"));
+            assert_eq!(request["params"]["methodId"].as_str().unwrap().len(), 4096);
+
+            tokio::task::spawn_local(async move {
+                agent_conn
+                    .authenticate(AuthenticateRequest {
+                        method_id: AuthMethodId("small".into()),
+                        meta: None,
+                    })
+                    .await
+            });
+            let (content_encoding, request) = read_frame(&mut reader).await;
+            assert_eq!(content_encoding, None);
+            assert_eq!(request["params"]["methodId"], "small");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let (_client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, _agent_to_client_tx) = piper::pipe(1024);
+
+            // Nothing answers on the agent side, and the incoming stream stays open.
+            let (agent_conn, io_task) =
+                ClientSideConnection::new(client, client_to_agent_tx, agent_to_client_rx, |fut| {
+                    tokio::task::spawn_local(fut);
+                });
+            let io_task = tokio::task::spawn_local(io_task);
+            let mut stream = agent_conn.subscribe();
+
+            let agent_conn = std::rc::Rc::new(agent_conn);
+            let pending = tokio::task::spawn_local({
+                let agent_conn = agent_conn.clone();
+                async move {
+                    agent_conn
+                        .initialize(InitializeRequest {
+                            protocol_version: VERSION,
+                            client_capabilities: ClientCapabilities::default(),
+                            client_info: None,
+                            meta: None,
+                        })
+                        .await
+                }
+            });
+            // Wait for the request to go out.
+            let message = stream.recv().await.unwrap();
+            assert!(matches!(
+                message.message,
+                StreamMessageContent::ConnectionOpened
+            ));
+            let message = stream.recv().await.unwrap();
+            assert_eq!(message.message.method(), Some("initialize"));
+
+            agent_conn.shutdown();
+            agent_conn.shutdown();
+
+            let error = pending.await.unwrap().expect_err("request should fail");
+            assert_eq!(error.code, ErrorCode::INTERNAL_ERROR.code);
+            assert_eq!(error.data, Some(json!("connection closed")));
+
+            io_task
+                .await
+                .unwrap()
+                .expect("io task should finish cleanly");
+            let message = stream.recv().await.unwrap();
+            assert_eq!(message.direction, StreamMessageDirection::Outgoing);
+            assert!(matches!(
+                message.message,
+                StreamMessageContent::ConnectionClosed { reason: None }
+            ));
+            assert!(stream.recv().await.is_err());
+
+            let error = agent_conn
+                .new_session(NewSessionRequest {
+                    mcp_servers: vec![],
+                    cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
+                    meta: None,
+                })
+                .await
+                .expect_err("requests after shutdown should fail");
+            assert_eq!(error.data, Some(json!("connection closed")));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_stream_message_classification() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+            let mut stream = agent_conn.subscribe();
+
+            agent_conn
+                .cancel(CancelNotification {
+                    session_id: SessionId("test-session".into()),
+                    meta: None,
+                })
+                .await
+                .unwrap();
+            agent_conn
+                .new_session(NewSessionRequest {
+                    mcp_servers: vec![],
+                    cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
+                    meta: None,
+                })
+                .await
+                .unwrap();
+
+            let mut messages = Vec::new();
+            for _ in 0..4 {
+                let message = stream.recv().await.unwrap().message;
+                let method = message
+                    .method()
+                    .map(|method| method.parse::<AcpMethod>().unwrap());
+                messages.push((message.kind(), method));
+            }
+            assert_eq!(
+                messages,
+                vec![
+                    (StreamMessageKind::Lifecycle, None),
+                    (StreamMessageKind::Notification, Some(AcpMethod::SessionCancel)),
+                    (StreamMessageKind::Request, Some(AcpMethod::SessionNew)),
+                    (StreamMessageKind::Response, None),
+                ]
+            );
+
+            assert_eq!(
+                StreamMessageKind::classify(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"})),
+                Some(StreamMessageKind::Request)
+            );
+            assert_eq!(
+                StreamMessageKind::classify(&json!({"jsonrpc": "2.0", "method": "session/update"})),
+                Some(StreamMessageKind::Notification)
+            );
+            assert_eq!(
+                StreamMessageKind::classify(
+                    &json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": "Parse error"}})
+                ),
+                Some(StreamMessageKind::Response)
+            );
+            assert_eq!(StreamMessageKind::classify(&json!({"jsonrpc": "2.0"})), None);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_subscribe_filtered() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+            let mut stream = agent_conn.subscribe_filtered(|message| {
+                message.direction == StreamMessageDirection::Outgoing
+                    && message.method == Some("session/cancel")
+            });
+
+            agent_conn
+                .new_session(NewSessionRequest {
+                    mcp_servers: vec![],
+                    cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
+                    meta: None,
+                })
+                .await
+                .unwrap();
+            agent_conn
+                .cancel(CancelNotification {
+                    session_id: SessionId("test-session".into()),
+                    meta: None,
+                })
+                .await
+                .unwrap();
+
+            let message = stream.recv().await.unwrap();
+            assert_eq!(message.direction, StreamMessageDirection::Outgoing);
+            match message.message {
+                StreamMessageContent::Notification { method, params } => {
+                    assert_eq!(&*method, "session/cancel");
+                    let params = params.unwrap();
+                    assert_eq!(params.raw().get(), r#"{"sessionId":"test-session"}"#);
+                    assert_eq!(params, json!({"sessionId": "test-session"}));
+                }
+                other => panic!("unexpected message: {other:?}"),
+            }
+            assert!(stream.is_empty());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_connection_stats() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            assert_eq!(agent_conn.stats(), ConnectionStats::default());
+
+            agent_conn
+                .cancel(CancelNotification {
+                    session_id: SessionId("test-session".into()),
+                    meta: None,
+                })
+                .await
+                .expect("cancel failed");
+            agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities::default(),
+                    client_info: None,
+                    meta: None,
+                })
+                .await
+                .expect("initialize failed");
+            agent_conn
+                .authenticate(AuthenticateRequest {
+                    method_id: AuthMethodId("expired".into()),
+                    meta: None,
+                })
+                .await
+                .expect_err("authenticate should fail");
+
+            assert_eq!(
+                agent_conn.stats(),
+                ConnectionStats {
+                    requests_sent: 2,
+                    requests_received: 0,
+                    responses_sent: 0,
+                    errors: 1,
+                    notifications: 1,
+                    in_flight: 0,
+                }
+            );
+            assert_eq!(
+                client_conn.stats(),
+                ConnectionStats {
+                    requests_sent: 0,
+                    requests_received: 2,
+                    responses_sent: 2,
+                    errors: 1,
+                    notifications: 1,
+                    in_flight: 0,
+                }
+            );
+
+            // A request counts as in flight until its response arrives.
+            let mut request = agent_conn.new_session(NewSessionRequest {
+                mcp_servers: Vec::new(),
+                cwd: std::path::PathBuf::from("/test"),
+                progress_token: None,
+                meta: None,
+            });
+            assert!(futures::poll!(&mut request).is_pending());
+            assert_eq!(agent_conn.stats().in_flight, 1);
+            request.await.expect("new_session failed");
+            assert_eq!(agent_conn.stats().in_flight, 0);
+            assert_eq!(agent_conn.stats().requests_sent, 3);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_cancel_resolves_pending_permission_requests() {
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _, StreamExt as _};
+
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let agent = TestAgent::new();
+            let (client_to_agent_rx, mut client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, agent_to_client_tx) = piper::pipe(1024);
+
+            // The test plays the client, and never answers permission requests on its own.
+            let (client_conn, io_task) = AgentSideConnection::new(
+                agent,
+                agent_to_client_tx,
+                client_to_agent_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::task::spawn_local(io_task);
+            let client_conn = std::rc::Rc::new(client_conn);
+            let mut requests = futures::io::BufReader::new(agent_to_client_rx).lines();
+
+            let request_permission = |session_id: &str| {
+                let client_conn = client_conn.clone();
+                let request = RequestPermissionRequest {
+                    session_id: SessionId(session_id.into()),
+                    tool_call: ToolCallUpdate::builder(ToolCallId("call-1".into()))
+                        .title("Run tests")
+                        .build(),
+                    options: PermissionOption::builder().allow_once("Allow once").build(),
+                    context: None,
+                    meta: None,
+                };
+                tokio::task::spawn_local(async move { client_conn.request_permission(request).await })
+            };
+            let cancelled = request_permission("session-1");
+            let other = request_permission("session-2");
+
+            let mut ids = std::collections::HashMap::new();
+            for _ in 0..2 {
+                let request: serde_json::Value =
+                    serde_json::from_str(&requests.next().await.unwrap().unwrap()).unwrap();
+                assert_eq!(request["method"], "session/request_permission");
+                ids.insert(
+                    request["params"]["sessionId"].as_str().unwrap().to_string(),
+                    request["id"].clone(),
+                );
+            }
+
+            let cancel = json!({
+                "jsonrpc": "2.0",
+                "method": "session/cancel",
+                "params": { "sessionId": "session-1" }
+            });
+            client_to_agent_tx
+                .write_all(format!("{cancel}\n").as_bytes())
+                .await
+                .unwrap();
+
+            let response = cancelled.await.unwrap().expect("request_permission failed");
+            assert!(matches!(
+                response.outcome,
+                RequestPermissionOutcome::Cancelled
+            ));
+            assert!(!other.is_finished());
+
+            // The client's own answer for the cancelled session is ignored.
+            let answer = |id: &serde_json::Value, option_id: &str| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": { "outcome": { "outcome": "selected", "optionId": option_id } }
+                })
+            };
+            client_to_agent_tx
+                .write_all(format!("{}\n", answer(&ids["session-1"], "allow-once")).as_bytes())
+                .await
+                .unwrap();
+            client_to_agent_tx
+                .write_all(format!("{}\n", answer(&ids["session-2"], "allow-once")).as_bytes())
+                .await
+                .unwrap();
+            let response = other.await.unwrap().expect("request_permission failed");
+            assert!(matches!(
+                response.outcome,
+                RequestPermissionOutcome::Selected { option_id } if option_id.0.as_ref() == "allow-once"
+            ));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_stream_lifecycle_events() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let mut client_stream = agent_conn.subscribe();
+            let mut agent_stream = client_conn.subscribe();
+
+            agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities::default(),
+                    client_info: None,
+                    meta: None,
+                })
+                .await
+                .unwrap();
+            client_conn.shutdown();
+
+            let mut events = Vec::new();
+            for stream in [&mut client_stream, &mut agent_stream] {
+                let mut timeline = Vec::new();
+                while let Ok(message) = stream.recv().await {
+                    let event = match message.message {
+                        StreamMessageContent::ConnectionOpened => "opened".to_string(),
+                        StreamMessageContent::Initialized { protocol_version } => {
+                            format!("initialized {protocol_version:?}")
+                        }
+                        StreamMessageContent::ConnectionClosed { reason } => {
+                            format!("closed {reason:?}")
+                        }
+                        message => format!("{:?}", message.kind()),
+                    };
+                    timeline.push((message.direction, event));
+                }
+                events.push(timeline);
+            }
+
+            use StreamMessageDirection::{Incoming, Outgoing};
+            assert_eq!(
+                events,
+                [
+                    vec![
+                        (Outgoing, "opened".to_string()),
+                        (Outgoing, "Request".to_string()),
+                        (Incoming, "Response".to_string()),
+                        (Incoming, format!("initialized {VERSION:?}")),
+                        (Incoming, "closed None".to_string()),
+                    ],
+                    vec![
+                        (Outgoing, "opened".to_string()),
+                        (Incoming, "Request".to_string()),
+                        (Outgoing, "Response".to_string()),
+                        (Outgoing, format!("initialized {VERSION:?}")),
+                        (Outgoing, "closed None".to_string()),
+                    ],
+                ]
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, client_conn) = create_connection_pair(&client, &agent);
+            let recording = tokio::task::spawn_local(
+                StreamRecorder::new(client_conn.subscribe(), Vec::new()).run(),
+            );
+
+            agent_conn
+                .initialize(InitializeRequest {
+                    protocol_version: VERSION,
+                    client_capabilities: ClientCapabilities::default(),
+                    client_info: None,
+                    meta: None,
+                })
+                .await
+                .unwrap();
+            let session_id = agent_conn
+                .new_session(NewSessionRequest {
+                    mcp_servers: vec![],
+                    cwd: std::path::PathBuf::from("/test"),
+                    progress_token: None,
+                    meta: None,
+                })
+                .await
+                .unwrap()
+                .session_id;
+            agent_conn
+                .prompt(PromptRequest {
+                    session_id,
+                    prompt: vec!["Hi".into()],
+                    context: Vec::new(),
+                    progress_token: None,
+                    turn_id: None,
+                    tools_enabled: None,
+                    meta: None,
+                })
+                .await
+                .unwrap();
+            client_conn.shutdown();
+
+            let recording = recording.await.unwrap().unwrap();
+            let replayer = StreamReplayer::from_reader(recording.as_slice()).unwrap();
+            assert_eq!(replayer.messages().len(), 6);
+            assert_eq!(replayer.expected_outgoing().len(), 3);
+
+            let transport = replayer.transport();
+            let written = transport.outgoing.clone();
+            let (_client_conn, io_task) = AgentSideConnection::new(
+                TestAgent::new(),
+                transport.outgoing,
+                transport.incoming,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::time::timeout(std::time::Duration::from_secs(5), io_task)
+                .await
+                .expect("replay should finish")
+                .unwrap();
+
+            assert_eq!(written.written(), replayer.expected_outgoing());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_stream_receiver_lagged() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let client = TestClient::new();
+            let agent = TestAgent::new();
+
+            let (agent_conn, _client_conn) = create_connection_pair(&client, &agent);
+            let mut stream = agent_conn.subscribe();
+            let message = stream.recv().await.unwrap();
+            assert!(matches!(
+                message.message,
+                StreamMessageContent::ConnectionOpened
+            ));
+            assert!(stream.is_empty());
+
+            for i in 0..100 {
+                agent_conn
+                    .ext_notification(ExtNotification {
+                        method: "example.com/notify".into(),
+                        params: raw_json!({"index": i}),
+                    })
+                    .await
+                    .unwrap();
+            }
+            while agent.extension_notifications.lock().unwrap().len() < 100 {
+                tokio::task::yield_now().await;
+            }
+
+            assert_eq!(stream.len(), 64);
+            assert_eq!(
+                stream.recv().await.unwrap_err(),
+                StreamRecvError::Lagged(36)
+            );
+
+            // The oldest message still buffered is the first one that wasn't dropped.
+            let message = stream.recv().await.unwrap();
+            assert!(matches!(
+                message.message,
+                StreamMessageContent::Notification { .. }
+            ));
+            assert_eq!(*message.message.params().unwrap(), json!({"index": 36}));
+            assert_eq!(stream.len(), 63);
+
+            agent_conn.shutdown();
+            while !matches!(stream.recv().await, Err(StreamRecvError::Closed)) {}
+        })
+        .await;
+}
+
+#[cfg(feature = "send")]
+struct SendTestClient;
+
+#[cfg(feature = "send")]
+#[async_trait::async_trait]
+impl SendClient for SendTestClient {
+    async fn request_permission(
+        &self,
+        _arguments: RequestPermissionRequest,
+    ) -> Result<RequestPermissionResponse, Error> {
+        Ok(RequestPermissionResponse {
+            outcome: RequestPermissionOutcome::Cancelled,
+            meta: None,
+        })
+    }
+
+    async fn session_notification(&self, _args: SessionNotification) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn read_text_file(
+        &self,
+        arguments: ReadTextFileRequest,
+    ) -> Result<ReadTextFileResponse, Error> {
+        tokio::task::yield_now().await;
+        Ok(ReadTextFileResponse {
+            content: format!("Hello from {}", arguments.path.display()),
+            next_byte_offset: None,
+            meta: None,
+        })
+    }
+}
+
+/// An agent and client that only implement the required methods.
+struct MinimalPeer;
+
+#[async_trait::async_trait(?Send)]
+impl Agent for MinimalPeer {
+    async fn initialize(&self, arguments: InitializeRequest) -> Result<InitializeResponse, Error> {
+        Ok(InitializeResponse {
+            protocol_version: arguments.protocol_version,
+            agent_capabilities: AgentCapabilities::default(),
+            auth_methods: vec![],
+            agent_info: None,
+            meta: None,
+        })
+    }
+
+    async fn new_session(&self, _args: NewSessionRequest) -> Result<NewSessionResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    async fn prompt(&self, _args: PromptRequest) -> Result<PromptResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    async fn cancel(&self, _args: CancelNotification) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Client for MinimalPeer {
+    async fn request_permission(
+        &self,
+        _arguments: RequestPermissionRequest,
+    ) -> Result<RequestPermissionResponse, Error> {
+        Ok(RequestPermissionResponse {
+            outcome: RequestPermissionOutcome::Cancelled,
+            meta: None,
+        })
+    }
+
+    async fn session_notification(&self, _args: SessionNotification) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_default_ext_methods() {
+    let local_set = tokio::task::LocalSet::new();
+    local_set
+        .run_until(async {
+            let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+            let (agent_to_client_rx, agent_to_client_tx) = piper::pipe(1024);
+            let (agent_conn, client_io_task) = ClientSideConnection::new(
+                MinimalPeer,
+                client_to_agent_tx,
+                agent_to_client_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            let (client_conn, agent_io_task) = AgentSideConnection::new(
+                MinimalPeer,
+                agent_to_client_tx,
+                client_to_agent_rx,
+                |fut| {
+                    tokio::task::spawn_local(fut);
+                },
+            );
+            tokio::task::spawn_local(client_io_task);
+            tokio::task::spawn_local(agent_io_task);
+
+            let request = ExtRequest {
+                method: "example.com/unknown".into(),
+                params: raw_json!({}),
+            };
+            let error = agent_conn.ext_method(request.clone()).await.unwrap_err();
+            assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND.code);
+            let error = client_conn.ext_method(request).await.unwrap_err();
+            assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND.code);
+
+            let notification = ExtNotification {
+                method: "example.com/unknown".into(),
+                params: raw_json!({}),
+            };
+            assert_eq!(
+                Agent::ext_notification(&MinimalPeer, notification.clone())
+                    .await
+                    .unwrap_err()
+                    .code,
+                ErrorCode::METHOD_NOT_FOUND.code
+            );
+            assert_eq!(
+                Client::ext_notification(&MinimalPeer, notification)
+                    .await
+                    .unwrap_err()
+                    .code,
+                ErrorCode::METHOD_NOT_FOUND.code
+            );
+        })
         .await;
 }
+
+#[cfg(feature = "send")]
+struct SendTestAgent;
+
+#[cfg(feature = "send")]
+#[async_trait::async_trait]
+impl SendAgent for SendTestAgent {
+    async fn initialize(&self, arguments: InitializeRequest) -> Result<InitializeResponse, Error> {
+        tokio::task::yield_now().await;
+        Ok(InitializeResponse {
+            protocol_version: arguments.protocol_version,
+            agent_capabilities: AgentCapabilities::default(),
+            auth_methods: vec![],
+            agent_info: None,
+            meta: None,
+        })
+    }
+
+    async fn new_session(&self, _args: NewSessionRequest) -> Result<NewSessionResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    async fn prompt(&self, _args: PromptRequest) -> Result<PromptResponse, Error> {
+        Err(Error::method_not_found())
+    }
+
+    async fn cancel(&self, _args: CancelNotification) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "send")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_connections() {
+    let client = SendTestClient;
+    let agent = SendTestAgent;
+
+    let (client_to_agent_rx, client_to_agent_tx) = piper::pipe(1024);
+    let (agent_to_client_rx, agent_to_client_tx) = piper::pipe(1024);
+
+    let (agent_conn, client_io_task) =
+        SendClientSideConnection::new(client, client_to_agent_tx, agent_to_client_rx, |fut| {
+            tokio::spawn(fut);
+        });
+    let (client_conn, agent_io_task) =
+        SendAgentSideConnection::new(agent, agent_to_client_tx, client_to_agent_rx, |fut| {
+            tokio::spawn(fut);
+        });
+    tokio::spawn(client_io_task);
+    tokio::spawn(agent_io_task);
+
+    let response = tokio::spawn(async move {
+        agent_conn
+            .initialize(InitializeRequest {
+                protocol_version: VERSION,
+                client_capabilities: ClientCapabilities {
+                    fs: FileSystemCapability {
+                        read_text_file: true,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                client_info: None,
+                meta: None,
+            })
+            .await
+    })
+    .await
+    .unwrap()
+    .expect("initialize failed");
+    assert_eq!(response.protocol_version, VERSION);
+
+    let response = tokio::spawn(async move {
+        client_conn
+            .read_text_file(ReadTextFileRequest {
+                session_id: SessionId("test-session".into()),
+                path: std::path::PathBuf::from("/test/file.txt"),
+                line: None,
+                limit: None,
+                byte_offset: None,
+                byte_limit: None,
+                meta: None,
+            })
+            .await
+    })
+    .await
+    .unwrap()
+    .expect("read_text_file failed");
+    assert_eq!(response.content, "Hello from /test/file.txt");
+
+    // Extension methods the peers don't implement are rejected.
+    let request = ExtRequest {
+        method: "example.com/unknown".into(),
+        params: raw_json!({}),
+    };
+    let error = SendAgent::ext_method(&SendTestAgent, request.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND.code);
+    let error = SendClient::ext_method(&SendTestClient, request)
+        .await
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::METHOD_NOT_FOUND.code);
+}
@@ -0,0 +1,324 @@
+//! Validation of JSON messages against the protocol's JSON Schema.
+//!
+//! The schema in `schema/schema.json` is generated from the types in this crate
+//! and bundled at compile time, so validation always matches the version of the
+//! crate in use. This is mostly useful in tests, to catch hand-built JSON that
+//! drifted from the protocol.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, LazyLock},
+};
+
+use parking_lot::Mutex;
+use serde_json::Value;
+
+/// The protocol's JSON Schema, as generated by `cargo run --bin generate`.
+pub const SCHEMA_JSON: &str = include_str!("../schema/schema.json");
+
+static SCHEMA: LazyLock<Value> =
+    LazyLock::new(|| serde_json::from_str(SCHEMA_JSON).expect("bundled schema is valid JSON"));
+
+/// A way in which a JSON value doesn't conform to the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// JSON pointer to the offending part of the value, e.g. `/update/content`.
+    pub instance_path: String,
+    /// JSON pointer to the part of the schema that rejected it.
+    pub schema_path: String,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.instance_path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.instance_path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl ValidationError {
+    fn new(instance_path: String, message: String) -> Self {
+        Self {
+            instance_path,
+            schema_path: String::new(),
+            message,
+        }
+    }
+
+    /// Converts `error`, replacing `anyOf` and `oneOf` failures with the errors of
+    /// the alternative that matched best, since "not valid under any of the
+    /// schemas" rarely says what is actually wrong.
+    fn collect(
+        error: &jsonschema::ValidationError<'_>,
+        path: &str,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        use jsonschema::error::ValidationErrorKind;
+
+        if let ValidationErrorKind::AnyOf { context }
+        | ValidationErrorKind::OneOfNotValid { context } = error.kind()
+            && let Some(best) = context.iter().max_by_key(|alternative| {
+                // The alternative that got furthest into the value is usually the
+                // one that was meant, ties going to the one with the fewest errors.
+                let depth = alternative
+                    .iter()
+                    .map(|error| error.instance_path().as_str().matches('/').count())
+                    .max()
+                    .unwrap_or_default();
+                (depth, std::cmp::Reverse(alternative.len()))
+            })
+            && !best.is_empty()
+        {
+            for error in best {
+                Self::collect(error, path, errors);
+            }
+            return;
+        }
+        errors.push(Self {
+            instance_path: format!("{path}{}", error.instance_path()),
+            schema_path: error.schema_path().to_string(),
+            message: error.to_string(),
+        });
+    }
+}
+
+/// Checks that `message`, a JSON-RPC message or batch of messages, conforms to
+/// the protocol.
+///
+/// The `params` of requests and notifications are checked against the type of
+/// their method, and unknown methods are rejected. Extension methods, whose names
+/// start with an underscore, can have any params. The `result` of a response isn't
+/// checked, since the method it answers isn't part of the message; use
+/// [`validate_against_definition`] to check it against the expected response type.
+///
+/// Error paths are relative to `message`, e.g. `/params/update/content`.
+pub fn validate_against_schema(message: &Value) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    match message {
+        Value::Array(batch) => {
+            for (index, message) in batch.iter().enumerate() {
+                validate_message(message, &format!("/{index}"), &mut errors);
+            }
+        }
+        message => validate_message(message, "", &mut errors),
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that `value` is a valid instance of the schema definition named
+/// `definition`, such as `"SessionNotification"` or `"PromptResponse"`.
+///
+/// Fails with a single error if the schema has no such definition.
+pub fn validate_against_definition(
+    definition: &str,
+    value: &Value,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    match definition_validator(definition) {
+        Some(validator) => collect_errors(&validator, value, "", &mut errors),
+        None => errors.push(ValidationError::new(
+            String::new(),
+            format!("the schema has no definition named {definition:?}"),
+        )),
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate_message(message: &Value, path: &str, errors: &mut Vec<ValidationError>) {
+    if message.get("jsonrpc") != Some(&Value::from("2.0")) {
+        errors.push(ValidationError::new(
+            format!("{path}/jsonrpc"),
+            "expected \"2.0\"".into(),
+        ));
+    }
+
+    if let Some(method) = message.get("method") {
+        let Some(method) = method.as_str() else {
+            errors.push(ValidationError::new(
+                format!("{path}/method"),
+                format!("{method} is not a string"),
+            ));
+            return;
+        };
+        if method.starts_with('_') {
+            return;
+        }
+        let Some(definition) = params_definition(method) else {
+            errors.push(ValidationError::new(
+                format!("{path}/method"),
+                format!("unknown method {method:?}"),
+            ));
+            return;
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let validator = definition_validator(definition).expect("definition exists");
+        collect_errors(&validator, params, &format!("{path}/params"), errors);
+    } else if message.get("result").is_some() {
+        // The result can only be checked by the caller, who knows its method.
+    } else if let Some(error) = message.get("error") {
+        if !error.get("code").is_some_and(Value::is_i64) {
+            errors.push(ValidationError::new(
+                format!("{path}/error/code"),
+                "expected an integer".into(),
+            ));
+        }
+        if !error.get("message").is_some_and(Value::is_string) {
+            errors.push(ValidationError::new(
+                format!("{path}/error/message"),
+                "expected a string".into(),
+            ));
+        }
+    } else {
+        errors.push(ValidationError::new(
+            path.to_string(),
+            "expected a request, notification or response".into(),
+        ));
+    }
+}
+
+/// The name of the definition describing the params of `method`.
+fn params_definition(method: &str) -> Option<&'static str> {
+    SCHEMA["$defs"]
+        .as_object()?
+        .iter()
+        .find(|(name, definition)| definition["x-method"] == method && !name.ends_with("Response"))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Compiled validators for single definitions, as compiling them is expensive.
+static DEFINITION_VALIDATORS: LazyLock<Mutex<HashMap<String, Arc<jsonschema::Validator>>>> =
+    LazyLock::new(Mutex::default);
+
+fn definition_validator(definition: &str) -> Option<Arc<jsonschema::Validator>> {
+    SCHEMA["$defs"].get(definition)?;
+    let mut validators = DEFINITION_VALIDATORS.lock();
+    let validator = validators
+        .entry(definition.to_string())
+        .or_insert_with(|| Arc::new(compile_definition(definition)));
+    Some(validator.clone())
+}
+
+/// Compiles the bundled schema with its root replaced by a reference to
+/// `definition`, so that the other definitions can still be referenced.
+fn compile_definition(definition: &str) -> jsonschema::Validator {
+    let mut schema = SCHEMA.clone();
+    let root = schema.as_object_mut().expect("bundled schema is an object");
+    root.remove("anyOf");
+    root.insert("$ref".into(), format!("#/$defs/{definition}").into());
+    jsonschema::validator_for(&schema).expect("bundled schema is a valid JSON Schema")
+}
+
+fn collect_errors(
+    validator: &jsonschema::Validator,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    for error in validator.iter_errors(value) {
+        ValidationError::collect(&error, path, errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContentBlock, SessionId, SessionNotification, SessionUpdate};
+    use serde_json::json;
+
+    fn notification(params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": "session/update", "params": params })
+    }
+
+    #[test]
+    fn test_serialized_messages_are_valid() {
+        let params = serde_json::to_value(SessionNotification {
+            session_id: SessionId("sess_abc123".into()),
+            update: SessionUpdate::AgentMessageChunk {
+                content: ContentBlock::from("Hello"),
+            },
+            turn_id: None,
+            meta: None,
+        })
+        .unwrap();
+        assert_eq!(
+            validate_against_schema(&notification(params.clone())),
+            Ok(())
+        );
+        assert_eq!(
+            validate_against_definition("SessionNotification", &params),
+            Ok(())
+        );
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "result": { "stopReason": "end_turn" } },
+            { "jsonrpc": "2.0", "id": 2, "error": { "code": -32601, "message": "Method not found" } },
+            { "jsonrpc": "2.0", "method": "_zed.dev/ping", "params": [1, 2, 3] },
+        ]);
+        assert_eq!(validate_against_schema(&batch), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_messages_are_rejected() {
+        let params = json!({
+            "sessionId": "sess_abc123",
+            "update": {
+                "sessionUpdate": "agent_message_chunk",
+                "content": { "type": "text", "text": 42 }
+            }
+        });
+        let errors = validate_against_schema(&notification(params.clone()))
+            .expect_err("text must be a string");
+        assert!(
+            errors
+                .iter()
+                .any(|error| error.instance_path == "/params/update/content/text"),
+            "{errors:?}"
+        );
+        let errors = validate_against_definition("SessionNotification", &params).unwrap_err();
+        assert!(
+            errors
+                .iter()
+                .any(|error| error.instance_path == "/update/content/text"),
+            "{errors:?}"
+        );
+
+        let errors = validate_against_schema(&json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "session/unknown", "params": {} },
+            { "jsonrpc": "1.0", "id": 2, "error": { "code": "oops" } },
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "/0/method: unknown method \"session/unknown\"",
+                "/1/jsonrpc: expected \"2.0\"",
+                "/1/error/code: expected an integer",
+                "/1/error/message: expected a string",
+            ]
+        );
+
+        let errors = validate_against_definition("NoSuchType", &params).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "the schema has no definition named \"NoSuchType\""
+        );
+    }
+}
@@ -0,0 +1,1721 @@
+//! Property tests that every protocol message survives a round trip through its
+//! wire format.
+//!
+//! Each request, response, and notification is generated with arbitrary field
+//! values, serialized, decoded the way a connection decodes it, and serialized
+//! again. Both serializations must be identical, which catches mismatched
+//! `serde` renames, fields that are written but never read, and untagged
+//! variants shadowing each other.
+
+use std::{path::PathBuf, sync::Arc};
+
+use proptest::{collection::vec, option, prelude::*};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, value::RawValue};
+
+use crate::*;
+use crate::{AgentSide, ClientSide, rpc::Side};
+
+// Assertions
+
+/// Serializes `value`, deserializes it as `T`, and checks that serializing it
+/// again produces the same JSON.
+fn assert_round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Result<(), TestCaseError> {
+    let json = serde_json::to_string(value).unwrap();
+    let decoded: T = serde_json::from_str(&json)
+        .map_err(|error| TestCaseError::fail(format!("{error}: {json}")))?;
+    assert_same_json(&decoded, &json)
+}
+
+fn assert_same_json(decoded: &impl Serialize, json: &str) -> Result<(), TestCaseError> {
+    let expected: Value = serde_json::from_str(json).unwrap();
+    prop_assert_eq!(serde_json::to_value(decoded).unwrap(), expected);
+    Ok(())
+}
+
+fn raw(value: &impl Serialize) -> Box<RawValue> {
+    serde_json::value::to_raw_value(value).unwrap()
+}
+
+// Building blocks
+
+fn text() -> impl Strategy<Value = String> {
+    // Any printable characters, including quotes and escapes.
+    "\\PC{0,12}"
+}
+
+fn arc_str() -> impl Strategy<Value = Arc<str>> {
+    text().prop_map(Into::into)
+}
+
+fn path() -> impl Strategy<Value = PathBuf> {
+    "(/[a-z0-9_. -]{1,8}){1,4}".prop_map(PathBuf::from)
+}
+
+/// A number that serializes to a short decimal, so that parsing it back yields
+/// exactly the same value.
+fn number() -> impl Strategy<Value = f64> {
+    (0u32..4000).prop_map(|n| f64::from(n) / 4.0)
+}
+
+fn json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        number().prop_map(Value::from),
+        text().prop_map(Value::from),
+    ];
+    leaf.prop_recursive(2, 12, 4, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..4).prop_map(Value::from),
+            prop::collection::btree_map("[a-zA-Z_]{1,6}", inner, 0..4)
+                .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+/// Arbitrary JSON other than `null`, for optional fields where `null` reads back
+/// as an absent value.
+fn non_null_json() -> impl Strategy<Value = Value> {
+    json().prop_filter("not null", |value| !value.is_null())
+}
+
+fn meta() -> impl Strategy<Value = Option<Value>> {
+    typed_meta().prop_map(|meta| meta.map(|meta| Value::Object(meta.0)))
+}
+
+fn typed_meta() -> impl Strategy<Value = Option<Meta>> {
+    option::of(
+        prop::collection::btree_map("[a-zA-Z_]{1,6}", json(), 0..3)
+            .prop_map(|map| Meta(map.into_iter().collect())),
+    )
+}
+
+fn ext_method() -> impl Strategy<Value = String> {
+    "_[a-z]{1,6}/[a-z_]{1,8}"
+}
+
+fn ext_params() -> impl Strategy<Value = Arc<RawValue>> {
+    prop::collection::btree_map("[a-zA-Z_]{1,6}", json(), 0..3).prop_map(|map| raw(&map).into())
+}
+
+fn session_id() -> impl Strategy<Value = SessionId> {
+    arc_str().prop_map(SessionId)
+}
+
+fn tool_call_id() -> impl Strategy<Value = ToolCallId> {
+    arc_str().prop_map(ToolCallId)
+}
+
+fn terminal_id() -> impl Strategy<Value = TerminalId> {
+    arc_str().prop_map(TerminalId)
+}
+
+fn progress_token() -> impl Strategy<Value = ProgressToken> {
+    arc_str().prop_map(ProgressToken)
+}
+
+fn turn_id() -> impl Strategy<Value = TurnId> {
+    arc_str().prop_map(TurnId)
+}
+
+fn session_mode_id() -> impl Strategy<Value = SessionModeId> {
+    arc_str().prop_map(SessionModeId)
+}
+
+fn permission_option_id() -> impl Strategy<Value = PermissionOptionId> {
+    arc_str().prop_map(PermissionOptionId)
+}
+
+// Content
+
+fn annotations() -> impl Strategy<Value = Option<Annotations>> {
+    option::of(
+        (
+            option::of(vec(
+                prop_oneof![Just(Role::User), Just(Role::Assistant)],
+                0..3,
+            )),
+            option::of(text()),
+            option::of(number()),
+            meta(),
+        )
+            .prop_map(|(audience, last_modified, priority, meta)| Annotations {
+                audience,
+                last_modified,
+                priority,
+                meta,
+            }),
+    )
+}
+
+fn content_block() -> impl Strategy<Value = ContentBlock> {
+    prop_oneof![
+        (annotations(), text(), meta()).prop_map(|(annotations, text, meta)| {
+            ContentBlock::Text(TextContent {
+                annotations,
+                text,
+                meta,
+            })
+        }),
+        (annotations(), text(), text(), option::of(text()), meta()).prop_map(
+            |(annotations, data, mime_type, uri, meta)| ContentBlock::Image(ImageContent {
+                annotations,
+                data,
+                mime_type,
+                uri,
+                meta,
+            })
+        ),
+        (annotations(), text(), text(), meta()).prop_map(|(annotations, data, mime_type, meta)| {
+            ContentBlock::Audio(AudioContent {
+                annotations,
+                data,
+                mime_type,
+                meta,
+            })
+        }),
+        (
+            annotations(),
+            option::of(text()),
+            option::of(text()),
+            text(),
+            option::of(any::<i64>()),
+            option::of(text()),
+            text(),
+            meta(),
+        )
+            .prop_map(
+                |(annotations, description, mime_type, name, size, title, uri, meta)| {
+                    ContentBlock::ResourceLink(ResourceLink {
+                        annotations,
+                        description,
+                        mime_type,
+                        name,
+                        size,
+                        title,
+                        uri: uri.into(),
+                        meta,
+                    })
+                }
+            ),
+        (
+            annotations(),
+            embedded_resource_resource(),
+            resource_summary(),
+            meta(),
+        )
+            .prop_map(
+                |(annotations, resource, summary, meta)| ContentBlock::Resource(EmbeddedResource {
+                    annotations,
+                    resource,
+                    summary,
+                    meta,
+                })
+            ),
+    ]
+}
+
+fn embedded_resource_resource() -> impl Strategy<Value = EmbeddedResourceResource> {
+    prop_oneof![
+        (option::of(text()), text(), text(), meta()).prop_map(|(mime_type, text, uri, meta)| {
+            EmbeddedResourceResource::TextResourceContents(TextResourceContents {
+                mime_type,
+                text,
+                uri: uri.into(),
+                meta,
+            })
+        }),
+        (text(), option::of(text()), text(), meta()).prop_map(|(blob, mime_type, uri, meta)| {
+            EmbeddedResourceResource::BlobResourceContents(BlobResourceContents {
+                blob,
+                mime_type,
+                uri: uri.into(),
+                meta,
+            })
+        }),
+    ]
+}
+
+fn resource_summary() -> impl Strategy<Value = Option<ResourceSummary>> {
+    option::of(
+        (any::<u64>(), option::of(any::<u64>()), any::<bool>()).prop_map(
+            |(byte_len, line_count, truncated)| ResourceSummary {
+                byte_len,
+                line_count,
+                truncated,
+            },
+        ),
+    )
+}
+
+// Tool calls
+
+fn tool_kind() -> impl Strategy<Value = ToolKind> {
+    prop_oneof![
+        Just(ToolKind::Read),
+        Just(ToolKind::Edit),
+        Just(ToolKind::Delete),
+        Just(ToolKind::Move),
+        Just(ToolKind::Search),
+        Just(ToolKind::Execute),
+        Just(ToolKind::Think),
+        Just(ToolKind::Fetch),
+        Just(ToolKind::SwitchMode),
+        Just(ToolKind::Other),
+    ]
+}
+
+fn tool_call_status() -> impl Strategy<Value = ToolCallStatus> {
+    prop_oneof![
+        Just(ToolCallStatus::Pending),
+        Just(ToolCallStatus::InProgress),
+        Just(ToolCallStatus::Completed),
+        Just(ToolCallStatus::Failed),
+        Just(ToolCallStatus::Cancelled),
+    ]
+}
+
+fn diff() -> impl Strategy<Value = Diff> {
+    (path(), option::of(text()), text(), meta()).prop_map(|(path, old_text, new_text, meta)| Diff {
+        path,
+        old_text,
+        new_text,
+        meta,
+    })
+}
+
+fn tool_call_content() -> impl Strategy<Value = ToolCallContent> {
+    prop_oneof![
+        content_block().prop_map(|content| ToolCallContent::Content { content }),
+        diff().prop_map(|diff| ToolCallContent::Diff { diff }),
+        terminal_id().prop_map(|terminal_id| ToolCallContent::Terminal { terminal_id }),
+    ]
+}
+
+fn tool_call_location() -> impl Strategy<Value = ToolCallLocation> {
+    (
+        path(),
+        option::of(any::<u32>()),
+        option::of(any::<u32>()),
+        option::of(any::<u32>()),
+        option::of(any::<u32>()),
+        meta(),
+    )
+        .prop_map(
+            |(path, line, column, end_line, end_column, meta)| ToolCallLocation {
+                path,
+                line,
+                column,
+                end_line,
+                end_column,
+                meta,
+            },
+        )
+}
+
+fn tool_call() -> impl Strategy<Value = ToolCall> {
+    (
+        tool_call_id(),
+        text(),
+        tool_kind(),
+        tool_call_status(),
+        vec(tool_call_content(), 0..3),
+        vec(tool_call_location(), 0..3),
+        option::of(non_null_json()),
+        option::of(non_null_json()),
+        meta(),
+    )
+        .prop_map(
+            |(id, title, kind, status, content, locations, raw_input, raw_output, meta)| ToolCall {
+                id,
+                title,
+                kind,
+                status,
+                content,
+                locations,
+                raw_input,
+                raw_output,
+                meta,
+            },
+        )
+}
+
+fn tool_call_update() -> impl Strategy<Value = ToolCallUpdate> {
+    (
+        tool_call_id(),
+        option::of(tool_kind()),
+        option::of(tool_call_status()),
+        option::of(text()),
+        option::of(vec(tool_call_content(), 0..3)),
+        option::of(vec(tool_call_location(), 0..3)),
+        option::of(non_null_json()),
+        option::of(non_null_json()),
+        meta(),
+    )
+        .prop_map(
+            |(id, kind, status, title, content, locations, raw_input, raw_output, meta)| {
+                ToolCallUpdate {
+                    id,
+                    fields: ToolCallUpdateFields {
+                        kind,
+                        status,
+                        title,
+                        content,
+                        locations,
+                        raw_input,
+                        raw_output,
+                    },
+                    meta,
+                }
+            },
+        )
+}
+
+// Plans
+
+fn plan_entry_priority() -> impl Strategy<Value = PlanEntryPriority> {
+    prop_oneof![
+        Just(PlanEntryPriority::High),
+        Just(PlanEntryPriority::Medium),
+        Just(PlanEntryPriority::Low),
+    ]
+}
+
+fn plan_entry_status() -> impl Strategy<Value = PlanEntryStatus> {
+    prop_oneof![
+        Just(PlanEntryStatus::Pending),
+        Just(PlanEntryStatus::InProgress),
+        Just(PlanEntryStatus::Completed),
+    ]
+}
+
+fn plan() -> impl Strategy<Value = Plan> {
+    (
+        vec(
+            (
+                text(),
+                plan_entry_priority(),
+                plan_entry_status(),
+                typed_meta(),
+            )
+                .prop_map(|(content, priority, status, meta)| PlanEntry {
+                    content,
+                    priority,
+                    status,
+                    meta,
+                }),
+            0..4,
+        ),
+        meta(),
+    )
+        .prop_map(|(entries, meta)| Plan { entries, meta })
+}
+
+fn plan_entry_update() -> impl Strategy<Value = PlanEntryUpdate> {
+    (
+        any::<u32>(),
+        option::of(text()),
+        option::of(plan_entry_priority()),
+        option::of(plan_entry_status()),
+        meta(),
+    )
+        .prop_map(|(index, content, priority, status, meta)| PlanEntryUpdate {
+            index,
+            content,
+            priority,
+            status,
+            meta,
+        })
+}
+
+// Sessions
+
+fn env_variable() -> impl Strategy<Value = EnvVariable> {
+    (text(), text(), meta()).prop_map(|(name, value, meta)| EnvVariable { name, value, meta })
+}
+
+fn http_header() -> impl Strategy<Value = HttpHeader> {
+    (text(), text(), meta()).prop_map(|(name, value, meta)| HttpHeader { name, value, meta })
+}
+
+fn mcp_server() -> impl Strategy<Value = McpServer> {
+    prop_oneof![
+        (text(), text(), vec(http_header(), 0..3), typed_meta()).prop_map(
+            |(name, url, headers, meta)| McpServer::Http {
+                name,
+                url,
+                headers,
+                meta,
+            }
+        ),
+        (text(), text(), vec(http_header(), 0..3), typed_meta()).prop_map(
+            |(name, url, headers, meta)| McpServer::Sse {
+                name,
+                url,
+                headers,
+                meta,
+            }
+        ),
+        (
+            text(),
+            path(),
+            vec(text(), 0..3),
+            vec(env_variable(), 0..3),
+            prop_oneof![
+                Just(EnvPolicy::Clear),
+                Just(EnvPolicy::Inherit),
+                Just(EnvPolicy::InheritAndOverride),
+            ],
+            typed_meta(),
+        )
+            .prop_map(|(name, command, args, env, env_policy, meta)| {
+                McpServer::Stdio {
+                    name,
+                    command,
+                    args,
+                    env,
+                    env_policy,
+                    meta,
+                }
+            }),
+    ]
+}
+
+fn session_mode_state() -> impl Strategy<Value = SessionModeState> {
+    (
+        session_mode_id(),
+        vec(
+            (session_mode_id(), text(), option::of(text()), meta()).prop_map(
+                |(id, name, description, meta)| SessionMode {
+                    id,
+                    name,
+                    description,
+                    meta,
+                },
+            ),
+            0..3,
+        ),
+        meta(),
+    )
+        .prop_map(
+            |(current_mode_id, available_modes, meta)| SessionModeState {
+                current_mode_id,
+                available_modes,
+                meta,
+            },
+        )
+}
+
+fn session_model_state() -> impl Strategy<Value = SessionModelState> {
+    (
+        arc_str().prop_map(ModelId),
+        vec(
+            (
+                arc_str().prop_map(ModelId),
+                text(),
+                option::of(text()),
+                meta(),
+            )
+                .prop_map(|(model_id, name, description, meta)| ModelInfo {
+                    model_id,
+                    name,
+                    description,
+                    meta,
+                }),
+            0..3,
+        ),
+        meta(),
+    )
+        .prop_map(
+            |(current_model_id, available_models, meta)| SessionModelState {
+                current_model_id,
+                available_models,
+                meta,
+            },
+        )
+}
+
+fn available_command() -> impl Strategy<Value = AvailableCommand> {
+    (
+        text(),
+        text(),
+        option::of(prop_oneof![
+            text().prop_map(|hint| AvailableCommandInput::Unstructured { hint }),
+            vec(command_input_option(), 0..3)
+                .prop_map(|options| AvailableCommandInput::Choice { options }),
+        ]),
+        meta(),
+    )
+        .prop_map(|(name, description, input, meta)| AvailableCommand {
+            name,
+            description,
+            input,
+            meta,
+        })
+}
+
+fn command_input_option() -> impl Strategy<Value = CommandInputOption> {
+    (text(), option::of(text()), meta()).prop_map(|(value, description, meta)| CommandInputOption {
+        value,
+        description,
+        meta,
+    })
+}
+
+fn token_usage() -> impl Strategy<Value = TokenUsage> {
+    (
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        option::of(number()),
+        meta(),
+    )
+        .prop_map(
+            |(input_tokens, output_tokens, cache_read_tokens, total_cost_usd, meta)| TokenUsage {
+                input_tokens,
+                output_tokens,
+                cache_read_tokens,
+                total_cost_usd,
+                meta,
+            },
+        )
+}
+
+fn permission_option() -> impl Strategy<Value = PermissionOption> {
+    (
+        permission_option_id(),
+        text(),
+        prop_oneof![
+            Just(PermissionOptionKind::AllowOnce),
+            Just(PermissionOptionKind::AllowAlways),
+            Just(PermissionOptionKind::RejectOnce),
+            Just(PermissionOptionKind::RejectAlways),
+        ],
+        typed_meta(),
+    )
+        .prop_map(|(id, name, kind, meta)| PermissionOption {
+            id,
+            name,
+            kind,
+            meta,
+        })
+}
+
+fn session_update() -> impl Strategy<Value = SessionUpdate> {
+    prop_oneof![
+        content_block().prop_map(|content| SessionUpdate::UserMessageChunk { content }),
+        content_block().prop_map(|content| SessionUpdate::AgentMessageChunk { content }),
+        (content_block(), option::of(text()), any::<bool>()).prop_map(
+            |(content, signature, redacted)| SessionUpdate::AgentThoughtChunk {
+                content,
+                signature,
+                redacted,
+            }
+        ),
+        tool_call().prop_map(SessionUpdate::ToolCall),
+        tool_call_update().prop_map(SessionUpdate::ToolCallUpdate),
+        (tool_call_id(), json(), any::<bool>()).prop_map(|(tool_call_id, chunk, append)| {
+            SessionUpdate::ToolCallOutputChunk {
+                tool_call_id,
+                chunk,
+                append,
+            }
+        }),
+        plan().prop_map(SessionUpdate::Plan),
+        plan_entry_update().prop_map(SessionUpdate::PlanEntryUpdate),
+        token_usage().prop_map(SessionUpdate::UsageUpdate),
+        vec(available_command(), 0..3).prop_map(|available_commands| {
+            SessionUpdate::AvailableCommandsUpdate { available_commands }
+        }),
+        session_mode_id()
+            .prop_map(|current_mode_id| SessionUpdate::CurrentModeUpdate { current_mode_id }),
+        arc_str().prop_map(|model_id| SessionUpdate::CurrentModelUpdate {
+            current_model_id: ModelId(model_id)
+        }),
+        (terminal_id(), text(), any::<u64>()).prop_map(|(terminal_id, chunk, offset)| {
+            SessionUpdate::TerminalOutputChunk {
+                terminal_id,
+                chunk,
+                offset,
+            }
+        }),
+        (
+            path(),
+            option::of(
+                (any::<u32>(), any::<u32>()).prop_map(|(start, end)| LineRange { start, end })
+            ),
+            prop_oneof![
+                Just(DiagnosticSeverity::Error),
+                Just(DiagnosticSeverity::Warning),
+                Just(DiagnosticSeverity::Info),
+                Just(DiagnosticSeverity::Hint),
+            ],
+            text(),
+            option::of(text()),
+        )
+            .prop_map(|(path, range, severity, message, source)| {
+                SessionUpdate::Diagnostic {
+                    path,
+                    range,
+                    severity,
+                    message,
+                    source,
+                }
+            }),
+        vec(
+            (text(), text(), meta()).prop_map(|(label, prompt_text, meta)| SuggestedPrompt {
+                label,
+                prompt_text,
+                meta,
+            }),
+            0..3,
+        )
+        .prop_map(|prompts| SessionUpdate::SuggestedPrompts { prompts }),
+        (
+            progress_token(),
+            option::of(text()),
+            option::of((0u8..=100).prop_map(f32::from)),
+        )
+            .prop_map(|(token, message, percent)| SessionUpdate::Progress {
+                token,
+                message,
+                percent,
+            }),
+        text().prop_map(|reason| SessionUpdate::SessionClosed { reason }),
+    ]
+}
+
+// Client to agent
+
+fn client_capabilities() -> impl Strategy<Value = ClientCapabilities> {
+    (
+        prop::array::uniform10(any::<bool>()),
+        prop::array::uniform5(any::<bool>()),
+        vec(
+            prop_oneof![Just(ContentEncoding::Gzip), Just(ContentEncoding::Deflate)],
+            0..3,
+        ),
+        meta(),
+        meta(),
+    )
+        .prop_map(
+            |(
+                fs,
+                [
+                    terminal,
+                    terminal_input,
+                    terminal_resize,
+                    terminal_exit_notification,
+                    request_user_input,
+                ],
+                compression,
+                fs_meta,
+                meta,
+            )| {
+                let [
+                    read_text_file,
+                    write_text_file,
+                    read_text_file_chunk,
+                    read_text_file_byte_range,
+                    list_directory,
+                    delete_file,
+                    rename_file,
+                    stat,
+                    search,
+                    grep,
+                ] = fs;
+                ClientCapabilities {
+                    fs: FileSystemCapability {
+                        read_text_file,
+                        write_text_file,
+                        read_text_file_chunk,
+                        read_text_file_byte_range,
+                        list_directory,
+                        delete_file,
+                        rename_file,
+                        stat,
+                        search,
+                        grep,
+                        meta: fs_meta,
+                    },
+                    terminal,
+                    terminal_input,
+                    terminal_resize,
+                    terminal_exit_notification,
+                    request_user_input,
+                    compression,
+                    meta,
+                }
+            },
+        )
+}
+
+fn agent_capabilities() -> impl Strategy<Value = AgentCapabilities> {
+    (
+        prop::array::uniform10(any::<bool>()),
+        option::of(any::<u32>()),
+        option::of(any::<u64>()),
+        vec(
+            prop_oneof![Just(ContentEncoding::Gzip), Just(ContentEncoding::Deflate)],
+            0..3,
+        ),
+        meta(),
+    )
+        .prop_map(|(flags, max_tokens, max_bytes, compression, meta)| {
+            let [
+                load_session,
+                list_commands,
+                invoke_command,
+                cancel_tool_call,
+                image,
+                audio,
+                embedded_context,
+                context,
+                http,
+                sse,
+            ] = flags;
+            AgentCapabilities {
+                load_session,
+                prompt_capabilities: PromptCapabilities {
+                    image,
+                    audio,
+                    embedded_context,
+                    context,
+                    max_tokens,
+                    max_bytes,
+                    meta: None,
+                },
+                mcp_capabilities: McpCapabilities {
+                    http,
+                    sse,
+                    meta: None,
+                },
+                list_commands,
+                invoke_command,
+                cancel_tool_call,
+                compression,
+                meta,
+            }
+        })
+}
+
+fn context_item() -> impl Strategy<Value = ContextItem> {
+    (
+        text(),
+        option::of((any::<u32>(), any::<u32>()).prop_map(|(start, end)| LineRange { start, end })),
+        option::of(text()),
+        meta(),
+    )
+        .prop_map(|(uri, line_range, reason, meta)| ContextItem {
+            uri: uri.into(),
+            line_range,
+            reason,
+            meta,
+        })
+}
+
+fn implementation() -> impl Strategy<Value = Implementation> {
+    (text(), option::of(text()), text(), meta()).prop_map(|(name, title, version, meta)| {
+        Implementation {
+            name,
+            title,
+            version,
+            meta,
+        }
+    })
+}
+
+fn client_request() -> impl Strategy<Value = (String, ClientRequest)> {
+    let methods = AGENT_METHOD_NAMES;
+    prop_oneof![
+        (client_capabilities(), option::of(implementation()), meta()).prop_map(
+            move |(client_capabilities, client_info, meta)| {
+                (
+                    methods.initialize.to_string(),
+                    ClientRequest::InitializeRequest(InitializeRequest {
+                        protocol_version: VERSION,
+                        client_capabilities,
+                        client_info,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (arc_str(), meta()).prop_map(move |(method_id, meta)| {
+            (
+                methods.authenticate.to_string(),
+                ClientRequest::AuthenticateRequest(AuthenticateRequest {
+                    method_id: AuthMethodId(method_id),
+                    meta,
+                }),
+            )
+        }),
+        (
+            path(),
+            vec(mcp_server(), 0..3),
+            option::of(progress_token()),
+            meta()
+        )
+            .prop_map(move |(cwd, mcp_servers, progress_token, meta)| {
+                (
+                    methods.session_new.to_string(),
+                    ClientRequest::NewSessionRequest(NewSessionRequest {
+                        cwd,
+                        mcp_servers,
+                        progress_token,
+                        meta,
+                    }),
+                )
+            }),
+        (vec(mcp_server(), 0..3), path(), session_id(), meta()).prop_map(
+            move |(mcp_servers, cwd, session_id, meta)| {
+                (
+                    methods.session_load.to_string(),
+                    ClientRequest::LoadSessionRequest(LoadSessionRequest {
+                        mcp_servers,
+                        cwd,
+                        session_id,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (session_id(), session_mode_id(), meta()).prop_map(move |(session_id, mode_id, meta)| {
+            (
+                methods.session_set_mode.to_string(),
+                ClientRequest::SetSessionModeRequest(SetSessionModeRequest {
+                    session_id,
+                    mode_id,
+                    meta,
+                }),
+            )
+        }),
+        (session_id(), arc_str(), meta()).prop_map(move |(session_id, model_id, meta)| {
+            (
+                methods.session_set_model.to_string(),
+                ClientRequest::SetSessionModelRequest(SetSessionModelRequest {
+                    session_id,
+                    model_id: ModelId(model_id),
+                    meta,
+                }),
+            )
+        }),
+        (
+            session_id(),
+            vec(content_block(), 0..3),
+            vec(context_item(), 0..3),
+            option::of(progress_token()),
+            option::of(turn_id()),
+            option::of(any::<bool>()),
+            meta(),
+        )
+            .prop_map(
+                move |(
+                    session_id,
+                    prompt,
+                    context,
+                    progress_token,
+                    turn_id,
+                    tools_enabled,
+                    meta,
+                )| {
+                    (
+                        methods.session_prompt.to_string(),
+                        ClientRequest::PromptRequest(PromptRequest {
+                            session_id,
+                            prompt,
+                            context,
+                            progress_token,
+                            turn_id,
+                            tools_enabled,
+                            meta,
+                        }),
+                    )
+                }
+            ),
+        (session_id(), meta()).prop_map(move |(session_id, meta)| {
+            (
+                methods.session_list_commands.to_string(),
+                ClientRequest::ListCommandsRequest(ListCommandsRequest { session_id, meta }),
+            )
+        }),
+        (
+            session_id(),
+            text(),
+            text(),
+            option::of(progress_token()),
+            option::of(turn_id()),
+            meta(),
+        )
+            .prop_map(
+                move |(session_id, name, arguments, progress_token, turn_id, meta)| {
+                    (
+                        methods.session_invoke_command.to_string(),
+                        ClientRequest::InvokeCommandRequest(InvokeCommandRequest {
+                            session_id,
+                            name,
+                            arguments,
+                            progress_token,
+                            turn_id,
+                            meta,
+                        }),
+                    )
+                }
+            ),
+        (ext_method(), ext_params()).prop_map(|(method, params)| {
+            (
+                method.clone(),
+                ClientRequest::ExtMethodRequest(ExtRequest {
+                    method: method[1..].into(),
+                    params,
+                }),
+            )
+        }),
+    ]
+}
+
+fn client_notification() -> impl Strategy<Value = (String, ClientNotification)> {
+    let methods = AGENT_METHOD_NAMES;
+    prop_oneof![
+        (session_id(), meta()).prop_map(move |(session_id, meta)| {
+            (
+                methods.session_cancel.to_string(),
+                ClientNotification::CancelNotification(CancelNotification { session_id, meta }),
+            )
+        }),
+        (session_id(), tool_call_id(), meta()).prop_map(move |(session_id, tool_call_id, meta)| {
+            (
+                methods.session_cancel_tool_call.to_string(),
+                ClientNotification::CancelToolCallNotification(CancelToolCallNotification {
+                    session_id,
+                    tool_call_id,
+                    meta,
+                }),
+            )
+        }),
+        (session_id(), terminal_id(), terminal_exit_status(), meta()).prop_map(
+            move |(session_id, terminal_id, exit_status, meta)| {
+                (
+                    methods.terminal_exited.to_string(),
+                    ClientNotification::TerminalExitedNotification(TerminalExitedNotification {
+                        session_id,
+                        terminal_id,
+                        exit_status,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (ext_method(), ext_params()).prop_map(|(method, params)| {
+            (
+                method.clone(),
+                ClientNotification::ExtNotification(ExtNotification {
+                    method: method[1..].into(),
+                    params,
+                }),
+            )
+        }),
+    ]
+}
+
+fn agent_response() -> impl Strategy<Value = AgentResponse> {
+    prop_oneof![
+        (
+            agent_capabilities(),
+            vec(
+                (
+                    arc_str(),
+                    text(),
+                    option::of(text()),
+                    option::of(prop_oneof![
+                        Just(AuthMethodKind::ApiKey),
+                        option::of(text()).prop_map(|authorize_url_hint| AuthMethodKind::OAuth {
+                            authorize_url_hint
+                        }),
+                        Just(AuthMethodKind::DeviceCode),
+                        Just(AuthMethodKind::External),
+                    ]),
+                    typed_meta(),
+                )
+                    .prop_map(|(id, name, description, kind, meta)| AuthMethod {
+                        id: AuthMethodId(id),
+                        name,
+                        description,
+                        kind,
+                        meta,
+                    }),
+                0..3
+            ),
+            option::of(implementation()),
+            meta(),
+        )
+            .prop_map(|(agent_capabilities, auth_methods, agent_info, meta)| {
+                AgentResponse::InitializeResponse(InitializeResponse {
+                    protocol_version: VERSION,
+                    agent_capabilities,
+                    auth_methods,
+                    agent_info,
+                    meta,
+                })
+            }),
+        meta().prop_map(|meta| AgentResponse::AuthenticateResponse(AuthenticateResponse { meta })),
+        (
+            session_id(),
+            option::of(session_mode_state()),
+            option::of(session_model_state()),
+            option::of(vec(permission_option(), 0..3)),
+            meta(),
+        )
+            .prop_map(
+                |(session_id, modes, models, default_permission_options, meta)| {
+                    AgentResponse::NewSessionResponse(NewSessionResponse {
+                        session_id,
+                        modes,
+                        models,
+                        default_permission_options,
+                        meta,
+                    })
+                }
+            ),
+        (
+            option::of(session_mode_state()),
+            option::of(session_model_state()),
+            meta(),
+        )
+            .prop_map(|(modes, models, meta)| {
+                AgentResponse::LoadSessionResponse(LoadSessionResponse {
+                    modes,
+                    models,
+                    meta,
+                })
+            }),
+        meta().prop_map(|meta| {
+            AgentResponse::SetSessionModeResponse(SetSessionModeResponse { meta })
+        }),
+        meta().prop_map(|meta| {
+            AgentResponse::SetSessionModelResponse(SetSessionModelResponse { meta })
+        }),
+        (
+            prop_oneof![
+                Just(StopReason::EndTurn),
+                Just(StopReason::MaxTokens),
+                Just(StopReason::MaxTurnRequests),
+                Just(StopReason::Refusal),
+                Just(StopReason::Cancelled),
+            ],
+            option::of(token_usage()),
+            option::of(
+                (
+                    prop_oneof![
+                        Just(RefusalCategory::Safety),
+                        Just(RefusalCategory::Policy),
+                        Just(RefusalCategory::Capability),
+                        Just(RefusalCategory::Other),
+                    ],
+                    text(),
+                )
+                    .prop_map(|(category, message)| RefusalInfo { category, message })
+            ),
+            meta(),
+        )
+            .prop_map(|(stop_reason, usage, refusal, meta)| {
+                AgentResponse::PromptResponse(PromptResponse {
+                    stop_reason,
+                    usage,
+                    refusal,
+                    meta,
+                })
+            }),
+        (vec(available_command(), 0..3), meta()).prop_map(|(commands, meta)| {
+            AgentResponse::ListCommandsResponse(ListCommandsResponse { commands, meta })
+        }),
+        ext_params().prop_map(AgentResponse::ExtMethodResponse),
+    ]
+}
+
+// Agent to client
+
+fn agent_request() -> impl Strategy<Value = (String, AgentRequest)> {
+    let methods = CLIENT_METHOD_NAMES;
+    let permission_context = prop_oneof![
+        (vec(text(), 0..3), text(), option::of(path()))
+            .prop_map(|(args, command, cwd)| PermissionContext::Command { command, args, cwd }),
+        diff().prop_map(|diff| PermissionContext::FileEdit { diff }),
+        vec(text(), 0..3).prop_map(|urls| PermissionContext::NetworkFetch { urls }),
+        (text(), text()).prop_map(|(server_name, tool_name)| PermissionContext::McpTool {
+            server_name,
+            tool_name,
+        }),
+    ];
+    let session = || (session_id(), meta());
+    let terminal = || (session_id(), terminal_id(), meta());
+    prop_oneof![
+        (
+            session(),
+            tool_call_update(),
+            vec(permission_option(), 0..3),
+            option::of(permission_context),
+        )
+            .prop_map(move |((session_id, meta), tool_call, options, context)| {
+                (
+                    methods.session_request_permission.to_string(),
+                    AgentRequest::RequestPermissionRequest(RequestPermissionRequest {
+                        session_id,
+                        tool_call,
+                        options,
+                        context,
+                        meta,
+                    }),
+                )
+            }),
+        (
+            session(),
+            text(),
+            prop_oneof![
+                Just(InputType::Text),
+                vec(text(), 0..3).prop_map(|options| InputType::Choice { options }),
+                Just(InputType::Confirm),
+            ],
+        )
+            .prop_map(move |((session_id, meta), prompt, input_type)| {
+                (
+                    methods.session_request_user_input.to_string(),
+                    AgentRequest::RequestUserInputRequest(RequestUserInputRequest {
+                        session_id,
+                        prompt,
+                        input_type,
+                        meta,
+                    }),
+                )
+            }),
+        (session(), path(), text(), any::<bool>()).prop_map(
+            move |((session_id, meta), path, content, dry_run)| {
+                (
+                    methods.fs_write_text_file.to_string(),
+                    AgentRequest::WriteTextFileRequest(WriteTextFileRequest {
+                        session_id,
+                        path,
+                        content,
+                        dry_run,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (
+            session(),
+            path(),
+            option::of(any::<u32>()),
+            option::of(any::<u32>()),
+            option::of(any::<u64>()),
+            option::of(any::<u64>())
+        )
+            .prop_map(
+                move |((session_id, meta), path, line, limit, byte_offset, byte_limit)| {
+                    (
+                        methods.fs_read_text_file.to_string(),
+                        AgentRequest::ReadTextFileRequest(ReadTextFileRequest {
+                            session_id,
+                            path,
+                            line,
+                            limit,
+                            byte_offset,
+                            byte_limit,
+                            meta,
+                        }),
+                    )
+                }
+            ),
+        (session(), path(), any::<u64>(), option::of(any::<u32>())).prop_map(
+            move |((session_id, meta), path, offset, max_bytes)| {
+                (
+                    methods.fs_read_text_file_chunk.to_string(),
+                    AgentRequest::ReadTextFileChunkRequest(ReadTextFileChunkRequest {
+                        session_id,
+                        path,
+                        offset,
+                        max_bytes,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (session(), path(), any::<bool>()).prop_map(
+            move |((session_id, meta), path, recursive)| {
+                (
+                    methods.fs_list_directory.to_string(),
+                    AgentRequest::ListDirectoryRequest(ListDirectoryRequest {
+                        session_id,
+                        path,
+                        recursive,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (session(), path()).prop_map(move |((session_id, meta), path)| {
+            (
+                methods.fs_delete_file.to_string(),
+                AgentRequest::DeleteFileRequest(DeleteFileRequest {
+                    session_id,
+                    path,
+                    meta,
+                }),
+            )
+        }),
+        (session(), path(), path()).prop_map(move |((session_id, meta), old_path, new_path)| {
+            (
+                methods.fs_rename_file.to_string(),
+                AgentRequest::RenameFileRequest(RenameFileRequest {
+                    session_id,
+                    old_path,
+                    new_path,
+                    meta,
+                }),
+            )
+        }),
+        (session(), path()).prop_map(move |((session_id, meta), path)| {
+            (
+                methods.fs_stat.to_string(),
+                AgentRequest::StatRequest(StatRequest {
+                    session_id,
+                    path,
+                    meta,
+                }),
+            )
+        }),
+        (session(), path(), text(), option::of(any::<u32>())).prop_map(
+            move |((session_id, meta), root, glob, max_results)| {
+                (
+                    methods.fs_search.to_string(),
+                    AgentRequest::SearchFilesRequest(SearchFilesRequest {
+                        session_id,
+                        root,
+                        glob,
+                        max_results,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (
+            session(),
+            text(),
+            any::<bool>(),
+            path(),
+            option::of(any::<u32>())
+        )
+            .prop_map(
+                move |((session_id, meta), query, is_regex, root, max_results)| {
+                    (
+                        methods.fs_grep.to_string(),
+                        AgentRequest::GrepRequest(GrepRequest {
+                            session_id,
+                            query,
+                            is_regex,
+                            root,
+                            max_results,
+                            meta,
+                        }),
+                    )
+                }
+            ),
+        (
+            session(),
+            text(),
+            vec(text(), 0..3),
+            vec(env_variable(), 0..3),
+            option::of(path()),
+            option::of(any::<u64>()),
+            option::of(any::<u16>()),
+            option::of(any::<u16>()),
+            any::<bool>(),
+            any::<bool>(),
+        )
+            .prop_map(
+                move |(
+                    (session_id, meta),
+                    command,
+                    args,
+                    env,
+                    cwd,
+                    output_byte_limit,
+                    cols,
+                    rows,
+                    separate_streams,
+                    notify_on_exit,
+                )| {
+                    (
+                        methods.terminal_create.to_string(),
+                        AgentRequest::CreateTerminalRequest(CreateTerminalRequest {
+                            session_id,
+                            command,
+                            args,
+                            env,
+                            cwd,
+                            output_byte_limit,
+                            cols,
+                            rows,
+                            separate_streams,
+                            notify_on_exit,
+                            meta,
+                        }),
+                    )
+                }
+            ),
+        terminal().prop_map(move |(session_id, terminal_id, meta)| {
+            (
+                methods.terminal_output.to_string(),
+                AgentRequest::TerminalOutputRequest(TerminalOutputRequest {
+                    session_id,
+                    terminal_id,
+                    meta,
+                }),
+            )
+        }),
+        terminal().prop_map(move |(session_id, terminal_id, meta)| {
+            (
+                methods.terminal_release.to_string(),
+                AgentRequest::ReleaseTerminalRequest(ReleaseTerminalRequest {
+                    session_id,
+                    terminal_id,
+                    meta,
+                }),
+            )
+        }),
+        terminal().prop_map(move |(session_id, terminal_id, meta)| {
+            (
+                methods.terminal_wait_for_exit.to_string(),
+                AgentRequest::WaitForTerminalExitRequest(WaitForTerminalExitRequest {
+                    session_id,
+                    terminal_id,
+                    meta,
+                }),
+            )
+        }),
+        terminal().prop_map(move |(session_id, terminal_id, meta)| {
+            (
+                methods.terminal_kill.to_string(),
+                AgentRequest::KillTerminalCommandRequest(KillTerminalCommandRequest {
+                    session_id,
+                    terminal_id,
+                    meta,
+                }),
+            )
+        }),
+        (terminal(), text()).prop_map(move |((session_id, terminal_id, meta), data)| {
+            (
+                methods.terminal_input.to_string(),
+                AgentRequest::WriteTerminalInputRequest(WriteTerminalInputRequest {
+                    session_id,
+                    terminal_id,
+                    data,
+                    meta,
+                }),
+            )
+        }),
+        (terminal(), any::<u16>(), any::<u16>()).prop_map(
+            move |((session_id, terminal_id, meta), cols, rows)| {
+                (
+                    methods.terminal_resize.to_string(),
+                    AgentRequest::ResizeTerminalRequest(ResizeTerminalRequest {
+                        session_id,
+                        terminal_id,
+                        cols,
+                        rows,
+                        meta,
+                    }),
+                )
+            }
+        ),
+        (ext_method(), ext_params()).prop_map(|(method, params)| {
+            (
+                method.clone(),
+                AgentRequest::ExtMethodRequest(ExtRequest {
+                    method: method[1..].into(),
+                    params,
+                }),
+            )
+        }),
+    ]
+}
+
+fn agent_notification() -> impl Strategy<Value = (String, AgentNotification)> {
+    prop_oneof![
+        (
+            session_id(),
+            session_update(),
+            option::of(turn_id()),
+            meta()
+        )
+            .prop_map(|(session_id, update, turn_id, meta)| {
+                (
+                    CLIENT_METHOD_NAMES.session_update.to_string(),
+                    AgentNotification::SessionNotification(SessionNotification {
+                        session_id,
+                        update,
+                        turn_id,
+                        meta,
+                    }),
+                )
+            }),
+        (ext_method(), ext_params()).prop_map(|(method, params)| {
+            (
+                method.clone(),
+                AgentNotification::ExtNotification(ExtNotification {
+                    method: method[1..].into(),
+                    params,
+                }),
+            )
+        }),
+    ]
+}
+
+fn terminal_exit_status() -> impl Strategy<Value = TerminalExitStatus> {
+    (option::of(any::<u32>()), option::of(text()), meta()).prop_map(|(exit_code, signal, meta)| {
+        TerminalExitStatus {
+            exit_code,
+            signal,
+            meta,
+        }
+    })
+}
+
+fn client_response() -> impl Strategy<Value = ClientResponse> {
+    prop_oneof![
+        (option::of(diff()), meta()).prop_map(|(diff, meta)| {
+            ClientResponse::WriteTextFileResponse(WriteTextFileResponse { diff, meta })
+        }),
+        (text(), option::of(any::<u64>()), meta()).prop_map(|(content, next_byte_offset, meta)| {
+            ClientResponse::ReadTextFileResponse(ReadTextFileResponse {
+                content,
+                next_byte_offset,
+                meta,
+            })
+        }),
+        (text(), any::<bool>(), meta()).prop_map(|(content, eof, meta)| {
+            ClientResponse::ReadTextFileChunkResponse(ReadTextFileChunkResponse {
+                content,
+                eof,
+                meta,
+            })
+        }),
+        (
+            prop_oneof![
+                Just(RequestPermissionOutcome::Cancelled),
+                permission_option_id()
+                    .prop_map(|option_id| RequestPermissionOutcome::Selected { option_id }),
+            ],
+            meta(),
+        )
+            .prop_map(|(outcome, meta)| {
+                ClientResponse::RequestPermissionResponse(RequestPermissionResponse {
+                    outcome,
+                    meta,
+                })
+            }),
+        (option::of(text()), meta()).prop_map(|(value, meta)| {
+            ClientResponse::RequestUserInputResponse(RequestUserInputResponse { value, meta })
+        }),
+        (
+            vec(
+                (
+                    text(),
+                    path(),
+                    any::<bool>(),
+                    option::of(any::<u64>()),
+                    meta()
+                )
+                    .prop_map(|(name, path, is_dir, size, meta)| DirEntry {
+                        name,
+                        path,
+                        is_dir,
+                        size,
+                        meta,
+                    }),
+                0..3
+            ),
+            meta(),
+        )
+            .prop_map(|(entries, meta)| {
+                ClientResponse::ListDirectoryResponse(ListDirectoryResponse { entries, meta })
+            }),
+        meta().prop_map(|meta| ClientResponse::DeleteFileResponse(DeleteFileResponse { meta })),
+        meta().prop_map(|meta| ClientResponse::RenameFileResponse(RenameFileResponse { meta })),
+        (
+            any::<bool>(),
+            any::<bool>(),
+            option::of(any::<u64>()),
+            option::of(text()),
+            meta(),
+        )
+            .prop_map(|(exists, is_dir, size, modified, meta)| {
+                ClientResponse::StatResponse(StatResponse {
+                    exists,
+                    is_dir,
+                    size,
+                    modified,
+                    meta,
+                })
+            }),
+        (vec(path(), 0..3), any::<bool>(), meta()).prop_map(|(paths, truncated, meta)| {
+            ClientResponse::SearchFilesResponse(SearchFilesResponse {
+                paths,
+                truncated,
+                meta,
+            })
+        }),
+        (
+            vec(
+                (path(), any::<u32>(), any::<u32>(), text(), meta()).prop_map(
+                    |(path, line, column, line_text, meta)| GrepMatch {
+                        path,
+                        line,
+                        column,
+                        line_text,
+                        meta,
+                    }
+                ),
+                0..3
+            ),
+            meta(),
+        )
+            .prop_map(|(matches, meta)| {
+                ClientResponse::GrepResponse(GrepResponse { matches, meta })
+            }),
+        (terminal_id(), meta()).prop_map(|(terminal_id, meta)| {
+            ClientResponse::CreateTerminalResponse(CreateTerminalResponse { terminal_id, meta })
+        }),
+        (
+            text(),
+            any::<bool>(),
+            option::of(any::<u64>()),
+            option::of(text()),
+            option::of(text()),
+            option::of(terminal_exit_status()),
+            meta()
+        )
+            .prop_map(
+                |(output, truncated, total_bytes, stdout, stderr, exit_status, meta)| {
+                    ClientResponse::TerminalOutputResponse(TerminalOutputResponse {
+                        output,
+                        truncated,
+                        total_bytes,
+                        stdout,
+                        stderr,
+                        exit_status,
+                        meta,
+                    })
+                },
+            ),
+        meta().prop_map(|meta| {
+            ClientResponse::ReleaseTerminalResponse(ReleaseTerminalResponse { meta })
+        }),
+        // The exit status is flattened, so only one of the two `_meta` fields can
+        // be written.
+        (terminal_exit_status(), meta(),).prop_map(|(exit_status, meta)| {
+            ClientResponse::WaitForTerminalExitResponse(WaitForTerminalExitResponse {
+                exit_status: TerminalExitStatus {
+                    meta: None,
+                    ..exit_status
+                },
+                meta,
+            })
+        }),
+        meta().prop_map(|meta| {
+            ClientResponse::KillTerminalResponse(KillTerminalCommandResponse { meta })
+        }),
+        meta().prop_map(|meta| {
+            ClientResponse::WriteTerminalInputResponse(WriteTerminalInputResponse { meta })
+        }),
+        meta().prop_map(|meta| {
+            ClientResponse::ResizeTerminalResponse(ResizeTerminalResponse { meta })
+        }),
+        ext_params().prop_map(ClientResponse::ExtMethodResponse),
+    ]
+}
+
+// Properties
+
+proptest! {
+    // The crate root isn't `lib.rs`, so failures can't be persisted next to it.
+    #![proptest_config(ProptestConfig {
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn test_client_request_round_trip((method, request) in client_request()) {
+        let params = raw(&request);
+        let decoded = AgentSide::decode_request(&method, Some(&params))
+            .map_err(|error| TestCaseError::fail(format!("{method}: {error:?}: {params}")))?;
+        assert_same_json(&decoded, params.get())?;
+    }
+
+    #[test]
+    fn test_client_notification_round_trip((method, notification) in client_notification()) {
+        let params = raw(&notification);
+        let decoded = AgentSide::decode_notification(&method, Some(&params))
+            .map_err(|error| TestCaseError::fail(format!("{method}: {error:?}: {params}")))?;
+        assert_same_json(&decoded, params.get())?;
+    }
+
+    #[test]
+    fn test_agent_response_round_trip(response in agent_response()) {
+        // Responses are decoded as the type the request expects, not as the enum.
+        match response {
+            AgentResponse::InitializeResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::AuthenticateResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::NewSessionResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::LoadSessionResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::SetSessionModeResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::PromptResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::SetSessionModelResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::ListCommandsResponse(response) => assert_round_trip(&response)?,
+            AgentResponse::ExtMethodResponse(response) => assert_round_trip(&response)?,
+        }
+    }
+
+    #[test]
+    fn test_agent_request_round_trip((method, request) in agent_request()) {
+        let params = raw(&request);
+        let decoded = ClientSide::decode_request(&method, Some(&params))
+            .map_err(|error| TestCaseError::fail(format!("{method}: {error:?}: {params}")))?;
+        assert_same_json(&decoded, params.get())?;
+    }
+
+    #[test]
+    fn test_agent_notification_round_trip((method, notification) in agent_notification()) {
+        let params = raw(&notification);
+        let decoded = ClientSide::decode_notification(&method, Some(&params))
+            .map_err(|error| TestCaseError::fail(format!("{method}: {error:?}: {params}")))?;
+        assert_same_json(&decoded, params.get())?;
+    }
+
+    #[test]
+    fn test_client_response_round_trip(response in client_response()) {
+        match response {
+            ClientResponse::WriteTextFileResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::ReadTextFileResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::RequestPermissionResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::CreateTerminalResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::TerminalOutputResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::ReleaseTerminalResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::WaitForTerminalExitResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::KillTerminalResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::ListDirectoryResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::DeleteFileResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::RenameFileResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::WriteTerminalInputResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::ResizeTerminalResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::ReadTextFileChunkResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::StatResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::SearchFilesResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::GrepResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::RequestUserInputResponse(response) => assert_round_trip(&response)?,
+            ClientResponse::ExtMethodResponse(response) => assert_round_trip(&response)?,
+        }
+    }
+}
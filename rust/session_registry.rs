@@ -0,0 +1,140 @@
+//! Tracking of the sessions an agent knows about.
+//!
+//! Clients may keep using a session ID the agent no longer recognizes, for
+//! example after the agent restarted. Agents should reject such requests with
+//! [`Error::session_not_found`] so that the client can offer to start a new
+//! session. A [`SessionRegistry`] keeps the known sessions, along with any state
+//! the agent attaches to them, and produces that error for unknown ones.
+//!
+//! See protocol docs: [Unknown Sessions](https://agentclientprotocol.com/protocol/session-setup#unknown-sessions)
+
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+
+use crate::{Error, SessionId};
+
+/// The sessions an agent created or loaded, each with state of type `T`.
+///
+/// With the default `()` state, the registry is simply a set of known session IDs.
+///
+/// Cloning the registry is cheap, and all clones share the same sessions.
+///
+/// # Example
+///
+/// ```
+/// use agent_client_protocol::{ErrorKind, SessionId, SessionRegistry};
+///
+/// let sessions = SessionRegistry::new();
+/// sessions.insert(SessionId("sess_1".into()), ());
+///
+/// assert!(sessions.get_or_err(&SessionId("sess_1".into())).is_ok());
+/// let error = sessions.get_or_err(&SessionId("sess_2".into())).unwrap_err();
+/// assert_eq!(error.kind(), Some(ErrorKind::SessionNotFound));
+/// ```
+#[derive(Debug)]
+pub struct SessionRegistry<T = ()> {
+    sessions: Arc<Mutex<HashMap<SessionId, T>>>,
+}
+
+impl<T> SessionRegistry<T> {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sessions: Arc::default(),
+        }
+    }
+
+    /// Registers a session, returning the state it previously had if it was
+    /// already known.
+    pub fn insert(&self, session_id: SessionId, state: T) -> Option<T> {
+        self.sessions.lock().insert(session_id, state)
+    }
+
+    /// Forgets a session, returning its state if it was known.
+    pub fn remove(&self, session_id: &SessionId) -> Option<T> {
+        self.sessions.lock().remove(session_id)
+    }
+
+    /// Returns `true` if the session is known.
+    #[must_use]
+    pub fn contains(&self, session_id: &SessionId) -> bool {
+        self.sessions.lock().contains_key(session_id)
+    }
+
+    /// The number of known sessions.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.sessions.lock().len()
+    }
+
+    /// Returns `true` if no sessions are known.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().is_empty()
+    }
+}
+
+impl<T: Clone> SessionRegistry<T> {
+    /// Returns the state of the session, or fails with [`Error::session_not_found`]
+    /// if the session isn't known.
+    ///
+    /// Call it at the start of every request that takes a session ID, and return
+    /// the error to the client as-is.
+    pub fn get_or_err(&self, session_id: &SessionId) -> Result<T, Error> {
+        self.sessions
+            .lock()
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| Error::session_not_found(session_id.0.as_ref()))
+    }
+}
+
+impl<T> Clone for SessionRegistry<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sessions: self.sessions.clone(),
+        }
+    }
+}
+
+impl<T> Default for SessionRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+
+    fn session(id: &str) -> SessionId {
+        SessionId(id.into())
+    }
+
+    #[test]
+    fn test_get_or_err() {
+        let sessions = SessionRegistry::new();
+        assert_eq!(sessions.insert(session("a"), 1), None);
+        assert_eq!(sessions.get_or_err(&session("a")).unwrap(), 1);
+
+        let error = sessions.get_or_err(&session("b")).unwrap_err();
+        assert_eq!(error.code, ErrorCode::SESSION_NOT_FOUND.code);
+        assert_eq!(error.data, Some(serde_json::json!({ "sessionId": "b" })));
+    }
+
+    #[test]
+    fn test_clones_share_sessions() {
+        let sessions = SessionRegistry::<()>::new();
+        let clone = sessions.clone();
+        clone.insert(session("a"), ());
+        assert!(sessions.contains(&session("a")));
+        assert_eq!(sessions.len(), 1);
+
+        assert_eq!(sessions.remove(&session("a")), Some(()));
+        assert!(clone.is_empty());
+        assert!(clone.get_or_err(&session("a")).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! Persistence of session state for agents that support `session/load`.
+//!
+//! When loading a session, the agent is expected to replay the entire conversation
+//! to the client through `session/update` notifications before responding. A
+//! [`SessionStore`] keeps a [`SessionSnapshot`] of each session around so that the
+//! agent has something to replay, and [`SessionSnapshot::replay`] sends it.
+//!
+//! See protocol docs: [Loading Sessions](https://agentclientprotocol.com/protocol/session-setup#loading-sessions)
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{Client, Error, ModelId, SessionId, SessionModeId, SessionNotification, SessionUpdate};
+
+/// The state of a session needed to restore it later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSnapshot {
+    /// Every update sent to the client during the session, in order.
+    #[serde(default)]
+    pub updates: Vec<SessionUpdate>,
+    /// The mode the session was in, if the agent supports modes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode_id: Option<SessionModeId>,
+    /// The model the session was using, if the agent supports selecting models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<ModelId>,
+}
+
+impl SessionSnapshot {
+    /// Creates an empty snapshot.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an update sent to the client.
+    ///
+    /// [`SessionUpdate::CurrentModeUpdate`] and [`SessionUpdate::CurrentModelUpdate`]
+    /// also change the recorded mode and model.
+    pub fn push(&mut self, update: SessionUpdate) {
+        match &update {
+            SessionUpdate::CurrentModeUpdate { current_mode_id } => {
+                self.mode_id = Some(current_mode_id.clone());
+            }
+            SessionUpdate::CurrentModelUpdate { current_model_id } => {
+                self.model_id = Some(current_model_id.clone());
+            }
+            _ => {}
+        }
+        self.updates.push(update);
+    }
+
+    /// Sends every recorded update to the client as a `session/update` notification,
+    /// in order.
+    ///
+    /// Call this from [`Agent::load_session`](crate::Agent::load_session) before
+    /// responding, as the protocol requires the whole conversation to be streamed
+    /// first.
+    pub async fn replay(
+        &self,
+        session_id: &SessionId,
+        client: &(impl Client + ?Sized),
+    ) -> Result<(), Error> {
+        replay_updates(session_id, client, self.updates.iter().cloned()).await
+    }
+}
+
+/// Sends `updates` to the client as `session/update` notifications for the given
+/// session, in order, stopping at the first one that fails.
+///
+/// This is what [`SessionSnapshot::replay`] and
+/// [`ConversationHistory::replay`](crate::ConversationHistory::replay) do, for
+/// agents that keep their history in some other form.
+pub async fn replay_updates(
+    session_id: &SessionId,
+    client: &(impl Client + ?Sized),
+    updates: impl IntoIterator<Item = SessionUpdate>,
+) -> Result<(), Error> {
+    for update in updates {
+        client
+            .session_notification(SessionNotification {
+                session_id: session_id.clone(),
+                update,
+                turn_id: None,
+                meta: None,
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Storage for [`SessionSnapshot`]s, keyed by session ID.
+///
+/// [`InMemorySessionStore`] and [`JsonFileSessionStore`] cover the common cases;
+/// implement this trait to keep sessions elsewhere, such as in a database.
+///
+/// Stores return [`Send`] futures, so that both [`Agent`](crate::Agent) and
+/// `SendAgent` implementations can use them.
+#[async_trait::async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Stores the snapshot of a session, replacing any previous one.
+    async fn save(&self, session_id: &SessionId, snapshot: SessionSnapshot) -> Result<(), Error>;
+
+    /// Returns the stored snapshot of a session, or `None` if there is none.
+    async fn load(&self, session_id: &SessionId) -> Result<Option<SessionSnapshot>, Error>;
+}
+
+/// A [`SessionStore`] that keeps snapshots in memory.
+///
+/// Cloning the store is cheap, and all clones share the same snapshots. They are
+/// lost when the process exits.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySessionStore {
+    snapshots: Arc<Mutex<HashMap<SessionId, SessionSnapshot>>>,
+}
+
+impl InMemorySessionStore {
+    /// Creates an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn save(&self, session_id: &SessionId, snapshot: SessionSnapshot) -> Result<(), Error> {
+        self.snapshots.lock().insert(session_id.clone(), snapshot);
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &SessionId) -> Result<Option<SessionSnapshot>, Error> {
+        Ok(self.snapshots.lock().get(session_id).cloned())
+    }
+}
+
+/// A [`SessionStore`] that writes each snapshot to a JSON file in a directory.
+///
+/// Files are named after the session ID, with any character other than ASCII
+/// letters, digits, `-` and `_` percent-encoded, so IDs can never point outside
+/// the directory. Snapshots are written to a temporary file first and then moved
+/// into place, so a crash never leaves a partially written snapshot behind.
+///
+/// File access is blocking, which is fine for the small files this produces but
+/// should be kept in mind on single-threaded runtimes.
+#[derive(Debug, Clone)]
+pub struct JsonFileSessionStore {
+    directory: PathBuf,
+}
+
+impl JsonFileSessionStore {
+    /// Creates a store that keeps its files in `directory`, which is created on the
+    /// first save if needed.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// The directory the snapshots are stored in.
+    #[must_use]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, session_id: &SessionId) -> PathBuf {
+        let mut file_name = String::with_capacity(session_id.0.len() + 5);
+        for byte in session_id.0.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                file_name.push(byte as char);
+            } else {
+                write!(file_name, "%{byte:02X}").expect("writing to a String never fails");
+            }
+        }
+        file_name.push_str(".json");
+        self.directory.join(file_name)
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for JsonFileSessionStore {
+    async fn save(&self, session_id: &SessionId, snapshot: SessionSnapshot) -> Result<(), Error> {
+        // Unique per save, so that concurrent saves of the same session, from this
+        // process or another one sharing the directory, never write the same file.
+        static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+        let path = self.path(session_id);
+        let temp_path = path.with_extension(format!(
+            "json.{}.{}.tmp",
+            std::process::id(),
+            NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let contents = serde_json::to_vec(&snapshot).map_err(Error::into_internal_error)?;
+        fs::create_dir_all(&self.directory).map_err(Error::into_internal_error)?;
+        let result =
+            write_durably(&temp_path, &contents).and_then(|()| fs::rename(&temp_path, &path));
+        if result.is_err() {
+            fs::remove_file(&temp_path).ok();
+        }
+        result.map_err(Error::into_internal_error)
+    }
+
+    async fn load(&self, session_id: &SessionId) -> Result<Option<SessionSnapshot>, Error> {
+        let contents = match fs::read(self.path(session_id)) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(Error::into_internal_error(error)),
+        };
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(Error::into_internal_error)
+    }
+}
+
+/// Writes `contents` to a new file at `path`, and waits for them to reach the disk
+/// so that renaming the file over a snapshot can't leave an empty one behind after
+/// a crash.
+fn write_durably(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ContentBlock;
+    use futures::executor::block_on;
+
+    fn session(id: &str) -> SessionId {
+        SessionId(id.into())
+    }
+
+    fn to_json(snapshot: Option<SessionSnapshot>) -> serde_json::Value {
+        serde_json::to_value(snapshot).unwrap()
+    }
+
+    fn snapshot() -> SessionSnapshot {
+        let mut snapshot = SessionSnapshot::new();
+        snapshot.push(SessionUpdate::UserMessageChunk {
+            content: ContentBlock::from("Hello"),
+        });
+        snapshot.push(SessionUpdate::CurrentModeUpdate {
+            current_mode_id: SessionModeId("code".into()),
+        });
+        snapshot.push(SessionUpdate::CurrentModelUpdate {
+            current_model_id: ModelId("sonnet".into()),
+        });
+        snapshot.push(SessionUpdate::AgentMessageChunk {
+            content: ContentBlock::from("Hi!"),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_push_tracks_mode_and_model() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.updates.len(), 4);
+        assert_eq!(snapshot.mode_id, Some(SessionModeId("code".into())));
+        assert_eq!(snapshot.model_id, Some(ModelId("sonnet".into())));
+    }
+
+    #[test]
+    fn test_in_memory_store() {
+        let store = InMemorySessionStore::new();
+        assert!(block_on(store.load(&session("s1"))).unwrap().is_none());
+
+        block_on(store.save(&session("s1"), snapshot())).unwrap();
+        assert_eq!(
+            to_json(block_on(store.clone().load(&session("s1"))).unwrap()),
+            to_json(Some(snapshot()))
+        );
+        assert!(block_on(store.load(&session("s2"))).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_json_file_store() {
+        let directory =
+            std::env::temp_dir().join(format!("acp-session-store-test-{}", std::process::id()));
+        let store = JsonFileSessionStore::new(&directory);
+        let session_id = session("../sess/1");
+        assert!(block_on(store.load(&session_id)).unwrap().is_none());
+
+        block_on(store.save(&session_id, SessionSnapshot::new())).unwrap();
+        block_on(store.save(&session_id, snapshot())).unwrap();
+        assert_eq!(
+            to_json(block_on(JsonFileSessionStore::new(&directory).load(&session_id)).unwrap()),
+            to_json(Some(snapshot()))
+        );
+
+        let files = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec!["%2E%2E%2Fsess%2F1.json"]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_json_file_store_concurrent_saves() {
+        let directory = std::env::temp_dir().join(format!(
+            "acp-session-store-concurrent-test-{}",
+            std::process::id()
+        ));
+        let store = JsonFileSessionStore::new(&directory);
+        let session_id = session("sess");
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        block_on(store.save(&session_id, snapshot())).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            to_json(block_on(store.load(&session_id)).unwrap()),
+            to_json(Some(snapshot()))
+        );
+        let files = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec!["sess.json"]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -4,34 +4,43 @@
 //! clients and agents. It's primarily used for debugging, logging, and building
 //! development tools that need to monitor the protocol communication.
 
-use std::sync::Arc;
+use std::{
+    fmt::Display,
+    sync::{Arc, OnceLock},
+};
 
-use anyhow::Result;
-use serde::Serialize;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 
 use crate::{
-    Error,
+    Error, INITIALIZE_METHOD_NAME, ProtocolVersion,
     rpc::{OutgoingMessage, ResponseResult, Side},
 };
 
 /// A message that flows through the RPC stream.
 ///
 /// This represents any JSON-RPC message (request, response, or notification)
-/// along with its direction (incoming or outgoing).
+/// along with its direction (incoming or outgoing), or an event in the lifecycle
+/// of the connection.
 ///
 /// Stream messages are used for observing and debugging the protocol communication
 /// without interfering with the actual message handling.
 #[derive(Debug, Clone)]
 pub struct StreamMessage {
     /// The direction of the message relative to this side of the connection.
+    ///
+    /// For lifecycle events, this is the side that caused them: `Outgoing` if this
+    /// side opened or closed the connection, or sent the `initialize` response, and
+    /// `Incoming` if the other side did, or the connection failed.
     pub direction: StreamMessageDirection,
     /// The actual content of the message.
     pub message: StreamMessageContent,
 }
 
 /// The direction of a message in the RPC stream.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StreamMessageDirection {
     /// A message received from the other side of the connection.
     Incoming,
@@ -45,7 +54,13 @@ pub enum StreamMessageDirection {
 /// - Requests: Method calls that expect a response
 /// - Responses: Replies to previous requests
 /// - Notifications: One-way messages that don't expect a response
+///
+/// It also marks when the connection opens, completes initialization, and closes,
+/// so that the stream gives a complete timeline of the connection. These events
+/// don't appear on the wire. More of them may be added, so matches should have a
+/// fallback arm.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum StreamMessageContent {
     /// A JSON-RPC request message.
     Request {
@@ -54,22 +69,225 @@ pub enum StreamMessageContent {
         /// The name of the method being called.
         method: Arc<str>,
         /// Optional parameters for the method.
-        params: Option<serde_json::Value>,
+        params: Option<StreamValue>,
     },
     /// A JSON-RPC response message.
     Response {
         /// The ID of the request this response is for.
         id: i32,
         /// The result of the request (success or error).
-        result: Result<Option<serde_json::Value>, Error>,
+        result: Result<Option<StreamValue>, Error>,
     },
     /// A JSON-RPC notification message.
     Notification {
         /// The name of the notification method.
         method: Arc<str>,
         /// Optional parameters for the notification.
-        params: Option<serde_json::Value>,
+        params: Option<StreamValue>,
+    },
+    /// The connection started exchanging messages.
+    ConnectionOpened,
+    /// The `initialize` request was answered successfully.
+    Initialized {
+        /// The protocol version the agent responded with.
+        protocol_version: ProtocolVersion,
     },
+    /// The connection stopped exchanging messages. Nothing follows this event.
+    ConnectionClosed {
+        /// The error that ended the connection, or `None` if either side closed it.
+        reason: Option<String>,
+    },
+}
+
+impl StreamMessageContent {
+    /// Whether this is a request, a response, a notification or a lifecycle event.
+    #[must_use]
+    pub fn kind(&self) -> StreamMessageKind {
+        match self {
+            StreamMessageContent::Request { .. } => StreamMessageKind::Request,
+            StreamMessageContent::Response { .. } => StreamMessageKind::Response,
+            StreamMessageContent::Notification { .. } => StreamMessageKind::Notification,
+            StreamMessageContent::ConnectionOpened
+            | StreamMessageContent::Initialized { .. }
+            | StreamMessageContent::ConnectionClosed { .. } => StreamMessageKind::Lifecycle,
+        }
+    }
+
+    /// The method of a request or notification, or `None` for a response or a
+    /// lifecycle event.
+    ///
+    /// Parse it into an [`AcpMethod`](crate::AcpMethod) to match on core methods.
+    #[must_use]
+    pub fn method(&self) -> Option<&str> {
+        match self {
+            StreamMessageContent::Request { method, .. }
+            | StreamMessageContent::Notification { method, .. } => Some(method),
+            StreamMessageContent::Response { .. }
+            | StreamMessageContent::ConnectionOpened
+            | StreamMessageContent::Initialized { .. }
+            | StreamMessageContent::ConnectionClosed { .. } => None,
+        }
+    }
+
+    /// The parameters of a request or notification, or the result of a successful
+    /// response.
+    #[must_use]
+    pub fn params(&self) -> Option<&StreamValue> {
+        match self {
+            StreamMessageContent::Request { params, .. }
+            | StreamMessageContent::Notification { params, .. }
+            | StreamMessageContent::Response {
+                result: Ok(params), ..
+            } => params.as_ref(),
+            StreamMessageContent::Response { result: Err(_), .. }
+            | StreamMessageContent::ConnectionOpened
+            | StreamMessageContent::Initialized { .. }
+            | StreamMessageContent::ConnectionClosed { .. } => None,
+        }
+    }
+}
+
+/// The parameters or result carried by a [`StreamMessageContent`].
+///
+/// The JSON text is kept as is and only parsed into a [`serde_json::Value`] the
+/// first time [`StreamValue::value`] is called, so observers that only look at
+/// the method or direction of messages don't pay for parsing large payloads.
+/// Clones share the text and the parsed value.
+#[derive(Clone)]
+pub struct StreamValue(Arc<StreamValueInner>);
+
+struct StreamValueInner {
+    raw: Box<RawValue>,
+    parsed: OnceLock<serde_json::Result<serde_json::Value>>,
+}
+
+impl StreamValue {
+    fn new(raw: Box<RawValue>) -> Self {
+        Self(Arc::new(StreamValueInner {
+            raw,
+            parsed: OnceLock::new(),
+        }))
+    }
+
+    fn serialize(value: &impl Serialize) -> Option<Self> {
+        serde_json::value::to_raw_value(value).ok().map(Self::new)
+    }
+
+    /// The JSON text of the value.
+    #[must_use]
+    pub fn raw(&self) -> &RawValue {
+        &self.0.raw
+    }
+
+    /// The parsed value, which is cached after the first call.
+    ///
+    /// # Errors
+    ///
+    /// The text is always valid JSON, but parsing fails if it is nested too deeply.
+    pub fn value(&self) -> Result<&serde_json::Value, &serde_json::Error> {
+        self.0
+            .parsed
+            .get_or_init(|| serde_json::from_str(self.0.raw.get()))
+            .as_ref()
+    }
+
+    /// Converts the value into a [`serde_json::Value`], parsing it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`StreamValue::value`].
+    pub fn into_value(self) -> serde_json::Result<serde_json::Value> {
+        match self.value() {
+            Ok(value) => Ok(value.clone()),
+            // The cached error can't be moved out of the shared value.
+            Err(_) => serde_json::from_str(self.0.raw.get()),
+        }
+    }
+}
+
+impl std::fmt::Debug for StreamValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0.raw.get())
+    }
+}
+
+impl PartialEq for StreamValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.value(), other.value()) {
+            (Ok(value), Ok(other)) => value == other,
+            // Values that can't be parsed are only equal to the same text.
+            _ => self.0.raw.get() == other.0.raw.get(),
+        }
+    }
+}
+
+impl PartialEq<serde_json::Value> for StreamValue {
+    fn eq(&self, other: &serde_json::Value) -> bool {
+        self.value().is_ok_and(|value| value == other)
+    }
+}
+
+impl Serialize for StreamValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.raw.serialize(serializer)
+    }
+}
+
+impl TryFrom<StreamValue> for serde_json::Value {
+    type Error = serde_json::Error;
+
+    fn try_from(value: StreamValue) -> serde_json::Result<Self> {
+        value.into_value()
+    }
+}
+
+/// The three kinds of JSON-RPC messages, plus connection lifecycle events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StreamMessageKind {
+    /// A method call that expects a response.
+    Request,
+    /// A reply to a previous request.
+    Response,
+    /// A one-way message that doesn't expect a response.
+    Notification,
+    /// An event in the lifecycle of the connection, which isn't sent on the wire.
+    Lifecycle,
+}
+
+impl StreamMessageKind {
+    /// Classifies a raw JSON-RPC message by the fields it carries.
+    ///
+    /// Returns `None` if the value isn't an object with either a `method`, a `result`
+    /// or an `error`.
+    #[must_use]
+    pub fn classify(message: &serde_json::Value) -> Option<Self> {
+        let message = message.as_object()?;
+        if message.contains_key("method") {
+            if message.get("id").is_some_and(|id| !id.is_null()) {
+                Some(StreamMessageKind::Request)
+            } else {
+                Some(StreamMessageKind::Notification)
+            }
+        } else if message.contains_key("result") || message.contains_key("error") {
+            Some(StreamMessageKind::Response)
+        } else {
+            None
+        }
+    }
+}
+
+/// What is known about a message before its parameters or result are serialized,
+/// as seen by the filter of a [filtered subscription](crate::ClientSideConnection::subscribe_filtered).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamMessageInfo<'a> {
+    /// The direction of the message relative to this side of the connection.
+    pub direction: StreamMessageDirection,
+    /// Whether this is a request, a response, a notification or a lifecycle event.
+    pub kind: StreamMessageKind,
+    /// The method of a request or notification, or `None` for a response or a
+    /// lifecycle event.
+    pub method: Option<&'a str>,
 }
 
 /// A receiver for observing the message stream.
@@ -82,11 +300,17 @@ pub enum StreamMessageContent {
 /// ```no_run
 /// use agent_client_protocol::{StreamReceiver, StreamMessageDirection};
 ///
+/// use agent_client_protocol::StreamRecvError;
+///
 /// async fn monitor_messages(mut receiver: StreamReceiver) {
-///     while let Ok(message) = receiver.recv().await {
-///         match message.direction {
-///             StreamMessageDirection::Incoming => println!("← Received: {:?}", message.message),
-///             StreamMessageDirection::Outgoing => println!("→ Sent: {:?}", message.message),
+///     loop {
+///         match receiver.recv().await {
+///             Ok(message) => match message.direction {
+///                 StreamMessageDirection::Incoming => println!("← Received: {:?}", message.message),
+///                 StreamMessageDirection::Outgoing => println!("→ Sent: {:?}", message.message),
+///             },
+///             Err(StreamRecvError::Lagged(skipped)) => println!("Skipped {skipped} messages"),
+///             Err(StreamRecvError::Closed) => break,
 ///         }
 ///     }
 /// }
@@ -101,9 +325,52 @@ impl StreamReceiver {
     /// # Returns
     ///
     /// - `Ok(StreamMessage)` when a message is received
-    /// - `Err` when the sender is dropped or the receiver is lagged
-    pub async fn recv(&mut self) -> Result<StreamMessage> {
-        Ok(self.0.recv().await?)
+    /// - `Err(StreamRecvError::Lagged)` when older messages were dropped because the
+    ///   receiver fell behind. Receiving again continues with the oldest message
+    ///   still buffered.
+    /// - `Err(StreamRecvError::Closed)` when the connection has ended
+    pub async fn recv(&mut self) -> Result<StreamMessage, StreamRecvError> {
+        self.0.recv().await.map_err(|error| match error {
+            async_broadcast::RecvError::Overflowed(skipped) => StreamRecvError::Lagged(skipped),
+            async_broadcast::RecvError::Closed => StreamRecvError::Closed,
+        })
+    }
+
+    /// Returns the number of messages waiting to be received.
+    ///
+    /// Once this reaches the buffer's capacity, further messages cause the oldest
+    /// ones to be dropped, so tools can use it to notice they are falling behind.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if there are no messages waiting to be received.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The error returned by [`StreamReceiver::recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamRecvError {
+    /// The receiver fell behind and this many messages were dropped.
+    Lagged(u64),
+    /// The connection has ended and no more messages will be received.
+    Closed,
+}
+
+impl std::error::Error for StreamRecvError {}
+
+impl Display for StreamRecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamRecvError::Lagged(skipped) => {
+                write!(f, "stream receiver lagged behind by {skipped} messages")
+            }
+            StreamRecvError::Closed => write!(f, "stream closed"),
+        }
     }
 }
 
@@ -111,12 +378,152 @@ impl StreamReceiver {
 ///
 /// This is used internally by the RPC system to broadcast messages to all receivers.
 /// You typically won't interact with this directly.
-pub(crate) struct StreamSender(async_broadcast::Sender<StreamMessage>);
+pub(crate) struct StreamSender {
+    sender: async_broadcast::Sender<StreamMessage>,
+    filtered: Arc<Mutex<Vec<FilteredSender>>>,
+    /// The ID and direction of the `initialize` request awaiting a response.
+    pending_initialize: Mutex<Option<(i32, StreamMessageDirection)>>,
+}
+
+type StreamFilter = Box<dyn Fn(&StreamMessageInfo<'_>) -> bool + Send + Sync>;
+
+/// The sending half of a filtered subscription.
+struct FilteredSender {
+    filter: StreamFilter,
+    sender: async_broadcast::Sender<StreamMessage>,
+}
+
+/// The senders of the receivers that want a given message.
+struct Recipients<'a> {
+    unfiltered: Option<&'a async_broadcast::Sender<StreamMessage>>,
+    filtered: Vec<async_broadcast::Sender<StreamMessage>>,
+}
+
+impl Recipients<'_> {
+    fn is_empty(&self) -> bool {
+        self.unfiltered.is_none() && self.filtered.is_empty()
+    }
+
+    fn send(self, message: StreamMessage) {
+        for sender in self.filtered {
+            sender.try_broadcast(message.clone()).ok();
+        }
+        if let Some(sender) = self.unfiltered {
+            sender.try_broadcast(message).ok();
+        }
+    }
+}
 
 impl StreamSender {
+    /// Returns the receivers that want a message, so that messages nobody wants
+    /// are never serialized.
+    fn recipients(
+        &self,
+        direction: StreamMessageDirection,
+        kind: StreamMessageKind,
+        method: Option<&str>,
+    ) -> Recipients<'_> {
+        let info = StreamMessageInfo {
+            direction,
+            kind,
+            method,
+        };
+        let mut filtered = self.filtered.lock();
+        filtered.retain(|filtered| filtered.sender.receiver_count() > 0);
+        Recipients {
+            unfiltered: (self.sender.receiver_count() > 0).then_some(&self.sender),
+            filtered: filtered
+                .iter()
+                .filter(|filtered| (filtered.filter)(&info))
+                .map(|filtered| filtered.sender.clone())
+                .collect(),
+        }
+    }
+
+    fn broadcast(&self, direction: StreamMessageDirection, message: StreamMessageContent) {
+        self.recipients(direction, message.kind(), message.method())
+            .send(StreamMessage { direction, message });
+    }
+
+    /// Remembers the request if it is an `initialize` request.
+    fn track_request(&self, id: i32, method: &str, direction: StreamMessageDirection) {
+        if method == INITIALIZE_METHOD_NAME {
+            *self.pending_initialize.lock() = Some((id, direction));
+        }
+    }
+
+    /// Returns whether the response answers the tracked `initialize` request.
+    fn answers_initialize(&self, id: i32, direction: StreamMessageDirection) -> bool {
+        let mut pending = self.pending_initialize.lock();
+        if pending.is_some_and(|(pending_id, request_direction)| {
+            pending_id == id && request_direction != direction
+        }) {
+            *pending = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Broadcasts [`StreamMessageContent::Initialized`] if `result` is a successful
+    /// `initialize` response.
+    fn initialized(&self, direction: StreamMessageDirection, result: Option<&StreamValue>) {
+        if let Some(protocol_version) = result
+            .and_then(|result| result.value().ok()?.get("protocolVersion"))
+            .and_then(|version| ProtocolVersion::deserialize(version).ok())
+        {
+            self.broadcast(
+                direction,
+                StreamMessageContent::Initialized { protocol_version },
+            );
+        }
+    }
+
+    /// Broadcasts that the IO task started.
+    pub(crate) fn connection_opened(&self) {
+        self.broadcast(
+            StreamMessageDirection::Outgoing,
+            StreamMessageContent::ConnectionOpened,
+        );
+    }
+
+    /// Broadcasts that the IO task finished, closed by the side `direction` points
+    /// to if it ended without an error.
+    pub(crate) fn connection_closed(&self, result: &anyhow::Result<StreamMessageDirection>) {
+        let (direction, reason) = match result {
+            Ok(direction) => (*direction, None),
+            Err(error) => (StreamMessageDirection::Incoming, Some(error.to_string())),
+        };
+        self.broadcast(direction, StreamMessageContent::ConnectionClosed { reason });
+    }
+
     /// Broadcasts an outgoing message to all receivers.
     pub(crate) fn outgoing<L: Side, R: Side>(&self, message: &OutgoingMessage<L, R>) {
-        if self.0.receiver_count() == 0 {
+        let direction = StreamMessageDirection::Outgoing;
+        let initialized = match message {
+            OutgoingMessage::Request { id, method, .. } => {
+                self.track_request(*id, method, direction);
+                false
+            }
+            OutgoingMessage::Response { id, result } => {
+                self.answers_initialize(*id, direction)
+                    && matches!(result, ResponseResult::Result(_))
+            }
+            OutgoingMessage::Notification { .. } => false,
+        };
+        let recipients = match message {
+            OutgoingMessage::Request { method, .. } => {
+                self.recipients(direction, StreamMessageKind::Request, Some(method))
+            }
+            OutgoingMessage::Response { .. } => {
+                self.recipients(direction, StreamMessageKind::Response, None)
+            }
+            OutgoingMessage::Notification { method, .. } => {
+                self.recipients(direction, StreamMessageKind::Notification, Some(method))
+            }
+        };
+        // The `initialize` result is still needed for the `Initialized` event.
+        if recipients.is_empty() && !initialized {
             return;
         }
 
@@ -126,25 +533,34 @@ impl StreamSender {
                 OutgoingMessage::Request { id, method, params } => StreamMessageContent::Request {
                     id: *id,
                     method: method.clone(),
-                    params: serde_json::to_value(params).ok(),
+                    params: StreamValue::serialize(params),
                 },
                 OutgoingMessage::Response { id, result } => StreamMessageContent::Response {
                     id: *id,
                     result: match result {
-                        ResponseResult::Result(value) => Ok(serde_json::to_value(value).ok()),
+                        ResponseResult::Result(value) => Ok(StreamValue::serialize(value)),
                         ResponseResult::Error(error) => Err(error.clone()),
                     },
                 },
                 OutgoingMessage::Notification { method, params } => {
                     StreamMessageContent::Notification {
                         method: method.clone(),
-                        params: serde_json::to_value(params).ok(),
+                        params: StreamValue::serialize(params),
                     }
                 }
             },
         };
 
-        self.0.try_broadcast(message).ok();
+        let result = match &message.message {
+            StreamMessageContent::Response {
+                result: Ok(result), ..
+            } if initialized => result.clone(),
+            _ => None,
+        };
+        recipients.send(message);
+        if initialized {
+            self.initialized(direction, result.as_ref());
+        }
     }
 
     /// Broadcasts an incoming request to all receivers.
@@ -152,63 +568,75 @@ impl StreamSender {
         &self,
         id: i32,
         method: impl Into<Arc<str>>,
-        params: &impl Serialize,
+        params: Option<&RawValue>,
     ) {
-        if self.0.receiver_count() == 0 {
+        let direction = StreamMessageDirection::Incoming;
+        let method = method.into();
+        self.track_request(id, &method, direction);
+        let recipients = self.recipients(direction, StreamMessageKind::Request, Some(&method));
+        if recipients.is_empty() {
             return;
         }
 
-        let message = StreamMessage {
-            direction: StreamMessageDirection::Incoming,
+        recipients.send(StreamMessage {
+            direction,
             message: StreamMessageContent::Request {
                 id,
-                method: method.into(),
-                params: serde_json::to_value(params).ok(),
+                method,
+                params: params.map(|params| StreamValue::new(params.to_owned())),
             },
-        };
-
-        self.0.try_broadcast(message).ok();
+        });
     }
 
     /// Broadcasts an incoming response to all receivers.
     pub(crate) fn incoming_response(&self, id: i32, result: Result<Option<&RawValue>, &Error>) {
-        if self.0.receiver_count() == 0 {
+        let direction = StreamMessageDirection::Incoming;
+        let initialized = self.answers_initialize(id, direction) && result.is_ok();
+        let recipients = self.recipients(direction, StreamMessageKind::Response, None);
+        // The `initialize` result is still needed for the `Initialized` event.
+        if recipients.is_empty() && !initialized {
             return;
         }
 
         let result = match result {
-            Ok(Some(value)) => Ok(serde_json::from_str(value.get()).ok()),
+            Ok(Some(value)) => Ok(Some(StreamValue::new(value.to_owned()))),
             Ok(None) => Ok(None),
             Err(err) => Err(err.clone()),
         };
-
-        let message = StreamMessage {
-            direction: StreamMessageDirection::Incoming,
-            message: StreamMessageContent::Response { id, result },
+        let initialize_result = match &result {
+            Ok(result) if initialized => result.clone(),
+            _ => None,
         };
 
-        self.0.try_broadcast(message).ok();
+        recipients.send(StreamMessage {
+            direction,
+            message: StreamMessageContent::Response { id, result },
+        });
+        if initialized {
+            self.initialized(direction, initialize_result.as_ref());
+        }
     }
 
     /// Broadcasts an incoming notification to all receivers.
     pub(crate) fn incoming_notification(
         &self,
         method: impl Into<Arc<str>>,
-        params: &impl Serialize,
+        params: Option<&RawValue>,
     ) {
-        if self.0.receiver_count() == 0 {
+        let direction = StreamMessageDirection::Incoming;
+        let method = method.into();
+        let recipients = self.recipients(direction, StreamMessageKind::Notification, Some(&method));
+        if recipients.is_empty() {
             return;
         }
 
-        let message = StreamMessage {
-            direction: StreamMessageDirection::Incoming,
+        recipients.send(StreamMessage {
+            direction,
             message: StreamMessageContent::Notification {
-                method: method.into(),
-                params: serde_json::to_value(params).ok(),
+                method,
+                params: params.map(|params| StreamValue::new(params.to_owned())),
             },
-        };
-
-        self.0.try_broadcast(message).ok();
+        });
     }
 }
 
@@ -218,6 +646,7 @@ impl StreamSender {
 /// to observe the message stream.
 pub(crate) struct StreamBroadcast {
     receiver: async_broadcast::InactiveReceiver<StreamMessage>,
+    filtered: Arc<Mutex<Vec<FilteredSender>>>,
 }
 
 impl StreamBroadcast {
@@ -226,11 +655,20 @@ impl StreamBroadcast {
     /// Returns a sender for broadcasting messages and the broadcast instance
     /// for creating receivers.
     pub(crate) fn new() -> (StreamSender, Self) {
-        let (sender, receiver) = async_broadcast::broadcast(1);
+        let (mut sender, receiver) = async_broadcast::broadcast(1);
+        // Drop the oldest messages when a receiver falls behind, so that it is told
+        // how many it missed instead of silently losing the newest ones.
+        sender.set_overflow(true);
+        let filtered = Arc::default();
         (
-            StreamSender(sender),
+            StreamSender {
+                sender,
+                filtered: Arc::clone(&filtered),
+                pending_initialize: Mutex::new(None),
+            },
             Self {
                 receiver: receiver.deactivate(),
+                filtered,
             },
         )
     }
@@ -247,6 +685,23 @@ impl StreamBroadcast {
         }
         StreamReceiver(new_receiver)
     }
+
+    /// Creates a new receiver that only gets the messages `filter` accepts.
+    ///
+    /// The filter runs before a message is serialized, so messages no receiver
+    /// wants are never serialized at all.
+    pub(crate) fn filtered_receiver(
+        &self,
+        filter: impl Fn(&StreamMessageInfo<'_>) -> bool + Send + Sync + 'static,
+    ) -> StreamReceiver {
+        let (mut sender, receiver) = async_broadcast::broadcast(64);
+        sender.set_overflow(true);
+        self.filtered.lock().push(FilteredSender {
+            filter: Box::new(filter),
+            sender,
+        });
+        StreamReceiver(receiver)
+    }
 }
 
 impl<Local: Side, Remote: Side> From<OutgoingMessage<Local, Remote>> for StreamMessage {
@@ -257,22 +712,51 @@ impl<Local: Side, Remote: Side> From<OutgoingMessage<Local, Remote>> for StreamM
                 OutgoingMessage::Request { id, method, params } => StreamMessageContent::Request {
                     id,
                     method,
-                    params: serde_json::to_value(params).ok(),
+                    params: StreamValue::serialize(&params),
                 },
                 OutgoingMessage::Response { id, result } => StreamMessageContent::Response {
                     id,
                     result: match result {
-                        ResponseResult::Result(value) => Ok(serde_json::to_value(value).ok()),
+                        ResponseResult::Result(value) => Ok(StreamValue::serialize(&value)),
                         ResponseResult::Error(error) => Err(error),
                     },
                 },
                 OutgoingMessage::Notification { method, params } => {
                     StreamMessageContent::Notification {
                         method,
-                        params: serde_json::to_value(params).ok(),
+                        params: StreamValue::serialize(&params),
                     }
                 }
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_stream_value() {
+        let value = StreamValue::serialize(&json!({"path": "/a.txt"})).unwrap();
+        assert_eq!(value.raw().get(), r#"{"path":"/a.txt"}"#);
+        assert_eq!(value.value().unwrap(), &json!({"path": "/a.txt"}));
+        assert_eq!(value, json!({"path": "/a.txt"}));
+        let spaced =
+            StreamValue::new(RawValue::from_string(r#"{ "path": "/a.txt" }"#.into()).unwrap());
+        assert_eq!(value, spaced);
+        assert_eq!(value.into_value().unwrap(), json!({"path": "/a.txt"}));
+
+        // Too deeply nested to parse, but still valid JSON.
+        let nested = |depth| {
+            StreamValue::new(RawValue::from_string("[".repeat(depth) + &"]".repeat(depth)).unwrap())
+        };
+        let deep = nested(200);
+        assert!(deep.value().is_err());
+        assert!(deep.clone().into_value().is_err());
+        assert_ne!(deep, json!(null));
+        assert_eq!(deep, nested(200));
+        assert_ne!(deep, nested(201));
+    }
+}
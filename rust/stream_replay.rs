@@ -0,0 +1,322 @@
+//! Recording and replaying of JSON-RPC message streams.
+//!
+//! [`StreamRecorder`] writes every message observed on a connection to a
+//! newline-delimited JSON file. [`StreamReplayer`] reads such a file back and
+//! provides a mock transport that plays the recorded incoming messages to a fresh
+//! connection, so that regression tests can check that an implementation still
+//! produces the recorded outgoing messages.
+
+use std::{
+    collections::VecDeque,
+    io::BufRead,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt as _};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    StreamMessage, StreamMessageContent, StreamMessageDirection, StreamReceiver, StreamRecvError,
+    rpc::JsonRpcMessage,
+};
+
+/// A single line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// When the message was recorded, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The direction of the message relative to the recorded side of the connection.
+    pub direction: StreamMessageDirection,
+    /// The message as it appears on the wire.
+    pub message: serde_json::Value,
+}
+
+/// Fails with the original message for connection lifecycle events, which don't
+/// appear on the wire.
+impl TryFrom<StreamMessage> for RecordedMessage {
+    type Error = StreamMessage;
+
+    fn try_from(message: StreamMessage) -> Result<Self, StreamMessage> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| {
+                u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+            });
+        let direction = message.direction;
+        let message = match message.message {
+            StreamMessageContent::Request { id, method, params } => {
+                serde_json::json!({ "id": id, "method": method, "params": params })
+            }
+            StreamMessageContent::Response { id, result } => match result {
+                Ok(result) => serde_json::json!({ "id": id, "result": result }),
+                Err(error) => serde_json::json!({ "id": id, "error": error }),
+            },
+            StreamMessageContent::Notification { method, params } => {
+                serde_json::json!({ "method": method, "params": params })
+            }
+            StreamMessageContent::ConnectionOpened
+            | StreamMessageContent::Initialized { .. }
+            | StreamMessageContent::ConnectionClosed { .. } => return Err(message),
+        };
+        let mut message = serde_json::to_value(JsonRpcMessage::wrap(message))
+            .expect("JSON values always serialize");
+        if let Some(object) = message.as_object_mut()
+            && object.get("params").is_some_and(serde_json::Value::is_null)
+        {
+            object.remove("params");
+        }
+        Ok(Self {
+            timestamp,
+            direction,
+            message,
+        })
+    }
+}
+
+/// Writes every message observed on a connection as newline-delimited JSON.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example(conn: agent_client_protocol::AgentSideConnection) -> anyhow::Result<()> {
+/// use agent_client_protocol::StreamRecorder;
+///
+/// let file = Vec::new();
+/// let recording = StreamRecorder::new(conn.subscribe(), file).run().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StreamRecorder<W> {
+    receiver: StreamReceiver,
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> StreamRecorder<W> {
+    /// Creates a recorder that writes the messages from `receiver` to `writer`.
+    ///
+    /// Subscribe before any messages are exchanged to capture the whole session.
+    pub fn new(receiver: StreamReceiver, writer: W) -> Self {
+        Self { receiver, writer }
+    }
+
+    /// Records messages until the connection ends, then returns the writer.
+    ///
+    /// Messages are timestamped as they are recorded, and connection lifecycle
+    /// events are skipped. Fails if the recorder falls so
+    /// far behind that messages are dropped, since the recording would then be
+    /// incomplete.
+    pub async fn run(mut self) -> Result<W> {
+        let mut line = Vec::new();
+        loop {
+            let message = match self.receiver.recv().await {
+                Ok(message) => message,
+                Err(StreamRecvError::Closed) => break,
+                Err(error @ StreamRecvError::Lagged(_)) => return Err(error.into()),
+            };
+            let Ok(message) = RecordedMessage::try_from(message) else {
+                continue;
+            };
+            line.clear();
+            serde_json::to_writer(&mut line, &message)?;
+            line.push(b'\n');
+            self.writer.write_all(&line).await?;
+        }
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+}
+
+/// A recording made by [`StreamRecorder`], ready to be replayed.
+#[derive(Debug, Clone)]
+pub struct StreamReplayer {
+    messages: Vec<RecordedMessage>,
+}
+
+impl StreamReplayer {
+    /// Reads a recording, one [`RecordedMessage`] per line. Empty lines are skipped.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self> {
+        let mut messages = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                messages.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self { messages })
+    }
+
+    /// The recorded messages, in order.
+    #[must_use]
+    pub fn messages(&self) -> &[RecordedMessage] {
+        &self.messages
+    }
+
+    /// The messages the recorded side sent, in order.
+    #[must_use]
+    pub fn expected_outgoing(&self) -> Vec<serde_json::Value> {
+        self.messages
+            .iter()
+            .filter(|message| message.direction == StreamMessageDirection::Outgoing)
+            .map(|message| message.message.clone())
+            .collect()
+    }
+
+    /// Creates a transport that plays the recording to a new connection.
+    ///
+    /// Each recorded incoming message is only made readable once the connection has
+    /// written as many messages as had been sent before it in the recording, so
+    /// responses never arrive ahead of the requests they answer. Once every incoming
+    /// message has been read and every expected message written, the incoming stream
+    /// ends, which lets the connection's IO task finish.
+    ///
+    /// Messages the recorded application sent on its own, rather than from its
+    /// handlers, are still expected, so the test must send them again. If the
+    /// connection writes fewer messages than recorded, the incoming stream never
+    /// ends, so tests should apply a timeout.
+    #[must_use]
+    pub fn transport(&self) -> ReplayTransport {
+        let state = Arc::new(Mutex::new(ReplayState::default()));
+        let mut sent = 0;
+        let mut incoming = VecDeque::new();
+        for message in &self.messages {
+            match message.direction {
+                StreamMessageDirection::Incoming => {
+                    let mut line = message.message.to_string().into_bytes();
+                    line.push(b'\n');
+                    incoming.push_back((sent, line));
+                }
+                StreamMessageDirection::Outgoing => sent += 1,
+            }
+        }
+        ReplayTransport {
+            incoming: ReplayReader {
+                state: state.clone(),
+                incoming,
+                current: Vec::new(),
+                offset: 0,
+                expected_outgoing: sent,
+            },
+            outgoing: ReplayWriter { state },
+        }
+    }
+}
+
+/// The mock transport returned by [`StreamReplayer::transport`].
+///
+/// Pass `outgoing` and `incoming` as the outgoing and incoming bytes of a connection.
+pub struct ReplayTransport {
+    /// Receives the messages written by the connection.
+    pub outgoing: ReplayWriter,
+    /// Plays the recorded incoming messages.
+    pub incoming: ReplayReader,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    written: Vec<serde_json::Value>,
+    partial_line: Vec<u8>,
+    reader_waker: Option<Waker>,
+}
+
+/// The incoming half of a [`ReplayTransport`].
+pub struct ReplayReader {
+    state: Arc<Mutex<ReplayState>>,
+    /// Recorded lines, each with the number of messages that must be written first.
+    incoming: VecDeque<(usize, Vec<u8>)>,
+    current: Vec<u8>,
+    offset: usize,
+    expected_outgoing: usize,
+}
+
+impl AsyncRead for ReplayReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.offset < this.current.len() {
+                let len = buf.len().min(this.current.len() - this.offset);
+                buf[..len].copy_from_slice(&this.current[this.offset..this.offset + len]);
+                this.offset += len;
+                return Poll::Ready(Ok(len));
+            }
+
+            let required = this
+                .incoming
+                .front()
+                .map_or(this.expected_outgoing, |(required, _)| *required);
+            let mut state = this.state.lock();
+            if state.written.len() < required {
+                state.reader_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            drop(state);
+
+            match this.incoming.pop_front() {
+                Some((_, line)) => {
+                    this.current = line;
+                    this.offset = 0;
+                }
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+    }
+}
+
+/// The outgoing half of a [`ReplayTransport`].
+///
+/// Clones share the same state, so keep one to inspect what the connection wrote.
+#[derive(Clone)]
+pub struct ReplayWriter {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayWriter {
+    /// The messages written so far. Batches are flattened into their messages.
+    #[must_use]
+    pub fn written(&self) -> Vec<serde_json::Value> {
+        self.state.lock().written.clone()
+    }
+}
+
+impl AsyncWrite for ReplayWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut state = self.state.lock();
+        state.partial_line.extend_from_slice(buf);
+        let mut wrote_message = false;
+        while let Some(end) = state.partial_line.iter().position(|byte| *byte == b'\n') {
+            let line = state.partial_line.drain(..=end).collect::<Vec<_>>();
+            let message = serde_json::from_slice(&line).unwrap_or_else(|_| {
+                serde_json::Value::String(String::from_utf8_lossy(&line).trim_end().to_string())
+            });
+            match message {
+                serde_json::Value::Array(messages) => state.written.extend(messages),
+                message => state.written.push(message),
+            }
+            wrote_message = true;
+        }
+        if wrote_message && let Some(waker) = state.reader_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -4,7 +4,7 @@
 //! running code, or fetching data—it generates tool calls that the agent executes on its behalf.
 //!
 /// See protocol docs: [Tool Calls](https://agentclientprotocol.com/protocol/tool-calls)
-use std::{path::PathBuf, sync::Arc};
+use std::{ops::Range, path::PathBuf, sync::Arc};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -51,6 +51,125 @@ pub struct ToolCall {
 }
 
 impl ToolCall {
+    /// Starts building a tool call with the given ID and title.
+    ///
+    /// All other fields default to their empty values until set on the builder.
+    pub fn builder(id: ToolCallId, title: impl Into<String>) -> ToolCallBuilder {
+        ToolCallBuilder {
+            tool_call: ToolCall {
+                id,
+                title: title.into(),
+                kind: ToolKind::default(),
+                status: ToolCallStatus::default(),
+                content: Vec::new(),
+                locations: Vec::new(),
+                raw_input: None,
+                raw_output: None,
+                meta: None,
+            },
+        }
+    }
+
+    /// Deserializes [`ToolCall::raw_input`] into the agent's own input type.
+    ///
+    /// Fails with an invalid params error if there is no raw input, or if it does
+    /// not match `T`.
+    ///
+    /// ```
+    /// use agent_client_protocol::{ToolCall, ToolCallId};
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    /// struct Grep {
+    ///     pattern: String,
+    ///     max_results: usize,
+    /// }
+    ///
+    /// let mut tool_call = ToolCall::builder(ToolCallId("grep-1".into()), "Search")
+    ///     .raw_input(serde_json::json!({ "pattern": "fn main", "max_results": 10 }))
+    ///     .build();
+    ///
+    /// let input: Grep = tool_call.typed_input().unwrap();
+    /// assert_eq!(input.pattern, "fn main");
+    ///
+    /// tool_call.set_typed_output(vec!["src/main.rs"]).unwrap();
+    /// assert_eq!(tool_call.raw_output, Some(serde_json::json!(["src/main.rs"])));
+    /// ```
+    pub fn typed_input<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        let raw_input = self.raw_input.as_ref().ok_or_else(|| {
+            Error::invalid_params().with_data(format!("tool call {} has no raw input", self.id.0))
+        })?;
+        Ok(T::deserialize(raw_input)?)
+    }
+
+    /// Serializes `output` into [`ToolCall::raw_output`], replacing any previous
+    /// value.
+    ///
+    /// Fails only if `output` cannot be represented as JSON, in which case the
+    /// tool call is left unchanged.
+    pub fn set_typed_output<T: Serialize>(&mut self, output: T) -> Result<(), Error> {
+        self.raw_output = Some(serde_json::to_value(output).map_err(Error::into_internal_error)?);
+        Ok(())
+    }
+
+    /// Merges a streamed piece of output, sent as a `tool_call_output_chunk`
+    /// session update, into [`ToolCall::raw_output`].
+    ///
+    /// Without `append`, the chunk replaces the raw output. With `append`, the
+    /// chunk is added to the raw output, which must be of the same kind: strings
+    /// are concatenated, arrays are extended with the chunk's elements, and
+    /// objects gain the chunk's fields, replacing those with the same name. A
+    /// chunk appended to no output becomes the raw output.
+    ///
+    /// Appending a chunk of a different kind fails with an invalid params error,
+    /// and leaves the tool call unchanged.
+    pub fn apply_output_chunk(
+        &mut self,
+        chunk: serde_json::Value,
+        append: bool,
+    ) -> Result<(), Error> {
+        use serde_json::Value;
+
+        let Some(raw_output) = self.raw_output.as_mut().filter(|_| append) else {
+            self.raw_output = Some(chunk);
+            return Ok(());
+        };
+        match (raw_output, chunk) {
+            (Value::String(output), Value::String(chunk)) => output.push_str(&chunk),
+            (Value::Array(output), Value::Array(chunk)) => output.extend(chunk),
+            (Value::Object(output), Value::Object(chunk)) => output.extend(chunk),
+            (_, chunk) => {
+                return Err(Error::invalid_params().with_data(format!(
+                    "cannot append {} to the raw output of tool call {}",
+                    json_kind(&chunk),
+                    self.id.0
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`ToolCall::update`], but rejects updates that would move the tool
+    /// call out of a final status.
+    ///
+    /// Once a tool call is `completed` or `failed` its status can no longer
+    /// change, so an update that sets a different status is most likely stale.
+    /// On error, the tool call is left unchanged.
+    pub fn apply_update(&mut self, fields: ToolCallUpdateFields) -> Result<(), Error> {
+        if let Some(status) = fields.status
+            && !self.status.can_transition_to(status)
+        {
+            return Err(Error::invalid_params().with_data(serde_json::json!(format!(
+                "tool call {} cannot transition from {} to {}",
+                self.id.0,
+                self.status.as_str(),
+                status.as_str()
+            ))));
+        }
+        self.update(fields);
+        Ok(())
+    }
+
     /// Update an existing tool call with the values in the provided update
     /// fields. Fields with collections of values are overwritten, not extended.
     pub fn update(&mut self, fields: ToolCallUpdateFields) {
@@ -78,6 +197,74 @@ impl ToolCall {
     }
 }
 
+fn json_kind(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Builder for [`ToolCall`], created with [`ToolCall::builder`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ToolCallBuilder {
+    tool_call: ToolCall,
+}
+
+impl ToolCallBuilder {
+    /// Sets the category of tool being invoked.
+    pub fn kind(mut self, kind: ToolKind) -> Self {
+        self.tool_call.kind = kind;
+        self
+    }
+
+    /// Sets the execution status.
+    pub fn status(mut self, status: ToolCallStatus) -> Self {
+        self.tool_call.status = status;
+        self
+    }
+
+    /// Appends an item to the content produced by the tool call.
+    pub fn content(mut self, content: impl Into<ToolCallContent>) -> Self {
+        self.tool_call.content.push(content.into());
+        self
+    }
+
+    /// Appends a file location affected by the tool call.
+    pub fn location(mut self, location: impl Into<ToolCallLocation>) -> Self {
+        self.tool_call.locations.push(location.into());
+        self
+    }
+
+    /// Sets the raw input parameters sent to the tool.
+    pub fn raw_input(mut self, raw_input: serde_json::Value) -> Self {
+        self.tool_call.raw_input = Some(raw_input);
+        self
+    }
+
+    /// Sets the raw output returned by the tool.
+    pub fn raw_output(mut self, raw_output: serde_json::Value) -> Self {
+        self.tool_call.raw_output = Some(raw_output);
+        self
+    }
+
+    /// Sets the extension metadata.
+    pub fn meta(mut self, meta: serde_json::Value) -> Self {
+        self.tool_call.meta = Some(meta);
+        self
+    }
+
+    /// Returns the built tool call.
+    #[must_use]
+    pub fn build(self) -> ToolCall {
+        self.tool_call
+    }
+}
+
 /// An update to an existing tool call.
 ///
 /// Used to report progress and results as tools execute. All fields except
@@ -98,6 +285,92 @@ pub struct ToolCallUpdate {
     pub meta: Option<serde_json::Value>,
 }
 
+impl ToolCallUpdate {
+    /// Starts building an update to the tool call with the given ID.
+    ///
+    /// Only the fields set on the builder are included in the update.
+    pub fn builder(id: ToolCallId) -> ToolCallUpdateBuilder {
+        ToolCallUpdateBuilder {
+            update: ToolCallUpdate {
+                id,
+                fields: ToolCallUpdateFields::default(),
+                meta: None,
+            },
+        }
+    }
+}
+
+/// Builder for [`ToolCallUpdate`], created with [`ToolCallUpdate::builder`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ToolCallUpdateBuilder {
+    update: ToolCallUpdate,
+}
+
+impl ToolCallUpdateBuilder {
+    /// Updates the tool kind.
+    pub fn kind(mut self, kind: ToolKind) -> Self {
+        self.update.fields.kind = Some(kind);
+        self
+    }
+
+    /// Updates the execution status.
+    pub fn status(mut self, status: ToolCallStatus) -> Self {
+        self.update.fields.status = Some(status);
+        self
+    }
+
+    /// Updates the human-readable title.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.update.fields.title = Some(title.into());
+        self
+    }
+
+    /// Appends an item to the replacement content collection.
+    pub fn content(mut self, content: impl Into<ToolCallContent>) -> Self {
+        self.update
+            .fields
+            .content
+            .get_or_insert_with(Vec::new)
+            .push(content.into());
+        self
+    }
+
+    /// Appends a file location to the replacement locations collection.
+    pub fn location(mut self, location: impl Into<ToolCallLocation>) -> Self {
+        self.update
+            .fields
+            .locations
+            .get_or_insert_with(Vec::new)
+            .push(location.into());
+        self
+    }
+
+    /// Updates the raw input.
+    pub fn raw_input(mut self, raw_input: serde_json::Value) -> Self {
+        self.update.fields.raw_input = Some(raw_input);
+        self
+    }
+
+    /// Updates the raw output.
+    pub fn raw_output(mut self, raw_output: serde_json::Value) -> Self {
+        self.update.fields.raw_output = Some(raw_output);
+        self
+    }
+
+    /// Sets the extension metadata.
+    pub fn meta(mut self, meta: serde_json::Value) -> Self {
+        self.update.meta = Some(meta);
+        self
+    }
+
+    /// Returns the built update.
+    #[must_use]
+    pub fn build(self) -> ToolCallUpdate {
+        self.update
+    }
+}
+
 /// Optional fields that can be updated in a tool call.
 ///
 /// All fields are optional - only include the ones being changed.
@@ -259,12 +532,43 @@ pub enum ToolCallStatus {
     Completed,
     /// The tool call failed with an error.
     Failed,
+    /// The tool call was aborted before finishing, because the client cancelled
+    /// the prompt turn or the tool call itself.
+    Cancelled,
 }
 
 impl ToolCallStatus {
     fn is_default(&self) -> bool {
         matches!(self, ToolCallStatus::Pending)
     }
+
+    /// Whether the tool call has finished running, successfully or not.
+    #[must_use]
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            ToolCallStatus::Completed | ToolCallStatus::Failed | ToolCallStatus::Cancelled
+        )
+    }
+
+    /// Whether a tool call with this status may move to `next`.
+    ///
+    /// Any transition is allowed until the tool call reaches a final status,
+    /// after which its status can no longer change.
+    #[must_use]
+    pub fn can_transition_to(self, next: ToolCallStatus) -> bool {
+        !self.is_final() || self == next
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ToolCallStatus::Pending => "pending",
+            ToolCallStatus::InProgress => "in_progress",
+            ToolCallStatus::Completed => "completed",
+            ToolCallStatus::Failed => "failed",
+            ToolCallStatus::Cancelled => "cancelled",
+        }
+    }
 }
 
 /// Content produced by a tool call.
@@ -329,6 +633,313 @@ pub struct Diff {
     pub meta: Option<serde_json::Value>,
 }
 
+impl Diff {
+    /// Renders the diff in unified diff format, with `context_lines` lines of
+    /// unchanged context around each change.
+    ///
+    /// New files, where `old_text` is `None`, are rendered as a single hunk adding
+    /// every line. Returns an empty string when the texts are identical.
+    #[must_use]
+    pub fn to_unified(&self, context_lines: usize) -> String {
+        let old_lines = split_lines(self.old_text.as_deref().unwrap_or_default());
+        let new_lines = split_lines(&self.new_text);
+        let edits = diff_lines(&old_lines, &new_lines);
+
+        let changes: Vec<usize> = edits
+            .iter()
+            .enumerate()
+            .filter(|(_, edit)| !matches!(edit, LineEdit::Equal(..)))
+            .map(|(ix, _)| ix)
+            .collect();
+        let Some(&first_change) = changes.first() else {
+            return String::new();
+        };
+
+        let path = self.path.display();
+        let mut output = if self.old_text.is_some() {
+            format!("--- {path}\n+++ {path}\n")
+        } else {
+            format!("--- /dev/null\n+++ {path}\n")
+        };
+
+        let mut hunk_start = first_change.saturating_sub(context_lines);
+        let mut last_change = first_change;
+        for &change in &changes[1..] {
+            if change - last_change - 1 > 2 * context_lines {
+                let hunk_end = (last_change + context_lines + 1).min(edits.len());
+                write_hunk(
+                    &mut output,
+                    &edits,
+                    hunk_start..hunk_end,
+                    &old_lines,
+                    &new_lines,
+                );
+                hunk_start = change - context_lines;
+            }
+            last_change = change;
+        }
+        let hunk_end = (last_change + context_lines + 1).min(edits.len());
+        write_hunk(
+            &mut output,
+            &edits,
+            hunk_start..hunk_end,
+            &old_lines,
+            &new_lines,
+        );
+
+        output
+    }
+
+    /// Returns the number of added and removed lines, in that order.
+    #[must_use]
+    pub fn line_stats(&self) -> (usize, usize) {
+        let old_lines = split_lines(self.old_text.as_deref().unwrap_or_default());
+        let new_lines = split_lines(&self.new_text);
+        diff_lines(&old_lines, &new_lines).iter().fold(
+            (0, 0),
+            |(added, removed), edit| match edit {
+                LineEdit::Equal(..) => (added, removed),
+                LineEdit::Insert(_) => (added + 1, removed),
+                LineEdit::Delete(_) => (added, removed + 1),
+            },
+        )
+    }
+}
+
+/// A single step of a line-based edit script, holding indices into the old and/or
+/// new lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEdit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Splits text into lines, keeping line terminators so that a missing newline at
+/// the end of the text counts as a change.
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Computes a shortest edit script between two sequences of lines using Myers'
+/// diff algorithm.
+///
+/// This is the linear space variant, which splits the problem at the middle snake
+/// of an optimal path and solves both halves recursively, so that memory use stays
+/// proportional to the number of lines however different the texts are.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<LineEdit> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut differ = Differ {
+        old,
+        new,
+        forward: Diagonals::new(max_d),
+        backward: Diagonals::new(max_d),
+        edits: Vec::with_capacity(old.len().max(new.len())),
+    };
+    differ.conquer(0..old.len(), 0..new.len());
+    differ.edits
+}
+
+/// The state of [`diff_lines`].
+struct Differ<'a> {
+    old: &'a [&'a str],
+    new: &'a [&'a str],
+    forward: Diagonals,
+    backward: Diagonals,
+    edits: Vec<LineEdit>,
+}
+
+impl Differ<'_> {
+    /// Appends the edits turning `old` into `new`.
+    fn conquer(&mut self, mut old: Range<usize>, mut new: Range<usize>) {
+        let prefix = self.common_prefix(old.clone(), new.clone());
+        for i in 0..prefix {
+            self.edits
+                .push(LineEdit::Equal(old.start + i, new.start + i));
+        }
+        old.start += prefix;
+        new.start += prefix;
+        let suffix = self.common_suffix(old.clone(), new.clone());
+        old.end -= suffix;
+        new.end -= suffix;
+
+        if old.is_empty() {
+            self.edits.extend(new.clone().map(LineEdit::Insert));
+        } else if new.is_empty() {
+            self.edits.extend(old.clone().map(LineEdit::Delete));
+        } else {
+            match self.middle_snake(old.clone(), new.clone()) {
+                Some((x, y))
+                    if (x, y) != (old.start, new.start) && (x, y) != (old.end, new.end) =>
+                {
+                    self.conquer(old.start..x, new.start..y);
+                    self.conquer(x..old.end, y..new.end);
+                }
+                // Can't happen with the common prefix and suffix removed, but
+                // replacing everything is still a valid script.
+                _ => {
+                    self.edits.extend(old.clone().map(LineEdit::Delete));
+                    self.edits.extend(new.clone().map(LineEdit::Insert));
+                }
+            }
+        }
+
+        for i in 0..suffix {
+            self.edits.push(LineEdit::Equal(old.end + i, new.end + i));
+        }
+    }
+
+    /// Finds where the forward and backward searches for an optimal path between
+    /// `old` and `new` first overlap, and returns a point on that path which splits
+    /// it in two.
+    fn middle_snake(&mut self, old: Range<usize>, new: Range<usize>) -> Option<(usize, usize)> {
+        let n = old.len();
+        let m = new.len();
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+        self.forward[1] = 0;
+        self.backward[1] = 0;
+        let max_d = (n + m).div_ceil(2) as isize + 1;
+        for d in 0..max_d {
+            for k in (-d..=d).step_by(2) {
+                let x0 = if k == -d || (k != d && self.forward[k - 1] < self.forward[k + 1]) {
+                    self.forward[k + 1]
+                } else {
+                    self.forward[k - 1] + 1
+                };
+                let y0 = (x0 as isize - k) as usize;
+                let x = x0
+                    + if x0 < n && y0 < m {
+                        self.common_prefix(old.start + x0..old.end, new.start + y0..new.end)
+                    } else {
+                        0
+                    };
+                self.forward[k] = x;
+                if odd && (k - delta).abs() < d && x + self.backward[delta - k] >= n {
+                    return Some((old.start + x0, new.start + y0));
+                }
+            }
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && self.backward[k - 1] < self.backward[k + 1]) {
+                    self.backward[k + 1]
+                } else {
+                    self.backward[k - 1] + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                if x < n && y < m {
+                    let suffix = self.common_suffix(old.start..old.end - x, new.start..new.end - y);
+                    x += suffix;
+                    y += suffix;
+                }
+                self.backward[k] = x;
+                if !odd && (k - delta).abs() <= d && x + self.forward[delta - k] >= n {
+                    return Some((old.end - x, new.end - y));
+                }
+            }
+        }
+        None
+    }
+
+    fn common_prefix(&self, old: Range<usize>, new: Range<usize>) -> usize {
+        self.old[old]
+            .iter()
+            .zip(&self.new[new])
+            .take_while(|(old, new)| old == new)
+            .count()
+    }
+
+    fn common_suffix(&self, old: Range<usize>, new: Range<usize>) -> usize {
+        self.old[old]
+            .iter()
+            .rev()
+            .zip(self.new[new].iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count()
+    }
+}
+
+/// The furthest x reached on each diagonal `k` of the edit graph, indexed from
+/// `-max_d` to `max_d`.
+struct Diagonals {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(max_d: usize) -> Self {
+        Self {
+            offset: max_d as isize,
+            x: vec![0; 2 * max_d + 1],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for Diagonals {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.x[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for Diagonals {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.x[(k + self.offset) as usize]
+    }
+}
+
+/// Returns how many old and new lines the edits span.
+fn line_counts(edits: &[LineEdit]) -> (usize, usize) {
+    edits
+        .iter()
+        .fold((0, 0), |(old_count, new_count), edit| match edit {
+            LineEdit::Equal(..) => (old_count + 1, new_count + 1),
+            LineEdit::Delete(_) => (old_count + 1, new_count),
+            LineEdit::Insert(_) => (old_count, new_count + 1),
+        })
+}
+
+/// Writes the hunk covering `edits[range]`.
+fn write_hunk(
+    output: &mut String,
+    edits: &[LineEdit],
+    range: std::ops::Range<usize>,
+    old: &[&str],
+    new: &[&str],
+) {
+    fn format_range(start: usize, count: usize) -> String {
+        // Empty ranges point at the line before the (empty) hunk.
+        let start = if count == 0 { start } else { start + 1 };
+        if count == 1 {
+            start.to_string()
+        } else {
+            format!("{start},{count}")
+        }
+    }
+
+    let (old_start, new_start) = line_counts(&edits[..range.start]);
+    let hunk = &edits[range];
+    let (old_count, new_count) = line_counts(hunk);
+    output.push_str(&format!(
+        "@@ -{} +{} @@\n",
+        format_range(old_start, old_count),
+        format_range(new_start, new_count)
+    ));
+
+    for edit in hunk {
+        let (prefix, line) = match *edit {
+            LineEdit::Equal(old_ix, _) => (' ', old[old_ix]),
+            LineEdit::Delete(old_ix) => ('-', old[old_ix]),
+            LineEdit::Insert(new_ix) => ('+', new[new_ix]),
+        };
+        output.push(prefix);
+        output.push_str(line);
+        if !line.ends_with('\n') {
+            output.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
 /// A file location being accessed or modified by a tool.
 ///
 /// Enables clients to implement "follow-along" features that track
@@ -341,9 +952,480 @@ pub struct ToolCallLocation {
     /// The file path being accessed or modified.
     pub path: PathBuf,
     /// Optional line number within the file.
+    ///
+    /// When the location is a range, this is the line it starts on.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub line: Option<u32>,
+    /// Optional column within `line` the location starts at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+    /// Optional line the location ends on, for locations spanning a range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u32>,
+    /// Optional column within `end_line` the location ends at, exclusive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>,
     /// Extension point for implementations
     #[serde(skip_serializing_if = "Option::is_none", rename = "_meta")]
     pub meta: Option<serde_json::Value>,
 }
+
+impl<P: Into<PathBuf>> From<P> for ToolCallLocation {
+    fn from(path: P) -> Self {
+        ToolCallLocation {
+            path: path.into(),
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+            meta: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorCode, TextContent};
+
+    #[test]
+    fn test_tool_call_builder() {
+        let id = ToolCallId("read-1".into());
+        assert_eq!(
+            ToolCall::builder(id.clone(), "Read file").build(),
+            ToolCall {
+                id: id.clone(),
+                title: "Read file".to_string(),
+                kind: ToolKind::Other,
+                status: ToolCallStatus::Pending,
+                content: vec![],
+                locations: vec![],
+                raw_input: None,
+                raw_output: None,
+                meta: None,
+            }
+        );
+
+        let tool_call = ToolCall::builder(id.clone(), "Read file")
+            .kind(ToolKind::Read)
+            .status(ToolCallStatus::InProgress)
+            .content("Reading")
+            .location("/test/data.txt")
+            .raw_input(serde_json::json!({ "path": "/test/data.txt" }))
+            .build();
+        assert_eq!(
+            tool_call,
+            ToolCall {
+                id,
+                title: "Read file".to_string(),
+                kind: ToolKind::Read,
+                status: ToolCallStatus::InProgress,
+                content: vec![ToolCallContent::Content {
+                    content: ContentBlock::Text(TextContent {
+                        annotations: None,
+                        text: "Reading".to_string(),
+                        meta: None,
+                    }),
+                }],
+                locations: vec![ToolCallLocation {
+                    path: PathBuf::from("/test/data.txt"),
+                    line: None,
+                    column: None,
+                    end_line: None,
+                    end_column: None,
+                    meta: None,
+                }],
+                raw_input: Some(serde_json::json!({ "path": "/test/data.txt" })),
+                raw_output: None,
+                meta: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_tool_call_update_builder() {
+        let id = ToolCallId("read-1".into());
+        assert_eq!(
+            ToolCallUpdate::builder(id.clone()).build(),
+            ToolCallUpdate {
+                id: id.clone(),
+                fields: ToolCallUpdateFields::default(),
+                meta: None,
+            }
+        );
+
+        let update = ToolCallUpdate::builder(id.clone())
+            .status(ToolCallStatus::Completed)
+            .title("Read /test/data.txt")
+            .content("Lorem ipsum")
+            .content("dolor sit amet")
+            .location("/test/data.txt")
+            .build();
+        assert_eq!(
+            update,
+            ToolCallUpdate {
+                id,
+                fields: ToolCallUpdateFields {
+                    status: Some(ToolCallStatus::Completed),
+                    title: Some("Read /test/data.txt".to_string()),
+                    content: Some(vec!["Lorem ipsum".into(), "dolor sit amet".into()]),
+                    locations: Some(vec!["/test/data.txt".into()]),
+                    ..Default::default()
+                },
+                meta: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_typed_input_and_output() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Input {
+            path: String,
+            limit: Option<u32>,
+        }
+
+        let mut tool_call = ToolCall::builder(ToolCallId("read-1".into()), "Read file").build();
+        assert_eq!(
+            tool_call.typed_input::<Input>().unwrap_err().code,
+            ErrorCode::INVALID_PARAMS.code
+        );
+
+        tool_call.raw_input = Some(serde_json::json!({ "path": "/a.rs", "limit": 5 }));
+        assert_eq!(
+            tool_call.typed_input::<Input>().unwrap(),
+            Input {
+                path: "/a.rs".into(),
+                limit: Some(5),
+            }
+        );
+        assert_eq!(
+            tool_call.typed_input::<Vec<String>>().unwrap_err().code,
+            ErrorCode::INVALID_PARAMS.code
+        );
+
+        tool_call
+            .set_typed_output(Input {
+                path: "/b.rs".into(),
+                limit: None,
+            })
+            .unwrap();
+        assert_eq!(
+            tool_call.raw_output,
+            Some(serde_json::json!({ "path": "/b.rs", "limit": null }))
+        );
+    }
+
+    #[test]
+    fn test_apply_output_chunk() {
+        use serde_json::json;
+
+        let mut tool_call = ToolCall::builder(ToolCallId("test-1".into()), "Run tests").build();
+
+        tool_call.apply_output_chunk(json!(["a"]), true).unwrap();
+        tool_call
+            .apply_output_chunk(json!(["b", "c"]), true)
+            .unwrap();
+        assert_eq!(tool_call.raw_output, Some(json!(["a", "b", "c"])));
+
+        let error = tool_call.apply_output_chunk(json!("d"), true).unwrap_err();
+        assert_eq!(error.code, ErrorCode::INVALID_PARAMS.code);
+        assert_eq!(tool_call.raw_output, Some(json!(["a", "b", "c"])));
+
+        tool_call
+            .apply_output_chunk(json!("running"), false)
+            .unwrap();
+        tool_call.apply_output_chunk(json!("... ok"), true).unwrap();
+        assert_eq!(tool_call.raw_output, Some(json!("running... ok")));
+
+        tool_call
+            .apply_output_chunk(json!({ "passed": 1, "failed": 0 }), false)
+            .unwrap();
+        tool_call
+            .apply_output_chunk(json!({ "passed": 2, "skipped": 1 }), true)
+            .unwrap();
+        assert_eq!(
+            tool_call.raw_output,
+            Some(json!({ "passed": 2, "failed": 0, "skipped": 1 }))
+        );
+    }
+
+    #[test]
+    fn test_apply_update_status_transitions() {
+        use ToolCallStatus::*;
+
+        let cases = [
+            (Pending, Pending, true),
+            (Pending, InProgress, true),
+            (Pending, Completed, true),
+            (Pending, Failed, true),
+            (Pending, Cancelled, true),
+            (InProgress, Pending, true),
+            (InProgress, InProgress, true),
+            (InProgress, Completed, true),
+            (InProgress, Failed, true),
+            (InProgress, Cancelled, true),
+            (Completed, Pending, false),
+            (Completed, InProgress, false),
+            (Completed, Completed, true),
+            (Completed, Failed, false),
+            (Failed, Pending, false),
+            (Failed, InProgress, false),
+            (Failed, Completed, false),
+            (Failed, Failed, true),
+            (Failed, Cancelled, false),
+            (Cancelled, InProgress, false),
+            (Cancelled, Failed, false),
+            (Cancelled, Cancelled, true),
+        ];
+
+        for (from, to, allowed) in cases {
+            let mut tool_call = ToolCall::builder(ToolCallId("call_1".into()), "Read file")
+                .status(from)
+                .build();
+            let result = tool_call.apply_update(ToolCallUpdateFields {
+                status: Some(to),
+                title: Some("Updated".to_string()),
+                ..Default::default()
+            });
+
+            if allowed {
+                assert!(result.is_ok(), "{from:?} -> {to:?} should be allowed");
+                assert_eq!(tool_call.status, to);
+                assert_eq!(tool_call.title, "Updated");
+            } else {
+                let error = result.expect_err(&format!("{from:?} -> {to:?} should be rejected"));
+                assert_eq!(error.code, crate::ErrorCode::INVALID_PARAMS.code);
+                assert_eq!(tool_call.status, from);
+                assert_eq!(tool_call.title, "Read file");
+            }
+        }
+    }
+
+    #[test]
+    fn test_status_serialization() {
+        assert_eq!(
+            serde_json::to_value(ToolCallStatus::Cancelled).unwrap(),
+            serde_json::json!("cancelled")
+        );
+        assert_eq!(
+            serde_json::from_value::<ToolCallStatus>(serde_json::json!("cancelled")).unwrap(),
+            ToolCallStatus::Cancelled
+        );
+
+        // Pending is still the default, and is left out of tool calls.
+        let tool_call = ToolCall::builder(ToolCallId("call_1".into()), "Read file").build();
+        assert_eq!(tool_call.status, ToolCallStatus::Pending);
+        let json = serde_json::to_value(&tool_call).unwrap();
+        assert!(json.get("status").is_none());
+        let tool_call: ToolCall = serde_json::from_value(json).unwrap();
+        assert_eq!(tool_call.status, ToolCallStatus::Pending);
+
+        let tool_call = ToolCall::builder(ToolCallId("call_1".into()), "Read file")
+            .status(ToolCallStatus::Cancelled)
+            .build();
+        assert_eq!(
+            serde_json::to_value(&tool_call).unwrap()["status"],
+            "cancelled"
+        );
+    }
+
+    #[test]
+    fn test_location_range_serialization() {
+        let location = ToolCallLocation {
+            line: Some(42),
+            column: Some(5),
+            end_line: Some(48),
+            end_column: Some(1),
+            ..ToolCallLocation::from("/project/src/main.rs")
+        };
+        let json = serde_json::json!({
+            "type": "ToolCallLocation",
+            "path": "/project/src/main.rs",
+            "line": 42,
+            "column": 5,
+            "endLine": 48,
+            "endColumn": 1
+        });
+        assert_eq!(serde_json::to_value(&location).unwrap(), json);
+        assert_eq!(
+            serde_json::from_value::<ToolCallLocation>(json).unwrap(),
+            location
+        );
+
+        // Unset fields are left out.
+        assert_eq!(
+            serde_json::to_value(ToolCallLocation::from("/project/src/main.rs")).unwrap(),
+            serde_json::json!({ "type": "ToolCallLocation", "path": "/project/src/main.rs" })
+        );
+    }
+
+    #[test]
+    fn test_apply_update_without_status_after_completion() {
+        let mut tool_call = ToolCall::builder(ToolCallId("call_1".into()), "Read file")
+            .status(ToolCallStatus::Completed)
+            .build();
+        tool_call
+            .apply_update(ToolCallUpdateFields {
+                content: Some(vec!["late output".into()]),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(tool_call.content, vec!["late output".into()]);
+
+        let error = tool_call
+            .apply_update(ToolCallUpdateFields {
+                status: Some(ToolCallStatus::InProgress),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.data,
+            Some(serde_json::json!(
+                "tool call call_1 cannot transition from completed to in_progress"
+            ))
+        );
+    }
+
+    fn diff(old_text: Option<&str>, new_text: &str) -> Diff {
+        Diff {
+            path: PathBuf::from("/project/file.txt"),
+            old_text: old_text.map(str::to_string),
+            new_text: new_text.to_string(),
+            meta: None,
+        }
+    }
+
+    const OLD_TEXT: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+    const NEW_TEXT: &str = "one\ntwo\n3\nfour\nfive\nsix\nseven\neight\nnine\nten\neleven";
+
+    #[test]
+    fn test_diff_to_unified() {
+        let diff = diff(Some(OLD_TEXT), NEW_TEXT);
+        pretty_assertions::assert_eq!(
+            diff.to_unified(3),
+            "\
+--- /project/file.txt
++++ /project/file.txt
+@@ -1,6 +1,6 @@
+ one
+ two
+-three
++3
+ four
+ five
+ six
+@@ -8,3 +8,4 @@
+ eight
+ nine
+ ten
++eleven
+\\ No newline at end of file
+"
+        );
+        pretty_assertions::assert_eq!(
+            diff.to_unified(1),
+            "\
+--- /project/file.txt
++++ /project/file.txt
+@@ -2,3 +2,3 @@
+ two
+-three
++3
+ four
+@@ -10 +10,2 @@
+ ten
++eleven
+\\ No newline at end of file
+"
+        );
+        assert_eq!(diff.line_stats(), (2, 1));
+    }
+
+    #[test]
+    fn test_diff_large_inputs() {
+        // A complete rewrite, the worst case for the diff.
+        let old_text: String = (0..4000).map(|i| format!("old {i}\n")).collect();
+        let new_text: String = (0..4000).map(|i| format!("new {i}\n")).collect();
+        let rewrite = diff(Some(&old_text), &new_text);
+        assert_eq!(rewrite.line_stats(), (4000, 4000));
+
+        // A large file with a few scattered changes.
+        let old_text: String = (0..20_000).map(|i| format!("line {i}\n")).collect();
+        let new_text: String = (0..20_000)
+            .filter(|i| i % 5000 != 0)
+            .map(|i| match i % 7000 {
+                0 => format!("changed {i}\n"),
+                _ => format!("line {i}\n"),
+            })
+            .collect();
+        let edited = diff(Some(&old_text), &new_text);
+        assert_eq!(edited.line_stats(), (2, 6));
+        assert_eq!(edited.to_unified(0).matches("\n@@ ").count(), 6);
+    }
+
+    #[test]
+    fn test_diff_to_unified_merges_nearby_hunks() {
+        let diff = diff(
+            Some(OLD_TEXT),
+            &OLD_TEXT.replace("three", "3").replace("six", "6"),
+        );
+        pretty_assertions::assert_eq!(
+            diff.to_unified(1),
+            "\
+--- /project/file.txt
++++ /project/file.txt
+@@ -2,6 +2,6 @@
+ two
+-three
++3
+ four
+ five
+-six
++6
+ seven
+"
+        );
+    }
+
+    #[test]
+    fn test_diff_to_unified_deletions_without_context() {
+        let diff = diff(Some("a\nb\nc\n"), "b\n");
+        pretty_assertions::assert_eq!(
+            diff.to_unified(0),
+            "\
+--- /project/file.txt
++++ /project/file.txt
+@@ -1 +0,0 @@
+-a
+@@ -3 +1,0 @@
+-c
+"
+        );
+        assert_eq!(diff.line_stats(), (0, 2));
+    }
+
+    #[test]
+    fn test_diff_to_unified_new_file() {
+        let diff = diff(None, "fn main() {}\n\nmod tests;\n");
+        pretty_assertions::assert_eq!(
+            diff.to_unified(3),
+            "\
+--- /dev/null
++++ /project/file.txt
+@@ -0,0 +1,3 @@
++fn main() {}
++
++mod tests;
+"
+        );
+        assert_eq!(diff.line_stats(), (3, 0));
+    }
+
+    #[test]
+    fn test_diff_to_unified_unchanged() {
+        let diff = diff(Some(OLD_TEXT), OLD_TEXT);
+        assert_eq!(diff.to_unified(3), "");
+        assert_eq!(diff.line_stats(), (0, 0));
+    }
+}
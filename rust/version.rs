@@ -1,3 +1,5 @@
+use std::{fmt::Display, str::FromStr};
+
 use schemars::JsonSchema;
 use serde::Serialize;
 
@@ -5,6 +7,11 @@ pub const V0: ProtocolVersion = ProtocolVersion(0);
 pub const V1: ProtocolVersion = ProtocolVersion(1);
 pub const VERSION: ProtocolVersion = V1;
 
+/// The protocol versions implemented by this crate, from oldest to newest.
+///
+/// Agents can pass this to [`ProtocolVersion::negotiate`] when answering `initialize`.
+pub const SUPPORTED_VERSIONS: &[ProtocolVersion] = &[V1];
+
 /// Protocol version identifier.
 ///
 /// This version is only bumped for breaking changes.
@@ -13,6 +20,36 @@ pub const VERSION: ProtocolVersion = V1;
 pub struct ProtocolVersion(u16);
 
 impl ProtocolVersion {
+    /// Picks the protocol version an agent should respond with during initialization.
+    ///
+    /// Returns the highest version in `agent_supported` that is not newer than
+    /// `client_latest`, the latest version the client supports. If the agent supports
+    /// no such version, its oldest supported version is returned instead, and the
+    /// client is expected to close the connection if it can't speak it. If
+    /// `agent_supported` is empty, `client_latest` is returned unchanged.
+    ///
+    /// See protocol docs: [Protocol version](https://agentclientprotocol.com/protocol/initialization#protocol-version)
+    #[must_use]
+    pub fn negotiate(
+        client_latest: ProtocolVersion,
+        agent_supported: &[ProtocolVersion],
+    ) -> ProtocolVersion {
+        agent_supported
+            .iter()
+            .filter(|version| **version <= client_latest)
+            .max()
+            .or_else(|| agent_supported.iter().min())
+            .cloned()
+            .unwrap_or(client_latest)
+    }
+
+    /// Returns `true` if this crate implements this version, i.e. it is one of
+    /// [`SUPPORTED_VERSIONS`].
+    #[must_use]
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_VERSIONS.contains(self)
+    }
+
     #[cfg(test)]
     #[must_use]
     pub const fn new(version: u16) -> Self {
@@ -20,6 +57,56 @@ impl ProtocolVersion {
     }
 }
 
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses a version number such as `"1"`.
+///
+/// Only versions in [`SUPPORTED_VERSIONS`] are accepted.
+impl FromStr for ProtocolVersion {
+    type Err = ParseProtocolVersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let version = s
+            .trim()
+            .parse::<u16>()
+            .map(ProtocolVersion)
+            .map_err(|_| ParseProtocolVersionError::Invalid(s.to_string()))?;
+        if version.is_supported() {
+            Ok(version)
+        } else {
+            Err(ParseProtocolVersionError::Unsupported(version))
+        }
+    }
+}
+
+/// The error returned when parsing a [`ProtocolVersion`] from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseProtocolVersionError {
+    /// The string is not a version number.
+    Invalid(String),
+    /// The version is not one of [`SUPPORTED_VERSIONS`].
+    Unsupported(ProtocolVersion),
+}
+
+impl std::error::Error for ParseProtocolVersionError {}
+
+impl Display for ParseProtocolVersionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseProtocolVersionError::Invalid(value) => {
+                write!(f, "invalid protocol version {value:?}: expected a number")
+            }
+            ParseProtocolVersionError::Unsupported(version) => {
+                write!(f, "unsupported protocol version {version}")
+            }
+        }
+    }
+}
+
 use serde::{Deserialize, Deserializer};
 
 impl<'de> Deserialize<'de> for ProtocolVersion {
@@ -102,6 +189,70 @@ mod tests {
         assert_eq!(version, ProtocolVersion::new(0));
     }
 
+    #[test]
+    fn test_negotiate_client_newer_than_agent() {
+        let supported = [V0, V1];
+        assert_eq!(
+            ProtocolVersion::negotiate(ProtocolVersion::new(5), &supported),
+            V1
+        );
+        assert_eq!(ProtocolVersion::negotiate(V1, &supported), V1);
+        assert_eq!(
+            ProtocolVersion::negotiate(VERSION, SUPPORTED_VERSIONS),
+            VERSION
+        );
+    }
+
+    #[test]
+    fn test_negotiate_client_older_than_agent() {
+        let supported = [V1, ProtocolVersion::new(2)];
+        assert_eq!(ProtocolVersion::negotiate(V0, &supported), V1);
+        assert_eq!(ProtocolVersion::negotiate(V0, SUPPORTED_VERSIONS), V1);
+    }
+
+    #[test]
+    fn test_negotiate_picks_highest_mutual_version() {
+        let supported = [V0, ProtocolVersion::new(2), ProtocolVersion::new(4)];
+        assert_eq!(
+            ProtocolVersion::negotiate(ProtocolVersion::new(3), &supported),
+            ProtocolVersion::new(2)
+        );
+        assert_eq!(ProtocolVersion::negotiate(V1, &[]), V1);
+    }
+
+    #[test]
+    fn test_display_and_from_str() {
+        assert_eq!(V1.to_string(), "1");
+        assert_eq!("1".parse::<ProtocolVersion>(), Ok(V1));
+        assert_eq!(VERSION.to_string().parse::<ProtocolVersion>(), Ok(VERSION));
+        assert_eq!(
+            "v1".parse::<ProtocolVersion>(),
+            Err(ParseProtocolVersionError::Invalid("v1".to_string()))
+        );
+        assert_eq!(
+            "1.0.0".parse::<ProtocolVersion>(),
+            Err(ParseProtocolVersionError::Invalid("1.0.0".to_string()))
+        );
+        assert_eq!(
+            "7".parse::<ProtocolVersion>(),
+            Err(ParseProtocolVersionError::Unsupported(
+                ProtocolVersion::new(7)
+            ))
+        );
+        assert_eq!(
+            "7".parse::<ProtocolVersion>().unwrap_err().to_string(),
+            "unsupported protocol version 7"
+        );
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(V1.is_supported());
+        assert!(!V0.is_supported());
+        assert!(!ProtocolVersion::new(2).is_supported());
+        assert!(V0 < V1);
+    }
+
     #[test]
     fn test_deserialize_max_u16() {
         let json = "65535";
@@ -0,0 +1,290 @@
+//! A transport adapter for running connections over WebSockets.
+//!
+//! Connections exchange newline-delimited JSON over byte streams, while WebSockets
+//! exchange discrete messages. [`WebSocketTransport`] bridges the two by sending
+//! each JSON-RPC message as one text message, and turning each received text or
+//! binary message into one line, with any line breaks inside it replaced by spaces.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures::{
+    AsyncRead, AsyncWrite, Sink, SinkExt as _, Stream, StreamExt as _,
+    stream::{SplitSink, SplitStream},
+};
+use tungstenite::{Error as WsError, Message};
+
+/// Splits a WebSocket into the byte streams expected by a connection.
+///
+/// Works with any WebSocket that is a [`Stream`] and [`Sink`] of [`tungstenite`]
+/// messages, such as `tokio_tungstenite::WebSocketStream`.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example(client: impl agent_client_protocol::MessageHandler<agent_client_protocol::ClientSide> + 'static) -> anyhow::Result<()> {
+/// use agent_client_protocol::{ClientSideConnection, WebSocketTransport};
+///
+/// let (ws, _) = tokio_tungstenite::connect_async("ws://127.0.0.1:8080").await?;
+/// let transport = WebSocketTransport::new(ws);
+/// let (conn, io_task) = ClientSideConnection::new(
+///     client,
+///     transport.outgoing,
+///     transport.incoming,
+///     |fut| {
+///         tokio::task::spawn_local(fut);
+///     },
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct WebSocketTransport<S> {
+    /// Sends each line written to it as a text message.
+    pub outgoing: WebSocketWriter<SplitSink<S, Message>>,
+    /// Yields each received text or binary message as a line.
+    pub incoming: WebSocketReader<SplitStream<S>>,
+}
+
+impl<S> WebSocketTransport<S>
+where
+    S: Stream<Item = Result<Message, WsError>> + Sink<Message, Error = WsError>,
+{
+    /// Splits `socket` into its outgoing and incoming halves.
+    pub fn new(socket: S) -> Self {
+        let (sink, stream) = socket.split();
+        Self {
+            outgoing: WebSocketWriter::new(sink),
+            incoming: WebSocketReader::new(stream),
+        }
+    }
+}
+
+/// The incoming half of a [`WebSocketTransport`].
+///
+/// Ping and pong messages are skipped. A close message, or the end of the
+/// underlying stream, ends the byte stream.
+pub struct WebSocketReader<S> {
+    stream: S,
+    current: Vec<u8>,
+    offset: usize,
+}
+
+impl<S> WebSocketReader<S>
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    /// Creates a reader that yields the messages received on `stream`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            current: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl<S> AsyncRead for WebSocketReader<S>
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.offset < this.current.len() {
+                let len = buf.len().min(this.current.len() - this.offset);
+                buf[..len].copy_from_slice(&this.current[this.offset..this.offset + len]);
+                this.offset += len;
+                return Poll::Ready(Ok(len));
+            }
+
+            let data = match ready!(this.stream.poll_next_unpin(cx)) {
+                Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => message.into_data(),
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => continue,
+                Some(Ok(Message::Close(_)))
+                | Some(Err(WsError::ConnectionClosed | WsError::AlreadyClosed))
+                | None => return Poll::Ready(Ok(0)),
+                Some(Err(error)) => return Poll::Ready(Err(io::Error::other(error))),
+            };
+            // A message must stay a single line, even if it was pretty-printed, since
+            // the connection would otherwise split it into several messages.
+            let data = data.trim_ascii_end();
+            this.current.clear();
+            this.current.extend(data.iter().map(|&byte| match byte {
+                b'\r' | b'\n' => b' ',
+                byte => byte,
+            }));
+            this.current.push(b'\n');
+            this.offset = 0;
+        }
+    }
+}
+
+/// The outgoing half of a [`WebSocketTransport`].
+///
+/// Bytes are buffered until a newline is written, at which point the line is sent
+/// as a text message. The newline is only reported as written once the message
+/// has been flushed to the socket, so that a connection which never flushes
+/// explicitly still sends every message.
+pub struct WebSocketWriter<S> {
+    sink: S,
+    line: Vec<u8>,
+    sending: bool,
+}
+
+impl<S> WebSocketWriter<S>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    /// Creates a writer that sends lines to `sink`.
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            line: Vec::new(),
+            sending: false,
+        }
+    }
+
+    fn poll_send_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.sending && !self.line.is_empty() {
+            ready!(self.sink.poll_ready_unpin(cx)).map_err(io::Error::other)?;
+            let line = String::from_utf8(std::mem::take(&mut self.line))
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            self.sink
+                .start_send_unpin(Message::text(line))
+                .map_err(io::Error::other)?;
+            self.sending = true;
+        }
+        if self.sending {
+            ready!(self.sink.poll_flush_unpin(cx)).map_err(io::Error::other)?;
+            self.sending = false;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WebSocketWriter<S>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match buf.iter().position(|byte| *byte == b'\n') {
+            Some(0) => {
+                ready!(this.poll_send_line(cx))?;
+                Poll::Ready(Ok(1))
+            }
+            Some(end) => {
+                this.line.extend_from_slice(&buf[..end]);
+                Poll::Ready(Ok(end))
+            }
+            None => {
+                this.line.extend_from_slice(buf);
+                Poll::Ready(Ok(buf.len()))
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.sending {
+            ready!(this.poll_send_line(cx))?;
+        }
+        this.sink.poll_flush_unpin(cx).map_err(io::Error::other)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_line(cx))?;
+        this.sink.poll_close_unpin(cx).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _, io::BufReader};
+    use tokio_tungstenite::{WebSocketStream, tungstenite::protocol::Role};
+
+    #[tokio::test]
+    async fn test_frames_map_to_lines() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (transport, mut peer) = tokio::join!(
+            WebSocketStream::from_raw_socket(a, Role::Client, None),
+            WebSocketStream::from_raw_socket(b, Role::Server, None),
+        );
+        let WebSocketTransport {
+            mut outgoing,
+            incoming,
+        } = WebSocketTransport::new(transport);
+
+        // Each line is sent as its own text message, without the newline.
+        outgoing
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"a\"}\n{\"jsonrpc\":")
+            .await
+            .unwrap();
+        outgoing
+            .write_all(b"\"2.0\",\"method\":\"b\"}\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            peer.next().await.unwrap().unwrap(),
+            Message::text(r#"{"jsonrpc":"2.0","method":"a"}"#)
+        );
+        assert_eq!(
+            peer.next().await.unwrap().unwrap(),
+            Message::text(r#"{"jsonrpc":"2.0","method":"b"}"#)
+        );
+
+        // Each received text or binary message becomes one line.
+        peer.send(Message::text(r#"{"id":1}"#)).await.unwrap();
+        peer.send(Message::Ping(Vec::new().into())).await.unwrap();
+        peer.send(Message::binary(&b"{\"id\":2}\n"[..]))
+            .await
+            .unwrap();
+        peer.close(None).await.unwrap();
+
+        let mut lines = BufReader::new(incoming).lines();
+        assert_eq!(lines.next().await.unwrap().unwrap(), r#"{"id":1}"#);
+        assert_eq!(lines.next().await.unwrap().unwrap(), r#"{"id":2}"#);
+        assert!(lines.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_multiline_messages_stay_one_line() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (transport, mut peer) = tokio::join!(
+            WebSocketStream::from_raw_socket(a, Role::Client, None),
+            WebSocketStream::from_raw_socket(b, Role::Server, None),
+        );
+        let WebSocketTransport { incoming, .. } = WebSocketTransport::new(transport);
+
+        peer.send(Message::text("{\r\n  \"id\": 1,\n  \"result\": null\n}\n"))
+            .await
+            .unwrap();
+        peer.send(Message::binary(&b"{\"id\":\n2}"[..]))
+            .await
+            .unwrap();
+        peer.close(None).await.unwrap();
+
+        let mut lines = BufReader::new(incoming).lines();
+        let line = lines.next().await.unwrap().unwrap();
+        assert_eq!(line, r#"{    "id": 1,   "result": null }"#);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            serde_json::json!({"id": 1, "result": null})
+        );
+        assert_eq!(lines.next().await.unwrap().unwrap(), r#"{"id": 2}"#);
+        assert!(lines.next().await.is_none());
+    }
+}